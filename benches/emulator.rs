@@ -0,0 +1,92 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use romoulade::gb::cartridge::Cartridge;
+use romoulade::gb::cpu::CPU;
+use romoulade::gb::headless::Headless;
+use romoulade::gb::memory::constants::{BOOT_ROM_OFF, PPU_BGP, PPU_LCDC, VRAM_BEGIN};
+use romoulade::gb::memory::MemoryBus;
+use romoulade::gb::ppu::PPU;
+use romoulade::gb::{AddressSpace, CYCLES_PER_FRAME};
+use std::cell::RefCell;
+
+/// Address of the benchmark loop, behind the cartridge header
+const PROGRAM_START: u16 = 0x0150;
+
+/// Number of instructions executed per iteration
+const STEPS: u64 = 1000;
+
+/// Loop over a mix of loads, ALU, CB prefixed, stack and jump instructions
+const PROGRAM: [u8; 16] = [
+    0x2A, // LD A,(HL+)
+    0x80, // ADD A,B
+    0x12, // LD (DE),A
+    0x13, // INC DE
+    0xCB, 0x37, // SWAP A
+    0x05, // DEC B
+    0xC5, // PUSH BC
+    0xC1, // POP BC
+    0x26, 0xC0, // LD H,0xC0
+    0x16, 0xC1, // LD D,0xC1
+    0xC3, 0x50, 0x01, // JP 0x0150
+];
+
+/// Creates a bus with the given program at `PROGRAM_START` and the boot ROM disabled
+fn create_bus(program: &[u8]) -> RefCell<MemoryBus> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x14D] = 0xE7;
+    let start = usize::from(PROGRAM_START);
+    rom[start..start + program.len()].copy_from_slice(program);
+    let bus = RefCell::new(MemoryBus::new(Cartridge::from_bytes(rom)));
+    bus.borrow_mut().write(BOOT_ROM_OFF, 1);
+    bus
+}
+
+fn cpu_step(c: &mut Criterion) {
+    let bus = create_bus(&PROGRAM);
+    let mut cpu = CPU::new(&bus);
+    cpu.pc = PROGRAM_START;
+    cpu.sp = 0xFFFE;
+    cpu.r.set_hl(0xC000);
+    cpu.r.set_de(0xC100);
+
+    let mut group = c.benchmark_group("cpu");
+    group.throughput(Throughput::Elements(STEPS));
+    group.bench_function("step", |b| {
+        b.iter(|| {
+            for _ in 0..STEPS {
+                cpu.step();
+            }
+        })
+    });
+    group.finish();
+}
+
+fn ppu_frame(c: &mut Criterion) {
+    let bus = create_bus(&[]);
+    {
+        // Different tiles on every position, each with a gradient in all four colors
+        let mut bus = bus.borrow_mut();
+        for (i, address) in (VRAM_BEGIN..0x9800).enumerate() {
+            bus.write(address, (i * 37) as u8);
+        }
+        for (i, address) in (0x9800..0x9C00).enumerate() {
+            bus.write(address, i as u8);
+        }
+        bus.write(PPU_BGP, 0xE4);
+        bus.write(PPU_LCDC, 0x91);
+    }
+    let mut screen = Headless::new();
+    let mut ppu = PPU::new(&bus, &mut screen);
+
+    c.bench_function("ppu frame", |b| {
+        b.iter(|| {
+            let mut cycles = 0;
+            while cycles < CYCLES_PER_FRAME {
+                ppu.step(4);
+                cycles += 4;
+            }
+        })
+    });
+}
+
+criterion_group!(benches, cpu_step, ppu_frame);
+criterion_main!(benches);
@@ -0,0 +1,354 @@
+use crate::gb::cartridge::Cartridge;
+use crate::gb::cpu::CPU;
+use crate::gb::image;
+use crate::gb::interrupt::IRQHandler;
+use crate::gb::joypad::Buttons;
+use crate::gb::memory::MemoryBus;
+use crate::gb::ppu::misc::Color;
+use crate::gb::ppu::PPU;
+use crate::gb::timer::Timer;
+use crate::gb::{AddressSpace, Screen, CYCLES_PER_FRAME};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Error codes defined by the JSON-RPC 2.0 specification
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Application defined error codes
+const NO_ROM_LOADED: i64 = -32000;
+const IO_ERROR: i64 = -32001;
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: String) -> Self {
+        Self { code, message }
+    }
+}
+
+impl From<io::Error> for RpcError {
+    fn from(err: io::Error) -> Self {
+        Self::new(IO_ERROR, err.to_string())
+    }
+}
+
+/// Buttons which are held during a range of frames
+struct Input {
+    frames: Range<u64>,
+    buttons: Buttons,
+}
+
+/// Screen which only forwards the buttons set by the automation client,
+/// frames are read directly from the PPU.
+struct RemoteScreen<'a> {
+    buttons: &'a Cell<Buttons>,
+}
+
+impl Screen for RemoteScreen<'_> {
+    fn write_pixel(&mut self, _x: u8, _y: u8, _value: Color) {}
+
+    fn render_screen(&mut self) {}
+
+    fn pressed_buttons(&self) -> Buttons {
+        self.buttons.get()
+    }
+}
+
+/// Reads newline delimited requests and writes the responses of a single client
+struct Connection<R: BufRead, W: Write> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: BufRead, W: Write> Connection<R, W> {
+    /// Returns the next valid request or None once the client disconnected.
+    /// Malformed requests are answered right away.
+    fn next(&mut self) -> io::Result<Option<Request>> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(request) => return Ok(Some(request)),
+                Err(err) => {
+                    let err = RpcError::new(PARSE_ERROR, err.to_string());
+                    self.respond(Value::Null, Err(err))?;
+                }
+            }
+        }
+    }
+
+    fn respond(&mut self, id: Value, result: Result<Value, RpcError>) -> io::Result<()> {
+        let response = match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(err) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": err.code, "message": err.message},
+            }),
+        };
+        writeln!(self.writer, "{}", response)?;
+        self.writer.flush()
+    }
+}
+
+/// Listens on the given address and serves one automation client after another
+pub fn listen(address: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    info!("Automation server listening on {}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let reader = BufReader::new(stream.try_clone()?);
+        if let Err(err) = serve(reader, stream) {
+            warn!("Automation client disconnected: {}", err);
+        }
+    }
+    Ok(())
+}
+
+/// Handles JSON-RPC requests until the client disconnects.
+/// Every request except `load_rom` requires a loaded ROM.
+pub fn serve<R: BufRead, W: Write>(reader: R, writer: W) -> io::Result<()> {
+    let mut connection = Connection { reader, writer };
+    let mut pending = connection.next()?;
+    while let Some(request) = pending {
+        pending = match request.method.as_str() {
+            "load_rom" => match load_rom(&request.params) {
+                Ok(cartridge) => {
+                    let title = cartridge.meta.title.clone();
+                    connection.respond(request.id, Ok(json!({ "title": title })))?;
+                    run_session(&mut connection, cartridge)?
+                }
+                Err(err) => {
+                    connection.respond(request.id, Err(err))?;
+                    connection.next()?
+                }
+            },
+            _ => {
+                let err = RpcError::new(NO_ROM_LOADED, "No ROM loaded".to_string());
+                connection.respond(request.id, Err(err))?;
+                connection.next()?
+            }
+        };
+    }
+    Ok(())
+}
+
+/// Emulates the given cartridge and handles requests until another ROM is loaded.
+/// Returns the `load_rom` request or None if the client disconnected.
+fn run_session<R: BufRead, W: Write>(
+    connection: &mut Connection<R, W>,
+    cartridge: Cartridge,
+) -> io::Result<Option<Request>> {
+    let bus = RefCell::new(MemoryBus::new(cartridge));
+    let buttons = Cell::new(Buttons::empty());
+    let mut screen = RemoteScreen { buttons: &buttons };
+    let mut ppu = PPU::new(&bus, &mut screen);
+    let cpu = RefCell::new(CPU::new(&bus));
+    let mut irq_handler = IRQHandler::new(&cpu, &bus);
+    let mut timer = Timer::new(&bus);
+
+    let mut inputs: Vec<Input> = Vec::new();
+    let mut cycles: u64 = 0;
+    while let Some(request) = connection.next()? {
+        let frame = cycles / u64::from(CYCLES_PER_FRAME);
+        let params = &request.params;
+        let result = match request.method.as_str() {
+            "load_rom" => return Ok(Some(request)),
+            "set_input" => parse_buttons(params).and_then(|pressed| {
+                let start = optional_param(params, "start")?.unwrap_or(frame);
+                let end = param(params, "end")?;
+                inputs.retain(|input| input.frames.end > frame);
+                inputs.push(Input {
+                    frames: start..end,
+                    buttons: pressed,
+                });
+                Ok(Value::Null)
+            }),
+            "advance" => param::<u64>(params, "frames").map(|frames| {
+                let target = (frame + frames) * u64::from(CYCLES_PER_FRAME);
+                while cycles < target {
+                    let frame = cycles / u64::from(CYCLES_PER_FRAME);
+                    buttons.set(
+                        inputs
+                            .iter()
+                            .filter(|input| input.frames.contains(&frame))
+                            .fold(Buttons::empty(), |pressed, input| pressed | input.buttons),
+                    );
+                    let step = cpu.borrow_mut().step();
+                    timer.step(step);
+                    ppu.step(step);
+                    irq_handler.handle();
+                    cycles += u64::from(step);
+                }
+                json!({ "frame": cycles / u64::from(CYCLES_PER_FRAME) })
+            }),
+            "read_memory" => param::<u16>(params, "address").and_then(|address| {
+                let length = optional_param::<u16>(params, "length")?.unwrap_or(1);
+                let bus = bus.borrow();
+                let bytes = (0..length)
+                    .map(|offset| bus.read(address.wrapping_add(offset)))
+                    .collect::<Vec<u8>>();
+                Ok(json!(bytes))
+            }),
+            "registers" => {
+                let cpu = cpu.borrow();
+                Ok(json!({
+                    "a": cpu.r.a,
+                    "f": cpu.r.get_af() as u8,
+                    "b": cpu.r.b,
+                    "c": cpu.r.c,
+                    "d": cpu.r.d,
+                    "e": cpu.r.e,
+                    "h": cpu.r.h,
+                    "l": cpu.r.l,
+                    "sp": cpu.sp,
+                    "pc": cpu.pc,
+                    "ime": cpu.ime,
+                }))
+            }
+            "screenshot" => param::<PathBuf>(params, "path").and_then(|path| {
+                image::write_png(&path, ppu.framebuffer())?;
+                Ok(Value::Null)
+            }),
+            method => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method: {}", method),
+            )),
+        };
+        connection.respond(request.id, result)?;
+    }
+    Ok(None)
+}
+
+fn load_rom(params: &Value) -> Result<Cartridge, RpcError> {
+    let path = param::<PathBuf>(params, "path")?;
+    Ok(Cartridge::from_path(Path::new(&path), None)?)
+}
+
+/// Parses a list of button names, e.g. `["A", "UP"]`
+fn parse_buttons(params: &Value) -> Result<Buttons, RpcError> {
+    let mut buttons = Buttons::empty();
+    for name in param::<Vec<String>>(params, "buttons")? {
+        buttons |= match name.to_uppercase().as_str() {
+            "RIGHT" => Buttons::RIGHT,
+            "LEFT" => Buttons::LEFT,
+            "UP" => Buttons::UP,
+            "DOWN" => Buttons::DOWN,
+            "A" => Buttons::A,
+            "B" => Buttons::B,
+            "SELECT" => Buttons::SELECT,
+            "START" => Buttons::START,
+            _ => {
+                let message = format!("Unknown button: {}", name);
+                return Err(RpcError::new(INVALID_PARAMS, message));
+            }
+        };
+    }
+    Ok(buttons)
+}
+
+fn param<T: DeserializeOwned>(params: &Value, name: &str) -> Result<T, RpcError> {
+    optional_param(params, name)?
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("Missing parameter: {}", name)))
+}
+
+fn optional_param<T: DeserializeOwned>(params: &Value, name: &str) -> Result<Option<T>, RpcError> {
+    match params.get(name) {
+        Some(value) if !value.is_null() => serde_json::from_value(value.clone())
+            .map(Some)
+            .map_err(|err| RpcError::new(INVALID_PARAMS, format!("{}: {}", name, err))),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn call(requests: &[Value]) -> Vec<Value> {
+        let input = requests
+            .iter()
+            .map(|request| format!("{}\n", request))
+            .collect::<String>();
+        let mut output = Vec::new();
+        serve(input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_requires_rom() {
+        let responses = call(&[json!({"jsonrpc": "2.0", "id": 1, "method": "registers"})]);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["error"]["code"], NO_ROM_LOADED);
+    }
+
+    #[test]
+    fn test_session() {
+        // An empty ROM with a valid header checksum
+        let path = env::temp_dir().join("romoulade-test-automation.gb");
+        let mut rom = vec![0; 0x8000];
+        rom[0x14D] = 0xE7;
+        fs::write(&path, rom).unwrap();
+
+        let responses = call(&[
+            json!({"id": 1, "method": "load_rom", "params": {"path": path}}),
+            json!({"id": 2, "method": "set_input", "params": {"buttons": ["a", "START"], "end": 2}}),
+            json!({"id": 3, "method": "advance", "params": {"frames": 2}}),
+            json!({"id": 4, "method": "read_memory", "params": {"address": 0x14D, "length": 2}}),
+            json!({"id": 5, "method": "registers"}),
+            json!({"id": 6, "method": "unknown"}),
+            json!({"id": 7, "method": "set_input", "params": {"buttons": ["X"], "end": 1}}),
+        ]);
+        fs::remove_file(&path).unwrap();
+
+        assert!(responses[0]["result"]["title"].is_string());
+        assert_eq!(responses[1]["result"], Value::Null);
+        assert_eq!(responses[2]["result"]["frame"], 2);
+        assert_eq!(responses[3]["result"], json!([0xE7, 0]));
+        assert!(responses[4]["result"]["pc"].is_u64());
+        assert_eq!(responses[5]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(responses[6]["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let mut output = Vec::new();
+        serve("{invalid\n".as_bytes(), &mut output).unwrap();
+        let response: Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+    }
+}
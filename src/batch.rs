@@ -0,0 +1,302 @@
+use crate::gb::cartridge::Cartridge;
+use crate::gb::cpu::CPU;
+use crate::gb::headless::Headless;
+use crate::gb::image;
+use crate::gb::interrupt::IRQHandler;
+use crate::gb::memory::constants::BOOT_ROM_OFF;
+use crate::gb::memory::MemoryBus;
+use crate::gb::pacer::Pacer;
+use crate::gb::ppu::PPU;
+use crate::gb::timer::Timer;
+use crate::gb::{AddressSpace, CYCLES_PER_FRAME};
+use crate::utils;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// File extensions which are considered to be ROMs
+const ROM_EXTENSIONS: [&str; 2] = ["gb", "gbc"];
+
+/// Contains the outcome of a single headless ROM run
+#[derive(Serialize, Deserialize)]
+pub struct RomReport {
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub booted: bool,
+    pub frames: usize,
+    pub frame_hash: Option<String>,
+    pub serial: String,
+    pub panic: Option<String>,
+    /// Set if the ROM couldn't be loaded, e.g. because of an unsupported MBC
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Column names of CSV reports
+const CSV_HEADER: &str = "path,title,booted,frames,frame_hash,serial,panic,error";
+
+impl RomReport {
+    /// Returns the report as a single CSV record
+    fn to_csv(&self) -> String {
+        [
+            csv_field(&self.path.to_string_lossy()),
+            csv_field(self.title.as_deref().unwrap_or_default()),
+            self.booted.to_string(),
+            self.frames.to_string(),
+            self.frame_hash.clone().unwrap_or_default(),
+            csv_field(&self.serial),
+            csv_field(self.panic.as_deref().unwrap_or_default()),
+            csv_field(self.error.as_deref().unwrap_or_default()),
+        ]
+        .join(",")
+    }
+}
+
+/// Quotes a CSV field if it contains separators, quotes or line breaks
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Runs every ROM in `dir` headlessly for the given amount of frames on `jobs` threads
+/// and writes the collected results to `report`, as CSV if it ends with `.csv` or as JSON otherwise.
+/// Emulation is paced to real time unless `unlocked` is set.
+pub fn run(
+    dir: &Path,
+    frames: u32,
+    unlocked: bool,
+    jobs: usize,
+    report: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let roms = find_roms(dir)?;
+
+    let next = AtomicUsize::new(0);
+    let mut reports = thread::scope(|scope| {
+        let workers = (0..jobs.clamp(1, roms.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    while let Some(path) = roms.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        println!("Running {}...", path.display());
+                        results.push(run_rom(path, frames, unlocked));
+                    }
+                    results
+                })
+            })
+            .collect::<Vec<_>>();
+        // Emulator panics are caught per ROM, anything else would lose reports
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|payload| panic::resume_unwind(payload))
+            })
+            .collect::<Vec<RomReport>>()
+    });
+    reports.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let is_csv = report
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    match is_csv {
+        true => write_csv(&mut BufWriter::new(File::create(report)?), &reports)?,
+        false => serde_json::to_writer_pretty(File::create(report)?, &reports)?,
+    }
+    println!(
+        "Wrote report for {} ROMs to {}",
+        reports.len(),
+        report.display()
+    );
+    Ok(())
+}
+
+/// Writes the reports as CSV with a header line
+fn write_csv(writer: &mut impl Write, reports: &[RomReport]) -> std::io::Result<()> {
+    writeln!(writer, "{}", CSV_HEADER)?;
+    for report in reports {
+        writeln!(writer, "{}", report.to_csv())?;
+    }
+    writer.flush()
+}
+
+/// Returns all ROMs in the given directory sorted by path
+pub(crate) fn find_roms(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut roms = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| ROM_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        })
+        .collect::<Vec<PathBuf>>();
+    roms.sort();
+    Ok(roms)
+}
+
+/// Runs a single ROM headlessly and collects the results
+fn run_rom(path: &Path, frames: u32, unlocked: bool) -> RomReport {
+    let mut report = RomReport {
+        path: path.to_path_buf(),
+        title: None,
+        booted: false,
+        frames: 0,
+        frame_hash: None,
+        serial: String::new(),
+        panic: None,
+        error: None,
+    };
+
+    // Battery backed RAM is not persisted to keep runs reproducible
+    let cartridge = match Cartridge::from_path(path, None) {
+        Ok(cartridge) => cartridge,
+        Err(err) => {
+            report.error = Some(format!("Unable to load cartridge: {}", err));
+            return report;
+        }
+    };
+    report.title = Some(cartridge.meta.title.clone());
+
+    let bus = RefCell::new(MemoryBus::new(cartridge));
+    let mut screen = Headless::new();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let pacer = match unlocked {
+            true => None,
+            false => Some(Pacer::new()),
+        };
+        emulate(&bus, &mut screen, frames, pacer);
+    }));
+    if let Err(payload) = result {
+        report.panic = Some(utils::panic_message(&*payload).to_string());
+    }
+
+    // A bus which is still borrowed after a panic can't be inspected anymore
+    if let Ok(bus) = bus.try_borrow() {
+        report.booted = bus.read(BOOT_ROM_OFF) != 0;
+        report.serial = String::from_utf8_lossy(bus.serial_output()).into_owned();
+    }
+    report.frames = screen.frames();
+    if report.frames > 0 {
+        report.frame_hash = Some(format!("{:016x}", screen.frame_hash()));
+    }
+    report
+}
+
+/// Runs a ROM headlessly for the given amount of frames and writes the last frame as PNG.
+/// If a reference image is given, an error is returned if any pixel differs from it.
+pub fn dump_frame(
+    rom: &Path,
+    frames: u32,
+    path: &Path,
+    reference: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let cartridge = Cartridge::from_path(rom, None)?;
+    let bus = RefCell::new(MemoryBus::new(cartridge));
+    let mut screen = Headless::new();
+    emulate(&bus, &mut screen, frames, None);
+    image::write_png(path, screen.frame())?;
+    println!("Wrote frame {} to {}", screen.frames(), path.display());
+
+    if let Some(reference) = reference {
+        let expected = image::read_png(reference)?;
+        let matching = expected
+            .iter()
+            .zip(screen.frame())
+            .filter(|(a, b)| a == b)
+            .count();
+        let differing = expected.len().max(screen.frame().len()) - matching;
+        if differing > 0 {
+            return Err(format!(
+                "Frame differs from {} in {} pixels",
+                reference.display(),
+                differing
+            )
+            .into());
+        }
+        println!("Frame matches {}", reference.display());
+    }
+    Ok(())
+}
+
+/// Emulates the given amount of frames, paced to real time if a pacer is given
+pub(crate) fn emulate(
+    bus: &RefCell<MemoryBus>,
+    screen: &mut Headless,
+    frames: u32,
+    pacer: Option<Pacer>,
+) {
+    emulate_until(bus, screen, frames, pacer, |_, _| None::<()>);
+}
+
+/// Emulates the given amount of frames like `emulate`, but calls `inspect` after every
+/// instruction with the CPU and the executed opcode. Stops early once it returns a result.
+pub(crate) fn emulate_until<R>(
+    bus: &RefCell<MemoryBus>,
+    screen: &mut Headless,
+    frames: u32,
+    mut pacer: Option<Pacer>,
+    mut inspect: impl FnMut(&CPU<MemoryBus>, u8) -> Option<R>,
+) -> Option<R> {
+    let mut ppu = PPU::new(bus, screen);
+    let cpu = RefCell::new(CPU::new(bus));
+    let mut irq_handler = IRQHandler::new(&cpu, bus);
+    let mut timer = Timer::new(bus);
+
+    // Large frame counts overflow u32 cycles
+    let total = u64::from(frames) * u64::from(CYCLES_PER_FRAME);
+    let mut cycles = 0;
+    while cycles < total {
+        let opcode = bus.borrow().read(cpu.borrow().pc);
+        let step = cpu.borrow_mut().step();
+        timer.step(step);
+        ppu.step(step);
+        irq_handler.handle();
+        if let Some(pacer) = &mut pacer {
+            pacer.step(step);
+        }
+        if let Some(result) = inspect(&cpu.borrow(), opcode) {
+            return Some(result);
+        }
+        cycles += u64::from(step);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv() {
+        let report = RomReport {
+            path: PathBuf::from("roms/a,b.gb"),
+            title: Some("TEST".to_string()),
+            booted: true,
+            frames: 60,
+            frame_hash: Some("00000000000000ff".to_string()),
+            serial: "say \"hi\"".to_string(),
+            panic: None,
+            error: None,
+        };
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &[report]).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            format!(
+                "{}\n\"roms/a,b.gb\",TEST,true,60,00000000000000ff,\"say \"\"hi\"\"\",,\n",
+                CSV_HEADER
+            )
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use crate::batch;
+use crate::gb::cartridge::Cartridge;
+use crate::gb::cpu::CPU;
+use crate::gb::headless::Headless;
+use crate::gb::interrupt::IRQHandler;
+use crate::gb::memory::MemoryBus;
+use crate::gb::ppu::PPU;
+use crate::gb::timer::Timer;
+use crate::gb::{AddressSpace, CPU_CLOCK_SPEED, CYCLES_PER_FRAME};
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Decorates an AddressSpace and measures the time spent in its accesses
+struct TimingBus<'a, T: AddressSpace> {
+    bus: &'a RefCell<T>,
+    elapsed: Cell<Duration>,
+}
+
+impl<'a, T: AddressSpace> TimingBus<'a, T> {
+    fn new(bus: &'a RefCell<T>) -> Self {
+        Self {
+            bus,
+            elapsed: Cell::new(Duration::default()),
+        }
+    }
+}
+
+impl<'a, T: AddressSpace> AddressSpace for TimingBus<'a, T> {
+    fn write(&mut self, address: u16, value: u8) {
+        let start = Instant::now();
+        self.bus.borrow_mut().write(address, value);
+        self.elapsed.set(self.elapsed.get() + start.elapsed());
+    }
+
+    fn read(&self, address: u16) -> u8 {
+        let start = Instant::now();
+        let value = self.bus.borrow().read(address);
+        self.elapsed.set(self.elapsed.get() + start.elapsed());
+        value
+    }
+
+    fn increment_address(&mut self, address: u16) {
+        self.bus.borrow_mut().increment_address(address);
+    }
+
+    fn tick(&mut self) {
+        self.bus.borrow_mut().tick();
+    }
+}
+
+/// Time spent in each subsystem
+#[derive(Debug, Default)]
+pub struct Breakdown {
+    /// Instruction execution without memory accesses
+    pub cpu: Duration,
+    /// Memory accesses of the CPU
+    pub bus: Duration,
+    pub ppu: Duration,
+    /// Timer and interrupt dispatch
+    pub timer: Duration,
+}
+
+impl Breakdown {
+    pub fn total(&self) -> Duration {
+        self.cpu + self.bus + self.ppu + self.timer
+    }
+}
+
+/// Runs a ROM headlessly for the given amount of frames as fast as possible
+/// and prints the emulation speed followed by the time spent in each subsystem.
+/// Measuring the subsystems slows down emulation, hence they are measured in a second run.
+pub fn run(rom: &Path, frames: u32) -> Result<(), Box<dyn Error>> {
+    let bus = RefCell::new(MemoryBus::new(Cartridge::from_path(rom, None)?));
+    let mut screen = Headless::new();
+    let start = Instant::now();
+    batch::emulate(&bus, &mut screen, frames, None);
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let emulated = f64::from(frames) * f64::from(CYCLES_PER_FRAME) / f64::from(CPU_CLOCK_SPEED);
+    println!(
+        "Emulated {} frames ({:.2} s) in {:.2} s: {:.2} emulated seconds per second, {:.0} fps",
+        frames,
+        emulated,
+        elapsed,
+        emulated / elapsed,
+        f64::from(frames) / elapsed
+    );
+
+    let breakdown = measure(Cartridge::from_path(rom, None)?, frames);
+    let total = breakdown.total().as_secs_f64();
+    for (name, duration) in [
+        ("CPU", breakdown.cpu),
+        ("Bus", breakdown.bus),
+        ("PPU", breakdown.ppu),
+        ("Timer/IRQ", breakdown.timer),
+    ]
+    .iter()
+    {
+        let seconds = duration.as_secs_f64();
+        println!(
+            "  {:<10} {:>8.3} s {:>5.1}%",
+            name,
+            seconds,
+            seconds * 100.0 / total
+        );
+    }
+    Ok(())
+}
+
+/// Emulates the given amount of frames and measures the time spent in each subsystem
+fn measure(cartridge: Cartridge, frames: u32) -> Breakdown {
+    let bus = RefCell::new(MemoryBus::new(cartridge));
+    let timing = RefCell::new(TimingBus::new(&bus));
+    let mut screen = Headless::new();
+    let mut ppu = PPU::new(&bus, &mut screen);
+    let cpu = RefCell::new(CPU::new(&timing));
+    let mut irq_handler = IRQHandler::new(&cpu, &timing);
+    let mut timer = Timer::new(&bus);
+
+    let mut breakdown = Breakdown::default();
+    let total = u64::from(frames) * u64::from(CYCLES_PER_FRAME);
+    let mut cycles = 0;
+    while cycles < total {
+        let bus_start = timing.borrow().elapsed.get();
+        let start = Instant::now();
+        let step = cpu.borrow_mut().step();
+        let cpu_done = Instant::now();
+        let bus_time = timing.borrow().elapsed.get() - bus_start;
+        ppu.step(step);
+        let ppu_done = Instant::now();
+        timer.step(step);
+        irq_handler.handle();
+        let done = Instant::now();
+
+        breakdown.bus += bus_time;
+        breakdown.cpu += (cpu_done - start).saturating_sub(bus_time);
+        breakdown.ppu += ppu_done - cpu_done;
+        breakdown.timer += done - ppu_done;
+        cycles += u64::from(step);
+    }
+    breakdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure() {
+        let breakdown = measure(Cartridge::from_bytes(vec![0; 0x8000]), 2);
+        assert!(breakdown.bus > Duration::default());
+        assert!(breakdown.ppu > Duration::default());
+        assert!(breakdown.total() >= breakdown.cpu + breakdown.bus);
+    }
+}
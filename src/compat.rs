@@ -0,0 +1,147 @@
+use crate::batch::RomReport;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs};
+
+/// Describes how well a game runs in the emulator
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Compatibility {
+    Perfect,
+    Playable,
+    Broken,
+    Unknown,
+}
+
+impl fmt::Display for Compatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compatibility::Perfect => write!(f, "Perfect"),
+            Compatibility::Playable => write!(f, "Playable"),
+            Compatibility::Broken => write!(f, "Broken"),
+            Compatibility::Unknown => write!(f, "Unknown"),
+        }
+    }
+}
+
+/// Contains the compatibility details of a single game
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub status: Compatibility,
+    pub path: PathBuf,
+    pub details: Option<String>,
+}
+
+/// Local per-game compatibility database, keyed by ROM title
+#[derive(Default, Serialize, Deserialize)]
+pub struct Database {
+    games: BTreeMap<String, Entry>,
+}
+
+impl Database {
+    /// Loads the database from the given path,
+    /// returns an empty database if there is none yet.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        serde_json::to_writer_pretty(File::create(path)?, self)?;
+        Ok(())
+    }
+
+    /// Returns the compatibility entry for the given title
+    pub fn lookup(&self, title: &str) -> Option<&Entry> {
+        self.games.get(title)
+    }
+
+    /// Updates the database with the results of a batch run.
+    /// A batch run can't tell whether a game is perfect,
+    /// so manually verified entries are kept as long as the game still runs.
+    pub fn update(&mut self, reports: Vec<RomReport>) {
+        for report in reports {
+            let title = match report.title {
+                Some(title) => title,
+                None => continue,
+            };
+            let mut status = match (&report.panic, report.booted) {
+                (Some(_), _) => Compatibility::Broken,
+                (None, true) => Compatibility::Playable,
+                (None, false) => Compatibility::Unknown,
+            };
+            let previous = self.games.get(&title).map(|e| e.status);
+            if previous == Some(Compatibility::Perfect) && status == Compatibility::Playable {
+                status = Compatibility::Perfect;
+            }
+            let entry = Entry {
+                status,
+                path: report.path,
+                details: report.panic,
+            };
+            self.games.insert(title, entry);
+        }
+    }
+}
+
+/// Regenerates the compatibility database from the given batch report
+pub fn regenerate(report: &Path, database: &Path) -> Result<(), Box<dyn Error>> {
+    let reports: Vec<RomReport> = serde_json::from_str(&fs::read_to_string(report)?)?;
+    let mut db = Database::load(database)?;
+    db.update(reports);
+    db.save(database)?;
+    println!("Updated compatibility database {}", database.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_report(title: &str, booted: bool, panic: Option<&str>) -> RomReport {
+        RomReport {
+            path: PathBuf::from(format!("{}.gb", title)),
+            title: Some(title.to_string()),
+            booted,
+            frames: 0,
+            frame_hash: None,
+            serial: String::new(),
+            panic: panic.map(str::to_string),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_update_status() {
+        let mut db = Database::default();
+        db.update(vec![
+            create_report("A", true, None),
+            create_report("B", true, Some("STOP is not implemented")),
+            create_report("C", false, None),
+        ]);
+        assert_eq!(db.lookup("A").unwrap().status, Compatibility::Playable);
+        assert_eq!(db.lookup("B").unwrap().status, Compatibility::Broken);
+        assert_eq!(db.lookup("C").unwrap().status, Compatibility::Unknown);
+        assert!(db.lookup("D").is_none());
+    }
+
+    #[test]
+    fn test_update_keeps_perfect() {
+        let mut db = Database::default();
+        db.update(vec![create_report("A", true, None)]);
+        db.games.get_mut("A").unwrap().status = Compatibility::Perfect;
+
+        db.update(vec![create_report("A", true, None)]);
+        assert_eq!(db.lookup("A").unwrap().status, Compatibility::Perfect);
+
+        db.update(vec![create_report("A", true, Some("panic"))]);
+        assert_eq!(db.lookup("A").unwrap().status, Compatibility::Broken);
+    }
+}
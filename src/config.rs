@@ -0,0 +1,456 @@
+use crate::gb::cheats::Cheat;
+use crate::gb::colors::ColorScheme;
+use crate::gb::diagnostics::Thresholds;
+use crate::gb::joypad::Buttons;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the configuration file
+const CONFIG_FILE: &str = "config.toml";
+/// Maximum number of remembered ROMs
+const MAX_RECENT_ROMS: usize = 10;
+
+/// Maps every Game Boy button and the hotkeys to the name of a keyboard key
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub up: String,
+    pub down: String,
+    pub left: String,
+    pub right: String,
+    pub a: String,
+    pub b: String,
+    pub select: String,
+    pub start: String,
+    pub record_macro: String,
+    pub play_macro: String,
+    pub record_video: String,
+    pub pause: String,
+    pub frame_advance: String,
+    pub show_speed: String,
+    pub show_log: String,
+    pub show_serial: String,
+    pub reset: String,
+}
+
+impl KeyBindings {
+    /// Returns all bindings together with the button they are bound to
+    pub fn buttons(&self) -> Vec<(Buttons, &str)> {
+        vec![
+            (Buttons::UP, &self.up),
+            (Buttons::DOWN, &self.down),
+            (Buttons::LEFT, &self.left),
+            (Buttons::RIGHT, &self.right),
+            (Buttons::A, &self.a),
+            (Buttons::B, &self.b),
+            (Buttons::SELECT, &self.select),
+            (Buttons::START, &self.start),
+        ]
+    }
+
+    /// Binds the given button to a key, returns false if `button` isn't a single button
+    pub fn bind(&mut self, button: Buttons, key: String) -> bool {
+        let binding = match button {
+            Buttons::UP => &mut self.up,
+            Buttons::DOWN => &mut self.down,
+            Buttons::LEFT => &mut self.left,
+            Buttons::RIGHT => &mut self.right,
+            Buttons::A => &mut self.a,
+            Buttons::B => &mut self.b,
+            Buttons::SELECT => &mut self.select,
+            Buttons::START => &mut self.start,
+            _ => return false,
+        };
+        *binding = key;
+        true
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            up: "Up".to_string(),
+            down: "Down".to_string(),
+            left: "Left".to_string(),
+            right: "Right".to_string(),
+            a: "X".to_string(),
+            b: "Z".to_string(),
+            select: "Backspace".to_string(),
+            start: "Return".to_string(),
+            record_macro: "F9".to_string(),
+            play_macro: "F10".to_string(),
+            record_video: "F11".to_string(),
+            pause: "F7".to_string(),
+            frame_advance: "F8".to_string(),
+            show_speed: "F6".to_string(),
+            show_log: "F5".to_string(),
+            show_serial: "F4".to_string(),
+            reset: "F2".to_string(),
+        }
+    }
+}
+
+/// Options which are applied whenever a ROM is launched,
+/// e.g. to start frequently debugged homebrew in the desired configuration
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LaunchOptions {
+    /// Start the debugger
+    pub debug: bool,
+    /// Advance all PPU modes cycle by cycle
+    pub accurate_ppu: bool,
+    pub no_fps_limit: bool,
+    /// Directory of the save file instead of the directory of the ROM
+    pub save_dir: Option<PathBuf>,
+}
+
+/// Settings of a single game which take precedence over the configuration,
+/// unset settings fall back to the configuration.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameSettings {
+    pub palette: Option<String>,
+    pub upscale: Option<u8>,
+    /// Write the save file whenever the game disables cartridge RAM
+    pub autosave: Option<bool>,
+    /// Name of the key binding profile
+    pub profile: Option<String>,
+    pub accurate_ppu: Option<bool>,
+    pub accurate_memory: Option<bool>,
+}
+
+/// Contains all persistent emulator settings,
+/// command line arguments take precedence.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub upscale: u8,
+    pub rotation: u16,
+    pub mirror: bool,
+    pub ambient: bool,
+    pub power_save: bool,
+    /// Pause emulation while the window is minimized or unfocused
+    pub pause_on_focus_loss: bool,
+    /// Advance all PPU modes cycle by cycle
+    pub accurate_ppu: bool,
+    /// Emulate memory quirks of the DMG which only few games depend on
+    pub accurate_memory: bool,
+    /// Panic on writes to unmapped I/O registers instead of ignoring them
+    pub strict: bool,
+    /// Number of backups which are kept of each save file
+    pub save_backups: u8,
+    /// Write the save file whenever the game disables cartridge RAM
+    pub autosave: bool,
+    /// Seconds between periodic autosaves, 0 disables them
+    pub autosave_interval: u64,
+    /// Number of slots which periodic autosaves rotate through
+    pub autosave_slots: u8,
+    /// Write the save file when emulation ends
+    pub save_on_exit: bool,
+    pub palette: String,
+    pub custom_palette: Vec<String>,
+    pub recent_roms: Vec<PathBuf>,
+    pub key_bindings: KeyBindings,
+    pub diagnostics: Thresholds,
+    /// Named key binding profiles which can be assigned to games
+    pub profiles: BTreeMap<String, KeyBindings>,
+    /// Maps game titles to the name of their profile
+    pub game_profiles: BTreeMap<String, String>,
+    /// Cheats of each game title
+    pub cheats: BTreeMap<String, Vec<Cheat>>,
+    /// Launch options of each ROM path
+    pub launch_options: BTreeMap<PathBuf, LaunchOptions>,
+    /// Settings of each game, identified by title and header checksum
+    pub games: BTreeMap<String, GameSettings>,
+}
+
+impl Config {
+    /// Returns the key bindings for the given game title,
+    /// games without an assigned profile use the default bindings.
+    pub fn key_bindings_for(&self, title: &str) -> &KeyBindings {
+        self.game_profiles
+            .get(title)
+            .and_then(|name| self.profiles.get(name))
+            .unwrap_or(&self.key_bindings)
+    }
+
+    /// Returns the profile with the given name, new profiles start with the default bindings.
+    /// Returns the default bindings if no name is given.
+    pub fn profile_mut(&mut self, name: Option<&str>) -> &mut KeyBindings {
+        match name {
+            Some(name) => {
+                let default = self.key_bindings.clone();
+                self.profiles.entry(name.to_string()).or_insert(default)
+            }
+            None => &mut self.key_bindings,
+        }
+    }
+
+    /// Assigns the given profile to a game, None restores the default bindings
+    pub fn assign_profile(&mut self, title: &str, profile: Option<&str>) -> Result<(), String> {
+        match profile {
+            Some(name) if !self.profiles.contains_key(name) => {
+                return Err(format!("Unknown profile: {}", name))
+            }
+            Some(name) => self
+                .game_profiles
+                .insert(title.to_string(), name.to_string()),
+            None => self.game_profiles.remove(title),
+        };
+        Ok(())
+    }
+
+    /// Returns the cheats of the given game title
+    pub fn cheats_for(&self, title: &str) -> &[Cheat] {
+        self.cheats.get(title).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the cheats of the given game title for editing
+    pub fn cheats_mut(&mut self, title: &str) -> &mut Vec<Cheat> {
+        self.cheats.entry(title.to_string()).or_default()
+    }
+
+    /// Returns the configured color scheme, `custom` refers to `custom_palette`
+    pub fn color_scheme(&self, palette: &str) -> Result<ColorScheme, String> {
+        match palette {
+            "custom" => ColorScheme::from_hex(&self.custom_palette),
+            name => ColorScheme::builtin(name).ok_or_else(|| format!("Unknown palette: {}", name)),
+        }
+    }
+
+    /// Returns the launch options of the given ROM path
+    pub fn launch_options_for(&self, rom: &Path) -> LaunchOptions {
+        self.launch_options.get(rom).cloned().unwrap_or_default()
+    }
+
+    /// Stores the launch options of the given ROM path, default options are removed
+    pub fn set_launch_options(&mut self, rom: &Path, options: LaunchOptions) {
+        match options == LaunchOptions::default() {
+            true => self.launch_options.remove(rom),
+            false => self.launch_options.insert(rom.to_path_buf(), options),
+        };
+    }
+
+    /// Returns the settings of the game with the given id
+    pub fn game_settings_for(&self, id: &str) -> GameSettings {
+        self.games.get(id).cloned().unwrap_or_default()
+    }
+
+    /// Stores the settings of the game with the given id, default settings are removed
+    pub fn set_game_settings(&mut self, id: &str, settings: GameSettings) -> Result<(), String> {
+        if let Some(palette) = &settings.palette {
+            self.color_scheme(palette)?;
+        }
+        match &settings.profile {
+            Some(name) if !self.profiles.contains_key(name) => {
+                return Err(format!("Unknown profile: {}", name))
+            }
+            _ => {}
+        }
+        match settings == GameSettings::default() {
+            true => self.games.remove(id),
+            false => self.games.insert(id.to_string(), settings),
+        };
+        Ok(())
+    }
+
+    /// Returns the key bindings for a game, the profile of its settings
+    /// takes precedence over the profile assigned to its title.
+    pub fn game_key_bindings(&self, title: &str, settings: &GameSettings) -> &KeyBindings {
+        settings
+            .profile
+            .as_ref()
+            .and_then(|name| self.profiles.get(name))
+            .unwrap_or_else(|| self.key_bindings_for(title))
+    }
+
+    /// Moves the given ROM to the top of the recently used ROMs
+    pub fn add_recent_rom(&mut self, rom: &Path) {
+        self.recent_roms.retain(|path| path != rom);
+        self.recent_roms.insert(0, rom.to_path_buf());
+        self.recent_roms.truncate(MAX_RECENT_ROMS);
+    }
+
+    /// Loads the configuration from the given path,
+    /// returns the default configuration if there is none yet.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the configuration to the given path
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            upscale: 2,
+            rotation: 0,
+            mirror: false,
+            ambient: false,
+            power_save: false,
+            pause_on_focus_loss: false,
+            accurate_ppu: false,
+            accurate_memory: false,
+            strict: false,
+            save_backups: 0,
+            autosave: true,
+            autosave_interval: 60,
+            autosave_slots: 3,
+            save_on_exit: true,
+            palette: "grayscale".to_string(),
+            custom_palette: Vec::new(),
+            recent_roms: Vec::new(),
+            key_bindings: KeyBindings::default(),
+            diagnostics: Thresholds::default(),
+            profiles: BTreeMap::new(),
+            game_profiles: BTreeMap::new(),
+            cheats: BTreeMap::new(),
+            launch_options: BTreeMap::new(),
+            games: BTreeMap::new(),
+        }
+    }
+}
+
+/// Returns the path of the configuration file inside the given directory
+pub fn config_path(dir: &Path) -> PathBuf {
+    dir.join(CONFIG_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind() {
+        let mut bindings = KeyBindings::default();
+        assert!(bindings.bind(Buttons::START, "Space".to_string()));
+        assert!(bindings.buttons().contains(&(Buttons::START, "Space")));
+        assert!(!bindings.bind(Buttons::A | Buttons::B, "Space".to_string()));
+        assert_eq!(bindings.a, "X");
+        assert_eq!(bindings.b, "Z");
+    }
+
+    #[test]
+    fn test_partial_config() {
+        let config: Config = toml::from_str("[key_bindings]\na = \"K\"\n").unwrap();
+        assert_eq!(config.key_bindings.a, "K");
+        assert_eq!(config.key_bindings.b, "Z");
+        assert_eq!(config.upscale, 2);
+    }
+
+    #[test]
+    fn test_custom_color_scheme() {
+        let mut config = Config::default();
+        assert!(config.color_scheme("custom").is_err());
+        config.custom_palette = vec!["#FFFFFF".to_string(); 4];
+        assert!(config.color_scheme("custom").is_ok());
+        assert!(config.color_scheme("unknown").is_err());
+    }
+
+    #[test]
+    fn test_game_profiles() {
+        let mut config = Config::default();
+        assert!(config.assign_profile("TETRIS", Some("swap")).is_err());
+
+        let profile = config.profile_mut(Some("swap"));
+        profile.bind(Buttons::A, "Z".to_string());
+        profile.bind(Buttons::B, "X".to_string());
+        config.assign_profile("TETRIS", Some("swap")).unwrap();
+        assert_eq!(config.key_bindings_for("TETRIS").a, "Z");
+        assert_eq!(config.key_bindings_for("ZELDA").a, "X");
+
+        // Profiles survive a round trip
+        let config: Config = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(config.key_bindings_for("TETRIS").a, "Z");
+
+        let mut config = config;
+        config.assign_profile("TETRIS", None).unwrap();
+        assert_eq!(config.key_bindings_for("TETRIS").a, "X");
+    }
+
+    #[test]
+    fn test_launch_options() {
+        let mut config = Config::default();
+        let rom = Path::new("/roms/homebrew.gb");
+        let options = LaunchOptions {
+            debug: true,
+            save_dir: Some(PathBuf::from("/saves")),
+            ..LaunchOptions::default()
+        };
+        config.set_launch_options(rom, options.clone());
+
+        // Launch options survive a round trip
+        let mut config: Config = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(config.launch_options_for(rom), options);
+        assert_eq!(
+            config.launch_options_for(Path::new("other.gb")),
+            LaunchOptions::default()
+        );
+
+        config.set_launch_options(rom, LaunchOptions::default());
+        assert!(config.launch_options.is_empty());
+    }
+
+    #[test]
+    fn test_game_settings() {
+        let mut config = Config::default();
+        let unknown = GameSettings {
+            palette: Some("unknown".to_string()),
+            ..GameSettings::default()
+        };
+        assert!(config.set_game_settings("TETRIS-0A", unknown).is_err());
+
+        config
+            .profile_mut(Some("swap"))
+            .bind(Buttons::A, "Z".to_string());
+        let settings = GameSettings {
+            upscale: Some(4),
+            autosave: Some(false),
+            profile: Some("swap".to_string()),
+            ..GameSettings::default()
+        };
+        config
+            .set_game_settings("TETRIS-0A", settings.clone())
+            .unwrap();
+
+        // Game settings survive a round trip
+        let mut config: Config = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(config.game_settings_for("TETRIS-0A"), settings);
+        // Other revisions of the same title use the configuration
+        let other = config.game_settings_for("TETRIS-0B");
+        assert_eq!(other, GameSettings::default());
+        assert_eq!(config.game_key_bindings("TETRIS", &settings).a, "Z");
+        assert_eq!(config.game_key_bindings("TETRIS", &other).a, "X");
+
+        config
+            .set_game_settings("TETRIS-0A", GameSettings::default())
+            .unwrap();
+        assert!(config.games.is_empty());
+    }
+
+    #[test]
+    fn test_add_recent_rom() {
+        let mut config = Config::default();
+        for i in 0..MAX_RECENT_ROMS + 1 {
+            config.add_recent_rom(Path::new(&format!("{}.gb", i)));
+        }
+        config.add_recent_rom(Path::new("5.gb"));
+        assert_eq!(config.recent_roms.len(), MAX_RECENT_ROMS);
+        assert_eq!(config.recent_roms[0], Path::new("5.gb"));
+        assert_eq!(config.recent_roms[1], Path::new("10.gb"));
+        assert!(!config.recent_roms.contains(&PathBuf::from("0.gb")));
+    }
+}
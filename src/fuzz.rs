@@ -0,0 +1,124 @@
+//! Entry points of the `cargo fuzz` targets in `fuzz/fuzz_targets`.
+//!
+//! Fuzz builds keep overflow checks enabled, so any PC or SP arithmetic
+//! which doesn't wrap around shows up as a panic.
+
+use crate::gb::cartridge::{Cartridge, Metadata};
+use crate::gb::cpu::{hostcall, CPU};
+use crate::gb::headless::Headless;
+use crate::gb::interrupt::IRQHandler;
+use crate::gb::memory::constants::{BOOT_END, BOOT_ROM_OFF};
+use crate::gb::memory::MemoryBus;
+use crate::gb::ppu::PPU;
+use crate::gb::timer::Timer;
+use crate::gb::AddressSpace;
+use std::cell::RefCell;
+
+/// Maximum number of instructions executed for a single input
+const MAX_STEPS: usize = 10_000;
+
+/// Opcode of STOP, which isn't emulated and halts the emulator
+const STOP: u8 = 0x10;
+
+/// Smallest ROM size, smaller inputs are padded with zeros
+const MIN_ROM_SIZE: usize = 0x8000;
+
+/// Flat 64 KiB of memory without any memory mapped IO
+struct FlatBus {
+    data: Vec<u8>,
+}
+
+impl AddressSpace for FlatBus {
+    fn write(&mut self, address: u16, value: u8) {
+        self.data[usize::from(address)] = value;
+    }
+
+    fn read(&self, address: u16) -> u8 {
+        self.data[usize::from(address)]
+    }
+}
+
+/// Returns whether the given opcode halts the emulator on purpose
+fn halts(opcode: u8) -> bool {
+    opcode == STOP || hostcall::is_illegal(opcode)
+}
+
+/// Checks the invariants which must hold after every instruction
+fn check_invariants<T: AddressSpace>(cpu: &CPU<T>) {
+    assert_eq!(cpu.r.get_af() & 0x0F, 0, "lower bits of F must be zero");
+}
+
+/// Executes the input as instruction stream starting at address 0 of a flat memory.
+/// Runs end at an intended halt, at the boot ROM hand-off which asserts the registers
+/// or when the CPU is halted, since there are no interrupts to wake it up.
+pub fn cpu(data: &[u8]) {
+    let mut memory = vec![0; 0x10000];
+    let len = data.len().min(memory.len());
+    memory[..len].copy_from_slice(&data[..len]);
+    let bus = RefCell::new(FlatBus { data: memory });
+    let mut cpu = CPU::new(&bus);
+
+    for _ in 0..MAX_STEPS {
+        let opcode = bus.borrow().read(cpu.pc);
+        if halts(opcode) || cpu.pc == BOOT_END + 1 || cpu.is_halted {
+            break;
+        }
+        cpu.step();
+        check_invariants(&cpu);
+    }
+}
+
+/// Runs the input as cartridge on the memory bus with all components attached,
+/// starting right after the boot ROM. Inputs with an unsupported cartridge type are ignored.
+pub fn cartridge(data: &[u8]) {
+    let mut rom = data.to_vec();
+    rom.resize(rom.len().max(MIN_ROM_SIZE), 0);
+    if !Metadata::is_supported(rom[0x147]) {
+        return;
+    }
+    let bus = RefCell::new(MemoryBus::new(Cartridge::from_bytes(rom)));
+    bus.borrow_mut().write(BOOT_ROM_OFF, 1);
+
+    let mut screen = Headless::new();
+    let mut ppu = PPU::new(&bus, &mut screen);
+    let cpu = RefCell::new(CPU::new(&bus));
+    {
+        // Register values left behind by the boot ROM
+        let mut cpu = cpu.borrow_mut();
+        cpu.r.set_af(0x01B0);
+        cpu.r.set_bc(0x0013);
+        cpu.r.set_de(0x00D8);
+        cpu.r.set_hl(0x014D);
+        cpu.sp = 0xFFFE;
+        cpu.pc = BOOT_END + 1;
+    }
+    let mut irq_handler = IRQHandler::new(&cpu, &bus);
+    let mut timer = Timer::new(&bus);
+
+    for _ in 0..MAX_STEPS {
+        let pc = cpu.borrow().pc;
+        if halts(bus.borrow().read(pc)) {
+            break;
+        }
+        let step = cpu.borrow_mut().step();
+        check_invariants(&cpu.borrow());
+        timer.step(step);
+        ppu.step(step);
+        irq_handler.handle();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_targets() {
+        // INC SP, JP 0xFFFF, wraps around to 0x0000 again
+        let program = [0x33, 0xC3, 0xFF, 0xFF];
+        cpu(&program);
+        cartridge(&program);
+        cpu(&[]);
+        cartridge(&[0xFF; 0x150]);
+    }
+}
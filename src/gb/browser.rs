@@ -0,0 +1,135 @@
+use crate::gb::colors::ColorScheme;
+use crate::gb::image;
+use crate::gb::ppu::misc::Color;
+use crate::gb::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::error;
+use std::path::{Path, PathBuf};
+
+/// Number of thumbnails per row
+const COLUMNS: usize = 3;
+/// Space around each thumbnail in pixels
+const PADDING: u32 = 4;
+
+/// Returns the path of the thumbnail for the given ROM
+pub fn thumbnail_path(thumbnail_dir: &Path, rom: &Path) -> PathBuf {
+    let name = rom.file_stem().unwrap_or_default();
+    thumbnail_dir.join(name).with_extension("pgm")
+}
+
+/// Opens a window which shows a grid of the given ROMs with their thumbnails.
+/// Returns the selected ROM or None if the window has been closed.
+pub fn browse(
+    roms: &[PathBuf],
+    thumbnail_dir: &Path,
+    scheme: ColorScheme,
+) -> Result<Option<PathBuf>, Box<dyn error::Error>> {
+    if roms.is_empty() {
+        return Err("No recently used ROMs".into());
+    }
+
+    let (cell_width, cell_height) = cell_size();
+    let rows = roms.len().div_ceil(COLUMNS);
+    let sdl = sdl2::init()?;
+    let window = sdl
+        .video()?
+        .window(
+            "Romoulade",
+            cell_width * COLUMNS.min(roms.len()) as u32,
+            cell_height * rows as u32,
+        )
+        .position_centered()
+        .build()?;
+    let mut canvas = window.into_canvas().build()?;
+    let mut event_pump = sdl.event_pump()?;
+
+    // Missing thumbnails are drawn as a blank screen
+    let thumbnails = roms
+        .iter()
+        .map(|rom| image::read_pgm(&thumbnail_path(thumbnail_dir, rom)).ok())
+        .collect::<Vec<Option<Vec<Color>>>>();
+
+    let mut selected = 0;
+    loop {
+        draw(&mut canvas, &thumbnails, selected, scheme)?;
+        let name = roms[selected].file_name().unwrap_or_default();
+        canvas.window_mut().set_title(&format!(
+            "Romoulade - {} (Arrow keys to select, Return to start)",
+            name.to_string_lossy()
+        ))?;
+
+        match event_pump.wait_event() {
+            Event::Quit { .. }
+            | Event::KeyDown {
+                keycode: Some(Keycode::Escape),
+                ..
+            } => return Ok(None),
+            Event::KeyDown {
+                keycode: Some(key), ..
+            } => match key {
+                Keycode::Return => return Ok(Some(roms[selected].clone())),
+                Keycode::Left if selected > 0 => selected -= 1,
+                Keycode::Right if selected + 1 < roms.len() => selected += 1,
+                Keycode::Up if selected >= COLUMNS => selected -= COLUMNS,
+                Keycode::Down if selected + COLUMNS < roms.len() => selected += COLUMNS,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Returns the size of a single grid cell
+fn cell_size() -> (u32, u32) {
+    (
+        SCREEN_WIDTH as u32 + 2 * PADDING,
+        SCREEN_HEIGHT as u32 + 2 * PADDING,
+    )
+}
+
+/// Draws all thumbnails and highlights the selected one
+fn draw(
+    canvas: &mut Canvas<Window>,
+    thumbnails: &[Option<Vec<Color>>],
+    selected: usize,
+    scheme: ColorScheme,
+) -> Result<(), String> {
+    let (cell_width, cell_height) = cell_size();
+    canvas.set_draw_color(pixels::Color::RGB(0x20, 0x20, 0x20));
+    canvas.clear();
+
+    for (i, thumbnail) in thumbnails.iter().enumerate() {
+        let x = (i % COLUMNS) as i32 * cell_width as i32;
+        let y = (i / COLUMNS) as i32 * cell_height as i32;
+        if i == selected {
+            canvas.set_draw_color(pixels::Color::RGB(0xE0, 0x40, 0x40));
+            canvas.fill_rect(Rect::new(x, y, cell_width, cell_height))?;
+        }
+
+        let (x, y) = (x + PADDING as i32, y + PADDING as i32);
+        match thumbnail {
+            Some(frame) => {
+                for (offset, color) in frame.iter().enumerate() {
+                    let (r, g, b) = scheme.rgb(*color);
+                    canvas.set_draw_color(pixels::Color::RGB(r, g, b));
+                    canvas.draw_point(Point::new(
+                        x + (offset % usize::from(SCREEN_WIDTH)) as i32,
+                        y + (offset / usize::from(SCREEN_WIDTH)) as i32,
+                    ))?;
+                }
+            }
+            None => {
+                let (r, g, b) = scheme.rgb(Color::White);
+                canvas.set_draw_color(pixels::Color::RGB(r, g, b));
+                canvas.fill_rect(Rect::new(x, y, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32))?;
+            }
+        }
+    }
+    canvas.present();
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use crate::gb::cartridge::Metadata;
+use crate::gb::cpu::CPU;
+use crate::gb::image;
+use crate::gb::ppu::misc::Color;
+use crate::gb::{AddressSpace, SCREEN_WIDTH};
+use std::fs::File;
+use std::io;
+use std::io::{Seek, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Memory which is included in the bundle, the ROM itself is left out
+const MEMORY_BEGIN: u16 = 0x8000;
+
+/// Collects everything which is needed to reproduce a bug into a single zip file,
+/// which can be attached to an issue: the emulator version, the cartridge header,
+/// the configuration, the machine state and the last executed instructions.
+#[derive(Clone, Default)]
+pub struct DebugBundle {
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl DebugBundle {
+    /// Creates a bundle with the emulator version, the cartridge header and the configuration
+    pub fn new(meta: &Metadata, config: &str) -> Self {
+        let mut bundle = Self::default();
+        let version = format!("romoulade {}\n", env!("CARGO_PKG_VERSION"));
+        bundle.add("version.txt", version);
+        bundle.add("cartridge.txt", format!("{}\n", meta));
+        bundle.add("config.toml", config);
+        bundle
+    }
+
+    /// Adds a file, a file with the same name is replaced
+    pub fn add<D: Into<Vec<u8>>>(&mut self, name: &str, data: D) {
+        self.files.retain(|(existing, _)| existing != name);
+        self.files.push((name.to_string(), data.into()));
+    }
+
+    /// Adds the registers, the memory from VRAM upwards and the current frame
+    pub fn add_state<T: AddressSpace, B: AddressSpace>(
+        &mut self,
+        cpu: &CPU<T>,
+        bus: &B,
+        frame: &[Color],
+    ) -> io::Result<()> {
+        let registers = format!(
+            "pc: {:#06x}, sp: {:#06x}, {}, ime: {}, halted: {}\n",
+            cpu.pc, cpu.sp, cpu.r, cpu.ime, cpu.is_halted
+        );
+        self.add("registers.txt", registers);
+        let memory = (MEMORY_BEGIN..=0xFFFF)
+            .map(|address| bus.read(address))
+            .collect::<Vec<u8>>();
+        self.add("memory-8000-ffff.bin", memory);
+        let mut png = Vec::new();
+        image::encode_png(&mut png, frame, usize::from(SCREEN_WIDTH))?;
+        self.add("frame.png", png);
+        Ok(())
+    }
+
+    /// Adds the given lines of an instruction trace
+    pub fn add_trace<'a, I: Iterator<Item = &'a String>>(&mut self, lines: I) {
+        let trace = lines.map(|line| format!("{}\n", line)).collect::<String>();
+        self.add("trace.txt", trace);
+    }
+
+    /// Writes all files to `<prefix>-<timestamp>.zip` and returns its path
+    pub fn save(&self, prefix: &str) -> io::Result<PathBuf> {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let path = PathBuf::from(format!("{}-{}.zip", prefix, secs));
+        self.write_to(File::create(&path)?)?;
+        Ok(path)
+    }
+
+    fn write_to<W: Write + Seek>(&self, writer: W) -> io::Result<()> {
+        let mut zip = ZipWriter::new(writer);
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, data) in &self.files {
+            zip.start_file(name.as_str(), options)?;
+            zip.write_all(data)?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gb::cartridge::Cartridge;
+    use crate::gb::memory::MemoryBus;
+    use std::cell::RefCell;
+    use std::io::{Cursor, Read};
+    use zip::ZipArchive;
+
+    fn read_file(archive: &[u8], name: &str) -> io::Result<Vec<u8>> {
+        let mut zip = ZipArchive::new(Cursor::new(archive))?;
+        let mut data = Vec::new();
+        zip.by_name(name)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    #[test]
+    fn test_bundle() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x134..0x138].copy_from_slice(b"TEST");
+        let meta = Metadata::from_buf(&rom);
+        let bus = RefCell::new(MemoryBus::new(Cartridge::from_bytes(rom)));
+        let cpu = CPU::new(&bus);
+        let mut bundle = DebugBundle::new(&meta, "upscale = 2\n");
+        bundle
+            .add_state(&cpu, &*bus.borrow(), &[Color::White; 160])
+            .unwrap();
+        bundle.add_trace(["0100: 00  NOP".to_string()].iter());
+
+        let mut archive = Cursor::new(Vec::new());
+        bundle.write_to(&mut archive).unwrap();
+        let read = |name| read_file(archive.get_ref(), name).unwrap();
+        assert_eq!(read("config.toml"), b"upscale = 2\n");
+        assert_eq!(read("trace.txt"), b"0100: 00  NOP\n");
+        assert_eq!(read("memory-8000-ffff.bin").len(), 0x8000);
+        assert!(read("registers.txt").starts_with(b"pc: 0x0000"));
+        assert!(read("cartridge.txt").starts_with(b"Title: TEST"));
+        assert!(read_file(archive.get_ref(), "game.gb").is_err());
+    }
+}
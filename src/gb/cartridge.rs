@@ -1,13 +1,17 @@
-use crate::gb::cartridge::BankingMode::MBC2;
 use crate::gb::memory::constants::{
     CRAM_BEGIN, CRAM_END, CRAM_SIZE, ROM_BANK_0_BEGIN, ROM_BANK_0_END, ROM_BANK_N_BEGIN,
     ROM_BANK_N_END, ROM_BANK_N_SIZE,
 };
+use crate::gb::rtc;
+use crate::gb::rtc::{Rtc, RTC_SAVE_SIZE};
 use crate::gb::AddressSpace;
 use crate::utils;
+use log::error;
+use std::cell::Cell;
+use std::ffi::OsString;
 use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::{convert, fmt, fs, io};
 
 /// This area of memory contains ROM title
@@ -25,12 +29,23 @@ const CARTRIDGE_CGB_FLAG: u16 = 0x0143;
 /// 0     => No memory banking
 /// 1..3  => MBC1
 /// 5     => MBC2
+/// 15..19 => MBC3
 const CARTRIDGE_ROM_BANKS: u16 = 0x0147;
 
 /// This address contains the number of RAM banks,
 /// maximum are 4 banks.
 const CARTRIDGE_RAM_BANKS: u16 = 0x0148;
 
+/// This address contains the size of the cartridge RAM
+const CARTRIDGE_RAM_SIZE: u16 = 0x0149;
+
+/// Checksum over the header bytes 0x0134-0x014C
+const CARTRIDGE_HEADER_CHECKSUM: u16 = 0x014D;
+
+/// MBC2 has 512 half-bytes of RAM built directly into the controller,
+/// the upper 4 bits of each byte are not connected.
+const MBC2_RAM_SIZE: usize = 512;
+
 #[derive(Debug, PartialEq)]
 #[repr(u8)]
 /// TODO: implement remaining modes
@@ -38,6 +53,7 @@ pub enum BankingMode {
     None,
     MBC1,
     MBC2, // Ram Baking is not used in MBC2!
+    MBC3,
 }
 
 impl convert::From<u8> for BankingMode {
@@ -46,6 +62,7 @@ impl convert::From<u8> for BankingMode {
             0 => BankingMode::None,
             1..=3 => BankingMode::MBC1,
             5..=6 => BankingMode::MBC2,
+            0x0F..=0x13 => BankingMode::MBC3,
             _ => unimplemented!(),
         }
     }
@@ -55,18 +72,56 @@ impl convert::From<u8> for BankingMode {
 pub struct Metadata {
     pub title: String,
     pub banking: BankingMode,
+    pub battery: bool,
+    pub rtc: bool,
     pub cgb_flag: u8,
+    /// Size of the cartridge RAM according to the header in bytes
+    pub ram_size: usize,
+    pub header_checksum: u8,
 }
 
 impl Metadata {
     pub fn from_buf(buf: &[u8]) -> Self {
+        let cartridge_type = buf[CARTRIDGE_ROM_BANKS as usize];
         Self {
             title: Metadata::parse_title(buf),
-            banking: BankingMode::from(buf[CARTRIDGE_ROM_BANKS as usize]),
+            banking: BankingMode::from(cartridge_type),
+            battery: Metadata::has_battery(cartridge_type),
+            rtc: matches!(cartridge_type, 0x0F | 0x10),
             cgb_flag: buf[CARTRIDGE_CGB_FLAG as usize],
+            ram_size: Metadata::parse_ram_size(buf[CARTRIDGE_RAM_SIZE as usize]),
+            header_checksum: buf[CARTRIDGE_HEADER_CHECKSUM as usize],
+        }
+    }
+
+    /// Identifies the game by its title and header checksum,
+    /// which tells apart revisions and ROM hacks with the same title.
+    pub fn id(&self) -> String {
+        format!("{}-{:02X}", self.title, self.header_checksum)
+    }
+
+    /// Returns the RAM size in bytes for the given header value
+    pub fn parse_ram_size(value: u8) -> usize {
+        match value {
+            0x01 => 0x800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            _ => 0,
         }
     }
 
+    /// Returns true if the memory bank controller of the given cartridge type is emulated
+    pub fn is_supported(cartridge_type: u8) -> bool {
+        matches!(cartridge_type, 0x00..=0x03 | 0x05 | 0x06 | 0x0F..=0x13)
+    }
+
+    /// Returns true if the given cartridge type has battery buffered RAM
+    fn has_battery(cartridge_type: u8) -> bool {
+        matches!(cartridge_type, 0x03 | 0x06 | 0x0F | 0x10 | 0x13)
+    }
+
     /// Returns title from metadata
     /// TODO: can it contain utf8 data?
     fn parse_title(buf: &[u8]) -> String {
@@ -82,8 +137,8 @@ impl fmt::Display for Metadata {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Title: {} (banking: {:?}, cgb_flag: {:#04X})",
-            self.title, self.banking, self.cgb_flag
+            "Title: {} (banking: {:?}, battery: {}, cgb_flag: {:#04X})",
+            self.title, self.banking, self.battery, self.cgb_flag
         )
     }
 }
@@ -99,42 +154,226 @@ pub struct Cartridge {
     // This variable is responsible for how to act when the game writes to
     // memory address 0x4000-0x6000
     rom_banking: bool,
+    // Location of the battery backed RAM, if the cartridge has a battery.
+    save_path: Option<PathBuf>,
+    rtc: Option<Rtc>,
+    // Last value written to 0x6000-0x7FFF, writing 0x00 and then 0x01 latches the RTC
+    rtc_latch: u8,
+    // Number of save file backups which are kept
+    backups: u8,
+    // Whether the save file has been backed up in this session
+    backed_up: Cell<bool>,
+    // Whether the save file is written whenever the game disables cartridge RAM
+    autosave: bool,
+    // Number of slots which periodic autosaves rotate through
+    autosave_slots: u8,
+    // Index of the slot which is written by the next periodic autosave
+    next_slot: Cell<u8>,
 }
 
 impl Cartridge {
-    /// Creates a new Cartridge from the given Path
-    pub fn from_path(path: &Path) -> io::Result<Self> {
+    /// Creates a new Cartridge from the given Path,
+    /// battery backed RAM is stored in `save_dir` if one is given.
+    pub fn from_path(path: &Path, save_dir: Option<&Path>) -> io::Result<Self> {
         let mut file = File::open(&path)?;
         let metadata = fs::metadata(&path)?;
         let mut buffer = vec![0; metadata.len() as usize];
         file.read_exact(&mut buffer)?;
+        if buffer.len() < 0x0150 {
+            let message = format!("Not a Game Boy ROM, it's only {} bytes", buffer.len());
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        let cartridge_type = buffer[CARTRIDGE_ROM_BANKS as usize];
+        if !Metadata::is_supported(cartridge_type) {
+            let message = format!(
+                "Unsupported cartridge type {:#04X}, only ROM only, MBC1, MBC2 and MBC3 \
+                 cartridges can be emulated. `romoulade info` shows the details",
+                cartridge_type
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
         let meta = Metadata::from_buf(&buffer);
+        let save_path = match (meta.battery, save_dir, path.file_name()) {
+            (true, Some(dir), Some(name)) => Some(dir.join(name).with_extension("sav")),
+            _ => None,
+        };
+
+        let mut cartridge = Self::new(meta, buffer, save_path);
+        cartridge.load_ram()?;
+        Ok(cartridge)
+    }
 
-        Ok(Self {
+    /// Creates a new Cartridge from the given ROM image without battery backed RAM.
+    pub fn from_bytes(rom: Vec<u8>) -> Self {
+        Self::new(Metadata::from_buf(&rom), rom, None)
+    }
+
+    fn new(meta: Metadata, rom: Vec<u8>, save_path: Option<PathBuf>) -> Self {
+        let rtc = match meta.rtc {
+            true => Some(Rtc::new(rtc::host_time())),
+            false => None,
+        };
+        Self {
             meta,
-            rom: buffer,
+            rom,
             ram: [0u8; CRAM_SIZE * 4],
             cur_rom_bank: 1,
             cur_ram_bank: 0,
             enable_ram: false,
             rom_banking: true,
-        })
+            save_path,
+            rtc,
+            rtc_latch: 0xFF,
+            backups: 0,
+            backed_up: Cell::new(false),
+            autosave: true,
+            autosave_slots: 0,
+            next_slot: Cell::new(0),
+        }
+    }
+
+    /// Restores the power-on state of the banking registers, the RAM is kept
+    pub fn reset(&mut self) {
+        self.cur_rom_bank = 1;
+        self.cur_ram_bank = 0;
+        self.enable_ram = false;
+        self.rom_banking = true;
+        self.rtc_latch = 0xFF;
+    }
+
+    /// Restores the battery backed RAM and the RTC from the save file, if there is any.
+    fn load_ram(&mut self) -> io::Result<()> {
+        let path = match &self.save_path {
+            Some(path) if path.exists() => path,
+            _ => return Ok(()),
+        };
+        let data = fs::read(path)?;
+        self.load_save_data(&data);
+        Ok(())
+    }
+
+    /// Restores the battery backed RAM and the RTC from a raw save file as written
+    /// by most emulators. A single byte appended by some emulators is ignored.
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        // All RAM sizes are multiples of the 512 bytes of MBC2
+        let mut data = data;
+        if data.len() % MBC2_RAM_SIZE == 1 {
+            data = &data[..data.len() - 1];
+        }
+        // The RTC state is appended to the RAM banks
+        if self.rtc.is_some() && data.len() % MBC2_RAM_SIZE == RTC_SAVE_SIZE {
+            let (ram, footer) = data.split_at(data.len() - RTC_SAVE_SIZE);
+            self.rtc = Rtc::from_bytes(footer);
+            data = ram;
+        }
+        let len = data.len().min(self.ram_size());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    /// Returns the battery backed RAM followed by the RTC as raw save file
+    pub fn save_data(&self) -> Vec<u8> {
+        let mut data = self.ram[..self.ram_size()].to_vec();
+        if let Some(rtc) = &self.rtc {
+            data.extend(rtc.to_bytes(rtc::host_time()));
+        }
+        data
+    }
+
+    /// Simulates a weak battery by flipping a random bit in the given fraction of
+    /// randomly chosen bytes of the battery backed RAM, returns the number of flipped bits.
+    /// Cartridges without a battery are left untouched.
+    pub fn corrupt_ram(&mut self, fraction: f64, seed: u64) -> usize {
+        if !self.meta.battery {
+            return 0;
+        }
+        let size = self.ram_size();
+        let count = (size as f64 * fraction.clamp(0.0, 1.0)).round() as usize;
+        let mut state = seed.max(1);
+        for _ in 0..count {
+            let random = utils::xorshift64(&mut state);
+            self.ram[random as usize % size] ^= 1 << ((random >> 32) % 8);
+        }
+        count
+    }
+
+    /// Keeps the given number of backups of the save file (`.sav.1`, `.sav.2`, ...),
+    /// the backups are rotated before the save file is written for the first time.
+    pub fn set_backups(&mut self, count: u8) {
+        self.backups = count;
+    }
+
+    /// Enables or disables writing the save file whenever the game disables cartridge RAM,
+    /// without autosave it's only written when emulation ends.
+    pub fn set_autosave(&mut self, autosave: bool) {
+        self.autosave = autosave;
+    }
+
+    /// Periodic autosaves rotate through the given number of slots (`.sav.auto1`, ...),
+    /// so an older state can be restored if the game corrupts its save.
+    pub fn set_autosave_slots(&mut self, count: u8) {
+        self.autosave_slots = count;
+    }
+
+    /// Writes the battery backed RAM and the RTC to the next autosave slot, overwriting
+    /// the oldest one. This is a no-op for cartridges without a battery or without slots.
+    pub fn write_autosave(&self) -> io::Result<()> {
+        let path = match &self.save_path {
+            Some(path) if self.autosave_slots > 0 => path,
+            _ => return Ok(()),
+        };
+        let slot = self.next_slot.get();
+        self.next_slot.set((slot + 1) % self.autosave_slots);
+        write_synced(&autosave_path(path, slot + 1), &self.save_data())
+    }
+
+    /// Writes the battery backed RAM and the RTC to the save file.
+    /// This is a no-op for cartridges without a battery.
+    pub fn save_ram(&self) -> io::Result<()> {
+        let path = match &self.save_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if !self.backed_up.get() {
+            rotate_backups(path, self.backups)?;
+            self.backed_up.set(true);
+        }
+        write_synced(path, &self.save_data())
+    }
+
+    /// Returns the real time clock of MBC3 cartridges which have one
+    pub fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.rtc.as_mut()
+    }
+
+    /// Returns the amount of usable cartridge RAM in bytes.
+    /// Cartridges which don't declare their RAM size in the header get all 4 banks.
+    fn ram_size(&self) -> usize {
+        match (&self.meta.banking, self.meta.ram_size) {
+            (BankingMode::MBC2, _) => MBC2_RAM_SIZE,
+            (_, 0) => self.ram.len(),
+            (_, size) => size.min(self.ram.len()),
+        }
     }
 
     fn handle_banking(&mut self, address: u16, value: u8) {
+        match self.meta.banking {
+            BankingMode::MBC2 => return self.handle_mbc2_banking(address, value),
+            BankingMode::MBC3 => return self.handle_mbc3_banking(address, value),
+            _ => {}
+        }
+
         match address {
             // Do RAM enable
             0x0000..=0x1FFF => {
-                if self.meta.banking == BankingMode::MBC1 || self.meta.banking == BankingMode::MBC2
-                {
-                    self.toggle_ram_banking(address, value);
+                if self.meta.banking == BankingMode::MBC1 {
+                    self.toggle_ram_banking(value);
                 }
             }
             // Do ROM bank change
             0x2000..=ROM_BANK_0_END => match self.meta.banking {
                 BankingMode::MBC1 | BankingMode::MBC2 => self.change_low_rom_bank(value),
                 // ROM banking requested, but Cartridge only uses 1 ROM bank. Safe to ignore.
-                BankingMode::None => {}
+                _ => {}
             },
             // Do ROM or RAM bank change
             0x4000..=0x5FFF => {
@@ -158,20 +397,58 @@ impl Cartridge {
         }
     }
 
+    /// MBC2 only listens to writes in 0x0000-0x3FFF, bit 8 of the address
+    /// selects whether the RAM enable or the ROM bank register is written.
+    fn handle_mbc2_banking(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=ROM_BANK_0_END if utils::bit_at((address >> 8) as u8, 0) => {
+                self.change_low_rom_bank(value)
+            }
+            0x0000..=ROM_BANK_0_END => self.toggle_ram_banking(value),
+            // Writes to 0x4000-0x7FFF have no effect on MBC2
+            _ => {}
+        }
+    }
+
+    /// MBC3 selects all 7 bits of the ROM bank at once and maps either
+    /// a RAM bank (0x00-0x03) or an RTC register (0x08-0x0C) to 0xA000-0xBFFF.
+    fn handle_mbc3_banking(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.toggle_ram_banking(value),
+            0x2000..=ROM_BANK_0_END => self.cur_rom_bank = (value & 0x7F).max(1),
+            0x4000..=0x5FFF => self.cur_ram_bank = value,
+            _ => {
+                if let (Some(rtc), 0x00, 0x01) = (&mut self.rtc, self.rtc_latch, value) {
+                    rtc.latch(rtc::host_time());
+                }
+                self.rtc_latch = value;
+            }
+        }
+    }
+
+    /// Returns the selected RTC register of MBC3 cartridges
+    fn rtc_register(&self) -> Option<(&Rtc, u8)> {
+        match (&self.rtc, self.cur_ram_bank) {
+            (Some(rtc), register @ 0x08..=0x0C) => Some((rtc, register)),
+            _ => None,
+        }
+    }
+
     /// Enables or disables RAM banking.
-    fn toggle_ram_banking(&mut self, address: u16, value: u8) {
-        // If MBC2 is enabled, bit 4 of the address must be zero.
-        if self.meta.banking == MBC2 && utils::bit_at(address as u8, 4) {
-            // TODO: implement me
-            panic!("TODO: implement RAM banking while MBC2 is enabled!");
-            return;
+    fn toggle_ram_banking(&mut self, value: u8) {
+        // Any value other than 0x0A in the lower nibble disables cartridge RAM
+        let enable_ram = value & 0x0F == 0x0A;
+        if self.enable_ram && !enable_ram && self.autosave {
+            self.flush_ram();
         }
+        self.enable_ram = enable_ram;
+    }
 
-        // If MBC1 is enabled, the lower nibble must be equal to 0X0A to enable cartridge RAM.
-        match value & 0x0F {
-            0x0A => self.enable_ram = true,
-            0x00 => self.enable_ram = false,
-            _ => panic!("{:#04X}", value),
+    /// Games disable cartridge RAM after they are done writing to it,
+    /// which makes this a good time to persist the battery backed RAM.
+    fn flush_ram(&self) {
+        if let Err(err) = self.save_ram() {
+            error!("Unable to write save file: {}", err);
         }
     }
 
@@ -192,8 +469,8 @@ impl Cartridge {
     /// Change ROM banking mode (bits 5 & 6)
     fn change_hi_rom_bank(&mut self, value: u8) {
         // Turn of the upper 3 bits of the current bank
-        // and turn of the lower 5 bits of the passed value
-        self.cur_rom_bank = (self.cur_rom_bank & 0x1F) | (value & 0xE0);
+        // and use the lower 2 bits of the passed value as bits 5 & 6
+        self.cur_rom_bank = (self.cur_rom_bank & 0x1F) | ((value & 0x03) << 5);
         self.sanitize_rom_bank();
     }
 
@@ -206,9 +483,11 @@ impl Cartridge {
         }
     }
 
+    /// A value of 0 in the lower 5 bits selects the next bank instead,
+    /// hence banks 0x00, 0x20, 0x40 and 0x60 can't be selected.
     fn sanitize_rom_bank(&mut self) {
-        if self.cur_rom_bank == 0 {
-            self.cur_rom_bank = 1;
+        if self.cur_rom_bank & 0x1F == 0 {
+            self.cur_rom_bank |= 1;
         }
     }
 }
@@ -217,6 +496,26 @@ impl AddressSpace for Cartridge {
     fn write(&mut self, address: u16, value: u8) {
         match address {
             0x0000..=ROM_BANK_N_END => self.handle_banking(address, value),
+            // Only the lower 9 bits of the address are used for MBC2 RAM,
+            // the remaining area echoes the first 512 half-bytes.
+            CRAM_BEGIN..=CRAM_END if self.meta.banking == BankingMode::MBC2 => {
+                if self.enable_ram {
+                    self.ram[usize::from(address - CRAM_BEGIN) % MBC2_RAM_SIZE] = value & 0x0F;
+                }
+            }
+            CRAM_BEGIN..=CRAM_END if self.meta.banking == BankingMode::MBC3 => {
+                if !self.enable_ram {
+                    return;
+                }
+                if let Some((_, register)) = self.rtc_register() {
+                    if let Some(rtc) = &mut self.rtc {
+                        rtc.write(register, value, rtc::host_time());
+                    }
+                } else if self.cur_ram_bank < 4 {
+                    let offset = usize::from(self.cur_ram_bank) * CRAM_SIZE;
+                    self.ram[usize::from(address - CRAM_BEGIN) + offset] = value;
+                }
+            }
             CRAM_BEGIN..=CRAM_END => {
                 let offset = self.cur_ram_bank as u16 * CRAM_SIZE as u16;
                 self.ram[usize::from(address - CRAM_BEGIN + offset)] = value
@@ -229,8 +528,28 @@ impl AddressSpace for Cartridge {
         match address {
             ROM_BANK_0_BEGIN..=ROM_BANK_0_END => self.rom[address as usize],
             ROM_BANK_N_BEGIN..=ROM_BANK_N_END => {
-                let offset = self.cur_rom_bank as u16 * ROM_BANK_N_SIZE as u16;
-                self.rom[usize::from(address - ROM_BANK_N_BEGIN + offset)]
+                // Bank numbers exceeding the ROM size wrap around
+                let banks = (self.rom.len() / ROM_BANK_N_SIZE).max(1);
+                let offset = usize::from(self.cur_rom_bank) % banks * ROM_BANK_N_SIZE;
+                self.rom[usize::from(address - ROM_BANK_N_BEGIN) + offset]
+            }
+            CRAM_BEGIN..=CRAM_END if self.meta.banking == BankingMode::MBC2 => {
+                match self.enable_ram {
+                    // The upper 4 bits are undefined, most hardware returns them as set
+                    true => self.ram[usize::from(address - CRAM_BEGIN) % MBC2_RAM_SIZE] | 0xF0,
+                    false => 0xFF,
+                }
+            }
+            CRAM_BEGIN..=CRAM_END if self.meta.banking == BankingMode::MBC3 => {
+                match (self.enable_ram, self.rtc_register()) {
+                    (false, _) => 0xFF,
+                    (true, Some((rtc, register))) => rtc.read(register),
+                    (true, None) if self.cur_ram_bank < 4 => {
+                        let offset = usize::from(self.cur_ram_bank) * CRAM_SIZE;
+                        self.ram[usize::from(address - CRAM_BEGIN) + offset]
+                    }
+                    (true, None) => 0xFF,
+                }
             }
             CRAM_BEGIN..=CRAM_END => {
                 let offset = self.cur_ram_bank as u16 * CRAM_SIZE as u16;
@@ -240,3 +559,287 @@ impl AddressSpace for Cartridge {
         }
     }
 }
+
+/// Returns the path of the nth backup of the given save file
+fn backup_path(path: &Path, n: u8) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Returns the path of the nth autosave slot of the given save file
+fn autosave_path(path: &Path, n: u8) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".auto{}", n));
+    PathBuf::from(name)
+}
+
+/// Shifts the existing backups by one, drops the oldest one
+/// and copies the save file to the first backup.
+fn rotate_backups(path: &Path, count: u8) -> io::Result<()> {
+    if count == 0 || !path.exists() {
+        return Ok(());
+    }
+    for n in (1..count).rev() {
+        let backup = backup_path(path, n);
+        if backup.exists() {
+            fs::rename(backup, backup_path(path, n + 1))?;
+        }
+    }
+    fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+/// Writes the data to a temporary file which replaces the given file once it has been
+/// synced to disk, a crash while writing never leaves a truncated file behind.
+fn write_synced(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".tmp");
+    let tmp = PathBuf::from(name);
+    let mut file = File::create(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Synthesizes a ROM image with the given cartridge type and amount of banks.
+    /// Each bank starts and ends with its number to verify the bank mapping.
+    fn create_rom(cartridge_type: u8, banks: usize) -> Vec<u8> {
+        let mut rom = vec![0u8; ROM_BANK_N_SIZE * banks];
+        for (bank, chunk) in rom.chunks_mut(ROM_BANK_N_SIZE).enumerate() {
+            chunk[0] = bank as u8;
+            chunk[1] = (bank >> 8) as u8;
+            chunk[ROM_BANK_N_SIZE - 1] = bank as u8;
+        }
+        rom[CARTRIDGE_ROM_BANKS as usize] = cartridge_type;
+        rom
+    }
+
+    fn create_cartridge(cartridge_type: u8, banks: usize) -> Cartridge {
+        Cartridge::from_bytes(create_rom(cartridge_type, banks))
+    }
+
+    /// Returns the number of the bank mapped to 0x4000-0x7FFF
+    fn mapped_bank(cartridge: &Cartridge) -> usize {
+        let bank = usize::from(cartridge.read(ROM_BANK_N_BEGIN))
+            | usize::from(cartridge.read(ROM_BANK_N_BEGIN + 1)) << 8;
+        assert_eq!(cartridge.read(ROM_BANK_N_END), bank as u8);
+        bank
+    }
+
+    fn create_mbc2() -> Cartridge {
+        create_cartridge(0x05, 16)
+    }
+
+    #[test]
+    fn test_corrupt_ram() {
+        // MBC1+RAM+BATTERY
+        let mut cartridge = create_cartridge(0x03, 4);
+        assert_eq!(cartridge.corrupt_ram(0.25, 42), CRAM_SIZE);
+        let corrupted = cartridge.ram;
+        let flipped = corrupted.iter().filter(|b| **b != 0).count();
+        assert!(flipped > CRAM_SIZE / 2 && flipped <= CRAM_SIZE);
+
+        // The same seed corrupts the same bits
+        let mut cartridge = create_cartridge(0x03, 4);
+        cartridge.corrupt_ram(0.25, 42);
+        assert_eq!(cartridge.ram[..], corrupted[..]);
+
+        // MBC1+RAM without battery
+        let mut cartridge = create_cartridge(0x02, 4);
+        assert_eq!(cartridge.corrupt_ram(1.0, 42), 0);
+    }
+
+    #[test]
+    fn test_save_backups() {
+        let dir = std::env::temp_dir().join("romoulade-test-backups");
+        fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("game.gb");
+        let save = dir.join("game.sav");
+        // MBC1+RAM+BATTERY
+        fs::write(&rom, create_rom(0x03, 4)).unwrap();
+        fs::write(&save, [1]).unwrap();
+        fs::write(backup_path(&save, 1), [2]).unwrap();
+        fs::write(backup_path(&save, 2), [3]).unwrap();
+
+        let mut cartridge = Cartridge::from_path(&rom, Some(&dir)).unwrap();
+        cartridge.set_backups(2);
+        cartridge.ram[0] = 4;
+        cartridge.save_ram().unwrap();
+        cartridge.ram[0] = 5;
+        cartridge.save_ram().unwrap();
+        // Backups are only rotated once per session
+        assert_eq!(fs::read(&save).unwrap()[0], 5);
+        assert_eq!(fs::read(backup_path(&save, 1)).unwrap(), [1]);
+        assert_eq!(fs::read(backup_path(&save, 2)).unwrap(), [2]);
+        assert!(!backup_path(&save, 3).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_autosave_slots() {
+        let dir = std::env::temp_dir().join("romoulade-test-autosave");
+        fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("game.gb");
+        let save = dir.join("game.sav");
+        // MBC1+RAM+BATTERY
+        fs::write(&rom, create_rom(0x03, 4)).unwrap();
+
+        let mut cartridge = Cartridge::from_path(&rom, Some(&dir)).unwrap();
+        cartridge.write_autosave().unwrap();
+        assert!(!autosave_path(&save, 1).exists());
+        cartridge.set_autosave_slots(2);
+        for value in 1..=3 {
+            cartridge.ram[0] = value;
+            cartridge.write_autosave().unwrap();
+        }
+        assert_eq!(fs::read(autosave_path(&save, 1)).unwrap()[0], 3);
+        assert_eq!(fs::read(autosave_path(&save, 2)).unwrap()[0], 2);
+        assert!(!autosave_path(&save, 3).exists());
+        assert!(!save.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unsupported_cartridge() {
+        let dir = std::env::temp_dir().join("romoulade-test-unsupported");
+        fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("game.gb");
+        // MBC5
+        fs::write(&rom, create_rom(0x19, 4)).unwrap();
+        let err = Cartridge::from_path(&rom, None).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::write(&rom, [0; 0x100]).unwrap();
+        assert!(Cartridge::from_path(&rom, None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_raw_save_data() {
+        // MBC1+RAM+BATTERY with 8KB RAM
+        let mut rom = create_rom(0x03, 4);
+        rom[CARTRIDGE_RAM_SIZE as usize] = 0x02;
+        let mut cartridge = Cartridge::from_bytes(rom);
+        let mut data = vec![0x42; CRAM_SIZE];
+        data.push(0xFF);
+        cartridge.load_save_data(&data);
+        assert_eq!(cartridge.save_data(), vec![0x42; CRAM_SIZE]);
+        assert_eq!(cartridge.ram[CRAM_SIZE], 0x00);
+    }
+
+    #[test]
+    fn test_mbc1_bank_mapping() {
+        for banks in [2, 4, 8, 16, 32, 64, 128].iter() {
+            let mut cartridge = create_cartridge(0x01, *banks);
+            for bank in 0..128usize {
+                cartridge.write(0x2000, bank as u8 & 0x1F);
+                cartridge.write(0x4000, (bank >> 5) as u8);
+                let expected = match bank & 0x1F {
+                    0 => bank + 1,
+                    _ => bank,
+                };
+                assert_eq!(mapped_bank(&cartridge), expected % banks, "{}", bank);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mbc2_bank_mapping() {
+        let mut cartridge = create_mbc2();
+        for bank in 0..32usize {
+            cartridge.write(0x2100, bank as u8);
+            let expected = match bank & 0x0F {
+                0 => 1,
+                b => b,
+            };
+            assert_eq!(mapped_bank(&cartridge), expected);
+        }
+    }
+
+    #[test]
+    fn test_mbc2_rom_bank_select() {
+        let mut cartridge = create_mbc2();
+        // Address bit 8 is set, selects ROM bank
+        cartridge.write(0x2100, 0x0F);
+        assert_eq!(cartridge.read(ROM_BANK_N_BEGIN), 0x0F);
+        // Bank 0 is mapped to bank 1
+        cartridge.write(0x0100, 0x00);
+        assert_eq!(cartridge.read(ROM_BANK_N_BEGIN), 0x01);
+    }
+
+    #[test]
+    fn test_mbc2_ram_enable() {
+        let mut cartridge = create_mbc2();
+        cartridge.write(CRAM_BEGIN, 0x05);
+        assert_eq!(cartridge.read(CRAM_BEGIN), 0xFF);
+
+        // Address bit 8 is clear, selects RAM enable
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(CRAM_BEGIN, 0x05);
+        assert_eq!(cartridge.read(CRAM_BEGIN), 0xF5);
+
+        // Writing RAM enable register with bit 8 set must not disable RAM
+        cartridge.write(0x0100, 0x00);
+        assert_eq!(cartridge.read(CRAM_BEGIN), 0xF5);
+    }
+
+    #[test]
+    fn test_mbc3_bank_mapping() {
+        let mut cartridge = create_cartridge(0x11, 128);
+        for bank in 0..128usize {
+            cartridge.write(0x2000, bank as u8);
+            assert_eq!(mapped_bank(&cartridge), bank.max(1));
+        }
+
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x4000, 0x03);
+        cartridge.write(CRAM_BEGIN, 0x42);
+        assert_eq!(cartridge.ram[CRAM_SIZE * 3], 0x42);
+        // MBC3+RAM has no RTC
+        cartridge.write(0x4000, 0x08);
+        assert_eq!(cartridge.read(CRAM_BEGIN), 0xFF);
+    }
+
+    #[test]
+    fn test_mbc3_rtc() {
+        // MBC3+TIMER+RAM+BATTERY
+        let mut cartridge = create_cartridge(0x10, 4);
+        cartridge.write(0x0000, 0x0A);
+        // Halts the clock and sets it to day 256, 00:00:30
+        cartridge.write(0x4000, 0x0C);
+        cartridge.write(CRAM_BEGIN, 0x41);
+        for (register, value) in [(0x08, 30), (0x09, 0), (0x0A, 0), (0x0B, 0)].iter() {
+            cartridge.write(0x4000, *register);
+            cartridge.write(CRAM_BEGIN, *value);
+        }
+        // The registers only change once the clock is latched
+        assert_eq!(cartridge.read(CRAM_BEGIN), 0x00);
+        cartridge.write(0x6000, 0x00);
+        cartridge.write(0x6000, 0x01);
+        let registers = (0x08..=0x0C)
+            .map(|register| {
+                cartridge.write(0x4000, register);
+                cartridge.read(CRAM_BEGIN)
+            })
+            .collect::<Vec<u8>>();
+        assert_eq!(registers, [30, 0, 0, 0, 0x41]);
+        assert_eq!(
+            cartridge.rtc_mut().unwrap().status(0),
+            "day 256, 00:00:30 (paused)"
+        );
+    }
+
+    #[test]
+    fn test_mbc2_ram_echo() {
+        let mut cartridge = create_mbc2();
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(CRAM_BEGIN + 0x0010, 0xAB);
+        assert_eq!(cartridge.read(CRAM_BEGIN + 0x0010), 0xFB);
+        assert_eq!(cartridge.read(CRAM_BEGIN + 0x0210), 0xFB);
+        assert_eq!(cartridge.read(CRAM_END - 0x01FF + 0x0010), 0xFB);
+    }
+}
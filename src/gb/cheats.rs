@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+/// A named cheat code which is stored per game
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cheat {
+    pub name: String,
+    pub code: String,
+    pub enabled: bool,
+}
+
+/// Decoded cheat code
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Code {
+    /// GameShark codes overwrite RAM, e.g. `010238C8` writes 0x02 to 0xC838
+    GameShark { address: u16, value: u8 },
+    /// Game Genie codes patch the ROM, e.g. `00A-17B-C49`.
+    /// The patch only applies if the original value matches `compare`.
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+}
+
+impl Code {
+    /// Parses a GameShark (`TTVVLLHH`) or Game Genie (`ABC-DEF` or `ABC-DEF-GHI`) code
+    pub fn parse(code: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid cheat code: {}", code);
+        let digits = code
+            .chars()
+            .filter(|c| *c != '-')
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+        let byte = |i: usize| digits[i] << 4 | digits[i + 1];
+
+        match digits.len() {
+            8 if !code.contains('-') => match byte(0) {
+                0x00 | 0x01 => Ok(Code::GameShark {
+                    address: u16::from_le_bytes([byte(4), byte(6)]),
+                    value: byte(2),
+                }),
+                kind => Err(format!("Unsupported GameShark code type {:02X}", kind)),
+            },
+            6 | 9 => {
+                let address = u16::from(digits[5] ^ 0xF) << 12
+                    | u16::from(digits[2]) << 8
+                    | u16::from(digits[3]) << 4
+                    | u16::from(digits[4]);
+                // The compare value is rotated and scrambled, H is unused
+                let compare = match digits.len() {
+                    9 => Some((digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA),
+                    _ => None,
+                };
+                Ok(Code::GameGenie {
+                    address,
+                    value: byte(0),
+                    compare,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Contains the decoded codes of all enabled cheats
+#[derive(Default)]
+pub struct Cheats {
+    ram_writes: Vec<(u16, u8)>,
+    rom_patches: Vec<(u16, u8, Option<u8>)>,
+}
+
+impl Cheats {
+    /// Decodes all enabled cheats
+    pub fn from_list(cheats: &[Cheat]) -> Result<Self, String> {
+        let mut result = Self::default();
+        for cheat in cheats.iter().filter(|cheat| cheat.enabled) {
+            match Code::parse(&cheat.code)? {
+                Code::GameShark { address, value } => result.ram_writes.push((address, value)),
+                Code::GameGenie {
+                    address,
+                    value,
+                    compare,
+                } => result.rom_patches.push((address, value, compare)),
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ram_writes.is_empty() && self.rom_patches.is_empty()
+    }
+
+    /// Returns the RAM writes which are applied each VBlank
+    pub fn ram_writes(&self) -> &[(u16, u8)] {
+        &self.ram_writes
+    }
+
+    /// Returns the value read from ROM after applying all matching patches
+    pub fn patch_rom(&self, address: u16, original: u8) -> u8 {
+        self.rom_patches
+            .iter()
+            .find(|(patched, _, compare)| {
+                *patched == address && compare.is_none_or(|compare| compare == original)
+            })
+            .map_or(original, |(_, value, _)| *value)
+    }
+}
+
+/// Returns the cheat with the given name
+pub fn find_mut<'a>(cheats: &'a mut [Cheat], name: &str) -> Result<&'a mut Cheat, String> {
+    cheats
+        .iter_mut()
+        .find(|cheat| cheat.name == name)
+        .ok_or_else(|| format!("Unknown cheat: {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_gameshark() {
+        assert_eq!(
+            Code::parse("010238C8"),
+            Ok(Code::GameShark {
+                address: 0xC838,
+                value: 0x02
+            })
+        );
+        assert!(Code::parse("910238C8").is_err());
+        assert!(Code::parse("010238C").is_err());
+    }
+
+    #[test]
+    fn test_parse_game_genie() {
+        assert_eq!(
+            Code::parse("3E2-BCF"),
+            Ok(Code::GameGenie {
+                address: 0x02BC,
+                value: 0x3E,
+                compare: None
+            })
+        );
+        // GI = 0xC9 rotated right by 2 is 0x72, XOR 0xBA is 0xC8
+        assert_eq!(
+            Code::parse("00A-17B-C49"),
+            Ok(Code::GameGenie {
+                address: 0x4A17,
+                value: 0x00,
+                compare: Some(0xC8)
+            })
+        );
+        assert!(Code::parse("00A-17B-C4").is_err());
+        assert!(Code::parse("XYZ-17B").is_err());
+    }
+
+    #[test]
+    fn test_cheats() {
+        let cheat = |code: &str, enabled| Cheat {
+            name: code.to_string(),
+            code: code.to_string(),
+            enabled,
+        };
+        let cheats = Cheats::from_list(&[
+            cheat("010238C8", true),
+            cheat("01FF00C0", false),
+            cheat("00A-17B-C49", true),
+        ])
+        .unwrap();
+        assert_eq!(cheats.ram_writes(), &[(0xC838, 0x02)]);
+        assert_eq!(cheats.patch_rom(0x4A17, 0xC8), 0x00);
+        assert_eq!(cheats.patch_rom(0x4A17, 0x12), 0x12);
+        assert_eq!(cheats.patch_rom(0x4A18, 0xC8), 0xC8);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::gb::ppu::misc::Color;
+
+/// Defines the RGB colors used to display the four shades of the LCD
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ColorScheme {
+    colors: [(u8, u8, u8); 4],
+}
+
+impl ColorScheme {
+    /// Returns the built-in color scheme with the given name
+    pub fn builtin(name: &str) -> Option<Self> {
+        let colors = match name {
+            "grayscale" => [
+                (0xFF, 0xFF, 0xFF),
+                (0xAB, 0xAB, 0xAB),
+                (0x55, 0x55, 0x55),
+                (0x00, 0x00, 0x00),
+            ],
+            // Colors of the original DMG LCD
+            "green" => [
+                (0x9B, 0xBC, 0x0F),
+                (0x8B, 0xAC, 0x0F),
+                (0x30, 0x62, 0x30),
+                (0x0F, 0x38, 0x0F),
+            ],
+            // Default palette of the Super Game Boy
+            "sgb" => [
+                (0xF7, 0xE7, 0xC6),
+                (0xD6, 0x8E, 0x49),
+                (0xA6, 0x37, 0x25),
+                (0x33, 0x1E, 0x50),
+            ],
+            _ => return None,
+        };
+        Some(Self { colors })
+    }
+
+    /// Creates a color scheme from four colors in the format `#RRGGBB`,
+    /// ordered from the lightest to the darkest shade.
+    pub fn from_hex(colors: &[String]) -> Result<Self, String> {
+        if colors.len() != 4 {
+            return Err(format!("Expected 4 colors, got {}", colors.len()));
+        }
+        let mut scheme = Self::default();
+        for (target, color) in scheme.colors.iter_mut().zip(colors) {
+            *target = parse_hex(color).ok_or_else(|| format!("Invalid color: {}", color))?;
+        }
+        Ok(scheme)
+    }
+
+    /// Returns the RGB color for the given shade
+    pub fn rgb(&self, color: Color) -> (u8, u8, u8) {
+        self.colors[u8::from(color) as usize]
+    }
+
+    /// Computes the average color from the number of pixels drawn in each shade
+    pub fn average(&self, color_counts: &[u32; 4]) -> (u8, u8, u8) {
+        let total: u32 = color_counts.iter().sum();
+        if total == 0 {
+            return self.colors[0];
+        }
+        let mut sum = (0, 0, 0);
+        for (count, (r, g, b)) in color_counts.iter().zip(self.colors.iter()) {
+            sum.0 += count * u32::from(*r);
+            sum.1 += count * u32::from(*g);
+            sum.2 += count * u32::from(*b);
+        }
+        (
+            (sum.0 / total) as u8,
+            (sum.1 / total) as u8,
+            (sum.2 / total) as u8,
+        )
+    }
+}
+
+impl Default for ColorScheme {
+    fn default() -> Self {
+        Self::builtin("grayscale").unwrap()
+    }
+}
+
+/// Parses a color in the format `#RRGGBB`
+fn parse_hex(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some(((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_schemes() {
+        for name in ["grayscale", "green", "sgb"].iter() {
+            assert!(ColorScheme::builtin(name).is_some());
+        }
+        assert_eq!(ColorScheme::builtin("unknown"), None);
+    }
+
+    #[test]
+    fn test_from_hex() {
+        let colors = ["#9BBC0F", "#8bac0f", "#306230", "#0F380F"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            ColorScheme::from_hex(&colors),
+            Ok(ColorScheme::builtin("green").unwrap())
+        );
+        assert!(ColorScheme::from_hex(&colors[..3]).is_err());
+        assert!(ColorScheme::from_hex(&vec!["#12345".to_string(); 4]).is_err());
+    }
+
+    #[test]
+    fn test_average() {
+        let scheme = ColorScheme::default();
+        assert_eq!(scheme.average(&[0, 0, 0, 0]), (0xFF, 0xFF, 0xFF));
+        assert_eq!(scheme.average(&[1, 0, 0, 1]), (0x7F, 0x7F, 0x7F));
+        assert_eq!(scheme.average(&[0, 3, 0, 0]), (0xAB, 0xAB, 0xAB));
+    }
+}
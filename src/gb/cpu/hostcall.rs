@@ -0,0 +1,51 @@
+use crate::gb::cpu::registers::Registers;
+use crate::gb::cpu::CPU;
+use crate::gb::AddressSpace;
+use std::time::Instant;
+
+/// Prints the registers
+pub const HOST_PRINT: u8 = 0xD3;
+/// Fails if the Z flag isn't set, e.g. after a `CP` of two different values
+pub const HOST_ASSERT: u8 = 0xDB;
+/// Prints the host time since the previous marker, A identifies the marker
+pub const HOST_BENCHMARK: u8 = 0xDD;
+
+/// Handler of an illegal opcode, receives the registers and the address of the opcode
+pub type HostCall = Box<dyn FnMut(&mut Registers, u16)>;
+
+/// Returns whether the opcode is an illegal opcode which can be used for host calls
+pub fn is_illegal(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD
+    )
+}
+
+/// Registers the built-in host calls for print, assert and benchmark markers
+pub fn install_defaults<T: AddressSpace>(cpu: &mut CPU<T>) {
+    cpu.register_host_call(
+        HOST_PRINT,
+        Box::new(|r, pc| println!("Host call at {:#06x}: a: {:#04x}, {}", pc, r.a, r)),
+    );
+    cpu.register_host_call(
+        HOST_ASSERT,
+        Box::new(|r, pc| assert!(r.f.zero, "Assertion at {:#06x} failed: {}", pc, r)),
+    );
+    let mut previous: Option<Instant> = None;
+    cpu.register_host_call(
+        HOST_BENCHMARK,
+        Box::new(move |r, pc| {
+            let now = Instant::now();
+            match previous {
+                Some(previous) => println!(
+                    "Benchmark marker {} at {:#06x}: {:?} since the previous marker",
+                    r.a,
+                    pc,
+                    now - previous
+                ),
+                None => println!("Benchmark marker {} at {:#06x}", r.a, pc),
+            }
+            previous = Some(now);
+        }),
+    );
+}
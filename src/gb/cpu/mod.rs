@@ -1,11 +1,17 @@
 use crate::gb::instruction::*;
-use crate::gb::memory::constants::BOOT_END;
+use crate::gb::memory::constants::{BOOT_END, INTERRUPT_ENABLE, INTERRUPT_FLAG};
 use crate::gb::timer::Clock;
 use crate::gb::AddressSpace;
 use crate::utils;
+use log::debug;
 use registers::Registers;
 use std::cell::RefCell;
+#[cfg(feature = "host-calls")]
+use std::collections::HashMap;
+use std::mem;
 
+#[cfg(feature = "host-calls")]
+pub mod hostcall;
 mod registers;
 #[cfg(test)]
 mod tests;
@@ -18,8 +24,17 @@ pub struct CPU<'a, T: AddressSpace> {
     pub sp: u16,   // Stack Pointer
     pub ime: bool, // Interrupt Master Enable
     pub is_halted: bool,
+    /// EI enables interrupts only after the following instruction
+    ei_delay: bool,
+    /// HALT with IME disabled and a pending interrupt doesn't halt,
+    /// instead the following opcode is read twice.
+    halt_bug: bool,
+    /// Leaving HALT takes an additional machine cycle
+    halt_exit: bool,
     bus: &'a RefCell<T>,
     clock: Clock,
+    #[cfg(feature = "host-calls")]
+    host_calls: HashMap<u8, hostcall::HostCall>,
 }
 
 impl<'a, T: AddressSpace> CPU<'a, T> {
@@ -30,11 +45,40 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
             sp: 0,
             ime: true,
             is_halted: false,
+            ei_delay: false,
+            halt_bug: false,
+            halt_exit: false,
             bus,
             clock: Clock::new(),
+            #[cfg(feature = "host-calls")]
+            host_calls: HashMap::new(),
         }
     }
 
+    /// Restores the power-on state, registered host calls are kept
+    pub fn reset(&mut self) {
+        self.r = Registers::default();
+        self.pc = 0;
+        self.sp = 0;
+        self.ime = true;
+        self.is_halted = false;
+        self.ei_delay = false;
+        self.halt_bug = false;
+        self.halt_exit = false;
+        self.clock = Clock::new();
+    }
+
+    /// Executes the given handler instead of halting on an illegal opcode
+    #[cfg(feature = "host-calls")]
+    pub fn register_host_call(&mut self, opcode: u8, handler: hostcall::HostCall) {
+        assert!(
+            hostcall::is_illegal(opcode),
+            "{:#04x} is a valid opcode",
+            opcode
+        );
+        self.host_calls.insert(opcode, handler);
+    }
+
     /// Makes one CPU step, this consumes one or more bytes depending on the
     /// next instruction and current CPU state (halted, stopped, etc.).
     pub fn step(&mut self) -> u32 {
@@ -43,26 +87,66 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
             self.clock.advance(4);
             return self.clock.ticks();
         }
+        if mem::take(&mut self.halt_exit) {
+            self.clock.advance(4);
+            self.tick();
+        }
+        let enable_ime = self.ei_delay;
 
         self.sanity_check(self.pc);
         // Read next opcode from memory
         let opcode = self.read(self.pc);
+        // The halt bug skips the PC increment after the opcode fetch,
+        // all following reads are shifted back by one byte.
+        if mem::take(&mut self.halt_bug) {
+            self.pc = self.pc.wrapping_sub(1);
+        }
         let (opcode, prefixed) = match opcode == 0xCB {
-            true => (self.read(self.pc + 1), true),
+            true => (self.read(self.pc.wrapping_add(1)), true),
             false => (opcode, false),
         };
 
         // Parse instruction from opcode, execute it and update program counter
         self.pc = match Instruction::from_byte(opcode, prefixed) {
             Some(instruction) => self.execute(instruction),
+            #[cfg(feature = "host-calls")]
+            None if !prefixed && self.host_calls.contains_key(&opcode) => {
+                let pc = self.pc;
+                let handler = self.host_calls.get_mut(&opcode).unwrap();
+                handler(&mut self.r, pc);
+                self.clock.advance(4);
+                pc.wrapping_add(1)
+            }
             None => {
                 let description = format!("0x{}{:02x}", if prefixed { "cb" } else { "" }, opcode);
                 panic!("Unresolved instruction: {}.\nHALTED!", description);
             }
         };
+        // DI right after EI cancels the delayed enable
+        if enable_ime && self.ei_delay {
+            self.ime = true;
+            self.ei_delay = false;
+        }
         self.clock.ticks()
     }
 
+    /// Returns the address which is pushed when an interrupt is dispatched.
+    /// If the halt bug occurred right before, the CPU returns to the HALT instruction.
+    pub fn return_address(&mut self) -> u16 {
+        match mem::take(&mut self.halt_bug) {
+            true => self.pc.wrapping_sub(1),
+            false => self.pc,
+        }
+    }
+
+    /// Leaves HALT because an interrupt is pending
+    pub fn wake(&mut self) {
+        if self.is_halted {
+            self.is_halted = false;
+            self.halt_exit = true;
+        }
+    }
+
     /*/// Prints the current registers, pointers with opcode and resolved instruction
     fn print_ctx(&self, opcode: u8, instruction: &Instruction) {
         println!(
@@ -138,7 +222,7 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
             assert_eq!(self.r.get_de(), 0x00D8, "DE is invalid, boot ROM failure!");
             assert_eq!(self.r.get_hl(), 0x014D, "HL is invalid, boot ROM failure!");
             assert_eq!(self.sp, 0xFFFE, "SP is invalid, boot ROM failure!");
-            //TODO: debug log: println!("Done with processing boot ROM. Switching to Cartridge...");
+            debug!("Done with processing boot ROM. Switching to Cartridge...");
         }
     }
 
@@ -167,9 +251,11 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
     /// Pop a u16 value from the stack
     fn pop(&mut self) -> u16 {
         let lsb = self.read(self.sp) as u16;
+        self.increment_address(self.sp);
         self.sp = self.sp.wrapping_add(1);
 
         let msb = self.read(self.sp) as u16;
+        self.increment_address(self.sp);
         self.sp = self.sp.wrapping_add(1);
 
         (msb << 8) | lsb
@@ -242,8 +328,10 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
         self.r.f.update(result == 0, false, half_carry, carry);
         self.r.a = result;
 
+        // The operand read takes an additional machine cycle
         match source {
             ByteSource::D8 => self.clock.advance(8),
+            ByteSource::HLI => self.clock.advance(8),
             _ => self.clock.advance(4),
         }
         self.pc.wrapping_add(1)
@@ -254,7 +342,13 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
         let value = source.read(self);
         self.r.a &= value;
         self.r.f.update(self.r.a == 0, false, true, false);
-        self.clock.advance(4);
+
+        // The operand read takes an additional machine cycle
+        match source {
+            ByteSource::D8 => self.clock.advance(8),
+            ByteSource::HLI => self.clock.advance(8),
+            _ => self.clock.advance(4),
+        }
         self.pc.wrapping_add(1)
     }
 
@@ -277,8 +371,11 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
         let next_pc = self.pc.wrapping_add(3);
         if should_jump {
             self.clock.advance(24);
+            let address = self.consume_word();
+            // SP is decremented in an additional cycle before the first write
+            self.tick();
             self.push(next_pc);
-            self.consume_word()
+            address
         } else {
             self.clock.advance(12);
             next_pc
@@ -364,6 +461,7 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
     /// Handles DEC instructions for words
     fn handle_dec_word(&mut self, target: IncDecWordTarget) -> u16 {
         let value = target.read(self);
+        self.increment_address(value);
         let result = value.wrapping_sub(1);
         target.write(self, result);
         self.clock.advance(8);
@@ -372,7 +470,11 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
 
     /// Handles HALT instruction
     fn handle_halt(&mut self) -> u16 {
-        self.is_halted = true;
+        let pending = self.read(INTERRUPT_ENABLE) & self.read(INTERRUPT_FLAG) & 0x1F;
+        match !self.ime && pending != 0 {
+            true => self.halt_bug = true,
+            false => self.is_halted = true,
+        }
         self.clock.advance(4);
         self.pc.wrapping_add(1)
     }
@@ -395,15 +497,22 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
     /// Handles INC instructions for words
     fn handle_inc_word(&mut self, target: IncDecWordTarget) -> u16 {
         let value = target.read(self);
+        self.increment_address(value);
         let result = value.wrapping_add(1);
         target.write(self, result);
         self.clock.advance(8);
         self.pc.wrapping_add(1)
     }
 
-    /// Handles EI and DI instructions
+    /// Handles EI and DI instructions, EI takes effect after the next instruction
     fn handle_interrupt(&mut self, enable: bool) -> u16 {
-        self.ime = enable;
+        match enable {
+            true => self.ei_delay = true,
+            false => {
+                self.ime = false;
+                self.ei_delay = false;
+            }
+        }
         self.clock.advance(4);
         self.pc.wrapping_add(1)
     }
@@ -470,6 +579,8 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
                 // that means if both source and target involve I/O
                 // it takes 12 cycles in total.
                 match source {
+                    // Reading through BC or DE is the same as through HL
+                    ByteSource::BCI | ByteSource::DEI => self.clock.advance(4),
                     ByteSource::CIFF00 => self.clock.advance(4),
                     ByteSource::D8 => self.clock.advance(4),
                     ByteSource::D8IFF00 => self.clock.advance(8),
@@ -492,7 +603,12 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
                     LoadWordTarget::SP => self.sp = value,
                     _ => unimplemented!(),
                 }
-                self.clock.advance(12);
+                // LD SP,HL copies the register in one internal cycle
+                // instead of reading two immediate bytes
+                match source {
+                    WordSource::D16 => self.clock.advance(12),
+                    _ => self.clock.advance(8),
+                }
                 self.pc.wrapping_add(1)
             }
             Load::IndirectFrom(target, source) => {
@@ -500,12 +616,14 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
                 let addr = match target {
                     LoadByteTarget::BCI => self.r.get_bc(),
                     LoadByteTarget::DEI => self.r.get_de(),
-                    LoadByteTarget::D16I => self.consume_word(),
-                    LoadByteTarget::HLI => self.r.get_hl(),
-                    LoadByteTarget::CIFF00 => {
+                    // Two more cycles to read the address
+                    LoadByteTarget::D16I => {
                         self.clock.advance(8);
-                        u16::from(self.r.c) | 0xFF00
+                        self.consume_word()
                     }
+                    LoadByteTarget::HLI => self.r.get_hl(),
+                    // The address is formed from C without reading memory
+                    LoadByteTarget::CIFF00 => u16::from(self.r.c) | 0xFF00,
                     LoadByteTarget::D8IFF00 => {
                         self.clock.advance(4);
                         u16::from(self.consume_byte()) | 0xFF00
@@ -641,6 +759,9 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
             StackTarget::DE => self.r.get_de(),
             StackTarget::HL => self.r.get_hl(),
         };
+        // SP is decremented in an additional cycle before the first write
+        self.increment_address(self.sp);
+        self.tick();
         self.push(value);
         self.clock.advance(16);
         self.pc.wrapping_add(1)
@@ -663,7 +784,15 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
     fn handle_ret(&mut self, test: JumpTest) -> u16 {
         let should_jump = test.resolve(self);
         if should_jump {
-            self.clock.advance(20);
+            // Fetch, two pops and the jump, conditional returns
+            // evaluate the condition in an additional cycle
+            match test {
+                JumpTest::Always => self.clock.advance(16),
+                _ => {
+                    self.tick();
+                    self.clock.advance(20)
+                }
+            }
             self.pop()
         } else {
             self.clock.advance(8);
@@ -781,6 +910,7 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
     /// Handles RST instructions
     fn handle_rst(&mut self, code: ResetCode) -> u16 {
         self.clock.advance(16);
+        self.tick();
         self.push(self.pc.wrapping_add(1));
         match code {
             ResetCode::RST00 => 0x00,
@@ -806,8 +936,10 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
             (a ^ value ^ result) & 0x10 != 0,
             result & 0x100 != 0,
         );
+        // The operand read takes an additional machine cycle
         match source {
             ByteSource::D8 => self.clock.advance(8),
+            ByteSource::HLI => self.clock.advance(8),
             _ => self.clock.advance(4),
         }
         self.pc.wrapping_add(1)
@@ -937,10 +1069,22 @@ impl<'a, T: AddressSpace> CPU<'a, T> {
 
 impl<'a, T: AddressSpace> AddressSpace for CPU<'a, T> {
     fn write(&mut self, address: u16, value: u8) {
-        self.bus.borrow_mut().write(address, value);
+        let mut bus = self.bus.borrow_mut();
+        bus.write(address, value);
+        bus.tick();
     }
 
     fn read(&self, address: u16) -> u8 {
-        self.bus.borrow().read(address)
+        let value = self.bus.borrow().read(address);
+        self.bus.borrow_mut().tick();
+        value
+    }
+
+    fn increment_address(&mut self, address: u16) {
+        self.bus.borrow_mut().increment_address(address);
+    }
+
+    fn tick(&mut self) {
+        self.bus.borrow_mut().tick();
     }
 }
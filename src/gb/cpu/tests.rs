@@ -1,5 +1,8 @@
 use crate::gb::cpu::registers::FlagsRegister;
 use crate::gb::cpu::CPU;
+#[cfg(feature = "frontend")]
+use crate::gb::instruction::Instruction;
+use crate::gb::memory::recorder::{BusAccess, RecordingBus};
 use crate::gb::AddressSpace;
 use std::cell::RefCell;
 
@@ -282,14 +285,29 @@ fn test_dec_word() {
 
 #[test]
 fn test_ei() {
-    // EI
-    let bus = RefCell::new(MockBus::new(vec![0xfb; 1]));
+    // EI; NOP
+    let bus = RefCell::new(MockBus::new(vec![0xfb, 0x00]));
     let mut cpu = CPU::new(&bus);
     cpu.ime = false;
     cpu.step();
-    assert_eq!(cpu.ime, true);
+    assert!(!cpu.ime);
     assert_eq!(cpu.clock.ticks(), 4);
     assert_eq!(cpu.pc, 1);
+    // Interrupts are enabled after the following instruction
+    cpu.step();
+    assert!(cpu.ime);
+}
+
+#[test]
+fn test_ei_di() {
+    // EI; DI; NOP
+    let bus = RefCell::new(MockBus::new(vec![0xfb, 0xf3, 0x00]));
+    let mut cpu = CPU::new(&bus);
+    cpu.ime = false;
+    cpu.step();
+    cpu.step();
+    cpu.step();
+    assert!(!cpu.ime);
 }
 
 #[test]
@@ -530,7 +548,8 @@ fn test_rst() {
     assert_eq!(cpu.r.b, 0x01);
     assert_eq!(cpu.r.c, 0x01);
     assert_eq!(cpu.pc, 0x05);
-    assert_eq!(ticks, 48);
+    // NOP 4 + RST 16 + INC 4 + RET 16 + INC 4
+    assert_eq!(ticks, 44);
 }
 
 #[test]
@@ -676,3 +695,146 @@ fn test_xor_zero() {
     assert_eq!(cpu.pc, 1);
     assert_flags(cpu.r.f, true, false, false, false);
 }
+
+#[test]
+fn test_bus_activity_push() {
+    // PUSH BC
+    let bus = RefCell::new(MockBus::new([0xc5, 0x00, 0x00, 0x00].into()));
+    let recorder = RefCell::new(RecordingBus::new(&bus));
+    let mut cpu = CPU::new(&recorder);
+    cpu.r.set_bc(0x1234);
+    cpu.sp = 0x04;
+    cpu.step();
+    assert_eq!(
+        *recorder.borrow().activity(),
+        vec![
+            BusAccess::read(0, 0x00, 0xc5),
+            BusAccess::write(8, 0x03, 0x12),
+            BusAccess::write(12, 0x02, 0x34),
+        ]
+    );
+}
+
+#[test]
+fn test_bus_activity_call() {
+    // CALL u16
+    let bus = RefCell::new(MockBus::new(
+        [0xcd, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00].into(),
+    ));
+    let recorder = RefCell::new(RecordingBus::new(&bus));
+    let mut cpu = CPU::new(&recorder);
+    cpu.sp = 0x07;
+    cpu.step();
+    assert_eq!(cpu.pc, 0x05);
+    assert_eq!(
+        *recorder.borrow().activity(),
+        vec![
+            BusAccess::read(0, 0x00, 0xcd),
+            BusAccess::read(4, 0x01, 0x05),
+            BusAccess::read(8, 0x02, 0x00),
+            BusAccess::write(16, 0x06, 0x00),
+            BusAccess::write(20, 0x05, 0x03),
+        ]
+    );
+}
+
+// Instructions are formatted by the debugger
+#[cfg(feature = "frontend")]
+#[test]
+fn test_instruction_reference() {
+    // Verifies cycles and flag effects of every opcode with all flags reset and set
+    for prefixed in [false, true].iter().copied() {
+        for opcode in 0..=0xFF {
+            for flags in [0x00, 0xF0].iter().copied() {
+                let mut data = vec![0x10; 0x10000];
+                match prefixed {
+                    true => data[..2].copy_from_slice(&[0xCB, opcode]),
+                    false => data[0] = opcode,
+                }
+                if !prefixed && opcode == 0xCB {
+                    continue;
+                }
+                let instruction = match Instruction::from_byte(opcode, prefixed) {
+                    // STOP is not implemented
+                    Some(Instruction::STOP) | None => continue,
+                    Some(instruction) => instruction,
+                };
+                let bus = RefCell::new(MockBus::new(data));
+                let mut cpu = CPU::new(&bus);
+                cpu.sp = 0x8000;
+                cpu.r.f = FlagsRegister::from(flags);
+                let before = cpu.r.f;
+
+                let taken = match &instruction {
+                    Instruction::CALL(test)
+                    | Instruction::JR(test)
+                    | Instruction::JP(test, _)
+                    | Instruction::RET(test) => test.resolve(&mut cpu),
+                    _ => false,
+                };
+                let reference = instruction.reference();
+                let expected = match taken {
+                    true => reference.cycles_taken.unwrap_or(reference.cycles),
+                    false => reference.cycles,
+                };
+                let ticks = cpu.step();
+                let name = format!("{:#04x} {}", opcode, instruction);
+                assert_eq!(ticks, u32::from(expected), "cycles of {}", name);
+
+                let after = [
+                    cpu.r.f.zero,
+                    cpu.r.f.negative,
+                    cpu.r.f.half_carry,
+                    cpu.r.f.carry,
+                ];
+                let previous = [
+                    before.zero,
+                    before.negative,
+                    before.half_carry,
+                    before.carry,
+                ];
+                for (i, effect) in reference.flags.chars().enumerate() {
+                    match effect {
+                        '-' => assert_eq!(after[i], previous[i], "flag {} of {}", i, name),
+                        '0' => assert!(!after[i], "flag {} of {}", i, name),
+                        '1' => assert!(after[i], "flag {} of {}", i, name),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "host-calls")]
+#[test]
+fn test_host_call() {
+    use crate::gb::cpu::hostcall::HOST_PRINT;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let bus = RefCell::new(MockBus::new(vec![HOST_PRINT, 0x00]));
+    let mut cpu = CPU::new(&bus);
+    let called = Rc::new(Cell::new(None));
+    let handler_called = called.clone();
+    cpu.register_host_call(
+        HOST_PRINT,
+        Box::new(move |r, pc| {
+            r.b = 0x42;
+            handler_called.set(Some(pc));
+        }),
+    );
+    assert_eq!(cpu.step(), 4);
+    assert_eq!(cpu.pc, 1);
+    assert_eq!(cpu.r.b, 0x42);
+    assert_eq!(called.get(), Some(0));
+}
+
+#[cfg(feature = "host-calls")]
+#[test]
+#[should_panic]
+fn test_host_call_valid_opcode() {
+    let bus = RefCell::new(MockBus::new(vec![0x00]));
+    let mut cpu = CPU::new(&bus);
+    cpu.register_host_call(0x00, Box::new(|_, _| {}));
+}
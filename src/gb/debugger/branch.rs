@@ -0,0 +1,56 @@
+use crate::gb::instruction::{Instruction, JumpTest, WordSource};
+
+/// Returns whether the instruction only branches if its condition is met
+pub fn is_conditional(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::JR(test)
+        | Instruction::JP(test, _)
+        | Instruction::CALL(test)
+        | Instruction::RET(test) => !matches!(test, JumpTest::Always),
+        _ => false,
+    }
+}
+
+/// Returns the target of a branch instruction at `pc` if it's known without executing it,
+/// `operand` contains the two bytes following the opcode.
+pub fn target(instruction: &Instruction, pc: u16, operand: [u8; 2]) -> Option<u16> {
+    match instruction {
+        // The offset is relative to the next instruction
+        Instruction::JR(_) => Some(pc.wrapping_add(2).wrapping_add(operand[0] as i8 as u16)),
+        Instruction::JP(_, WordSource::D16) | Instruction::CALL(_) => {
+            Some(u16::from_le_bytes(operand))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_conditional() {
+        assert!(is_conditional(&Instruction::JR(JumpTest::NotZero)));
+        assert!(is_conditional(&Instruction::RET(JumpTest::Carry)));
+        assert!(!is_conditional(&Instruction::JP(
+            JumpTest::Always,
+            WordSource::D16
+        )));
+        assert!(!is_conditional(&Instruction::NOP));
+    }
+
+    #[test]
+    fn test_target() {
+        let jr = Instruction::JR(JumpTest::Zero);
+        assert_eq!(target(&jr, 0x0200, [0xFE, 0x00]), Some(0x0200));
+        assert_eq!(target(&jr, 0x0200, [0x10, 0x00]), Some(0x0212));
+        let call = Instruction::CALL(JumpTest::NotCarry);
+        assert_eq!(target(&call, 0x0200, [0x50, 0x01]), Some(0x0150));
+        let jp = Instruction::JP(JumpTest::Zero, WordSource::HL);
+        assert_eq!(target(&jp, 0x0200, [0x50, 0x01]), None);
+        assert_eq!(
+            target(&Instruction::RET(JumpTest::Zero), 0x0200, [0, 0]),
+            None
+        );
+    }
+}
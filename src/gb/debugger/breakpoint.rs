@@ -1,6 +1,11 @@
 use crate::gb::debugger::utils::centered_rect_abs;
-use std::collections::BTreeSet;
+use crate::gb::debugger::watchpoint::Watchpoint;
+use crate::gb::interrupt::IRQ;
+use crate::gb::memory::constants::BOOT_END;
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
 use termion::event::Key;
 use tui::backend::Backend;
 use tui::style::{Color, Style};
@@ -9,24 +14,73 @@ use tui::Frame;
 use unicode_width::UnicodeWidthStr;
 
 pub struct BreakpointHandler {
-    pub breakpoints: BTreeSet<u16>,
+    pub breakpoints: BTreeMap<u16, bool>, // Whether the breakpoint at an address is enabled
+    pub watchpoints: Vec<Watchpoint>,
     pub active: bool,
     pub input: String,
+    pub selected: usize,   // Selected breakpoint in the list
+    path: Option<PathBuf>, // File the breakpoints are persisted to
 }
 
 impl BreakpointHandler {
     pub fn new() -> Self {
         Self {
-            breakpoints: BTreeSet::new(),
+            breakpoints: BTreeMap::new(),
+            watchpoints: Vec::new(),
             active: false,
             input: String::new(),
+            selected: 0,
+            path: None,
         }
     }
 
-    /// Shows "Add Breakpoint" dialog
+    /// Loads the breakpoints from the given file if it exists
+    /// and saves them there after every change.
+    pub fn persist(&mut self, path: PathBuf) -> Result<(), Box<dyn Error>> {
+        if path.exists() {
+            self.breakpoints = serde_json::from_str(&fs::read_to_string(&path)?)?;
+        }
+        self.path = Some(path);
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, serde_json::to_string_pretty(&self.breakpoints)?)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the selection by the given amount of breakpoints
+    pub fn select(&mut self, offset: isize) {
+        let last = self.breakpoints.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(offset).min(last);
+    }
+
+    /// Enables or disables the selected breakpoint
+    pub fn toggle_selected(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(enabled) = self.breakpoints.values_mut().nth(self.selected) {
+            *enabled = !*enabled;
+        }
+        self.save()
+    }
+
+    /// Removes the selected breakpoint
+    pub fn remove_selected(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(address) = self.breakpoints.keys().nth(self.selected).copied() {
+            self.breakpoints.remove(&address);
+            self.select(0);
+        }
+        self.save()
+    }
+
+    /// Shows "Add Breakpoint" dialog, watchpoints are prefixed with their condition
     /// TODO: create dialog with fixed minimum size
     pub fn show_dialog<B: Backend>(&mut self, f: &mut Frame<B>) {
-        let area = centered_rect_abs(18, 3, f.size());
+        let area = centered_rect_abs(24, 3, f.size());
         let input = Paragraph::new(format!("> {}", self.input))
             .style(Style::default().fg(Color::Yellow))
             .block(
@@ -44,9 +98,19 @@ impl BreakpointHandler {
         assert!(self.active);
         match key {
             Key::Char('\n') => {
-                if let Ok(address) = self.parse_input() {
-                    self.breakpoints.insert(address);
+                let input = self.input.drain(..).collect::<String>();
+                if let Some(preset) = preset(&input) {
+                    self.breakpoints
+                        .extend(preset.into_iter().map(|address| (address, true)));
+                    self.active = false;
+                    self.save()?;
+                } else if let Ok(watchpoint) = Watchpoint::parse(&input) {
+                    self.watchpoints.push(watchpoint);
                     self.active = false;
+                } else if let Ok(address) = parse_address(&input) {
+                    self.breakpoints.insert(address, true);
+                    self.active = false;
+                    self.save()?;
                 }
             }
 
@@ -61,15 +125,65 @@ impl BreakpointHandler {
         Ok(())
     }
 
-    /// Checks if a given address is marked as breakpoint
+    /// Checks if a given address is marked as enabled breakpoint
     pub fn contains(&self, address: u16) -> bool {
-        self.breakpoints.contains(&address)
+        self.breakpoints.get(&address) == Some(&true)
+    }
+}
+
+/// Resolves the addresses of a breakpoint preset:
+/// the program entry, all RST vectors or all interrupt vectors
+fn preset(input: &str) -> Option<Vec<u16>> {
+    match input {
+        "entry" => Some(vec![BOOT_END + 1]),
+        "rst" => Some((0..8).map(|i| i * 0x08).collect()),
+        "irq" => Some((0..5).map(|i| IRQ::from(i).vector()).collect()),
+        _ => None,
     }
+}
+
+fn parse_address(input: &str) -> Result<u16, Box<dyn Error>> {
+    Ok(u16::from_str_radix(input.trim_start_matches("0x"), 16)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset() {
+        assert_eq!(preset("entry"), Some(vec![0x0100]));
+        assert_eq!(
+            preset("irq"),
+            Some(vec![0x0040, 0x0048, 0x0050, 0x0058, 0x0060])
+        );
+        assert_eq!(preset("rst").map(|p| p.len()), Some(8));
+        assert_eq!(preset("c000"), None);
+    }
+
+    #[test]
+    fn test_manage_breakpoints() {
+        let path = std::env::temp_dir().join("romoulade-test-breakpoints.json");
+        let _ = fs::remove_file(&path);
+        let mut handler = BreakpointHandler::new();
+        handler.persist(path.clone()).unwrap();
+        for input in ["0150\n", "c000\n"] {
+            handler.active = true;
+            input
+                .chars()
+                .for_each(|c| handler.handle_dialog_input(Key::Char(c)).unwrap());
+        }
+        assert!(handler.contains(0x0150));
+
+        handler.toggle_selected().unwrap();
+        assert!(!handler.contains(0x0150));
+        handler.select(1);
+        handler.remove_selected().unwrap();
+        assert_eq!(handler.selected, 0);
 
-    fn parse_input(&mut self) -> Result<u16, Box<dyn Error>> {
-        let bp = self.input.drain(..).collect::<String>();
-        let bp = bp.trim_start_matches("0x");
-        let bp = u16::from_str_radix(bp, 16)?;
-        Ok(bp)
+        let mut restored = BreakpointHandler::new();
+        restored.persist(path.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(restored.breakpoints, BTreeMap::from([(0x0150, false)]));
     }
 }
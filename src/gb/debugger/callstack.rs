@@ -0,0 +1,93 @@
+use crate::gb::interrupt::IRQ;
+use std::fmt;
+
+/// Defines how a stack frame has been entered
+#[derive(Debug, Copy, Clone)]
+pub enum FrameKind {
+    Call,
+    Rst,
+    Interrupt(IRQ),
+}
+
+/// A return address on the stack together with the instruction which pushed it
+#[derive(Debug, Copy, Clone)]
+pub struct StackFrame {
+    pub kind: FrameKind,
+    /// Address of the calling instruction or of the interrupted instruction
+    pub call_site: u16,
+    pub target: u16,
+    /// Stack pointer after the return address has been pushed
+    pub sp: u16,
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            FrameKind::Call => "CALL".to_string(),
+            FrameKind::Rst => "RST".to_string(),
+            FrameKind::Interrupt(irq) => format!("{:?}", irq),
+        };
+        write!(f, "{:#06x} {:#06x} {}", self.target, self.call_site, kind)
+    }
+}
+
+/// Shadow stack which reconstructs the call stack from calls, RSTs and interrupt dispatches.
+/// Frames are dropped once the stack pointer moved above their return address,
+/// this also covers routines which pop their return address instead of returning.
+#[derive(Default)]
+pub struct CallStack {
+    frames: Vec<StackFrame>,
+}
+
+impl CallStack {
+    pub fn push(&mut self, frame: StackFrame) {
+        self.frames.push(frame);
+    }
+
+    /// Drops all frames whose return address has been popped
+    pub fn unwind(&mut self, sp: u16) {
+        while self.frames.last().filter(|frame| sp > frame.sp).is_some() {
+            self.frames.pop();
+        }
+    }
+
+    /// Returns the frames, the innermost first
+    pub fn frames(&self) -> impl Iterator<Item = &StackFrame> {
+        self.frames.iter().rev()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(kind: FrameKind, target: u16, sp: u16) -> StackFrame {
+        StackFrame {
+            kind,
+            call_site: 0x0150,
+            target,
+            sp,
+        }
+    }
+
+    #[test]
+    fn test_unwind() {
+        let mut stack = CallStack::default();
+        stack.push(frame(FrameKind::Call, 0x2000, 0xFFFC));
+        stack.push(frame(FrameKind::Interrupt(IRQ::VBlank), 0x0040, 0xFFFA));
+        // Registers pushed by the interrupt handler
+        stack.unwind(0xFFF8);
+        assert_eq!(stack.frames().count(), 2);
+        // RETI
+        stack.unwind(0xFFFC);
+        assert_eq!(
+            stack.frames().map(|f| f.target).collect::<Vec<u16>>(),
+            [0x2000]
+        );
+
+        stack.push(frame(FrameKind::Rst, 0x0038, 0xFFFA));
+        // Both return addresses are popped at once, e.g. by `ADD SP,4`
+        stack.unwind(0xFFFE);
+        assert_eq!(stack.frames().count(), 0);
+    }
+}
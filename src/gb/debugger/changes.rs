@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use tui::style::{Color, Modifier, Style};
+
+/// Number of debugger steps a changed value stays highlighted
+const FADE_STEPS: u8 = 3;
+
+#[derive(Copy, Clone)]
+struct Change {
+    value: u16,
+    previous: u16,
+    age: u8, // Number of steps since the last change
+}
+
+/// Keeps track of register values which changed during the last debugger steps
+#[derive(Default)]
+pub struct ChangeTracker {
+    values: HashMap<&'static str, Change>,
+}
+
+impl ChangeTracker {
+    /// Records the value of a register after a debugger step
+    pub fn update(&mut self, name: &'static str, value: u16) {
+        match self.values.get_mut(name) {
+            Some(change) if change.value != value => {
+                *change = Change {
+                    value,
+                    previous: change.value,
+                    age: 0,
+                }
+            }
+            Some(change) => change.age = change.age.saturating_add(1),
+            // Initial values are not highlighted
+            None => {
+                self.values.insert(
+                    name,
+                    Change {
+                        value,
+                        previous: value,
+                        age: FADE_STEPS,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Returns the previous value of a register if it changed recently
+    pub fn previous(&self, name: &str) -> Option<u16> {
+        self.values
+            .get(name)
+            .filter(|change| change.age < FADE_STEPS)
+            .map(|change| change.previous)
+    }
+
+    /// Returns the style of a register, the highlight fades out over the steps after a change
+    pub fn style(&self, name: &str) -> Style {
+        match self.values.get(name).map(|change| change.age) {
+            Some(0) => Style::default()
+                .fg(Color::LightYellow)
+                .add_modifier(Modifier::BOLD),
+            Some(1) => Style::default().fg(Color::Yellow),
+            Some(2) => Style::default().fg(Color::Gray),
+            _ => Style::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initial_value() {
+        let mut tracker = ChangeTracker::default();
+        tracker.update("A", 0x01);
+        assert_eq!(tracker.previous("A"), None);
+        assert_eq!(tracker.style("A"), Style::default());
+    }
+
+    #[test]
+    fn test_fade() {
+        let mut tracker = ChangeTracker::default();
+        tracker.update("A", 0x01);
+        tracker.update("A", 0x02);
+        assert_eq!(tracker.previous("A"), Some(0x01));
+        assert_ne!(tracker.style("A"), Style::default());
+
+        for _ in 0..FADE_STEPS {
+            tracker.update("A", 0x02);
+        }
+        assert_eq!(tracker.previous("A"), None);
+        assert_eq!(tracker.style("A"), Style::default());
+    }
+}
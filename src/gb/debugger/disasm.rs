@@ -0,0 +1,181 @@
+use crate::gb::debugger::branch;
+use crate::gb::debugger::utils::resolve_byte_length;
+use crate::gb::instruction::{Instruction, JumpTest};
+use crate::gb::AddressSpace;
+use std::collections::HashSet;
+
+/// Maximum number of instructions which are explored when following the control flow
+const EXPLORE_LIMIT: usize = 0x4000;
+/// Maximum number of bytes coalesced into a single `db` line
+pub const DATA_LINE_SIZE: u16 = 4;
+/// Interrupt vectors which are explored in addition to the given entry points
+const INTERRUPT_VECTORS: [u16; 5] = [0x40, 0x48, 0x50, 0x58, 0x60];
+
+/// A line of the disassembly
+#[derive(Debug)]
+pub enum Line {
+    /// Instruction at the address followed by the address of the next line,
+    /// `None` for invalid opcodes
+    Code(u16, Option<Instruction>, u16),
+    /// Bytes which are not reached by the control flow
+    Data(u16, u16),
+}
+
+impl Line {
+    pub fn address(&self) -> u16 {
+        match self {
+            Line::Code(address, _, _) | Line::Data(address, _) => *address,
+        }
+    }
+
+    /// Returns the address following this line
+    pub fn next(&self) -> u16 {
+        match self {
+            Line::Code(_, _, next) => *next,
+            Line::Data(address, length) => address.wrapping_add(*length),
+        }
+    }
+}
+
+/// Decodes the instruction at `pc` and returns it together with the address of the next one
+pub fn decode<T: AddressSpace>(bus: &T, pc: u16) -> (Option<Instruction>, u16) {
+    let opcode = bus.read(pc);
+    let (opcode, prefixed) = match opcode == 0xCB {
+        true => (bus.read(pc.wrapping_add(1)), true),
+        false => (opcode, false),
+    };
+    match Instruction::from_byte(opcode, prefixed) {
+        Some(instruction) => (
+            Some(instruction),
+            pc.wrapping_add(u16::from(resolve_byte_length(opcode, prefixed))),
+        ),
+        None => (None, pc),
+    }
+}
+
+/// Returns whether execution never continues with the following instruction
+fn ends_flow(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::JP(JumpTest::Always, _)
+            | Instruction::JR(JumpTest::Always)
+            | Instruction::RET(JumpTest::Always)
+            | Instruction::RETI
+    )
+}
+
+/// Follows the control flow from the given entry points and the interrupt vectors
+/// and returns the addresses of all reached instructions. Targets of `JP HL` and
+/// returns can't be known statically, which is why the entry points should include
+/// the current PC and the frames of the call stack.
+pub fn explore<T: AddressSpace>(bus: &T, entries: &[u16]) -> HashSet<u16> {
+    let mut code = HashSet::new();
+    let mut pending = entries.to_vec();
+    pending.extend_from_slice(&INTERRUPT_VECTORS);
+    while let Some(pc) = pending.pop() {
+        if code.len() >= EXPLORE_LIMIT || code.contains(&pc) {
+            continue;
+        }
+        let instruction = match decode(bus, pc) {
+            (Some(instruction), _) => instruction,
+            (None, _) => continue,
+        };
+        code.insert(pc);
+        let operand = [bus.read(pc.wrapping_add(1)), bus.read(pc.wrapping_add(2))];
+        if let Some(target) = branch::target(&instruction, pc, operand) {
+            pending.push(target);
+        }
+        if let Instruction::RST(_) = instruction {
+            pending.push(u16::from(bus.read(pc) & 0x38));
+        }
+        if !ends_flow(&instruction) {
+            pending.push(decode(bus, pc).1);
+        }
+    }
+    code
+}
+
+/// Disassembles `count` lines from `start` linearly, every byte is decoded as instruction
+pub fn linear<T: AddressSpace>(bus: &T, start: u16, count: u16) -> Vec<Line> {
+    let mut pc = start;
+    (0..count)
+        .map(|_| {
+            let line = match decode(bus, pc) {
+                (Some(instruction), next) => Line::Code(pc, Some(instruction), next),
+                (None, _) => Line::Code(pc, None, pc.wrapping_add(1)),
+            };
+            pc = line.next();
+            line
+        })
+        .collect()
+}
+
+/// Disassembles `count` lines from `start`, only addresses in `code` are decoded
+/// as instructions, the bytes in between are coalesced to `db` lines.
+pub fn follow<T: AddressSpace>(bus: &T, code: &HashSet<u16>, start: u16, count: u16) -> Vec<Line> {
+    let mut pc = start;
+    (0..count)
+        .map(|_| {
+            let line = match code.contains(&pc) {
+                true => {
+                    let (instruction, next) = decode(bus, pc);
+                    Line::Code(pc, instruction, next)
+                }
+                false => {
+                    let length = (1..DATA_LINE_SIZE)
+                        .find(|offset| code.contains(&pc.wrapping_add(*offset)))
+                        .unwrap_or(DATA_LINE_SIZE);
+                    Line::Data(pc, length)
+                }
+            };
+            pc = line.next();
+            line
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gb::cartridge::Cartridge;
+    use crate::gb::memory::MemoryBus;
+
+    fn bus(program: &[u8]) -> MemoryBus {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0150..0x0150 + program.len()].copy_from_slice(program);
+        MemoryBus::new(Cartridge::from_bytes(rom))
+    }
+
+    #[test]
+    fn test_explore() {
+        // jr +3; db 0xDD, 0x01, 0x02; call 0x0160; jp 0x0150; 0x0160: ret
+        let mut program = vec![
+            0x18, 0x03, 0xDD, 0x01, 0x02, 0xCD, 0x60, 0x01, 0xC3, 0x50, 0x01,
+        ];
+        program.resize(0x10, 0xFF);
+        program.push(0xC9);
+        let bus = bus(&program);
+        let code = explore(&bus, &[0x0150]);
+        assert!(code.contains(&0x0150));
+        assert!(!code.contains(&0x0152));
+        assert!(code.contains(&0x0155));
+        assert!(code.contains(&0x0158));
+        assert!(code.contains(&0x0160));
+        assert!(!code.contains(&0x015B));
+        assert!(!code.contains(&0x0161));
+        assert!(code.contains(&0x0040));
+
+        let lines = follow(&bus, &code, 0x0150, 4);
+        assert_eq!(lines[0].next(), 0x0152);
+        assert!(matches!(lines[1], Line::Data(0x0152, 3)));
+        assert_eq!(lines[2].address(), 0x0155);
+        assert_eq!(lines[3].address(), 0x0158);
+        let lines = follow(&bus, &code, 0x015B, 1);
+        assert!(matches!(lines[0], Line::Data(0x015B, 4)));
+
+        // The linear disassembly decodes the data as instructions
+        let lines = linear(&bus, 0x0150, 3);
+        assert!(matches!(lines[1], Line::Code(0x0152, None, 0x0153)));
+        assert_eq!(lines[2].address(), 0x0153);
+    }
+}
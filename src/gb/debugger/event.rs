@@ -2,6 +2,7 @@ use std::io;
 use std::sync::mpsc;
 use std::thread;
 
+use log::error;
 use termion::event::Key;
 use termion::input::TermRead;
 
@@ -26,7 +27,7 @@ impl Events {
                 for evt in stdin.keys() {
                     if let Ok(key) = evt {
                         if let Err(err) = tx.send(Event::Input(key)) {
-                            eprintln!("{}", err);
+                            error!("{}", err);
                             return;
                         }
                     }
@@ -39,4 +40,9 @@ impl Events {
     pub fn next(&self) -> Result<Event<Key>, mpsc::RecvError> {
         self.rx.recv()
     }
+
+    /// Returns the next event if there is one without blocking
+    pub fn try_next(&self) -> Option<Event<Key>> {
+        self.rx.try_recv().ok()
+    }
 }
@@ -0,0 +1,330 @@
+use crate::gb::debugger::utils::centered_rect_abs;
+use crate::gb::memory::constants::*;
+use crate::gb::AddressSpace;
+use std::error::Error;
+use termion::event::Key;
+use tui::backend::Backend;
+use tui::layout::Rect;
+use tui::style::{Color, Modifier, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::{Block, Borders, Clear, List, ListItem, Paragraph};
+use tui::Frame;
+use unicode_width::UnicodeWidthStr;
+
+/// Named regions of the memory map, sorted by their first address
+const REGIONS: [(&str, u16); 11] = [
+    ("rom0", ROM_BANK_0_BEGIN),
+    ("romx", ROM_BANK_N_BEGIN),
+    ("vram", VRAM_BEGIN),
+    ("sram", CRAM_BEGIN),
+    ("wram", WRAM_BEGIN),
+    ("echo", ERAM_BEGIN),
+    ("oam", OAM_BEGIN),
+    ("unused", UNUSED_BEGIN),
+    ("io", IO_BEGIN),
+    ("hram", HRAM_BEGIN),
+    ("ie", INTERRUPT_ENABLE),
+];
+
+#[derive(Copy, Clone, PartialEq)]
+enum Prompt {
+    Goto,
+    Search,
+}
+
+/// Hex view of the memory map with goto, search and
+/// highlighting of the bytes changed by the last command.
+pub struct MemoryView {
+    offset: u16,
+    rows: u16,                // Number of rows shown by the last draw
+    previous: (u16, Vec<u8>), // Visible bytes before the last command
+    current: (u16, Vec<u8>),  // Visible bytes after the last command
+    prompt: Option<Prompt>,
+    input: String,
+    pattern: Vec<u8>,   // Bytes of the last search
+    found: Option<u16>, // Address of the last match
+}
+
+impl MemoryView {
+    pub fn new() -> Self {
+        Self {
+            offset: 0,
+            rows: 32,
+            previous: (0, Vec::new()),
+            current: (0, Vec::new()),
+            prompt: None,
+            input: String::new(),
+            pattern: Vec::new(),
+            found: None,
+        }
+    }
+
+    /// Whether the goto or search dialog is open
+    pub fn is_active(&self) -> bool {
+        self.prompt.is_some()
+    }
+
+    /// Opens the dialog to jump to an address or region
+    pub fn goto(&mut self) {
+        self.prompt = Some(Prompt::Goto);
+    }
+
+    /// Opens the dialog to search for hex bytes or quoted ASCII text
+    pub fn search(&mut self) {
+        self.prompt = Some(Prompt::Search);
+    }
+
+    /// Records the visible bytes after a command, bytes which differ
+    /// from the previous command are highlighted.
+    pub fn track<T: AddressSpace>(&mut self, bus: &T) {
+        let bytes = self.visible().map(|address| bus.read(address)).collect();
+        self.previous = std::mem::replace(&mut self.current, (self.offset, bytes));
+    }
+
+    pub fn scroll(&mut self, rows: i16) {
+        self.offset = self.offset.wrapping_add((rows as u16).wrapping_mul(16));
+    }
+
+    /// Jumps to the next match of the last search
+    pub fn find_next<T: AddressSpace>(&mut self, bus: &T) {
+        let start = self
+            .found
+            .map_or(self.offset, |found| found.wrapping_add(1));
+        self.found = find(bus, &self.pattern, start);
+        if let Some(found) = self.found {
+            self.offset = found & 0xFFF0;
+        }
+    }
+
+    pub fn handle_dialog_input<T: AddressSpace>(&mut self, key: Key, bus: &T) {
+        let prompt = match self.prompt {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        match key {
+            Key::Esc => {
+                self.input.clear();
+                self.prompt = None;
+            }
+            Key::Char('\n') => {
+                let input = self.input.drain(..).collect::<String>();
+                match prompt {
+                    Prompt::Goto => {
+                        if let Some(address) = resolve_address(&input) {
+                            self.offset = address & 0xFFF0;
+                            self.prompt = None;
+                        }
+                    }
+                    Prompt::Search => {
+                        if let Ok(pattern) = parse_pattern(&input) {
+                            self.pattern = pattern;
+                            self.found = None;
+                            self.find_next(bus);
+                            self.prompt = None;
+                        }
+                    }
+                }
+            }
+            Key::Char(c) => self.input.push(c),
+            Key::Backspace => {
+                self.input.pop();
+            }
+            _ => {}
+        }
+    }
+
+    /// Shows the goto or search dialog
+    pub fn show_dialog<B: Backend>(&self, f: &mut Frame<B>) {
+        let title = match self.prompt {
+            Some(Prompt::Goto) => "Go to Address",
+            Some(Prompt::Search) => "Search",
+            None => return,
+        };
+        let area = centered_rect_abs(30, 3, f.size());
+        let input = Paragraph::new(format!("> {}", self.input))
+            .style(Style::default().fg(Color::Yellow))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(Clear, area);
+        f.render_widget(input, area);
+        f.set_cursor(area.x + self.input.width() as u16 + 3, area.y + 1);
+    }
+
+    /// Draws 16 bytes per row followed by their ASCII representation.
+    /// Changed bytes are yellow and the last search match is reversed.
+    pub fn draw<B: Backend, T: AddressSpace>(&mut self, f: &mut Frame<B>, area: Rect, bus: &T) {
+        self.rows = area.height;
+        let rows = (0..self.rows)
+            .map(|row| self.offset.wrapping_add(row * 16))
+            .map(|offset| {
+                let mut spans = vec![Span::styled(
+                    format!("{:#06x}:  ", offset),
+                    Style::default().bg(Color::Black).fg(Color::Cyan),
+                )];
+                let bytes = (0..16)
+                    .map(|i| offset.wrapping_add(i))
+                    .map(|address| (address, bus.read(address)))
+                    .collect::<Vec<(u16, u8)>>();
+                for (address, value) in &bytes {
+                    spans.push(Span::styled(format!("{:02x}", value), self.style(*address)));
+                    spans.push(Span::raw(" "));
+                }
+                let ascii = bytes
+                    .iter()
+                    .map(|(_, value)| match value.is_ascii_graphic() {
+                        true => char::from(*value),
+                        false => '.',
+                    })
+                    .collect::<String>();
+                spans.push(Span::styled(
+                    format!(" {}", ascii),
+                    Style::default().fg(Color::DarkGray),
+                ));
+                ListItem::new(Spans::from(spans))
+            })
+            .collect::<Vec<ListItem>>();
+
+        let title = format!("Memory - {}", region(self.offset));
+        let list = List::new(rows)
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .style(Style::default().fg(Color::White));
+        f.render_widget(list, area);
+    }
+
+    fn style(&self, address: u16) -> Style {
+        let matched = self
+            .found
+            .is_some_and(|found| address.wrapping_sub(found) < self.pattern.len() as u16);
+        if matched {
+            return Style::default().add_modifier(Modifier::REVERSED);
+        }
+        let changed = match (
+            recorded(&self.previous, address),
+            recorded(&self.current, address),
+        ) {
+            (Some(previous), Some(current)) => previous != current,
+            _ => false,
+        };
+        match changed {
+            true => Style::default().fg(Color::Yellow),
+            false => Style::default(),
+        }
+    }
+
+    fn visible(&self) -> impl Iterator<Item = u16> {
+        let offset = self.offset;
+        (0..u32::from(self.rows) * 16).map(move |i| offset.wrapping_add(i as u16))
+    }
+}
+
+/// Returns the value of the given address in a snapshot, if it was visible
+fn recorded(snapshot: &(u16, Vec<u8>), address: u16) -> Option<u8> {
+    let (offset, bytes) = snapshot;
+    bytes
+        .get(usize::from(address.wrapping_sub(*offset)))
+        .copied()
+}
+
+/// Returns the address of the first match of the pattern at or after `start`,
+/// the search wraps around at the end of the memory map. I/O registers are skipped,
+/// since reads of registers which don't exist on the DMG are reported.
+fn find<T: AddressSpace>(bus: &T, pattern: &[u8], start: u16) -> Option<u16> {
+    if pattern.is_empty() {
+        return None;
+    }
+    (0..=u16::MAX)
+        .map(|i| start.wrapping_add(i))
+        .find(|address| {
+            pattern.iter().enumerate().all(|(i, byte)| {
+                let address = address.wrapping_add(i as u16);
+                !(IO_BEGIN..=IO_END).contains(&address) && bus.read(address) == *byte
+            })
+        })
+}
+
+/// Returns the name of the region containing the given address
+fn region(address: u16) -> &'static str {
+    REGIONS
+        .iter()
+        .rev()
+        .find(|(_, begin)| address >= *begin)
+        .map_or("rom0", |(name, _)| name)
+}
+
+/// Resolves a hex address or the name of a region
+fn resolve_address(input: &str) -> Option<u16> {
+    let input = input.trim().to_ascii_lowercase();
+    REGIONS
+        .iter()
+        .find(|(name, _)| *name == input)
+        .map(|(_, begin)| *begin)
+        .or_else(|| u16::from_str_radix(input.trim_start_matches("0x"), 16).ok())
+}
+
+/// Parses a search pattern, either quoted ASCII text (`"HELLO"`)
+/// or hex bytes which may be separated by spaces (`de ad`, `dead`)
+fn parse_pattern(input: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let input = input.trim();
+    if let Some(text) = input.strip_prefix('"') {
+        let text = text.strip_suffix('"').unwrap_or(text);
+        return match text.is_empty() {
+            true => Err("Empty search".into()),
+            false => Ok(text.as_bytes().to_vec()),
+        };
+    }
+    let digits = input.split_whitespace().collect::<String>();
+    if digits.is_empty() || digits.len() % 2 != 0 {
+        return Err(format!("Invalid hex bytes: {}", input).into());
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| Ok(u8::from_str_radix(&digits[i..i + 2], 16)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gb::cartridge::Cartridge;
+    use crate::gb::memory::MemoryBus;
+
+    #[test]
+    fn test_parse_pattern() {
+        assert_eq!(parse_pattern("de ad").unwrap(), vec![0xDE, 0xAD]);
+        assert_eq!(parse_pattern("DEAD").unwrap(), vec![0xDE, 0xAD]);
+        assert_eq!(parse_pattern("\"Hi\"").unwrap(), b"Hi".to_vec());
+        assert!(parse_pattern("dea").is_err());
+        assert!(parse_pattern("\"\"").is_err());
+    }
+
+    #[test]
+    fn test_regions() {
+        assert_eq!(resolve_address("VRAM"), Some(0x8000));
+        assert_eq!(resolve_address("0xc123"), Some(0xC123));
+        assert_eq!(resolve_address("nowhere"), None);
+        assert_eq!(region(0x4567), "romx");
+        assert_eq!(region(0xFF44), "io");
+        assert_eq!(region(0xFFFF), "ie");
+    }
+
+    #[test]
+    fn test_search() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x14D] = 0xE7;
+        let mut bus = MemoryBus::new(Cartridge::from_bytes(rom));
+        bus.write(0xC100, 0x12);
+        bus.write(0xC101, 0x34);
+        bus.write(0xD100, 0x12);
+        bus.write(0xD101, 0x34);
+
+        let mut view = MemoryView::new();
+        view.pattern = vec![0x12, 0x34];
+        view.find_next(&bus);
+        assert_eq!(view.found, Some(0xC100));
+        assert_eq!(view.offset, 0xC100);
+        view.find_next(&bus);
+        assert_eq!(view.found, Some(0xD100));
+        // Echo RAM mirrors the first match
+        view.find_next(&bus);
+        assert_eq!(view.found, Some(0xE100));
+    }
+}
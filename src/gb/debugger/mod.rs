@@ -1,22 +1,50 @@
+mod branch;
 mod breakpoint;
+mod callstack;
+mod changes;
+mod disasm;
 mod event;
 pub mod format;
+mod memory;
+mod profiler;
+pub mod trace;
 mod utils;
+mod vram;
+mod watchpoint;
 
+use crate::gb::bundle::DebugBundle;
 use crate::gb::cpu::CPU;
 use crate::gb::debugger::breakpoint::BreakpointHandler;
+use crate::gb::debugger::callstack::{CallStack, FrameKind, StackFrame};
+use crate::gb::debugger::changes::ChangeTracker;
+use crate::gb::debugger::disasm::Line;
 use crate::gb::debugger::event::{Event, Events};
-use crate::gb::debugger::utils::resolve_byte_length;
+use crate::gb::debugger::memory::MemoryView;
+use crate::gb::debugger::profiler::{
+    FrameProfile, IsrProfiler, Latency, BUCKET_SIZE, VBLANK_BUDGET,
+};
+use crate::gb::debugger::trace::{Tracer, TRACE_CAPACITY};
+use crate::gb::debugger::vram::Sprite;
+use crate::gb::debugger::watchpoint::{Condition, WatchRecord, Watchpoint};
+use crate::gb::frontend::Frontend;
+use crate::gb::image::write_pgm;
 use crate::gb::instruction::Instruction;
-use crate::gb::interrupt::IRQHandler;
+use crate::gb::interrupt::{IRQHandler, IRQ};
 use crate::gb::memory::constants::*;
+use crate::gb::memory::recorder::{BusAccess, RecordingBus};
 use crate::gb::memory::MemoryBus;
 use crate::gb::ppu::PPU;
 use crate::gb::timer::Timer;
-use crate::gb::AddressSpace;
+use crate::gb::{AddressSpace, CYCLES_PER_FRAME, SCREEN_HEIGHT};
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::error::Error;
+use std::fs;
+use std::fs::{File, OpenOptions};
 use std::io;
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use termion::event::Key;
 use termion::raw::IntoRawMode;
 use termion::screen::AlternateScreen;
@@ -27,34 +55,109 @@ use tui::text::{Span, Spans};
 use tui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
 use tui::{Frame, Terminal};
 
-pub struct Debugger<'a, T: AddressSpace> {
-    cpu: &'a RefCell<CPU<'a, T>>,
+/// File the RAM state is exported to and imported from
+const RAM_FILE: &str = "ram.bin";
+/// File the accesses of logging watchpoints are appended to
+const WATCH_LOG_FILE: &str = "watchpoints.csv";
+/// Interval in which the panels are redrawn in live mode
+const LIVE_REFRESH: Duration = Duration::from_millis(250);
+
+/// The CPU is attached to a recording bus to keep track
+/// of the bus activity of the last executed instruction.
+pub type DebugBus<'a> = RecordingBus<'a, MemoryBus>;
+
+pub struct Debugger<'a> {
+    cpu: &'a RefCell<CPU<'a, DebugBus<'a>>>,
     bus: &'a RefCell<MemoryBus>,
+    recorder: &'a RefCell<DebugBus<'a>>,
     ppu: &'a mut PPU<'a>,
     timer: &'a mut Timer<'a>,
-    irq_handler: &'a mut IRQHandler<'a, T>,
+    irq_handler: &'a mut IRQHandler<'a, DebugBus<'a>>,
     bp_handler: BreakpointHandler,
-    memory_offset: u16,
+    isr_profiler: IsrProfiler,
+    branches: HashMap<u16, bool>, // Whether the conditional branch at an address was taken last time
+    changes: ChangeTracker,
+    watch_hit: Option<(Watchpoint, BusAccess)>, // Watchpoint and access which stopped the last run
+    cursor: u16, // Selected line in the assembly view, relative to PC
+    tracer: Tracer,
+    tracing: bool,
+    bundle: DebugBundle,
+    watch_log: Option<LineWriter<File>>,
+    call_stack: CallStack,
+    cycles: u64, // Cycles since the debugger has been started
+    frames: u64,
+    elapsed: u64,                  // Cycles executed by the last command
+    profile: Option<FrameProfile>, // Profile of the last frame, shown instead of the assembly
+    show_trace: bool,              // Whether the trace is shown instead of the assembly
+    show_vram: bool, // Whether OAM is shown instead of the assembly and VRAM is exported
+    follow_flow: bool, // Whether the assembly follows the control flow instead of decoding linearly
+    live: bool,      // Whether emulation keeps running while the panels refresh periodically
+    memory: MemoryView,
+    frontend: Option<Frontend<'a>>, // Hooks shared with the other emulation loops
 }
 
-impl<'a, T: AddressSpace> Debugger<'a, T> {
+impl<'a> Debugger<'a> {
     /// Creates a new debugger
     pub fn new(
-        cpu: &'a RefCell<CPU<'a, T>>,
+        cpu: &'a RefCell<CPU<'a, DebugBus<'a>>>,
         bus: &'a RefCell<MemoryBus>,
+        recorder: &'a RefCell<DebugBus<'a>>,
         ppu: &'a mut PPU<'a>,
         timer: &'a mut Timer<'a>,
-        irq_handler: &'a mut IRQHandler<'a, T>,
+        irq_handler: &'a mut IRQHandler<'a, DebugBus<'a>>,
     ) -> Self {
-        Self {
+        let mut debugger = Self {
             cpu,
             bus,
+            recorder,
             ppu,
             timer,
             irq_handler,
             bp_handler: BreakpointHandler::new(),
-            memory_offset: 0,
-        }
+            isr_profiler: IsrProfiler::new(),
+            branches: HashMap::new(),
+            changes: ChangeTracker::default(),
+            watch_hit: None,
+            cursor: 0,
+            tracer: Tracer::new(TRACE_CAPACITY).with_memory(),
+            tracing: false,
+            bundle: DebugBundle::default(),
+            watch_log: None,
+            call_stack: CallStack::default(),
+            cycles: 0,
+            frames: 0,
+            elapsed: 0,
+            profile: None,
+            show_trace: false,
+            show_vram: false,
+            follow_flow: false,
+            live: false,
+            memory: MemoryView::new(),
+            frontend: None,
+        };
+        debugger.track_changes();
+        debugger
+    }
+
+    /// Traces every executed instruction from the start
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = tracer;
+        self.tracing = true;
+    }
+
+    /// Loads the breakpoints from the given file and keeps it up to date
+    pub fn persist_breakpoints(&mut self, path: PathBuf) -> Result<(), Box<dyn Error>> {
+        self.bp_handler.persist(path)
+    }
+
+    /// Runs the frontend hooks around every executed instruction
+    pub fn set_frontend(&mut self, frontend: Frontend<'a>) {
+        self.frontend = Some(frontend);
+    }
+
+    /// Sets the debug bundle which is completed and written with `b`
+    pub fn set_bundle(&mut self, bundle: DebugBundle) {
+        self.bundle = bundle;
     }
 
     /// Starts the emulating loop
@@ -71,36 +174,92 @@ impl<'a, T: AddressSpace> Debugger<'a, T> {
                 self.draw_assembly(f, upper[0]);
                 self.draw_memory(f, upper[1]);
                 self.draw_breakpoints(f, upper[2]);
+                self.draw_call_stack(f, upper[3]);
+                self.draw_bus_activity(f, upper[4]);
                 self.draw_cpu_registers(f, middle[0]);
                 self.draw_cpu_flags(f, middle[1]);
                 self.draw_interrupts(f, middle[2]);
                 self.draw_ppu_flags(f, middle[3]);
                 self.draw_timer_registers(f, middle[4]);
-                self.draw_help(f, lower[0]);
+                self.draw_isr_profile(f, middle[5]);
+                self.draw_reference(f, lower[0]);
+                self.draw_help(f, lower[1]);
                 if self.bp_handler.active {
                     self.bp_handler.show_dialog(f);
                 }
+                self.memory.show_dialog(f);
             })?;
 
-            match events.next()? {
+            let cycles = self.cycles;
+            let event = match self.live {
+                true => match self.run_live(&events) {
+                    Some(event) => event,
+                    None => continue,
+                },
+                false => events.next()?,
+            };
+            match event {
                 Event::Input(input) => match input {
-                    Key::Ctrl('c') => break,
+                    Key::Ctrl('c') => {
+                        self.tracer.flush()?;
+                        break;
+                    }
                     Key::F(2) => {
                         while !self.bp_handler.contains(self.cpu.borrow().pc) {
                             self.execute();
+                            if self.watch_hit.is_some() {
+                                break;
+                            }
                         }
+                        self.track_changes();
                     }
                     Key::F(3) => {
                         self.execute();
+                        self.track_changes();
                     }
                     Key::F(4) => self.bp_handler.active = !self.bp_handler.active,
+                    Key::F(5) => self.capture_frame()?,
+                    Key::F(6) => fs::write(RAM_FILE, self.bus.borrow().export_ram())?,
+                    Key::F(7) => self.bus.borrow_mut().import_ram(&fs::read(RAM_FILE)?)?,
+                    Key::F(8) => self.run_to_vblank(),
+                    Key::F(9) => self.run_to_cursor(),
+                    Key::F(10) => self.step_over(),
+                    Key::F(11) => self.step_out(),
+                    Key::F(12) => self.tracing = !self.tracing,
                     Key::Esc if self.bp_handler.active => self.bp_handler.active = false,
                     key if self.bp_handler.active => self.bp_handler.handle_dialog_input(key)?,
-                    Key::PageUp => self.memory_offset = self.memory_offset.wrapping_sub(20 * 16),
-                    Key::PageDown => self.memory_offset = self.memory_offset.wrapping_add(20 * 16),
+                    key if self.memory.is_active() => {
+                        self.memory.handle_dialog_input(key, &*self.bus.borrow())
+                    }
+                    Key::Char('g') => self.memory.goto(),
+                    Key::Char('/') => self.memory.search(),
+                    Key::Char('n') => self.memory.find_next(&*self.bus.borrow()),
+                    Key::Char('[') => self.bp_handler.select(-1),
+                    Key::Char(']') => self.bp_handler.select(1),
+                    Key::Char('e') => self.bp_handler.toggle_selected()?,
+                    Key::Char('x') => self.bp_handler.remove_selected()?,
+                    Key::Char('t') => self.show_trace = !self.show_trace,
+                    Key::Char('v') => self.show_vram = !self.show_vram,
+                    Key::Char('d') => self.follow_flow = !self.follow_flow,
+                    Key::Char('l') => self.live = !self.live,
+                    Key::Char('b') => {
+                        self.export_bundle()?;
+                    }
+                    Key::Char('p') if self.profile.is_some() => self.profile = None,
+                    Key::Char('p') => self.profile_frame(),
+                    Key::Up => self.cursor = self.cursor.saturating_sub(1),
+                    Key::Down => self.cursor = self.cursor.saturating_add(1),
+                    Key::PageUp => self.memory.scroll(-20),
+                    Key::PageDown => self.memory.scroll(20),
                     _ => {}
                 },
             }
+            if self.cycles != cycles {
+                self.elapsed = self.cycles - cycles;
+            }
+            if self.show_vram {
+                vram::export(&*self.bus.borrow(), Path::new("."))?;
+            }
         }
         Ok(())
     }
@@ -114,31 +273,46 @@ impl<'a, T: AddressSpace> Debugger<'a, T> {
                 [
                     Constraint::Percentage(74),
                     Constraint::Length(6),
+                    Constraint::Length(3),
                     Constraint::Length(2),
                 ]
                 .as_ref(),
             )
             .split(f.size());
         // Defines layout for assembly, memory and breakpoints widget
-        let upper = Layout::default()
+        let mut upper = Layout::default()
             .direction(Direction::Horizontal)
             .margin(1)
             .constraints(
                 [
-                    Constraint::Percentage(40),
-                    Constraint::Percentage(50),
-                    Constraint::Percentage(10),
+                    Constraint::Percentage(37),
+                    Constraint::Percentage(45),
+                    Constraint::Percentage(18),
                 ]
                 .as_ref(),
             )
             .split(root[0]);
+        // Splits the right column into breakpoints, call stack and bus activity widget
+        let right = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Percentage(30),
+                    Constraint::Percentage(35),
+                    Constraint::Percentage(35),
+                ]
+                .as_ref(),
+            )
+            .split(upper[2]);
+        upper.truncate(2);
+        upper.extend(right);
         // Defines layout for register widget
         let middle = Layout::default()
             .direction(Direction::Horizontal)
             .horizontal_margin(1)
             .constraints(
                 [
-                    Constraint::Length(26),
+                    Constraint::Length(40),
                     Constraint::Length(16),
                     Constraint::Length(14),
                     Constraint::Length(41),
@@ -148,22 +322,43 @@ impl<'a, T: AddressSpace> Debugger<'a, T> {
                 .as_ref(),
             )
             .split(root[1]);
-        // Defines layout for help view
+        // Defines layout for opcode reference and help view
         let lower = Layout::default()
-            .direction(Direction::Horizontal)
+            .direction(Direction::Vertical)
             .horizontal_margin(1)
-            .constraints([Constraint::Percentage(100)].as_ref())
-            .split(root[2]);
+            .constraints([Constraint::Length(3), Constraint::Length(2)].as_ref())
+            .split(root[2].union(root[3]));
         (upper, middle, lower)
     }
 
     /// Draws assembly widget
     fn draw_assembly<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        if self.show_vram {
+            return self.draw_oam(f, area);
+        }
+        if self.show_trace {
+            return self.draw_trace(f, area);
+        }
+        if self.profile.is_some() {
+            return self.draw_frame_profile(f, area);
+        }
         // Read next instructions to display
         let (selected, instructions) = self.read_instructions(area.height * 2);
 
+        let mode = match self.follow_flow {
+            true => "flow",
+            false => "linear",
+        };
+        let title = format!(
+            "Assembly, {}{} (frame {}, cycle {}, +{})",
+            mode,
+            if self.live { ", live" } else { "" },
+            self.frames,
+            self.cycles,
+            self.elapsed
+        );
         let list = List::new(instructions)
-            .block(Block::default().title("Assembly").borders(Borders::ALL))
+            .block(Block::default().title(title).borders(Borders::ALL))
             .style(Style::default().fg(Color::White))
             .highlight_style(Style::default().add_modifier(Modifier::BOLD))
             .highlight_symbol(">> ");
@@ -174,44 +369,103 @@ impl<'a, T: AddressSpace> Debugger<'a, T> {
         f.render_stateful_widget(list, area, &mut state);
     }
 
-    /// Draws memory widget
-    fn draw_memory<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
-        let count = area.height;
-        let bus = self.bus.borrow();
+    /// Draws the attributes of all sprites in OAM, hidden sprites are dimmed
+    fn draw_oam<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let sprites = Sprite::read_all(&*self.bus.borrow())
+            .iter()
+            .enumerate()
+            .map(|(i, sprite)| {
+                let style = match sprite.is_visible() {
+                    true => Style::default(),
+                    false => Style::default().fg(Color::DarkGray),
+                };
+                ListItem::new(format!(" {:2}: {}", i, sprite)).style(style)
+            })
+            .collect::<Vec<ListItem>>();
+        let list = List::new(sprites)
+            .block(Block::default().title("OAM").borders(Borders::ALL))
+            .style(Style::default().fg(Color::White));
+        f.render_widget(list, area);
+    }
 
-        // Read memory region to display
-        let memory = (self.memory_offset..self.memory_offset.saturating_add(count * 16))
-            .step_by(16)
-            .map(|offset| {
-                ListItem::new(Spans::from(vec![
-                    Span::styled(
-                        format!("{:#06x}:  ", offset),
-                        Style::default().bg(Color::Black).fg(Color::Cyan),
-                    ),
-                    Span::raw(
-                        (offset..offset.saturating_add(16))
-                            .map(|l| format!("{:02x}", bus.read(l)))
-                            .collect::<Vec<String>>()
-                            .join(" "),
-                    ),
-                ]))
+    /// Draws the cycles spent in each address range during the profiled frame, the hottest first
+    fn draw_frame_profile<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let profile = match &self.profile {
+            Some(profile) => profile,
+            None => return,
+        };
+        let hottest = profile.hottest();
+        let max = hottest.first().map_or(1, |(_, cycles)| *cycles);
+        let width = u32::from(area.width.saturating_sub(36));
+        let items = hottest
+            .iter()
+            .map(|(start, cycles)| {
+                let bar = "#".repeat((cycles * width / max) as usize);
+                ListItem::new(format!(
+                    " {:#06x}-{:#06x} {:>6} {:>5.1}% {}",
+                    start,
+                    start + (BUCKET_SIZE - 1),
+                    cycles,
+                    f64::from(*cycles) * 100.0 / f64::from(profile.total.max(1)),
+                    bar
+                ))
             })
             .collect::<Vec<ListItem>>();
+        let title = format!("Frame Profile ({} cycles)", profile.total);
+        let list = List::new(items)
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .style(Style::default().fg(Color::White));
+        f.render_widget(list, area);
+    }
 
-        let list = List::new(memory)
-            .block(Block::default().title("Memory").borders(Borders::ALL))
+    /// Draws the last traced instructions, the newest at the bottom
+    fn draw_trace<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let count = usize::from(area.height.saturating_sub(2));
+        let mut lines = self
+            .tracer
+            .lines()
+            .rev()
+            .take(count)
+            .map(|line| ListItem::new(format!(" {}", line)))
+            .collect::<Vec<ListItem>>();
+        lines.reverse();
+        let title = match self.tracing {
+            true => "Trace",
+            false => "Trace (paused)",
+        };
+        let list = List::new(lines)
+            .block(Block::default().title(title).borders(Borders::ALL))
             .style(Style::default().fg(Color::White));
         f.render_widget(list, area);
     }
 
-    /// Draws CPU registers
+    /// Draws memory widget
+    fn draw_memory<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        self.memory.draw(f, area, &*self.bus.borrow());
+    }
+
+    /// Draws CPU registers, values which changed during the last steps
+    /// are highlighted and followed by their previous value
     fn draw_cpu_registers<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
         let cpu = self.cpu.borrow();
+        let register = |name: &'static str, value: u16| {
+            let previous = match self.changes.previous(name) {
+                Some(previous) => format!(" {:#06x}", previous),
+                None => " ".repeat(7),
+            };
+            vec![
+                Span::raw(format!(" {}: ", name)),
+                Span::styled(format!("{:#06x}", value), self.changes.style(name)),
+                Span::styled(previous, Style::default().fg(Color::DarkGray)),
+            ]
+        };
+        let mut af = register("AF", cpu.r.get_af());
+        af.extend(register("SP", cpu.sp));
         let text = vec![
-            Spans::from(format!(" AF: {:#06x}  SP: {:#06x}", cpu.r.get_af(), cpu.sp)),
-            Spans::from(format!(" BC: {:#06x}", cpu.r.get_bc())),
-            Spans::from(format!(" DE: {:#06x}", cpu.r.get_de())),
-            Spans::from(format!(" HL: {:#06x}", cpu.r.get_hl())),
+            Spans::from(af),
+            Spans::from(register("BC", cpu.r.get_bc())),
+            Spans::from(register("DE", cpu.r.get_de())),
+            Spans::from(register("HL", cpu.r.get_hl())),
         ];
         let block = Block::default().title("CPU Reg.").borders(Borders::ALL);
         let registers = Paragraph::new(text)
@@ -220,14 +474,20 @@ impl<'a, T: AddressSpace> Debugger<'a, T> {
         f.render_widget(registers, area);
     }
 
-    /// Draws CPU flags
+    /// Draws CPU flags, flags which changed during the last steps are highlighted
     fn draw_cpu_flags<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
         let r = self.cpu.borrow().r;
+        let flag = |label: &'static str, name: &'static str, value: bool| {
+            Spans::from(vec![
+                Span::raw(label),
+                Span::styled(format!("{}", value as u8), self.changes.style(name)),
+            ])
+        };
         let text = vec![
-            Spans::from(format!(" Zero:      {}", r.f.zero as u8)),
-            Spans::from(format!(" Negative:  {}", r.f.negative as u8)),
-            Spans::from(format!(" HalfCarry: {}", r.f.half_carry as u8)),
-            Spans::from(format!(" Carry:     {}", r.f.carry as u8)),
+            flag(" Zero:      ", "Z", r.f.zero),
+            flag(" Negative:  ", "N", r.f.negative),
+            flag(" HalfCarry: ", "H", r.f.half_carry),
+            flag(" Carry:     ", "C", r.f.carry),
         ];
         let block = Block::default().title("CPU Flags").borders(Borders::ALL);
         let registers = Paragraph::new(text)
@@ -306,22 +566,151 @@ impl<'a, T: AddressSpace> Debugger<'a, T> {
         f.render_widget(registers, area);
     }
 
+    /// Draws the cycles spent in each interrupt service routine during the last frame
+    fn draw_isr_profile<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let cycles = |irq: IRQ| self.isr_profiler.last_frame[u8::from(irq) as usize];
+        let vblank_style = match cycles(IRQ::VBlank) > VBLANK_BUDGET {
+            true => Style::default().fg(Color::Red),
+            false => Style::default(),
+        };
+        // Shows the routine cycles and the worst latency within the last frame
+        let routine = |label: &'static str, irq: IRQ, style: Style| {
+            let latency = self.isr_profiler.last_latency[u8::from(irq) as usize];
+            vec![
+                Span::raw(label),
+                Span::styled(format!("{:>6}", cycles(irq)), style),
+                Span::raw(" / "),
+                Span::styled(format_latency(latency), latency_style(latency)),
+            ]
+        };
+
+        let mut vblank = routine(" VBlank: ", IRQ::VBlank, vblank_style);
+        vblank.push(Span::raw(format!("  Budget: {} cycles", VBLANK_BUDGET)));
+        let mut serial = routine(" Serial: ", IRQ::Serial, Style::default());
+        serial.extend(routine("  Joypad: ", IRQ::Joypad, Style::default()));
+        let text = vec![
+            Spans::from(vblank),
+            Spans::from(routine(" LCD:    ", IRQ::LCD, Style::default())),
+            Spans::from(routine(" Timer:  ", IRQ::Timer, Style::default())),
+            Spans::from(serial),
+        ];
+        let block = Block::default()
+            .title("ISR Profile (cycles / latency, I: IME off, P: preempted)")
+            .borders(Borders::ALL);
+        let profile = Paragraph::new(text)
+            .block(block)
+            .style(Style::default().fg(Color::White).bg(Color::Black));
+        f.render_widget(profile, area);
+    }
+
+    /// Draws breakpoints followed by watchpoints. The breakpoint at PC
+    /// and the watchpoint which stopped the last run are highlighted.
     fn draw_breakpoints<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
-        // Create list widget
+        let hit = self.watch_hit.map(|(watchpoint, _)| watchpoint);
+        let pc = self.cpu.borrow().pc;
+        let items = self
+            .bp_handler
+            .breakpoints
+            .iter()
+            .map(|(address, enabled)| {
+                let style = match (*address == pc, enabled) {
+                    (true, _) => Style::default().fg(Color::Green),
+                    (false, true) => Style::default(),
+                    (false, false) => Style::default().fg(Color::DarkGray),
+                };
+                let check = if *enabled { 'x' } else { ' ' };
+                ListItem::new(format!(" [{}] {:#06x}", check, address)).style(style)
+            })
+            .chain(self.bp_handler.watchpoints.iter().map(|w| {
+                let style = match hit == Some(*w) {
+                    true => Style::default().fg(Color::Red),
+                    false => Style::default(),
+                };
+                ListItem::new(format!(" {}", w)).style(style)
+            }))
+            .collect::<Vec<ListItem>>();
+        let list = List::new(items)
+            .block(Block::default().title("Breakpoints").borders(Borders::ALL))
+            .style(Style::default().fg(Color::White))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default();
+        if !self.bp_handler.breakpoints.is_empty() {
+            state.select(Some(self.bp_handler.selected));
+        }
+        f.render_stateful_widget(list, area, &mut state);
+    }
+
+    /// Draws the reconstructed call stack, the innermost frame first
+    fn draw_call_stack<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let list = List::new(
+            self.call_stack
+                .frames()
+                .map(|frame| ListItem::new(format!(" {}", frame)))
+                .collect::<Vec<ListItem>>(),
+        )
+        .block(
+            Block::default()
+                .title("Call Stack (target/site)")
+                .borders(Borders::ALL),
+        )
+        .style(Style::default().fg(Color::White));
+        f.render_widget(list, area);
+    }
+
+    /// Draws all bus accesses of the last executed instruction,
+    /// the access which triggered a watchpoint is highlighted.
+    fn draw_bus_activity<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let recorder = self.recorder.borrow();
+        let hit = self.watch_hit.map(|(_, access)| access);
         let list = List::new(
-            self.bp_handler
-                .breakpoints
+            recorder
+                .activity()
                 .iter()
-                .map(|a| ListItem::new(format!(" {:#06x}", a)))
+                .map(|a| {
+                    let style = match hit == Some(*a) {
+                        true => Style::default().fg(Color::Red),
+                        false => Style::default(),
+                    };
+                    ListItem::new(format!(" {}", a)).style(style)
+                })
                 .collect::<Vec<ListItem>>(),
         )
-        .block(Block::default().title("Breakpoints").borders(Borders::ALL))
-        .style(Style::default().fg(Color::White))
-        .highlight_style(Style::default().fg(Color::Green));
-        let mut state = ListState::default();
-        let pc = self.cpu.borrow().pc;
-        state.select(self.bp_handler.breakpoints.iter().position(|a| a == &pc));
-        f.render_stateful_widget(list, area, &mut state);
+        .block(Block::default().title("Bus Activity").borders(Borders::ALL))
+        .style(Style::default().fg(Color::White));
+        f.render_widget(list, area);
+    }
+
+    /// Draws the reference of the instruction at PC
+    fn draw_reference<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let (instruction, _) = self.simulate_step(self.cpu.borrow().pc);
+        let text = match instruction {
+            Some(instruction) => {
+                let reference = instruction.reference();
+                let cycles = match reference.cycles_taken {
+                    Some(taken) => format!("{}/{}", reference.cycles, taken),
+                    None => reference.cycles.to_string(),
+                };
+                Spans::from(vec![
+                    Span::styled(
+                        format!(" {:<16}", instruction.to_string()),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(format!("{:<60}", reference.description)),
+                    Span::styled("ZNHC ", Style::default().fg(Color::Gray)),
+                    Span::raw(format!("{:<6}", reference.flags)),
+                    Span::styled("Cycles ", Style::default().fg(Color::Gray)),
+                    Span::raw(cycles),
+                ])
+            }
+            None => Spans::from(" Invalid opcode"),
+        };
+        let block = Block::default()
+            .title("Opcode Reference")
+            .borders(Borders::ALL);
+        let paragraph = Paragraph::new(text)
+            .block(block)
+            .style(Style::default().fg(Color::White).bg(Color::Black));
+        f.render_widget(paragraph, area);
     }
 
     /// Draws the static help text
@@ -333,11 +722,51 @@ impl<'a, T: AddressSpace> Debugger<'a, T> {
             Span::raw(" Step    "),
             Span::styled("F4", Style::default().bg(Color::Gray).fg(Color::Black)),
             Span::raw(" Set Breakpoint    "),
+            Span::styled("[/]", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Select Breakpoint    "),
+            Span::styled("e", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Enable/Disable    "),
+            Span::styled("x", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Delete    "),
+            Span::styled("F5", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Capture Frame    "),
+            Span::styled("F6", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Export RAM    "),
+            Span::styled("F7", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Import RAM    "),
+            Span::styled("F8", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Run to VBlank    "),
+            Span::styled("F9", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Run to Cursor    "),
+            Span::styled("F10", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Step Over    "),
+            Span::styled("F11", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Step Out    "),
+            Span::styled("F12", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Trace    "),
+            Span::styled("t", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Show Trace    "),
+            Span::styled("v", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" VRAM    "),
+            Span::styled("d", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Follow Flow    "),
+            Span::styled("l", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Live    "),
+            Span::styled("b", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Debug Bundle    "),
+            Span::styled("p", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Profile Frame    "),
             Span::styled(
                 "PgUp/PgDn",
                 Style::default().bg(Color::Gray).fg(Color::Black),
             ),
             Span::raw(" Navigate Memory    "),
+            Span::styled("g", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Go to Address    "),
+            Span::styled("/", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Search    "),
+            Span::styled("n", Style::default().bg(Color::Gray).fg(Color::Black)),
+            Span::raw(" Next Match    "),
             Span::styled("^C", Style::default().bg(Color::Gray).fg(Color::Black)),
             Span::raw(" Quit    "),
         ]);
@@ -349,29 +778,94 @@ impl<'a, T: AddressSpace> Debugger<'a, T> {
         f.render_widget(paragraph, area);
     }
 
+    /// Writes the debug bundle with the current state, the trace and the call stack
+    fn export_bundle(&self) -> io::Result<PathBuf> {
+        let mut bundle = self.bundle.clone();
+        bundle.add_state(
+            &*self.cpu.borrow(),
+            &*self.bus.borrow(),
+            self.ppu.framebuffer(),
+        )?;
+        bundle.add_trace(self.tracer.lines());
+        let call_stack = self
+            .call_stack
+            .frames()
+            .map(|frame| format!("{}\n", frame))
+            .collect::<String>();
+        bundle.add("callstack.txt", call_stack);
+        bundle.save("debug-bundle")
+    }
+
+    /// Exports the frame rendered so far, lines below LY still show the previous frame
+    fn capture_frame(&self) -> io::Result<()> {
+        let ly = self.bus.borrow().read(PPU_LY);
+        let path = format!("frame-ly{:03}.pgm", ly);
+        write_pgm(Path::new(&path), self.ppu.framebuffer())
+    }
+
+    /// Disassembles the next n lines from PC, either linearly or by following
+    /// the control flow from PC and the call stack.
+    fn disassemble(&self, count: u16) -> Vec<Line> {
+        let pc = self.cpu.borrow().pc;
+        let bus = self.bus.borrow();
+        match self.follow_flow {
+            true => {
+                let mut entries = vec![pc];
+                for frame in self.call_stack.frames() {
+                    entries.extend_from_slice(&[frame.target, frame.call_site]);
+                }
+                let code = disasm::explore(&*bus, &entries);
+                disasm::follow(&*bus, &code, pc, count)
+            }
+            false => disasm::linear(&*bus, pc, count),
+        }
+    }
+
     /// Reads next n instructions and returns a tuple
     /// with the index of the current pc and a vector of formatted Strings.
     fn read_instructions(&self, count: u16) -> (u16, Vec<ListItem>) {
         let cpu_pc = self.cpu.borrow().pc;
-        let mut pc = cpu_pc;
         let mut frames = Vec::with_capacity(usize::from(count));
         let mut pc_index = 0;
-        for i in 0..count {
-            let (instruction, new_pc) = self.simulate_step(pc);
+        for (i, line) in self.disassemble(count).into_iter().enumerate() {
+            let pc = line.address();
             if cpu_pc == pc {
-                pc_index = i;
+                pc_index = i as u16;
             }
             // Collect bytes for this instruction as string
-            let bytes = (pc..new_pc)
+            let bytes = (pc..line.next())
                 .map(|i| format!("{:02x}", self.bus.borrow().read(i)))
                 .collect::<Vec<String>>()
                 .join(" ");
-            frames.push(self.format_instruction(pc, &bytes, instruction));
-            pc = new_pc;
+            let mut item = match line {
+                Line::Code(_, instruction, _) => self.format_instruction(pc, &bytes, instruction),
+                Line::Data(..) => self.format_data(pc, &bytes),
+            };
+            if i as u16 == self.cursor {
+                item = item.style(Style::default().bg(Color::DarkGray));
+            }
+            frames.push(item);
         }
         (pc_index, frames)
     }
 
+    /// Formats bytes which aren't reached by the control flow as `db` line
+    fn format_data(&self, pc: u16, bytes: &str) -> ListItem<'static> {
+        let data = bytes
+            .split(' ')
+            .map(|byte| format!("${}", byte))
+            .collect::<Vec<String>>()
+            .join(", ");
+        ListItem::new(Spans::from(vec![
+            Span::styled(format!("{:#06x}:  ", pc), Style::default().fg(Color::Cyan)),
+            Span::styled(format!("{:<10}", ""), Style::default()),
+            Span::styled(
+                format!(" db {}", data),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]))
+    }
+
     /// Formats and colorizes the given instruction (including raw bytes)
     /// and returns a TUI compatible ListItem.
     fn format_instruction(
@@ -385,42 +879,313 @@ impl<'a, T: AddressSpace> Debugger<'a, T> {
             false => Style::default().bg(Color::Black).fg(Color::Cyan),
         };
         let bytes_style = Style::default().bg(Color::Black).fg(Color::Gray);
-        let instruction_span = match instruction {
+        let instruction_span = match &instruction {
             Some(i) => Span::raw(format!(" {}", i)),
             None => Span::styled(" DATA", Style::default().fg(Color::Red)),
         };
-        ListItem::new(Spans::from(vec![
+        let mut spans = vec![
             Span::styled(format!("{:#06x}:  ", pc), address_style),
             Span::styled(format!("{:<10}", bytes), bytes_style),
             instruction_span,
-        ]))
+        ];
+        if let Some(instruction) = instruction.filter(branch::is_conditional) {
+            spans.extend(self.format_branch(pc, &instruction));
+        }
+        ListItem::new(Spans::from(spans))
+    }
+
+    /// Annotates a conditional branch with an arrow to its target
+    /// and whether it has been taken on the last execution.
+    fn format_branch(&self, pc: u16, instruction: &Instruction) -> Vec<Span<'static>> {
+        let bus = self.bus.borrow();
+        let operand = [bus.read(pc.wrapping_add(1)), bus.read(pc.wrapping_add(2))];
+        let mut spans = Vec::with_capacity(2);
+        if let Some(target) = branch::target(instruction, pc, operand) {
+            let arrow = match target <= pc {
+                true => '↑',
+                false => '↓',
+            };
+            spans.push(Span::styled(
+                format!("  {} {:#06x}", arrow, target),
+                Style::default().fg(Color::Magenta),
+            ));
+        }
+        match self.branches.get(&pc) {
+            Some(true) => spans.push(Span::styled(" taken", Style::default().fg(Color::Green))),
+            Some(false) => spans.push(Span::styled(
+                " not taken",
+                Style::default().fg(Color::DarkGray),
+            )),
+            None => {}
+        }
+        spans
     }
 
     /// Simulates one CPU step without executing it.
     /// Returns a tuple with the instruction and the updated program counter.
     fn simulate_step(&self, pc: u16) -> (Option<Instruction>, u16) {
-        // Read next opcode from memory
-        let opcode = self.bus.borrow().read(pc);
-        let (opcode, prefixed) = match opcode == 0xCB {
-            true => (self.bus.borrow().read(pc + 1), true),
-            false => (opcode, false),
+        disasm::decode(&*self.bus.borrow(), pc)
+    }
+
+    /// Records the CPU registers and flags after a debugger step
+    fn track_changes(&mut self) {
+        let cpu = self.cpu.borrow();
+        let r = cpu.r;
+        self.changes.update("AF", r.get_af());
+        self.changes.update("BC", r.get_bc());
+        self.changes.update("DE", r.get_de());
+        self.changes.update("HL", r.get_hl());
+        self.changes.update("SP", cpu.sp);
+        self.changes.update("Z", u16::from(r.f.zero));
+        self.changes.update("N", u16::from(r.f.negative));
+        self.changes.update("H", u16::from(r.f.half_carry));
+        self.changes.update("C", u16::from(r.f.carry));
+        self.memory.track(&*self.bus.borrow());
+    }
+
+    /// Executes instructions until `done` returns true after a step,
+    /// breakpoints and watchpoints still apply.
+    fn run_until<F: FnMut(&Self, Option<Instruction>) -> bool>(&mut self, mut done: F) {
+        loop {
+            let (instruction, _) = self.simulate_step(self.cpu.borrow().pc);
+            self.execute();
+            if done(self, instruction)
+                || self.watch_hit.is_some()
+                || self.bp_handler.contains(self.cpu.borrow().pc)
+            {
+                break;
+            }
+        }
+        self.track_changes();
+    }
+
+    /// Runs until the PPU enters VBlank
+    fn run_to_vblank(&mut self) {
+        let mut ly = self.bus.borrow().read(PPU_LY);
+        self.run_until(|debugger, _| {
+            let previous = ly;
+            ly = debugger.bus.borrow().read(PPU_LY);
+            previous < SCREEN_HEIGHT && ly >= SCREEN_HEIGHT
+        });
+    }
+
+    /// Emulates frame by frame until a key is pressed or the panels are due
+    /// to be redrawn. Hitting a breakpoint or watchpoint pauses live mode.
+    fn run_live(&mut self, events: &Events) -> Option<Event<Key>> {
+        let start = Instant::now();
+        while start.elapsed() < LIVE_REFRESH {
+            if let Some(event) = events.try_next() {
+                return Some(event);
+            }
+            self.run_to_vblank();
+            if self.watch_hit.is_some() || self.bp_handler.contains(self.cpu.borrow().pc) {
+                self.live = false;
+                break;
+            }
+        }
+        None
+    }
+
+    /// Runs for the duration of a frame while attributing the cycles
+    /// of each instruction to its address range
+    fn profile_frame(&mut self) {
+        self.profile = Some(FrameProfile::default());
+        let start = self.cycles;
+        self.run_until(|debugger, _| debugger.cycles - start >= u64::from(CYCLES_PER_FRAME));
+    }
+
+    /// Runs until PC reaches the instruction selected in the assembly view
+    fn run_to_cursor(&mut self) {
+        let target = match self.disassemble(self.cursor + 1).last() {
+            Some(line) => line.address(),
+            None => return,
         };
+        self.cursor = 0;
+        self.run_until(|debugger, _| debugger.cpu.borrow().pc == target);
+    }
 
-        // Parse instruction from opcode and return it together with the next program counter
-        match Instruction::from_byte(opcode, prefixed) {
-            Some(instruction) => (
-                Some(instruction),
-                pc + resolve_byte_length(opcode, prefixed) as u16,
-            ),
-            None => (None, pc),
+    /// Executes calls and RSTs as a single step by running until they return
+    fn step_over(&mut self) {
+        let pc = self.cpu.borrow().pc;
+        let sp = self.cpu.borrow().sp;
+        match self.simulate_step(pc) {
+            (Some(Instruction::CALL(_)), next) | (Some(Instruction::RST(_)), next) => {
+                // The stack pointer distinguishes the return from recursive calls
+                self.run_until(|debugger, _| {
+                    let cpu = debugger.cpu.borrow();
+                    cpu.pc == next && cpu.sp >= sp
+                });
+            }
+            _ => {
+                self.execute();
+                self.track_changes();
+            }
         }
     }
 
-    /// Executes a single step
+    /// Runs until the current call frame returns
+    fn step_out(&mut self) {
+        let sp = self.cpu.borrow().sp;
+        self.run_until(|debugger, instruction| {
+            let returned = matches!(
+                instruction,
+                Some(Instruction::RET(_)) | Some(Instruction::RETI)
+            );
+            returned && debugger.cpu.borrow().sp > sp
+        });
+    }
+
+    /// Pushes a frame for a taken call or RST and drops the frames which have been returned from
+    fn update_call_stack(&mut self, instruction: Option<&Instruction>, pc: u16, sp: u16) {
+        let cpu = self.cpu.borrow();
+        let kind = match instruction {
+            Some(Instruction::CALL(_)) => FrameKind::Call,
+            Some(Instruction::RST(_)) => FrameKind::Rst,
+            _ => return self.call_stack.unwind(cpu.sp),
+        };
+        // Calls which weren't taken don't push a return address
+        if cpu.sp == sp.wrapping_sub(2) {
+            self.call_stack.push(StackFrame {
+                kind,
+                call_site: pc,
+                target: cpu.pc,
+                sp: cpu.sp,
+            });
+        }
+    }
+
+    /// Appends the record to the watchpoint log, which is created on the first record
+    fn log_watch(&mut self, record: &WatchRecord) -> io::Result<()> {
+        if self.watch_log.is_none() {
+            let exists = Path::new(WATCH_LOG_FILE).exists();
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(WATCH_LOG_FILE)?;
+            let mut writer = LineWriter::new(file);
+            if !exists {
+                writeln!(writer, "{}", WatchRecord::CSV_HEADER)?;
+            }
+            self.watch_log = Some(writer);
+        }
+        match &mut self.watch_log {
+            Some(writer) => writeln!(writer, "{}", record.to_csv()),
+            None => Ok(()),
+        }
+    }
+
+    /// Executes a single step and checks the recorded bus activity against all watchpoints
     fn execute(&mut self) {
+        self.recorder.borrow_mut().clear();
+        let trace = match self.tracing {
+            true => Some(Tracer::format(&*self.cpu.borrow(), &*self.bus.borrow())),
+            false => None,
+        };
+        // Values before the step are required to detect and log changes
+        let previous = self
+            .bp_handler
+            .watchpoints
+            .iter()
+            .filter(|w| w.condition == Condition::Change || w.log)
+            .flat_map(|w| w.start..=w.end)
+            .map(|address| (address, self.bus.borrow().read(address)))
+            .collect::<HashMap<u16, u8>>();
+        let ly = self.bus.borrow().read(PPU_LY);
+        let pc = self.cpu.borrow().pc;
+        let sp = self.cpu.borrow().sp;
+        let (instruction, next_pc) = self.simulate_step(pc);
+        if let Some(frontend) = &mut self.frontend {
+            frontend.before_step(&self.cpu.borrow());
+        }
         let cycles = self.cpu.borrow_mut().step();
+        self.update_call_stack(instruction.as_ref(), pc, sp);
+        if instruction.filter(branch::is_conditional).is_some() {
+            self.branches.insert(pc, self.cpu.borrow().pc != next_pc);
+        }
+        self.isr_profiler.step(cycles, self.cpu.borrow().sp);
+        if let Some(profile) = self.profile.as_mut() {
+            profile.add(pc, cycles);
+        }
         self.timer.step(cycles);
         self.ppu.step(cycles);
-        self.irq_handler.handle();
+        let requests =
+            self.bus.borrow().read(INTERRUPT_FLAG) & self.bus.borrow().read(INTERRUPT_ENABLE);
+        self.isr_profiler
+            .pending(requests, self.cpu.borrow().ime, cycles);
+        let interrupted = self.cpu.borrow().pc;
+        if let Some(irq) = self.irq_handler.handle() {
+            let cpu = self.cpu.borrow();
+            self.isr_profiler.dispatch(irq, cpu.sp);
+            self.call_stack.push(StackFrame {
+                kind: FrameKind::Interrupt(irq),
+                call_site: interrupted,
+                target: cpu.pc,
+                sp: cpu.sp,
+            });
+        }
+        if let Some(frontend) = &mut self.frontend {
+            frontend.after_step(self.cpu, self.ppu, self.timer, cycles);
+        }
+        // LY wraps around once a frame is complete
+        if self.bus.borrow().read(PPU_LY) < ly {
+            self.isr_profiler.end_frame();
+            self.frames += 1;
+        }
+        self.cycles += u64::from(cycles);
+
+        self.watch_hit = None;
+        let mut records = Vec::new();
+        for access in self.recorder.borrow().activity().iter() {
+            let previous = previous
+                .get(&access.address)
+                .copied()
+                .unwrap_or(access.value);
+            let triggered = self
+                .bp_handler
+                .watchpoints
+                .iter()
+                .filter(|w| w.triggers(access, previous));
+            for watchpoint in triggered {
+                match watchpoint.log {
+                    true => records.push(WatchRecord {
+                        frame: self.frames,
+                        cycle: self.cycles,
+                        pc,
+                        access: *access,
+                        previous,
+                    }),
+                    false if self.watch_hit.is_none() => {
+                        self.watch_hit = Some((*watchpoint, *access))
+                    }
+                    false => {}
+                }
+            }
+        }
+        for record in records {
+            self.log_watch(&record).expect("Unable to log watchpoint");
+        }
+        if let Some(line) = trace {
+            self.tracer
+                .record(line, &self.recorder.borrow().activity())
+                .expect("Unable to write trace");
+        }
+    }
+}
+
+/// Formats the latency followed by markers for delaying causes
+fn format_latency(latency: Latency) -> String {
+    format!(
+        "{:>5}{}{}",
+        latency.cycles,
+        if latency.ime_off { "I" } else { " " },
+        if latency.preempted { "P" } else { " " }
+    )
+}
+
+/// Highlights latencies which have been delayed by disabled interrupts or preemption
+fn latency_style(latency: Latency) -> Style {
+    match latency.ime_off || latency.preempted {
+        true => Style::default().fg(Color::Yellow),
+        false => Style::default(),
     }
 }
@@ -0,0 +1,220 @@
+use crate::gb::interrupt::IRQ;
+use crate::utils;
+use std::collections::HashMap;
+
+/// Number of clock cycles available during VBlank (10 scanlines)
+pub const VBLANK_BUDGET: u32 = 10 * 456;
+
+/// Size of the address ranges cycles are attributed to by the frame profile
+pub const BUCKET_SIZE: u16 = 0x100;
+
+/// Keeps track of a running interrupt service routine
+struct Routine {
+    irq: IRQ,
+    sp: u16, // Stack pointer right after the return address has been pushed
+}
+
+/// Delay between an interrupt being requested and its routine being dispatched
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Latency {
+    pub cycles: u32,
+    /// IME was disabled while the request was pending
+    pub ime_off: bool,
+    /// A higher priority interrupt has been dispatched while the request was pending
+    pub preempted: bool,
+}
+
+impl Latency {
+    /// Keeps the worst case of both latencies
+    fn merge(&mut self, other: Latency) {
+        self.cycles = self.cycles.max(other.cycles);
+        self.ime_off |= other.ime_off;
+        self.preempted |= other.preempted;
+    }
+}
+
+/// Measures the clock cycles spent inside each interrupt service routine,
+/// from dispatch until the routine returns, and the latency of each interrupt.
+pub struct IsrProfiler {
+    running: Vec<Routine>,
+    current: [u32; 5],
+    pub last_frame: [u32; 5],
+    waiting: [Option<Latency>; 5],
+    current_latency: [Latency; 5],
+    pub last_latency: [Latency; 5],
+}
+
+impl IsrProfiler {
+    pub fn new() -> Self {
+        Self {
+            running: Vec::new(),
+            current: [0; 5],
+            last_frame: [0; 5],
+            waiting: [None; 5],
+            current_latency: [Latency::default(); 5],
+            last_latency: [Latency::default(); 5],
+        }
+    }
+
+    /// Accounts the cycles of an executed instruction for all pending interrupts,
+    /// `requests` contains the requested and enabled interrupts (IF & IE).
+    pub fn pending(&mut self, requests: u8, ime: bool, cycles: u32) {
+        for (i, waiting) in self.waiting.iter_mut().enumerate() {
+            match (utils::bit_at(requests, i as u8), waiting) {
+                (false, waiting) => *waiting = None,
+                (true, waiting @ None) => {
+                    *waiting = Some(Latency {
+                        ime_off: !ime,
+                        ..Latency::default()
+                    })
+                }
+                (true, Some(latency)) => {
+                    latency.cycles += cycles;
+                    latency.ime_off |= !ime;
+                }
+            }
+        }
+    }
+
+    /// Marks the start of the routine for the given interrupt.
+    /// Nested interrupts are attributed to the innermost routine.
+    pub fn dispatch(&mut self, irq: IRQ, sp: u16) {
+        self.running.push(Routine { irq, sp });
+        let index = u8::from(irq) as usize;
+        if let Some(latency) = self.waiting[index].take() {
+            self.current_latency[index].merge(latency);
+        }
+        // Interrupts with a higher index have a lower priority
+        for latency in self.waiting[index + 1..].iter_mut().flatten() {
+            latency.preempted = true;
+        }
+    }
+
+    /// Accounts the cycles of an executed instruction.
+    /// A routine has returned once the stack pointer is above its return address.
+    pub fn step(&mut self, cycles: u32, sp: u16) {
+        if let Some(routine) = self.running.last() {
+            self.current[u8::from(routine.irq) as usize] += cycles;
+        }
+        while self.running.last().is_some_and(|r| sp > r.sp) {
+            self.running.pop();
+        }
+    }
+
+    /// Finishes the current frame, the totals are available in `last_frame`
+    pub fn end_frame(&mut self) {
+        self.last_frame = self.current;
+        self.current = [0; 5];
+        self.last_latency = self.current_latency;
+        self.current_latency = [Latency::default(); 5];
+    }
+}
+
+/// Attributes the cycles of executed instructions to coarse address ranges of PC,
+/// which points out hot loops within a frame.
+#[derive(Default)]
+pub struct FrameProfile {
+    buckets: HashMap<u16, u32>,
+    pub total: u32,
+}
+
+impl FrameProfile {
+    /// Accounts the cycles of the instruction at the given address
+    pub fn add(&mut self, pc: u16, cycles: u32) {
+        *self.buckets.entry(pc - pc % BUCKET_SIZE).or_insert(0) += cycles;
+        self.total += cycles;
+    }
+
+    /// Returns the start address and cycles of all ranges, the hottest first
+    pub fn hottest(&self) -> Vec<(u16, u32)> {
+        let mut buckets = self
+            .buckets
+            .iter()
+            .map(|(start, cycles)| (*start, *cycles))
+            .collect::<Vec<(u16, u32)>>();
+        buckets.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        buckets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routine_cycles() {
+        let mut profiler = IsrProfiler::new();
+        profiler.step(8, 0xFFFE);
+        profiler.dispatch(IRQ::VBlank, 0xFFFC);
+        profiler.step(16, 0xFFFA);
+        profiler.step(16, 0xFFFC);
+        profiler.step(16, 0xFFFE);
+        profiler.step(4, 0xFFFE);
+        profiler.end_frame();
+        assert_eq!(profiler.last_frame[u8::from(IRQ::VBlank) as usize], 48);
+    }
+
+    #[test]
+    fn test_nested_routine_cycles() {
+        let mut profiler = IsrProfiler::new();
+        profiler.dispatch(IRQ::VBlank, 0xFFFC);
+        profiler.step(4, 0xFFFC);
+        profiler.dispatch(IRQ::Timer, 0xFFFA);
+        profiler.step(16, 0xFFFC);
+        profiler.step(16, 0xFFFE);
+        profiler.end_frame();
+        assert_eq!(profiler.last_frame[u8::from(IRQ::VBlank) as usize], 20);
+        assert_eq!(profiler.last_frame[u8::from(IRQ::Timer) as usize], 16);
+    }
+
+    #[test]
+    fn test_latency() {
+        let vblank = 1 << u8::from(IRQ::VBlank);
+        let mut profiler = IsrProfiler::new();
+        profiler.pending(vblank, false, 4);
+        profiler.pending(vblank, false, 8);
+        profiler.pending(vblank, true, 12);
+        profiler.dispatch(IRQ::VBlank, 0xFFFC);
+        profiler.pending(0, true, 20);
+        profiler.end_frame();
+        let expected = Latency {
+            cycles: 20,
+            ime_off: true,
+            preempted: false,
+        };
+        assert_eq!(
+            profiler.last_latency[u8::from(IRQ::VBlank) as usize],
+            expected
+        );
+    }
+
+    #[test]
+    fn test_preempted_latency() {
+        let requests = 1 << u8::from(IRQ::VBlank) | 1 << u8::from(IRQ::Timer);
+        let mut profiler = IsrProfiler::new();
+        profiler.pending(requests, true, 4);
+        profiler.dispatch(IRQ::VBlank, 0xFFFC);
+        profiler.pending(1 << u8::from(IRQ::Timer), true, 16);
+        profiler.step(16, 0xFFFE);
+        profiler.dispatch(IRQ::Timer, 0xFFFC);
+        profiler.end_frame();
+        let latency = profiler.last_latency[u8::from(IRQ::Timer) as usize];
+        assert_eq!(latency.cycles, 16);
+        assert!(latency.preempted);
+        assert!(!profiler.last_latency[u8::from(IRQ::VBlank) as usize].preempted);
+    }
+
+    #[test]
+    fn test_frame_profile() {
+        let mut profile = FrameProfile::default();
+        profile.add(0x0150, 4);
+        profile.add(0x3FFF, 8);
+        profile.add(0x01FF, 12);
+        profile.add(0xFF80, 8);
+        assert_eq!(profile.total, 32);
+        assert_eq!(
+            profile.hottest(),
+            vec![(0x0100, 16), (0x3F00, 8), (0xFF00, 8)]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use crate::gb::cpu::CPU;
+use crate::gb::debugger::utils::resolve_byte_length;
+use crate::gb::instruction::Instruction;
+use crate::gb::memory::recorder::BusAccess;
+use crate::gb::AddressSpace;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Number of lines kept in the rolling buffer
+pub const TRACE_CAPACITY: usize = 1000;
+
+/// Logs executed instructions together with the register state before their execution
+/// and optionally their memory accesses. The last lines are kept in a rolling buffer,
+/// every line is written to the trace file if there is one.
+pub struct Tracer {
+    buffer: VecDeque<String>,
+    capacity: usize,
+    file: Option<BufWriter<File>>,
+    memory: bool,
+}
+
+impl Tracer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buffer: VecDeque::with_capacity(capacity),
+            capacity,
+            file: None,
+            memory: false,
+        }
+    }
+
+    /// Writes every traced line to the given file
+    pub fn with_file(mut self, path: &Path) -> io::Result<Self> {
+        self.file = Some(BufWriter::new(File::create(path)?));
+        Ok(self)
+    }
+
+    /// Appends the memory accesses to each line
+    pub fn with_memory(mut self) -> Self {
+        self.memory = true;
+        self
+    }
+
+    /// Formats the instruction at PC, this has to be called before it's executed
+    pub fn format<T: AddressSpace, B: AddressSpace>(cpu: &CPU<T>, bus: &B) -> String {
+        let pc = cpu.pc;
+        let opcode = bus.read(pc);
+        let (opcode, prefixed) = match opcode == 0xCB {
+            true => (bus.read(pc.wrapping_add(1)), true),
+            false => (opcode, false),
+        };
+        let length = resolve_byte_length(opcode, prefixed);
+        let bytes = (0..u16::from(length))
+            .map(|i| format!("{:02x}", bus.read(pc.wrapping_add(i))))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let instruction = match Instruction::from_byte(opcode, prefixed) {
+            Some(instruction) => instruction.to_string(),
+            None => "DATA".to_string(),
+        };
+        format!(
+            "{:04x}: {:<8}  {:<14} a: {:#04x}, {}, sp: {:#06x}",
+            pc, bytes, instruction, cpu.r.a, cpu.r, cpu.sp
+        )
+    }
+
+    /// Records a formatted instruction and the memory accesses during its execution
+    pub fn record(&mut self, mut line: String, accesses: &[BusAccess]) -> io::Result<()> {
+        if self.memory {
+            for access in accesses {
+                write!(line, " | {}", access).expect("Unable to format access");
+            }
+        }
+        if let Some(file) = &mut self.file {
+            writeln!(file, "{}", line)?;
+        }
+        if self.buffer.len() == self.capacity {
+            self.buffer.pop_front();
+        }
+        self.buffer.push_back(line);
+        Ok(())
+    }
+
+    /// Returns the lines in the rolling buffer, oldest first
+    pub fn lines(&self) -> impl DoubleEndedIterator<Item = &String> {
+        self.buffer.iter()
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gb::cartridge::Cartridge;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_rolling_buffer() {
+        // LD A,0x42
+        let mut rom = vec![0; 0x8000];
+        rom[..2].copy_from_slice(&[0x3E, 0x42]);
+        let bus = RefCell::new(Cartridge::from_bytes(rom));
+        let cpu = CPU::new(&bus);
+        let line = Tracer::format(&cpu, &*bus.borrow());
+        assert!(line.starts_with("0000: 3e 42     LD A,u8"), "{}", line);
+
+        let mut tracer = Tracer::new(2).with_memory();
+        for i in 0..3 {
+            let access = BusAccess::write(0, 0xC000, i);
+            tracer.record(i.to_string(), &[access]).unwrap();
+        }
+        let lines = tracer.lines().collect::<Vec<&String>>();
+        assert_eq!(lines, ["1 | W 0xc000 0x01 @0", "2 | W 0xc000 0x02 @0"]);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::gb::image::write_png_with_width;
+use crate::gb::memory::constants::*;
+use crate::gb::ppu::misc::{Color, Palette, Pixel};
+use crate::gb::{AddressSpace, SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Number of tiles in VRAM
+const TILE_COUNT: usize = 384;
+/// Tiles per row in the decoded tile data
+const TILES_PER_ROW: usize = 16;
+/// Width and height of a background map in pixels
+const MAP_SIZE: usize = 256;
+/// Selects the unsigned tile data addressing at 0x8000 in PPU_LCDC
+const LCDC_TILE_SEL: u8 = 0b0001_0000;
+/// Selects the background map at 0x9C00 in PPU_LCDC
+const LCDC_BG_MAP: u8 = 0b0000_1000;
+
+/// Returns the shade of a pixel of the given tile line, the leftmost pixel is 0
+fn tile_pixel<T: AddressSpace>(bus: &T, address: u16, line: u8, x: u8) -> Pixel {
+    let low = bus.read(address + u16::from(line) * 2);
+    let high = bus.read(address + u16::from(line) * 2 + 1);
+    let bit = 7 - x;
+    Pixel::from((high >> bit & 1) << 1 | low >> bit & 1)
+}
+
+/// Decodes all tiles in VRAM with their raw shades, 16 tiles per row
+pub fn decode_tiles<T: AddressSpace>(bus: &T) -> Vec<Color> {
+    let width = TILES_PER_ROW * 8;
+    let height = TILE_COUNT / TILES_PER_ROW * 8;
+    let mut pixels = vec![Color::White; width * height];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let (x, y) = (i % width, i / width);
+        let tile = y / 8 * TILES_PER_ROW + x / 8;
+        let address = VRAM_BEGIN + tile as u16 * 16;
+        *pixel = Color::from(u8::from(tile_pixel(
+            bus,
+            address,
+            (y % 8) as u8,
+            (x % 8) as u8,
+        )));
+    }
+    pixels
+}
+
+/// Decodes the 32x32 tile background map at the given address with the current
+/// tile data addressing and BGP. The viewport is outlined if the PPU shows this map.
+pub fn decode_map<T: AddressSpace>(bus: &T, map: u16) -> Vec<Color> {
+    let lcdc = bus.read(PPU_LCDC);
+    let palette = Palette::from(bus.read(PPU_BGP));
+    let mut pixels = vec![Color::White; MAP_SIZE * MAP_SIZE];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let (x, y) = (i % MAP_SIZE, i / MAP_SIZE);
+        let index = bus.read(map + (y / 8 * 32 + x / 8) as u16);
+        let address = match lcdc & LCDC_TILE_SEL != 0 {
+            true => VRAM_BEGIN + u16::from(index) * 16,
+            false => 0x9000u16.wrapping_add((index as i8 as i16 * 16) as u16),
+        };
+        *pixel = palette.colorize(tile_pixel(bus, address, (y % 8) as u8, (x % 8) as u8));
+    }
+
+    let shown = match lcdc & LCDC_BG_MAP != 0 {
+        true => 0x9C00,
+        false => 0x9800,
+    };
+    if map == shown {
+        let scx = usize::from(bus.read(PPU_SCX));
+        let scy = usize::from(bus.read(PPU_SCY));
+        let (width, height) = (usize::from(SCREEN_WIDTH), usize::from(SCREEN_HEIGHT));
+        for dy in 0..height {
+            for dx in 0..width {
+                if dx == 0 || dy == 0 || dx == width - 1 || dy == height - 1 {
+                    let i = (scy + dy) % MAP_SIZE * MAP_SIZE + (scx + dx) % MAP_SIZE;
+                    pixels[i] = Color::from(3 - u8::from(pixels[i]));
+                }
+            }
+        }
+    }
+    pixels
+}
+
+/// Writes the tile data and both background maps as PNG images to the given directory
+pub fn export<T: AddressSpace>(bus: &T, dir: &Path) -> io::Result<()> {
+    write_png_with_width(
+        &dir.join("vram-tiles.png"),
+        &decode_tiles(bus),
+        TILES_PER_ROW * 8,
+    )?;
+    for map in [0x9800, 0x9C00].iter() {
+        let path = dir.join(format!("vram-map-{:04x}.png", map));
+        write_png_with_width(&path, &decode_map(bus, *map), MAP_SIZE)?;
+    }
+    Ok(())
+}
+
+/// Sprite attributes in OAM
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sprite {
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub flags: u8,
+}
+
+impl Sprite {
+    /// Reads all 40 sprites from OAM
+    pub fn read_all<T: AddressSpace>(bus: &T) -> Vec<Self> {
+        (OAM_BEGIN..=OAM_END)
+            .step_by(4)
+            .map(|address| Self {
+                y: bus.read(address),
+                x: bus.read(address + 1),
+                tile: bus.read(address + 2),
+                flags: bus.read(address + 3),
+            })
+            .collect()
+    }
+
+    /// Whether the sprite is within the visible area, assuming 8x16 sprites
+    pub fn is_visible(&self) -> bool {
+        self.x > 0 && self.x < SCREEN_WIDTH + 8 && self.y > 0 && self.y < SCREEN_HEIGHT + 16
+    }
+}
+
+impl fmt::Display for Sprite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let palette = match self.flags & 0x10 != 0 {
+            true => "OBP1",
+            false => "OBP0",
+        };
+        write!(
+            f,
+            "x: {:3}, y: {:3}, tile: {:#04x}, {}",
+            self.x, self.y, self.tile, palette
+        )?;
+        for (bit, name) in [(0x20, "flip-x"), (0x40, "flip-y"), (0x80, "behind")].iter() {
+            if self.flags & bit != 0 {
+                write!(f, " {}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gb::cartridge::Cartridge;
+    use crate::gb::memory::MemoryBus;
+
+    #[test]
+    fn test_decode() {
+        let mut bus = MemoryBus::new(Cartridge::from_bytes(vec![0; 0x8000]));
+        // First line of tile 1 has the shades 3, 2, 1, 0, 0, 0, 0, 0
+        bus.write(0x8010, 0b1010_0000);
+        bus.write(0x8011, 0b1100_0000);
+        bus.write(PPU_BGP, 0b1110_0100);
+        bus.write(PPU_LCDC, 0b1001_0001);
+        bus.write(0x9800, 0x01);
+
+        let tiles = decode_tiles(&bus);
+        assert_eq!(tiles.len(), 128 * 192);
+        assert_eq!(
+            &tiles[8..12],
+            &[
+                Color::Black,
+                Color::DarkGrey,
+                Color::LightGrey,
+                Color::White
+            ]
+        );
+
+        bus.write(PPU_SCX, 1);
+        bus.write(PPU_SCY, 1);
+        let map = decode_map(&bus, 0x9800);
+        assert_eq!(&map[..2], &[Color::Black, Color::DarkGrey]);
+        // The viewport outline inverts the shades
+        assert_eq!(map[MAP_SIZE + 1], Color::Black);
+        assert_eq!(map[MAP_SIZE * 2 + 2], Color::White);
+        assert_eq!(decode_map(&bus, 0x9C00)[MAP_SIZE + 1], Color::White);
+    }
+
+    #[test]
+    fn test_sprites() {
+        let mut bus = MemoryBus::new(Cartridge::from_bytes(vec![0; 0x8000]));
+        for (i, value) in [16, 8, 0x2A, 0x30].iter().enumerate() {
+            bus.write(OAM_BEGIN + i as u16, *value);
+        }
+        let sprites = Sprite::read_all(&bus);
+        assert_eq!(sprites.len(), 40);
+        assert!(sprites[0].is_visible());
+        assert!(!sprites[1].is_visible());
+        assert_eq!(
+            sprites[0].to_string(),
+            "x:   8, y:  16, tile: 0x2a, OBP1 flip-x"
+        );
+    }
+}
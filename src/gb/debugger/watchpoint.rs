@@ -0,0 +1,183 @@
+use crate::gb::memory::recorder::{AccessKind, BusAccess};
+use std::fmt;
+
+/// Defines which accesses trigger a watchpoint
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Condition {
+    Read,
+    Write,
+    Access,
+    /// A write which changes the value
+    Change,
+}
+
+/// Breaks on accesses within an address range
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub condition: Condition,
+    /// Triggered accesses are logged instead of pausing
+    pub log: bool,
+}
+
+impl Watchpoint {
+    /// Parses a watchpoint in the format `<r|w|rw|c> <address>[-<address>] [log]`
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid watchpoint: {}", input);
+        let mut parts = input.split_whitespace();
+        let condition = match parts.next().ok_or_else(invalid)? {
+            "r" => Condition::Read,
+            "w" => Condition::Write,
+            "rw" => Condition::Access,
+            "c" => Condition::Change,
+            _ => return Err(invalid()),
+        };
+        let range = parts.next().ok_or_else(invalid)?;
+        let mut addresses = range.splitn(2, '-').map(|address| {
+            u16::from_str_radix(address.trim_start_matches("0x"), 16).map_err(|_| invalid())
+        });
+        let start = addresses.next().ok_or_else(invalid)??;
+        let end = addresses.next().unwrap_or(Ok(start))?;
+        let log = match parts.next() {
+            Some("log") => true,
+            Some(_) => return Err(invalid()),
+            None => false,
+        };
+        if end < start || parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            start,
+            end,
+            condition,
+            log,
+        })
+    }
+
+    pub fn contains(&self, address: u16) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+
+    /// Returns whether the given access triggers the watchpoint,
+    /// `previous` is the value before the instruction has been executed.
+    pub fn triggers(&self, access: &BusAccess, previous: u8) -> bool {
+        if !self.contains(access.address) {
+            return false;
+        }
+        match (self.condition, access.kind) {
+            (Condition::Read, AccessKind::Read) => true,
+            (Condition::Write, AccessKind::Write) => true,
+            (Condition::Access, _) => true,
+            (Condition::Change, AccessKind::Write) => access.value != previous,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let condition = match self.condition {
+            Condition::Read => "R",
+            Condition::Write => "W",
+            Condition::Access => "RW",
+            Condition::Change => "C",
+        };
+        match self.start == self.end {
+            true => write!(f, "{:<2} {:#06x}", condition, self.start)?,
+            false => write!(f, "{:<2} {:#06x}-{:#06x}", condition, self.start, self.end)?,
+        }
+        match self.log {
+            true => write!(f, " log"),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Access which triggered a logging watchpoint
+pub struct WatchRecord {
+    pub frame: u64,
+    pub cycle: u64,
+    pub pc: u16,
+    pub access: BusAccess,
+    /// Value before the instruction has been executed
+    pub previous: u8,
+}
+
+impl WatchRecord {
+    pub const CSV_HEADER: &'static str = "frame,cycle,pc,kind,address,old,new";
+
+    pub fn to_csv(&self) -> String {
+        let kind = match self.access.kind {
+            AccessKind::Read => "R",
+            AccessKind::Write => "W",
+        };
+        format!(
+            "{},{},{:#06x},{},{:#06x},{:#04x},{:#04x}",
+            self.frame,
+            self.cycle,
+            self.pc,
+            kind,
+            self.access.address,
+            self.previous,
+            self.access.value
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Watchpoint::parse("w c000-c0ff"),
+            Ok(Watchpoint {
+                start: 0xC000,
+                end: 0xC0FF,
+                condition: Condition::Write,
+                log: false
+            })
+        );
+        assert_eq!(
+            Watchpoint::parse("rw 0xff80"),
+            Ok(Watchpoint {
+                start: 0xFF80,
+                end: 0xFF80,
+                condition: Condition::Access,
+                log: false
+            })
+        );
+        assert!(Watchpoint::parse("c c000 log").unwrap().log);
+        assert!(Watchpoint::parse("c c000 pause").is_err());
+        assert!(Watchpoint::parse("x c000").is_err());
+        assert!(Watchpoint::parse("r c0ff-c000").is_err());
+        assert!(Watchpoint::parse("r").is_err());
+    }
+
+    #[test]
+    fn test_triggers() {
+        let change = Watchpoint::parse("c c000").unwrap();
+        assert!(change.triggers(&BusAccess::write(0, 0xC000, 1), 0));
+        assert!(!change.triggers(&BusAccess::write(0, 0xC000, 1), 1));
+        assert!(!change.triggers(&BusAccess::read(0, 0xC000, 2), 1));
+
+        let read = Watchpoint::parse("r c000-c001").unwrap();
+        assert!(read.triggers(&BusAccess::read(0, 0xC001, 0), 0));
+        assert!(!read.triggers(&BusAccess::write(0, 0xC001, 0), 0));
+        assert!(!read.triggers(&BusAccess::read(0, 0xC002, 0), 0));
+    }
+
+    #[test]
+    fn test_record() {
+        let record = WatchRecord {
+            frame: 3,
+            cycle: 70224,
+            pc: 0x0150,
+            access: BusAccess::write(0, 0xC000, 0x12),
+            previous: 0x11,
+        };
+        assert_eq!(record.to_csv(), "3,70224,0x0150,W,0xc000,0x11,0x12");
+    }
+}
@@ -0,0 +1,313 @@
+use crate::gb::interrupt::IRQ;
+use crate::gb::memory::constants::{
+    ERAM_BEGIN, ERAM_END, INTERRUPT_ENABLE, PPU_LY, UNUSED_BEGIN, UNUSED_END,
+};
+use crate::gb::memory::recorder::{AccessKind, BusAccess};
+use crate::gb::memory::MemoryBus;
+use crate::gb::{AddressSpace, CYCLES_PER_FRAME, SCREEN_HEIGHT};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Defines when suspicious timing patterns are reported
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Thresholds {
+    /// Number of LY reads within a frame without HALT
+    pub ly_polls: u32,
+    /// Number of clock cycles with disabled interrupts
+    pub ime_off_cycles: u32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            ly_polls: 100,
+            ime_off_cycles: CYCLES_PER_FRAME,
+        }
+    }
+}
+
+/// Represents a suspicious timing pattern
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Warning {
+    /// LY is polled in a busy loop instead of waiting with HALT
+    LYSpin { pc: u16 },
+    /// The VBlank interrupt was still pending when the next VBlank started
+    MissedVBlank,
+    /// Interrupts have been disabled for a long time at the given address
+    LongIMEOff { pc: u16 },
+    /// Echo RAM has been accessed instead of Working RAM
+    EchoRAM { pc: u16 },
+    /// The unusable region between OAM and I/O registers has been accessed
+    UnusableRegion { pc: u16 },
+    /// A write-only register has been read, which always returns 0xFF
+    WriteOnlyRead { pc: u16, address: u16 },
+}
+
+/// Registers which can only be written, these are the sound frequency and length registers
+const WRITE_ONLY: [u16; 5] = [0xFF13, 0xFF18, 0xFF1B, 0xFF1D, 0xFF20];
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::LYSpin { pc } => write!(f, "Spinning on LY without HALT at {:#06x}", pc),
+            Warning::MissedVBlank => write!(f, "VBlank interrupt has been missed"),
+            Warning::LongIMEOff { pc } => write!(
+                f,
+                "Interrupts disabled for more than a frame, disabled at {:#06x}",
+                pc
+            ),
+            Warning::EchoRAM { pc } => write!(f, "Echo RAM accessed at {:#06x}", pc),
+            Warning::UnusableRegion { pc } => write!(
+                f,
+                "Unusable region {:#06x}-{:#06x} accessed at {:#06x}",
+                UNUSED_BEGIN, UNUSED_END, pc
+            ),
+            Warning::WriteOnlyRead { pc, address } => write!(
+                f,
+                "Write-only register {:#06x} read at {:#06x}",
+                address, pc
+            ),
+        }
+    }
+}
+
+/// Watches the executed instructions for timing patterns which
+/// are known to cause compatibility problems, e.g. with speedhacks.
+/// Every warning is only reported once.
+pub struct Diagnostics {
+    thresholds: Thresholds,
+    reported: HashSet<Warning>,
+    ly_polls: u32,
+    halted: bool,
+    ime_off: Option<(u16, u32)>, // Address where IME has been disabled and elapsed cycles
+    missed_vblank: bool,
+    memory: bool,
+}
+
+impl Diagnostics {
+    pub fn new(thresholds: Thresholds) -> Self {
+        Self {
+            thresholds,
+            reported: HashSet::new(),
+            ly_polls: 0,
+            halted: false,
+            ime_off: None,
+            missed_vblank: false,
+            memory: false,
+        }
+    }
+
+    /// Enables warnings for memory accesses which are legal,
+    /// but usually accidental, e.g. in homebrew games.
+    pub fn enable_memory_warnings(&mut self) {
+        self.memory = true;
+    }
+
+    /// Inspects a single bus access of the instruction at the given address
+    pub fn access(&mut self, pc: u16, access: &BusAccess) -> Option<Warning> {
+        if !self.memory {
+            return None;
+        }
+        match (access.kind, access.address) {
+            (_, ERAM_BEGIN..=ERAM_END) => self.report(Warning::EchoRAM { pc }),
+            (_, UNUSED_BEGIN..=UNUSED_END) => self.report(Warning::UnusableRegion { pc }),
+            (AccessKind::Read, address) if WRITE_ONLY.contains(&address) => {
+                self.report(Warning::WriteOnlyRead { pc, address })
+            }
+            _ => None,
+        }
+    }
+
+    /// Inspects a single executed instruction
+    pub fn instruction(
+        &mut self,
+        pc: u16,
+        cycles: u32,
+        ime: bool,
+        halted: bool,
+        polled_ly: bool,
+    ) -> Option<Warning> {
+        self.halted |= halted;
+        if polled_ly {
+            self.ly_polls += 1;
+            if !self.halted && self.ly_polls > self.thresholds.ly_polls {
+                return self.report(Warning::LYSpin { pc });
+            }
+        }
+
+        match (ime, &mut self.ime_off) {
+            (true, _) => self.ime_off = None,
+            (false, None) => self.ime_off = Some((pc, cycles)),
+            (false, Some((disabled_at, elapsed))) => {
+                *elapsed += cycles;
+                if *elapsed > self.thresholds.ime_off_cycles {
+                    let pc = *disabled_at;
+                    return self.report(Warning::LongIMEOff { pc });
+                }
+            }
+        }
+        None
+    }
+
+    /// Inspects a PPU step, `ly` and `requests` are LY and IF before the step.
+    /// Entering VBlank while the enabled VBlank interrupt is still pending counts as missed.
+    pub fn ppu_step(&mut self, ly: u8, requests: u8, bus: &MemoryBus) -> Option<Warning> {
+        if ly == SCREEN_HEIGHT || bus.read(PPU_LY) != SCREEN_HEIGHT {
+            return None;
+        }
+        let vblank = 1 << u8::from(IRQ::VBlank);
+        self.vblank(requests & bus.read(INTERRUPT_ENABLE) & vblank != 0)
+    }
+
+    /// Marks the start of a VBlank period, `pending` defines
+    /// whether the previous VBlank interrupt hasn't been serviced yet.
+    pub fn vblank(&mut self, pending: bool) -> Option<Warning> {
+        self.ly_polls = 0;
+        self.halted = false;
+        // Only report the first frame of a streak
+        let first = pending && !self.missed_vblank;
+        self.missed_vblank = pending;
+        match first {
+            true => Some(Warning::MissedVBlank),
+            false => None,
+        }
+    }
+
+    fn report(&mut self, warning: Warning) -> Option<Warning> {
+        match self.reported.insert(warning) {
+            true => Some(warning),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gb::cartridge::Cartridge;
+    use crate::gb::headless::Headless;
+    use crate::gb::memory::constants::{INTERRUPT_FLAG, PPU_LCDC};
+    use crate::gb::ppu::PPU;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_ly_spin() {
+        let mut diagnostics = Diagnostics::new(Thresholds {
+            ly_polls: 2,
+            ime_off_cycles: CYCLES_PER_FRAME,
+        });
+        assert_eq!(diagnostics.instruction(0x200, 12, true, false, true), None);
+        assert_eq!(diagnostics.instruction(0x200, 12, true, false, true), None);
+        assert_eq!(
+            diagnostics.instruction(0x200, 12, true, false, true),
+            Some(Warning::LYSpin { pc: 0x200 })
+        );
+        assert_eq!(diagnostics.instruction(0x200, 12, true, false, true), None);
+    }
+
+    #[test]
+    fn test_ly_poll_with_halt() {
+        let mut diagnostics = Diagnostics::new(Thresholds {
+            ly_polls: 1,
+            ime_off_cycles: CYCLES_PER_FRAME,
+        });
+        assert_eq!(diagnostics.instruction(0x200, 4, true, true, false), None);
+        for _ in 0..3 {
+            assert_eq!(diagnostics.instruction(0x201, 12, true, false, true), None);
+        }
+    }
+
+    #[test]
+    fn test_long_ime_off() {
+        let mut diagnostics = Diagnostics::new(Thresholds {
+            ly_polls: 100,
+            ime_off_cycles: 8,
+        });
+        assert_eq!(diagnostics.instruction(0x150, 4, false, false, false), None);
+        assert_eq!(diagnostics.instruction(0x151, 4, false, false, false), None);
+        assert_eq!(
+            diagnostics.instruction(0x152, 4, false, false, false),
+            Some(Warning::LongIMEOff { pc: 0x150 })
+        );
+    }
+
+    #[test]
+    fn test_missed_vblank() {
+        let mut diagnostics = Diagnostics::new(Thresholds::default());
+        assert_eq!(diagnostics.vblank(true), Some(Warning::MissedVBlank));
+        assert_eq!(diagnostics.vblank(true), None);
+        assert_eq!(diagnostics.vblank(false), None);
+        assert_eq!(diagnostics.vblank(true), Some(Warning::MissedVBlank));
+    }
+
+    /// Runs the PPU for the given amount of frames and returns the reported warnings.
+    /// The VBlank interrupt is enabled but only serviced if `service` is set.
+    fn run_frames(frames: u32, service: bool) -> Vec<Warning> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x14D] = 0xE7;
+        let bus = RefCell::new(MemoryBus::new(Cartridge::from_bytes(rom)));
+        bus.borrow_mut()
+            .write(INTERRUPT_ENABLE, 1 << u8::from(IRQ::VBlank));
+        bus.borrow_mut().write(INTERRUPT_FLAG, 0);
+        bus.borrow_mut().write(PPU_LCDC, 0x91);
+        let mut screen = Headless::new();
+        let mut ppu = PPU::new(&bus, &mut screen);
+        let mut diagnostics = Diagnostics::new(Thresholds::default());
+        let mut warnings = Vec::new();
+        for _ in 0..frames * CYCLES_PER_FRAME / 4 {
+            let (ly, requests) = (bus.borrow().read(PPU_LY), bus.borrow().read(INTERRUPT_FLAG));
+            ppu.step(4);
+            warnings.extend(diagnostics.ppu_step(ly, requests, &bus.borrow()));
+            if service {
+                bus.borrow_mut().write(INTERRUPT_FLAG, 0);
+            }
+        }
+        warnings
+    }
+
+    #[test]
+    fn test_missed_vblank_on_bus() {
+        assert_eq!(run_frames(3, false), vec![Warning::MissedVBlank]);
+        assert_eq!(run_frames(3, true), vec![]);
+    }
+
+    #[test]
+    fn test_memory_warnings() {
+        let mut diagnostics = Diagnostics::new(Thresholds::default());
+        assert_eq!(
+            diagnostics.access(0x200, &BusAccess::read(0, 0xE000, 0)),
+            None
+        );
+
+        diagnostics.enable_memory_warnings();
+        assert_eq!(
+            diagnostics.access(0x200, &BusAccess::write(0, 0xE010, 0)),
+            Some(Warning::EchoRAM { pc: 0x200 })
+        );
+        assert_eq!(
+            diagnostics.access(0x200, &BusAccess::read(0, 0xE011, 0)),
+            None
+        );
+        assert_eq!(
+            diagnostics.access(0x210, &BusAccess::read(0, 0xFEA0, 0xFF)),
+            Some(Warning::UnusableRegion { pc: 0x210 })
+        );
+        assert_eq!(
+            diagnostics.access(0x220, &BusAccess::read(0, 0xFF13, 0xFF)),
+            Some(Warning::WriteOnlyRead {
+                pc: 0x220,
+                address: 0xFF13
+            })
+        );
+        assert_eq!(
+            diagnostics.access(0x230, &BusAccess::write(0, 0xFF13, 0)),
+            None
+        );
+        assert_eq!(
+            diagnostics.access(0x240, &BusAccess::read(0, 0xC000, 0)),
+            None
+        );
+    }
+}
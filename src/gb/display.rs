@@ -1,42 +1,258 @@
 use sdl2::video::Window;
 
+use crate::config::KeyBindings;
+use crate::gb::colors::ColorScheme;
+use crate::gb::joypad::{Buttons, InputMacro};
+use crate::gb::latency::LatencyProbe;
+use crate::gb::movie::MovieMode;
 use crate::gb::ppu::misc::Color;
-use crate::gb::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::gb::video::VideoRecorder;
+use crate::gb::{
+    Screen, CPU_CLOCK_SPEED, CYCLES_PER_FRAME, DISPLAY_REFRESH_RATE, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+use crate::notifications::{self, Level};
+use crate::utils;
+use log::{info, warn};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::messagebox::{self, MessageBoxFlag};
 use sdl2::pixels;
 use sdl2::rect::{Point, Rect};
 use sdl2::render::Canvas;
-use sdl2::EventPump;
-use std::collections::VecDeque;
-use std::time::{Duration, Instant};
-use std::{error, process, thread};
+use sdl2::{EventPump, TimerSubsystem};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{error, mem, thread};
 
 const NAME: &str = "Romoulade";
+/// Duration for which notifications are shown in the title
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+/// Maximum number of notifications shown by the log dialog
+const LOG_LINES: usize = 20;
+/// Maximum number of lines shown by the serial console
+const SERIAL_LINES: usize = 40;
+
+/// Width of the ambient light border in LCD pixels
+const AMBIENT_BORDER: u32 = 16;
+
+/// Number of identical frames without input after which the game is considered idle
+const IDLE_FRAMES: u32 = 5 * DISPLAY_REFRESH_RATE;
+/// Frame time while the game is idle
+const IDLE_FRAME_TIME: Duration = Duration::from_millis(100);
+
+/// Defines the clockwise rotation of the display output
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Rotation {
+    None,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl Rotation {
+    /// Converts the given degrees to a rotation
+    pub fn from_degrees(degrees: u16) -> Option<Self> {
+        match degrees {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Deg90),
+            180 => Some(Rotation::Deg180),
+            270 => Some(Rotation::Deg270),
+            _ => None,
+        }
+    }
+}
+
+/// Defines how the LCD is oriented on screen.
+/// The image is mirrored horizontally first and rotated afterwards.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Orientation {
+    pub rotation: Rotation,
+    pub mirror: bool,
+}
+
+impl Orientation {
+    /// Returns the size of the output in pixels
+    fn size(&self) -> (u32, u32) {
+        let (width, height) = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        match self.rotation {
+            Rotation::None | Rotation::Deg180 => (width, height),
+            Rotation::Deg90 | Rotation::Deg270 => (height, width),
+        }
+    }
+
+    /// Translates LCD coordinates to output coordinates
+    fn translate(&self, x: i32, y: i32) -> (i32, i32) {
+        let (max_x, max_y) = (SCREEN_WIDTH as i32 - 1, SCREEN_HEIGHT as i32 - 1);
+        let x = if self.mirror { max_x - x } else { x };
+        match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Deg90 => (max_y - y, x),
+            Rotation::Deg180 => (max_x - x, max_y - y),
+            Rotation::Deg270 => (y, max_x - x),
+        }
+    }
+
+    /// Translates a direction on the LCD to a direction on the output
+    fn translate_direction(&self, (dx, dy): (i32, i32)) -> (i32, i32) {
+        let dx = if self.mirror { -dx } else { dx };
+        match self.rotation {
+            Rotation::None => (dx, dy),
+            Rotation::Deg90 => (-dy, dx),
+            Rotation::Deg180 => (-dx, -dy),
+            Rotation::Deg270 => (dy, -dx),
+        }
+    }
+
+    /// Remaps the D-pad so that the direction pressed
+    /// matches the direction on the rotated output.
+    fn remap_buttons(&self, pressed: Buttons) -> Buttons {
+        const DIRECTIONS: [(Buttons, (i32, i32)); 4] = [
+            (Buttons::RIGHT, (1, 0)),
+            (Buttons::LEFT, (-1, 0)),
+            (Buttons::UP, (0, -1)),
+            (Buttons::DOWN, (0, 1)),
+        ];
+        let mut buttons = pressed - (Buttons::RIGHT | Buttons::LEFT | Buttons::UP | Buttons::DOWN);
+        for (button, direction) in DIRECTIONS.iter() {
+            let output = self.translate_direction(*direction);
+            let (pressed_button, _) = DIRECTIONS.iter().find(|(_, d)| *d == output).unwrap();
+            if pressed.contains(*pressed_button) {
+                buttons.insert(*button);
+            }
+        }
+        buttons
+    }
+}
+
+/// Parameters of the FNV-1a hash used to compare frames
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Detects games which are idle, i.e. frames which don't change while no button is pressed
+struct IdleDetector {
+    hash: u64,
+    last_hash: u64,
+    idle_frames: u32,
+}
+
+impl IdleDetector {
+    fn new() -> Self {
+        Self {
+            hash: FNV_OFFSET,
+            last_hash: 0,
+            idle_frames: 0,
+        }
+    }
+
+    /// Adds a pixel to the hash of the current frame
+    fn pixel(&mut self, color: Color) {
+        self.hash = (self.hash ^ u64::from(u8::from(color))).wrapping_mul(FNV_PRIME);
+    }
+
+    /// Finishes the current frame and returns whether the game is idle
+    fn end_frame(&mut self, input: bool) -> bool {
+        match !input && self.hash == self.last_hash {
+            true => self.idle_frames = self.idle_frames.saturating_add(1),
+            false => self.idle_frames = 0,
+        }
+        self.last_hash = self.hash;
+        self.hash = FNV_OFFSET;
+        self.is_idle()
+    }
+
+    fn is_idle(&self) -> bool {
+        self.idle_frames >= IDLE_FRAMES
+    }
+
+    /// Resumes immediately, e.g. after a button has been pressed
+    fn reset(&mut self) {
+        self.idle_frames = 0;
+    }
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Self {
+            rotation: Rotation::None,
+            mirror: false,
+        }
+    }
+}
 
 /// Display with sdl2 backend to emulate the LCD.
 pub struct Display {
     canvas: Canvas<Window>,
     event_pump: EventPump,
-    upscale: u8,
+    timer: TimerSubsystem,
+    orientation: Orientation,
+    border: u32,
+    scheme: ColorScheme,
+    color_counts: [u32; 4],
     last_second_frames: VecDeque<Instant>,
     limiter: FrameLimiter,
+    status: Option<String>,
+    hint: Option<String>,
+    key_map: HashMap<Keycode, Buttons>,
+    buttons: Buttons,
+    /// Buttons pressed since the last poll, so taps shorter than a frame aren't lost
+    tapped: Buttons,
+    frame_buttons: Buttons,
+    input_macro: InputMacro,
+    macro_keys: (Keycode, Keycode), // Keys to record and play the macro
+    video_key: Keycode,
+    video: Option<VideoRecorder>,
+    pause_keys: (Keycode, Keycode), // Keys to pause and to advance by a single frame
+    paused: bool,
+    pause_on_focus_loss: bool,
+    focus_paused: bool, // Whether emulation has been paused because the window lost focus
+    advance: bool,      // Whether the next frame is emulated while paused
+    speed_key: Keycode,
+    show_speed: bool, // Whether the speed and frame time are shown next to the FPS
+    log_key: Keycode,
+    serial_key: Keycode,
+    reset_key: Keycode,
+    serial: Vec<u8>, // Bytes sent over the serial port, e.g. results of test ROMs
+    toast: Option<(String, Instant)>, // Latest notification and when it expires
+    movie: Option<MovieMode>,
+    frame: Vec<u8>, // Shade of each pixel in the current frame
+    idle: Option<IdleDetector>,
+    latency: Option<LatencyProbe>,
 }
 
 impl Display {
-    /// Creates a new display with the given int upscale.
-    pub fn new(upscale: u8, fps_limit: u32) -> Result<Self, Box<dyn error::Error>> {
+    /// Creates a new display with the given int upscale and orientation.
+    /// The LCD is drawn in its native resolution and scaled by the renderer,
+    /// so the window can be resized at any time.
+    /// If `ambient` is set, the LCD is surrounded by a border
+    /// which is filled with the average color of the last frame.
+    pub fn new(
+        upscale: u8,
+        orientation: Orientation,
+        ambient: bool,
+        scheme: ColorScheme,
+        fps_limit: u32,
+        bindings: &KeyBindings,
+    ) -> Result<Self, Box<dyn error::Error>> {
         let sdl = sdl2::init()?;
         let up = 1 << (upscale as usize);
 
-        let x_res = SCREEN_WIDTH as u32 * up;
-        let y_res = SCREEN_HEIGHT as u32 * up;
+        let border = match ambient {
+            true => AMBIENT_BORDER,
+            false => 0,
+        };
+        let (width, height) = orientation.size();
+        let (x_res, y_res) = (width + 2 * border, height + 2 * border);
 
         let video_subsystem = sdl.video()?;
         let window = video_subsystem
-            .window(NAME, x_res, y_res)
+            .window(NAME, x_res * up, y_res * up)
             .position_centered()
+            .resizable()
             .build()?;
 
-        let canvas = window.into_canvas().build()?;
+        let mut canvas = window.into_canvas().build()?;
+        canvas.set_logical_size(x_res, y_res)?;
         let limiter = match fps_limit {
             0 => FrameLimiter::new(LimitStrategy::Disabled),
             _ => FrameLimiter::new(LimitStrategy::Sleep(Duration::from_secs(1) / fps_limit)),
@@ -44,53 +260,200 @@ impl Display {
         Ok(Self {
             canvas,
             event_pump: sdl.event_pump()?,
-            upscale,
+            timer: sdl.timer()?,
+            orientation,
+            border,
+            scheme,
+            color_counts: [0; 4],
             last_second_frames: VecDeque::with_capacity(60),
             limiter,
+            status: None,
+            hint: None,
+            key_map: create_key_map(bindings)?,
+            buttons: Buttons::empty(),
+            tapped: Buttons::empty(),
+            frame_buttons: Buttons::empty(),
+            input_macro: InputMacro::new(),
+            macro_keys: (
+                resolve_key(&bindings.record_macro)?,
+                resolve_key(&bindings.play_macro)?,
+            ),
+            video_key: resolve_key(&bindings.record_video)?,
+            video: None,
+            pause_keys: (
+                resolve_key(&bindings.pause)?,
+                resolve_key(&bindings.frame_advance)?,
+            ),
+            paused: false,
+            pause_on_focus_loss: false,
+            focus_paused: false,
+            advance: false,
+            speed_key: resolve_key(&bindings.show_speed)?,
+            show_speed: false,
+            log_key: resolve_key(&bindings.show_log)?,
+            serial_key: resolve_key(&bindings.show_serial)?,
+            reset_key: resolve_key(&bindings.reset)?,
+            serial: Vec::new(),
+            toast: None,
+            movie: None,
+            frame: vec![0; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize],
+            idle: None,
+            latency: None,
         })
     }
 
-    /// Renders the current canvas to screen
-    pub fn render_screen(&mut self) {
-        self.update();
-        self.limiter.wait();
+    /// Sets a status text which is shown next to the FPS counter
+    pub fn set_status(&mut self, status: &str) {
+        self.status = Some(status.to_string());
+    }
 
-        let fps = self.calc_fps();
-        self.canvas
-            .window_mut()
-            .set_title(&format!("{} - FPS: {}", NAME, fps))
-            .expect("Unable to update title");
+    /// Measures the latency of each key press until the frame reflecting it is presented,
+    /// this frame is flashed white.
+    pub fn enable_latency_measurement(&mut self) {
+        self.latency = Some(LatencyProbe::new());
     }
 
-    /// Writes a pixel to the given coordinates
-    pub fn write_pixel(&mut self, x: u8, y: u8, value: Color) {
-        let color = self.translate_color(value);
-        self.canvas.set_draw_color(color);
-        if self.upscale == 0 {
-            self.canvas
-                .draw_point(Point::new(x as i32, y as i32))
-                .unwrap();
-            return;
+    /// Records the buttons of each frame to a movie or replays them from it
+    pub fn set_movie(&mut self, movie: MovieMode) {
+        self.movie = Some(movie);
+    }
+
+    /// Throttles emulation while the game is idle to save power,
+    /// it resumes as soon as a button is pressed.
+    pub fn enable_power_saving(&mut self) {
+        self.idle = Some(IdleDetector::new());
+    }
+
+    /// Pauses emulation while the window is minimized or unfocused,
+    /// it's resumed once the window regains focus unless it has been paused by hand.
+    pub fn enable_pause_on_focus_loss(&mut self) {
+        self.pause_on_focus_loss = true;
+    }
+
+    /// Starts recording a video or finishes the current recording
+    fn toggle_video_recording(&mut self) {
+        match self.video.take() {
+            Some(video) => match video.finish() {
+                Ok(0) => notifications::info("Finished recording"),
+                Ok(dropped) => {
+                    notifications::warn(format!("Finished recording, {} frames dropped", dropped))
+                }
+                Err(err) => notifications::error(format!("Unable to record video: {}", err)),
+            },
+            None => {
+                let secs = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default();
+                let path = PathBuf::from(format!("recording-{}.gif", secs));
+                match VideoRecorder::start(&path, self.scheme) {
+                    Ok(video) => {
+                        notifications::info(format!("Recording to {}", path.display()));
+                        self.video = Some(video);
+                    }
+                    Err(err) => notifications::error(format!("Unable to record video: {}", err)),
+                }
+            }
         }
+    }
 
-        // Translate coordinates
-        let up = 1 << (self.upscale as usize);
-        let x = x as i32 * up;
-        let y = y as i32 * up;
+    /// Shows new errors as dialog, which blocks emulation until it's dismissed,
+    /// the latest other notification is shown in the title for a few seconds.
+    fn show_notifications(&mut self) {
+        for notification in notifications::take_unread() {
+            match notification.level {
+                Level::Error => self.show_dialog(MessageBoxFlag::ERROR, &notification.message),
+                _ => self.toast = Some((notification.to_string(), Instant::now() + TOAST_DURATION)),
+            }
+        }
+        if self
+            .toast
+            .as_ref()
+            .is_some_and(|(_, until)| *until < Instant::now())
+        {
+            self.toast = None;
+        }
+    }
 
-        self.canvas
-            .fill_rect(Rect::new(x, y, up as u32, up as u32))
-            .unwrap();
+    /// Shows the recent notifications in a dialog
+    fn show_log(&mut self) {
+        let log = notifications::log();
+        let lines: Vec<String> = log
+            .iter()
+            .skip(log.len().saturating_sub(LOG_LINES))
+            .map(ToString::to_string)
+            .collect();
+        let text = match lines.is_empty() {
+            true => "No notifications".to_string(),
+            false => lines.join("\n"),
+        };
+        self.show_dialog(MessageBoxFlag::INFORMATION, &text);
+    }
+
+    /// Shows the text which has been sent over the serial port in a dialog
+    fn show_serial_console(&mut self) {
+        let output = String::from_utf8_lossy(&self.serial);
+        let lines: Vec<&str> = output.lines().collect();
+        let text = match lines.is_empty() {
+            true => "No serial output".to_string(),
+            false => lines[lines.len().saturating_sub(SERIAL_LINES)..].join("\n"),
+        };
+        self.show_dialog(
+            MessageBoxFlag::INFORMATION,
+            &format!("Serial Console\n\n{}", text),
+        );
+    }
+
+    fn show_dialog(&mut self, flags: MessageBoxFlag, message: &str) {
+        if let Err(err) =
+            messagebox::show_simple_message_box(flags, NAME, message, self.canvas.window())
+        {
+            warn!("Unable to show dialog: {}", err);
+        }
     }
 
     /// Translates given color to sdl2::pixels::Color
     fn translate_color(&self, color: Color) -> pixels::Color {
-        match color {
-            Color::White => pixels::Color::RGB(0xff, 0xff, 0xff),
-            Color::LightGrey => pixels::Color::RGB(0xab, 0xab, 0xab),
-            Color::DarkGrey => pixels::Color::RGB(0x55, 0x55, 0x55),
-            Color::Black => pixels::Color::RGB(0x00, 0x00, 0x00),
+        let (r, g, b) = self.scheme.rgb(color);
+        pixels::Color::RGB(r, g, b)
+    }
+
+    /// Fills the border around the LCD with the average color of the current frame
+    fn draw_ambient_border(&mut self) {
+        let (r, g, b) = self.scheme.average(&self.color_counts);
+        self.color_counts = [0; 4];
+        if self.border == 0 {
+            return;
         }
+
+        let (width, height) = self.orientation.size();
+        let border = self.border;
+        let total_width = width + 2 * border;
+        let offset = border as i32;
+        self.canvas.set_draw_color(pixels::Color::RGB(r, g, b));
+        self.canvas
+            .fill_rects(&[
+                Rect::new(0, 0, total_width, border),
+                Rect::new(0, offset + height as i32, total_width, border),
+                Rect::new(0, offset, border, height),
+                Rect::new(offset + width as i32, offset, border, height),
+            ])
+            .unwrap();
+    }
+
+    /// Draws a pause symbol into the top left corner of the LCD,
+    /// the next frame draws over it once emulation is resumed.
+    fn draw_pause_indicator(&mut self) {
+        let (x, y) = ((self.border + 4) as i32, (self.border + 4) as i32);
+        let at =
+            |dx: i32, dy: i32, width: u32, height: u32| Rect::new(x + dx, y + dy, width, height);
+        self.canvas
+            .set_draw_color(pixels::Color::RGB(0x20, 0x20, 0x20));
+        self.canvas.fill_rect(at(0, 0, 11, 12)).unwrap();
+        self.canvas.set_draw_color(pixels::Color::WHITE);
+        self.canvas
+            .fill_rects(&[at(2, 2, 3, 8), at(6, 2, 3, 8)])
+            .unwrap();
     }
 
     /// Returns the current frames per second
@@ -114,13 +477,310 @@ impl Display {
     /// if there are any events that need to be handled.
     fn update(&mut self) {
         self.canvas.present();
-        for event in self.event_pump.poll_iter() {
-            match event {
-                sdl2::event::Event::Quit { .. } => process::exit(0),
+        if let Some(latency) = &mut self.latency {
+            if let Some(measurement) = latency.frame_presented(Instant::now()) {
+                let average = latency.average().unwrap_or_default();
+                info!(
+                    "Input latency {}, average {:.1}ms",
+                    measurement,
+                    average.as_secs_f64() * 1000.0
+                );
+            }
+        }
+        while let Some(event) = self.event_pump.poll_event() {
+            self.handle_event(event);
+        }
+    }
+
+    /// Blocks while emulation is paused until the next frame is advanced or emulation resumes
+    fn wait_while_paused(&mut self) {
+        if !self.paused || mem::take(&mut self.advance) {
+            return;
+        }
+        let title = format!("{} - Paused", self.canvas.window().title());
+        self.canvas
+            .window_mut()
+            .set_title(&title)
+            .expect("Unable to update title");
+        while self.paused && !mem::take(&mut self.advance) {
+            let event = self.event_pump.wait_event();
+            self.handle_event(event);
+        }
+    }
+
+    /// Waits for the given duration unless a button is pressed
+    fn wait_for_input(&mut self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        let mut now = Instant::now();
+        while now < deadline && self.buttons.is_empty() {
+            let remaining = (deadline - now).as_millis() as u32;
+            if let Some(event) = self.event_pump.wait_event_timeout(remaining) {
+                self.handle_event(event);
+            }
+            now = Instant::now();
+        }
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::Quit { .. } => {
+                utils::request_shutdown();
+                self.paused = false;
+            }
+            Event::Window {
+                win_event: WindowEvent::FocusLost | WindowEvent::Minimized,
+                ..
+            } if self.pause_on_focus_loss && !self.paused => {
+                self.paused = true;
+                self.focus_paused = true;
+            }
+            Event::Window {
+                win_event: WindowEvent::FocusGained | WindowEvent::Restored,
+                ..
+            } if mem::take(&mut self.focus_paused) => self.paused = false,
+            Event::KeyDown {
+                keycode: Some(key),
+                repeat: false,
+                ..
+            } if key == self.macro_keys.0 => self.input_macro.toggle_recording(),
+            Event::KeyDown {
+                keycode: Some(key),
+                repeat: false,
+                ..
+            } if key == self.macro_keys.1 => self.input_macro.play(),
+            Event::KeyDown {
+                keycode: Some(key),
+                repeat: false,
+                ..
+            } if key == self.video_key => self.toggle_video_recording(),
+            Event::KeyDown {
+                keycode: Some(key),
+                repeat: false,
+                ..
+            } if key == self.pause_keys.0 => {
+                self.paused = !self.paused;
+                self.focus_paused = false;
+            }
+            Event::KeyDown {
+                keycode: Some(key),
+                repeat: false,
+                ..
+            } if key == self.speed_key => self.show_speed = !self.show_speed,
+            Event::KeyDown {
+                keycode: Some(key),
+                repeat: false,
+                ..
+            } if key == self.log_key => self.show_log(),
+            Event::KeyDown {
+                keycode: Some(key),
+                repeat: false,
+                ..
+            } if key == self.serial_key => self.show_serial_console(),
+            Event::KeyDown {
+                keycode: Some(key),
+                repeat: false,
+                ..
+            } if key == self.reset_key => utils::request_reset(),
+            Event::KeyDown {
+                keycode: Some(key), ..
+            } if key == self.pause_keys.1 => {
+                self.paused = true;
+                self.advance = true;
+            }
+            Event::KeyDown {
+                keycode: Some(key),
+                timestamp,
+                repeat,
+                ..
+            } => {
+                if let Some(button) = self.key_map.get(&key) {
+                    if let Some(latency) = self.latency.as_mut().filter(|_| !repeat) {
+                        // SDL timestamps are milliseconds since initialization
+                        let queue = self.timer.ticks().saturating_sub(timestamp);
+                        latency.key_pressed(Duration::from_millis(queue.into()), Instant::now());
+                    }
+                    self.buttons.insert(*button);
+                    self.tapped.insert(*button);
+                    if let Some(idle) = &mut self.idle {
+                        idle.reset();
+                    }
+                }
+            }
+            Event::KeyUp {
+                keycode: Some(key), ..
+            } => {
+                if let Some(button) = self.key_map.get(&key) {
+                    self.buttons.remove(*button);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Screen for Display {
+    fn pressed_buttons(&self) -> Buttons {
+        self.frame_buttons
+    }
+
+    /// Renders the current canvas to screen
+    fn render_screen(&mut self) {
+        self.draw_ambient_border();
+        if self
+            .latency
+            .as_mut()
+            .is_some_and(|latency| latency.frame_rendered(Instant::now()))
+        {
+            self.canvas.set_draw_color(pixels::Color::WHITE);
+            self.canvas.clear();
+        }
+        if let Some(video) = &mut self.video {
+            video.push_frame(&self.frame);
+        }
+        // After pausing one more frame is emulated to show the pause symbol
+        let paused = self.paused;
+        if paused {
+            self.draw_pause_indicator();
+        }
+        self.update();
+        self.limiter.wait();
+        if paused {
+            self.wait_while_paused();
+        }
+
+        // Buttons are polled once per frame, which makes macros frame-exact
+        let pressed = self.orientation.remap_buttons(self.buttons | self.tapped);
+        self.tapped = Buttons::empty();
+        self.frame_buttons = self.input_macro.next_frame(pressed);
+        if let Some(latency) = &mut self.latency {
+            latency.buttons_applied(Instant::now());
+        }
+        if let Some(movie) = &mut self.movie {
+            match movie.next_frame(self.frame_buttons) {
+                Ok(buttons) => self.frame_buttons = buttons,
+                Err(err) => {
+                    notifications::error(format!("Unable to record movie: {}", err));
+                    self.movie = None;
+                }
+            }
+        }
+
+        let input = !self.frame_buttons.is_empty();
+        if self.idle.as_mut().is_some_and(|idle| idle.end_frame(input)) {
+            self.wait_for_input(IDLE_FRAME_TIME);
+        }
+
+        self.show_notifications();
+        let fps = self.calc_fps();
+        let mut title = match &self.status {
+            Some(status) => format!("{} - FPS: {} - {}", NAME, fps, status),
+            None => format!("{} - FPS: {}", NAME, fps),
+        };
+        if let Some(speed) = speed_status(&self.last_second_frames).filter(|_| self.show_speed) {
+            title.push_str(&format!(" - {}", speed));
+        }
+        if self.input_macro.is_recording() {
+            title.push_str(" - Recording macro");
+        } else if self.input_macro.is_playing() {
+            title.push_str(" - Playing macro");
+        }
+        if self.video.is_some() {
+            title.push_str(" - Recording video");
+        }
+        if let Some(movie) = &self.movie {
+            title.push_str(&format!(" - {}", movie.status()));
+        }
+        if let Some(hint) = &self.hint {
+            title.push_str(&format!(" - {}", hint));
+        }
+        if let Some((toast, _)) = &self.toast {
+            title.push_str(&format!(" - {}", toast));
+        }
+        self.canvas
+            .window_mut()
+            .set_title(&title)
+            .expect("Unable to update title");
+    }
+
+    fn show_hint(&mut self, hint: Option<&str>) {
+        self.hint = hint.map(str::to_string);
+    }
+
+    fn serial_output(&mut self, bytes: &[u8]) {
+        self.serial.extend_from_slice(bytes);
+    }
+
+    /// Writes a pixel to the given coordinates
+    fn write_pixel(&mut self, x: u8, y: u8, value: Color) {
+        self.color_counts[u8::from(value) as usize] += 1;
+        self.frame[y as usize * SCREEN_WIDTH as usize + x as usize] = u8::from(value);
+        if let Some(idle) = &mut self.idle {
+            idle.pixel(value);
+        }
+        let color = self.translate_color(value);
+        self.canvas.set_draw_color(color);
+        let (x, y) = self.orientation.translate(x as i32, y as i32);
+        let (x, y) = (x + self.border as i32, y + self.border as i32);
+        self.canvas.draw_point(Point::new(x, y)).unwrap();
+    }
+}
+
+/// Resolves the given key name to a SDL keycode
+fn resolve_key(name: &str) -> Result<Keycode, String> {
+    Keycode::from_name(name).ok_or_else(|| format!("Unknown key '{}'", name))
+}
+
+/// Resolves the key names of the given bindings to SDL keycodes
+fn create_key_map(bindings: &KeyBindings) -> Result<HashMap<Keycode, Buttons>, String> {
+    bindings
+        .buttons()
+        .into_iter()
+        .map(|(button, name)| match Keycode::from_name(name) {
+            Some(key) => Ok((key, button)),
+            None => Err(format!("Unknown key '{}' for {:?}", name, button)),
+        })
+        .collect()
+}
+
+/// Opens a window which asks to press a key for each button in turn.
+/// Pressing Escape keeps the current binding of a button.
+pub fn remap_keys(bindings: &mut KeyBindings) -> Result<(), Box<dyn error::Error>> {
+    let sdl = sdl2::init()?;
+    let mut window = sdl
+        .video()?
+        .window(NAME, SCREEN_WIDTH as u32 * 2, 32)
+        .position_centered()
+        .build()?;
+    let mut event_pump = sdl.event_pump()?;
+
+    let buttons = bindings
+        .buttons()
+        .into_iter()
+        .map(|(button, name)| (button, name.to_string()))
+        .collect::<Vec<(Buttons, String)>>();
+    for (button, current) in buttons {
+        window.set_title(&format!(
+            "Press key for {:?} (current: {})",
+            button, current
+        ))?;
+        loop {
+            match event_pump.wait_event() {
+                Event::Quit { .. } => return Ok(()),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break,
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } => {
+                    bindings.bind(button, key.name());
+                    break;
+                }
                 _ => {}
             }
         }
     }
+    Ok(())
 }
 
 /// Defines FrameLimit strategies
@@ -158,3 +818,97 @@ impl FrameLimiter {
         self.last_call = Instant::now();
     }
 }
+
+/// Formats the emulation speed relative to the hardware and the average frame time
+/// from the presentation times of the frames of the last second
+fn speed_status(frames: &VecDeque<Instant>) -> Option<String> {
+    if frames.len() < 2 {
+        return None;
+    }
+    let frame_time = (*frames.back()? - *frames.front()?) / (frames.len() as u32 - 1);
+    let hardware_fps = f64::from(CPU_CLOCK_SPEED) / f64::from(CYCLES_PER_FRAME);
+    let speed = 100.0 / (frame_time.as_secs_f64() * hardware_fps);
+    Some(format!(
+        "Speed: {:.0}% - Frame time: {:.1}ms",
+        speed,
+        frame_time.as_secs_f64() * 1000.0
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_speed_status() {
+        let start = Instant::now();
+        let mut frames = VecDeque::new();
+        frames.push_back(start);
+        assert_eq!(speed_status(&frames), None);
+        // Twice as fast as the hardware
+        for i in 1..=4 {
+            frames.push_back(start + Duration::from_micros(i * 8372));
+        }
+        assert_eq!(
+            speed_status(&frames).unwrap(),
+            "Speed: 200% - Frame time: 8.4ms"
+        );
+    }
+
+    #[test]
+    fn test_translate_rotation() {
+        let orientation = Orientation {
+            rotation: Rotation::Deg90,
+            mirror: false,
+        };
+        assert_eq!(orientation.size(), (144, 160));
+        assert_eq!(orientation.translate(0, 0), (143, 0));
+        assert_eq!(orientation.translate(159, 143), (0, 159));
+    }
+
+    #[test]
+    fn test_translate_mirror() {
+        let orientation = Orientation {
+            rotation: Rotation::None,
+            mirror: true,
+        };
+        assert_eq!(orientation.translate(0, 10), (159, 10));
+    }
+
+    #[test]
+    fn test_idle_detector() {
+        let mut idle = IdleDetector::new();
+        for _ in 0..IDLE_FRAMES {
+            idle.pixel(Color::Black);
+            assert!(!idle.end_frame(false));
+        }
+        idle.pixel(Color::Black);
+        assert!(idle.end_frame(false));
+
+        // Input resumes immediately
+        idle.pixel(Color::Black);
+        assert!(!idle.end_frame(true));
+
+        // A changing frame is not idle
+        idle.pixel(Color::White);
+        assert!(!idle.end_frame(false));
+    }
+
+    #[test]
+    fn test_remap_buttons() {
+        let orientation = Orientation {
+            rotation: Rotation::Deg90,
+            mirror: false,
+        };
+        // LCD right points down on the output
+        let buttons = orientation.remap_buttons(Buttons::DOWN | Buttons::A);
+        assert_eq!(buttons, Buttons::RIGHT | Buttons::A);
+
+        let orientation = Orientation {
+            rotation: Rotation::None,
+            mirror: true,
+        };
+        assert_eq!(orientation.remap_buttons(Buttons::LEFT), Buttons::RIGHT);
+        assert_eq!(orientation.remap_buttons(Buttons::UP), Buttons::UP);
+    }
+}
@@ -0,0 +1,367 @@
+//! Emulation loops of the command line frontend. Every loop, including the one
+//! of the debugger, runs the per-frame hooks of [`Frontend`].
+
+use crate::gb::bundle::DebugBundle;
+use crate::gb::cpu::CPU;
+use crate::gb::debugger::trace::{Tracer, TRACE_CAPACITY};
+use crate::gb::debugger::DebugBus;
+use crate::gb::diagnostics::Diagnostics;
+use crate::gb::image;
+use crate::gb::interrupt::IRQHandler;
+use crate::gb::link::LinkClient;
+use crate::gb::memory::constants::{INTERRUPT_FLAG, PPU_LY};
+use crate::gb::memory::MemoryBus;
+use crate::gb::pacer::Pacer;
+use crate::gb::ppu::PPU;
+use crate::gb::sink::FrameSink;
+use crate::gb::stall::{StallDetector, StallEvent};
+use crate::gb::timer::Timer;
+use crate::gb::{AddressSpace, CYCLES_PER_FRAME, DISPLAY_REFRESH_RATE, SCREEN_HEIGHT};
+use crate::utils;
+use log::{error, info, warn};
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Thumbnails are captured after the game has been running for 5 seconds.
+const THUMBNAIL_DELAY: u32 = 5 * DISPLAY_REFRESH_RATE * CYCLES_PER_FRAME;
+/// Number of clock cycles between polls of the DMG-07 link
+const LINK_POLL_CYCLES: u32 = 456;
+
+/// Hooks which run around every instruction of an emulation loop: the serial output
+/// is shown, the game is checked for stalls, autosaves are written, requested resets
+/// are performed, a thumbnail is captured and the DMG-07 link is polled.
+pub struct Frontend<'a> {
+    bus: &'a RefCell<MemoryBus>,
+    ly: u8, // LY before the current instruction, to detect the end of a frame
+    stall: StallDetector,
+    stall_report: Option<(Tracer, DebugBundle)>,
+    serial_len: usize,
+    autosave: Option<Duration>,
+    last_autosave: Instant,
+    thumbnail: Option<PathBuf>,
+    elapsed: u32,
+    link: Option<LinkClient>,
+    link_cycles: u32,
+}
+
+impl<'a> Frontend<'a> {
+    pub fn new(bus: &'a RefCell<MemoryBus>) -> Self {
+        Self {
+            bus,
+            ly: 0,
+            stall: StallDetector::new(),
+            stall_report: None,
+            serial_len: 0,
+            autosave: None,
+            last_autosave: Instant::now(),
+            thumbnail: None,
+            elapsed: 0,
+            link: None,
+            link_cycles: 0,
+        }
+    }
+
+    /// Writes a debug bundle with the last traced instructions if the game appears to be stuck
+    pub fn set_stall_report(&mut self, bundle: DebugBundle) {
+        self.stall_report = Some((Tracer::new(TRACE_CAPACITY), bundle));
+    }
+
+    /// Writes the battery backed RAM to the next autosave slot after each interval
+    pub fn set_autosave(&mut self, interval: Duration) {
+        self.autosave = Some(interval);
+    }
+
+    /// Captures a thumbnail to the given path after `THUMBNAIL_DELAY`
+    pub fn set_thumbnail(&mut self, path: PathBuf) {
+        self.thumbnail = Some(path);
+    }
+
+    /// Connects the serial port to a DMG-07 adapter
+    pub fn set_link(&mut self, link: LinkClient) {
+        self.link = Some(link);
+    }
+
+    /// Has to be called right before the CPU executes the next instruction
+    pub fn before_step<T: AddressSpace>(&mut self, cpu: &CPU<T>) {
+        self.ly = self.bus.borrow().read(PPU_LY);
+        self.stall.instruction(cpu.pc);
+        if let Some((tracer, _)) = &mut self.stall_report {
+            let line = Tracer::format(cpu, &*self.bus.borrow());
+            tracer
+                .record(line, &[])
+                .expect("Unable to trace instruction");
+        }
+    }
+
+    /// Has to be called once the instruction and pending interrupts have been handled.
+    /// A requested reset is performed at the end of a frame, the cartridge stays loaded.
+    /// Returns whether a frame has been completed.
+    pub fn after_step<T: AddressSpace>(
+        &mut self,
+        cpu: &RefCell<CPU<T>>,
+        ppu: &mut PPU,
+        timer: &mut Timer,
+        cycles: u32,
+    ) -> bool {
+        let frame_done =
+            self.ly != SCREEN_HEIGHT && self.bus.borrow().read(PPU_LY) == SCREEN_HEIGHT;
+        if frame_done {
+            self.end_frame(cpu, ppu, timer);
+        }
+
+        if let Some(path) = &self.thumbnail {
+            self.elapsed += cycles;
+            if self.elapsed >= THUMBNAIL_DELAY {
+                if let Err(err) = save_thumbnail(path, ppu) {
+                    warn!("Unable to save thumbnail: {}", err);
+                }
+                self.thumbnail = None;
+            }
+        }
+
+        // The link is polled once per scanline
+        self.link_cycles += cycles;
+        if self.link_cycles >= LINK_POLL_CYCLES {
+            self.link_cycles = 0;
+            let bus = self.bus;
+            if let Some(Err(err)) = self.link.as_mut().map(|link| link.poll(bus)) {
+                warn!("Link disconnected: {}", err);
+                self.link = None;
+            }
+        }
+        frame_done
+    }
+
+    fn end_frame<T: AddressSpace>(
+        &mut self,
+        cpu: &RefCell<CPU<T>>,
+        ppu: &mut PPU,
+        timer: &mut Timer,
+    ) {
+        let serial = self.bus.borrow().serial_output()[self.serial_len..].to_vec();
+        if !serial.is_empty() {
+            ppu.serial_output(&serial);
+            self.serial_len += serial.len();
+        }
+        if self
+            .autosave
+            .is_some_and(|interval| self.last_autosave.elapsed() >= interval)
+        {
+            if let Err(err) = self.bus.borrow().write_autosave() {
+                error!("Unable to write autosave: {}", err);
+            }
+            self.last_autosave = Instant::now();
+        }
+        if utils::take_reset_request() {
+            self.bus.borrow_mut().reset();
+            cpu.borrow_mut().reset();
+            ppu.reset();
+            timer.reset();
+            self.stall = StallDetector::new();
+            info!("Reset");
+        }
+        let input = !self.bus.borrow().buttons().is_empty();
+        match self.stall.end_frame(ppu.framebuffer(), input) {
+            Some(StallEvent::Stuck(stall)) => {
+                warn!("{}", stall);
+                ppu.show_hint(Some("Game appears stuck"));
+                if let Some((tracer, bundle)) = &self.stall_report {
+                    let mut bundle = bundle.clone();
+                    bundle.add_trace(tracer.lines());
+                    let result = bundle
+                        .add_state(&*cpu.borrow(), &*self.bus.borrow(), ppu.framebuffer())
+                        .and_then(|_| bundle.save("stall"));
+                    match result {
+                        Ok(path) => info!("Debug bundle written to {}", path.display()),
+                        Err(err) => error!("Unable to write debug bundle: {}", err),
+                    }
+                }
+            }
+            Some(StallEvent::Resumed) => ppu.show_hint(None),
+            None => {}
+        }
+    }
+}
+
+/// Writes the current frame to the given path
+fn save_thumbnail(path: &Path, ppu: &PPU) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    image::write_pgm(path, ppu.framebuffer())
+}
+
+/// Starts the emulating loop
+pub fn emulate<T: AddressSpace>(
+    cpu: &RefCell<CPU<T>>,
+    ppu: &mut PPU,
+    timer: &mut Timer,
+    irq_handler: &mut IRQHandler<T>,
+    mut frontend: Frontend,
+) {
+    while !utils::shutdown_requested() {
+        frontend.before_step(&cpu.borrow());
+        let cycles = cpu.borrow_mut().step();
+        timer.step(cycles);
+        ppu.step(cycles);
+        irq_handler.handle();
+        frontend.after_step(cpu, ppu, timer, cycles);
+    }
+}
+
+/// Starts the emulating loop and forwards every rendered frame to the given sink.
+/// Emulation is paced to real time if a pacer is given.
+pub fn stream<T: AddressSpace>(
+    cpu: &RefCell<CPU<T>>,
+    ppu: &mut PPU,
+    timer: &mut Timer,
+    irq_handler: &mut IRQHandler<T>,
+    mut frontend: Frontend,
+    mut sink: Box<dyn FrameSink>,
+    mut pacer: Option<Pacer>,
+) -> io::Result<()> {
+    while !utils::shutdown_requested() {
+        frontend.before_step(&cpu.borrow());
+        let cycles = cpu.borrow_mut().step();
+        timer.step(cycles);
+        ppu.step(cycles);
+        irq_handler.handle();
+        if frontend.after_step(cpu, ppu, timer, cycles) {
+            sink.push_frame(ppu.framebuffer())?;
+        }
+        if let Some(pacer) = &mut pacer {
+            pacer.step(cycles);
+        }
+    }
+    Ok(())
+}
+
+/// Starts the emulating loop and traces every executed instruction.
+/// The trace file is flushed once per frame and if the emulator panics.
+#[allow(clippy::too_many_arguments)]
+pub fn trace<'a>(
+    cpu: &RefCell<CPU<'a, DebugBus<'a>>>,
+    bus: &RefCell<MemoryBus>,
+    recorder: &RefCell<DebugBus<'a>>,
+    ppu: &mut PPU,
+    timer: &mut Timer,
+    irq_handler: &mut IRQHandler<'a, DebugBus<'a>>,
+    mut frontend: Frontend,
+    mut tracer: Tracer,
+) -> io::Result<()> {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| -> io::Result<()> {
+        while !utils::shutdown_requested() {
+            recorder.borrow_mut().clear();
+            let line = Tracer::format(&*cpu.borrow(), &*bus.borrow());
+            frontend.before_step(&cpu.borrow());
+            let cycles = cpu.borrow_mut().step();
+            timer.step(cycles);
+            ppu.step(cycles);
+            irq_handler.handle();
+            tracer.record(line, &recorder.borrow().activity())?;
+            if frontend.after_step(cpu, ppu, timer, cycles) {
+                tracer.flush()?;
+            }
+        }
+        tracer.flush()
+    }));
+    match result {
+        Ok(result) => result,
+        Err(payload) => {
+            tracer.flush()?;
+            panic::resume_unwind(payload)
+        }
+    }
+}
+
+/// Starts the emulating loop and reports suspicious timing patterns
+#[allow(clippy::too_many_arguments)]
+pub fn diagnose<'a>(
+    cpu: &RefCell<CPU<'a, DebugBus<'a>>>,
+    bus: &RefCell<MemoryBus>,
+    recorder: &RefCell<DebugBus<'a>>,
+    ppu: &mut PPU,
+    timer: &mut Timer,
+    irq_handler: &mut IRQHandler<'a, DebugBus<'a>>,
+    mut frontend: Frontend,
+    mut diagnostics: Diagnostics,
+) {
+    while !utils::shutdown_requested() {
+        recorder.borrow_mut().clear();
+        frontend.before_step(&cpu.borrow());
+        let pc = cpu.borrow().pc;
+        let cycles = cpu.borrow_mut().step();
+        let polled_ly = recorder
+            .borrow()
+            .activity()
+            .iter()
+            .any(|access| access.address == PPU_LY);
+        for access in recorder.borrow().activity().iter() {
+            if let Some(warning) = diagnostics.access(pc, access) {
+                warn!("{}", warning);
+            }
+        }
+        let (ime, halted) = (cpu.borrow().ime, cpu.borrow().is_halted);
+        if let Some(warning) = diagnostics.instruction(pc, cycles, ime, halted, polled_ly) {
+            warn!("{}", warning);
+        }
+
+        let (ly, requests) = (bus.borrow().read(PPU_LY), bus.borrow().read(INTERRUPT_FLAG));
+        timer.step(cycles);
+        ppu.step(cycles);
+        if let Some(warning) = diagnostics.ppu_step(ly, requests, &bus.borrow()) {
+            warn!("{}", warning);
+        }
+        irq_handler.handle();
+        frontend.after_step(cpu, ppu, timer, cycles);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gb::cartridge::Cartridge;
+    use crate::gb::headless::Headless;
+    use crate::gb::memory::constants::{BOOT_ROM_OFF, PPU_LCDC};
+
+    #[test]
+    fn test_reset_at_end_of_frame() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x14D] = 0xE7;
+        let bus = RefCell::new(MemoryBus::new(Cartridge::from_bytes(rom)));
+        bus.borrow_mut().write(BOOT_ROM_OFF, 1);
+        bus.borrow_mut().write(PPU_LCDC, 0x91);
+        bus.borrow_mut().write(0xC000, 0x42);
+        let mut screen = Headless::new();
+        let mut ppu = PPU::new(&bus, &mut screen);
+        let cpu = RefCell::new(CPU::new(&bus));
+        cpu.borrow_mut().pc = 0x150;
+        let mut irq_handler = IRQHandler::new(&cpu, &bus);
+        let mut timer = Timer::new(&bus);
+        let mut frontend = Frontend::new(&bus);
+
+        utils::request_reset();
+        let mut frames = 0;
+        while frames == 0 {
+            frontend.before_step(&cpu.borrow());
+            let cycles = cpu.borrow_mut().step();
+            timer.step(cycles);
+            ppu.step(cycles);
+            irq_handler.handle();
+            if frontend.after_step(&cpu, &mut ppu, &mut timer, cycles) {
+                frames += 1;
+            }
+            // The reset is deferred until the frame is complete
+            if frames == 0 {
+                assert_eq!(bus.borrow().read(0xC000), 0x42);
+            }
+        }
+        assert_eq!(bus.borrow().read(0xC000), 0);
+        assert_eq!(cpu.borrow().pc, 0);
+    }
+}
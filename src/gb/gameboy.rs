@@ -0,0 +1,175 @@
+use crate::gb::cartridge::Cartridge;
+use crate::gb::cpu::CPU;
+use crate::gb::interrupt::IRQHandler;
+use crate::gb::joypad::Buttons;
+use crate::gb::memory::constants::PPU_LY;
+use crate::gb::memory::MemoryBus;
+use crate::gb::ppu::misc::Color;
+use crate::gb::ppu::PPU;
+use crate::gb::timer::Timer;
+use crate::gb::{AddressSpace, Screen, CYCLES_PER_FRAME, SCREEN_HEIGHT};
+use std::cell::{Cell, RefCell};
+
+/// Screen which only provides the buttons set with `GameBoy::set_input`,
+/// the frame is read from the PPU.
+struct InputScreen<'a> {
+    input: &'a Cell<Buttons>,
+}
+
+impl<'a> Screen for InputScreen<'a> {
+    fn write_pixel(&mut self, _x: u8, _y: u8, _value: Color) {}
+
+    fn render_screen(&mut self) {}
+
+    fn pressed_buttons(&self) -> Buttons {
+        self.input.get()
+    }
+}
+
+/// A Game Boy without any output device for embedding the emulator core,
+/// e.g. in fuzzers or alternative frontends.
+pub struct GameBoy<'a> {
+    bus: &'a RefCell<MemoryBus>,
+    cpu: &'a RefCell<CPU<'a, MemoryBus>>,
+    ppu: PPU<'a>,
+    timer: Timer<'a>,
+    irq_handler: IRQHandler<'a, MemoryBus>,
+    input: &'a Cell<Buttons>,
+    frames: u64,
+    /// Cycles the last frame ran past its end, they are deducted from the next one
+    overshoot: u32,
+}
+
+impl<'a> GameBoy<'a> {
+    /// Inserts the given ROM and passes the Game Boy to `f`. The components
+    /// borrow the shared memory bus, hence the Game Boy only lives within `f`.
+    pub fn run<R, F: for<'b> FnOnce(&mut GameBoy<'b>) -> R>(rom: Vec<u8>, f: F) -> R {
+        let bus = RefCell::new(MemoryBus::new(Cartridge::from_bytes(rom)));
+        let input = Cell::new(Buttons::empty());
+        let mut screen = InputScreen { input: &input };
+        let cpu = RefCell::new(CPU::new(&bus));
+        let mut game_boy = GameBoy {
+            bus: &bus,
+            cpu: &cpu,
+            ppu: PPU::new(&bus, &mut screen),
+            timer: Timer::new(&bus),
+            irq_handler: IRQHandler::new(&cpu, &bus),
+            input: &input,
+            frames: 0,
+            overshoot: 0,
+        };
+        f(&mut game_boy)
+    }
+
+    /// Emulates until the next frame is complete, if the LCD is off
+    /// this returns after the duration of a frame.
+    pub fn step_frame(&mut self) {
+        let mut cycles = 0;
+        while cycles < CYCLES_PER_FRAME {
+            let ly = self.bus.borrow().read(PPU_LY);
+            cycles += self.step();
+            if ly < SCREEN_HEIGHT && self.bus.borrow().read(PPU_LY) >= SCREEN_HEIGHT {
+                break;
+            }
+        }
+        self.frames += 1;
+    }
+
+    /// Emulates exactly one frame worth of cycles regardless of the PPU state
+    /// and returns the framebuffer. Instructions can run past the end of a frame,
+    /// these cycles are deducted from the next frame so `n` frames always take
+    /// `n * CYCLES_PER_FRAME` cycles, which makes the output reproducible.
+    pub fn run_frame(&mut self) -> &[Color] {
+        let target = CYCLES_PER_FRAME.saturating_sub(self.overshoot);
+        let mut cycles = 0;
+        while cycles < target {
+            cycles += self.step();
+        }
+        self.overshoot = cycles - target;
+        self.frames += 1;
+        self.ppu.framebuffer()
+    }
+
+    /// Executes a single instruction and returns the consumed cycles
+    fn step(&mut self) -> u32 {
+        let cycles = self.cpu.borrow_mut().step();
+        self.timer.step(cycles);
+        self.ppu.step(cycles);
+        self.irq_handler.handle();
+        cycles
+    }
+
+    /// Returns the last complete frame
+    pub fn framebuffer(&self) -> &[Color] {
+        self.ppu.framebuffer()
+    }
+
+    /// Sets the pressed buttons, the game sees them from the next frame on
+    pub fn set_input(&mut self, buttons: Buttons) {
+        self.input.set(buttons);
+    }
+
+    /// Reads a byte from the memory bus
+    pub fn read_memory(&self, address: u16) -> u8 {
+        self.bus.borrow().read(address)
+    }
+
+    /// Returns the number of emulated frames
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gb::memory::constants::{JOYPAD, PPU_BGP, PPU_LCDC};
+
+    #[test]
+    fn test_game_boy() {
+        // An empty ROM with a valid header checksum
+        let mut rom = vec![0; 0x8000];
+        rom[0x14D] = 0xE7;
+        GameBoy::run(rom, |game_boy| {
+            // The LCD is off, a frame ends after its duration
+            game_boy.step_frame();
+            assert_eq!(game_boy.read_memory(PPU_LY), 0);
+
+            game_boy.bus.borrow_mut().write(PPU_LCDC, 0x91);
+            game_boy.step_frame();
+            assert_eq!(game_boy.read_memory(PPU_LY), SCREEN_HEIGHT);
+            assert_eq!(game_boy.framebuffer().len(), 160 * 144);
+
+            game_boy.bus.borrow_mut().write(JOYPAD, 0x10);
+            game_boy.set_input(Buttons::A);
+            game_boy.step_frame();
+            assert_eq!(game_boy.read_memory(JOYPAD) & 0x01, 0);
+            assert_eq!(game_boy.frames(), 3);
+        });
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x14D] = 0xE7;
+        let run = |frames| {
+            GameBoy::run(rom.clone(), |game_boy| {
+                game_boy.bus.borrow_mut().write(PPU_LCDC, 0x91);
+                // Color 0 of the empty tiles is mapped to black
+                game_boy.bus.borrow_mut().write(PPU_BGP, 0xFF);
+                for _ in 1..frames {
+                    game_boy.run_frame();
+                }
+                let frame = game_boy.run_frame().to_vec();
+                (frame, game_boy.read_memory(PPU_LY), game_boy.overshoot)
+            })
+        };
+
+        let (frame, ly, overshoot) = run(3);
+        assert!(frame.iter().all(|color| *color == Color::Black));
+        assert!(overshoot < 24);
+        assert_eq!(run(3), (frame, ly, overshoot));
+        // Every frame takes the same amount of cycles, so the PPU is at the same line
+        assert_eq!(run(2).1, ly);
+    }
+}
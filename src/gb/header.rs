@@ -0,0 +1,220 @@
+use crate::gb::cartridge::Metadata;
+use std::fmt;
+
+/// Header area which is covered by the header checksum
+const CHECKSUM_BEGIN: usize = 0x0134;
+const CHECKSUM_END: usize = 0x014C;
+const HEADER_END: usize = 0x0150;
+/// Old licensee code which indicates that the new licensee code is used
+const USE_NEW_LICENSEE: u8 = 0x33;
+
+/// All fields of the cartridge header at 0x0100-0x014F,
+/// including the ones which aren't needed for emulation.
+#[derive(Debug)]
+pub struct Header {
+    pub title: String,
+    pub new_licensee: [u8; 2],
+    pub sgb_flag: u8,
+    pub cgb_flag: u8,
+    pub cartridge_type: u8,
+    pub rom_size: u8,
+    pub ram_size: u8,
+    pub destination: u8,
+    pub old_licensee: u8,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    /// Checksums computed from the ROM
+    pub computed_header_checksum: u8,
+    pub computed_global_checksum: u16,
+    /// Size of the ROM file in bytes
+    pub file_size: usize,
+}
+
+impl Header {
+    /// Parses the header of the given ROM, fails if the ROM is too small to contain one
+    pub fn parse(rom: &[u8]) -> Result<Self, String> {
+        if rom.len() < HEADER_END {
+            return Err(format!("The ROM is too small: {} bytes", rom.len()));
+        }
+        let global_checksum = u16::from_be_bytes([rom[0x014E], rom[0x014F]]);
+        Ok(Self {
+            title: String::from_utf8_lossy(&rom[0x0134..=0x0143])
+                .trim_end_matches('\0')
+                .to_string(),
+            new_licensee: [rom[0x0144], rom[0x0145]],
+            sgb_flag: rom[0x0146],
+            cgb_flag: rom[0x0143],
+            cartridge_type: rom[0x0147],
+            rom_size: rom[0x0148],
+            ram_size: rom[0x0149],
+            destination: rom[0x014A],
+            old_licensee: rom[0x014B],
+            version: rom[0x014C],
+            header_checksum: rom[0x014D],
+            global_checksum,
+            computed_header_checksum: rom[CHECKSUM_BEGIN..=CHECKSUM_END]
+                .iter()
+                .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1)),
+            // The global checksum excludes its own bytes
+            computed_global_checksum: rom
+                .iter()
+                .fold(0u16, |sum, byte| sum.wrapping_add(u16::from(*byte)))
+                .wrapping_sub(u16::from(rom[0x014E]) + u16::from(rom[0x014F])),
+            file_size: rom.len(),
+        })
+    }
+
+    /// Returns the ROM size in bytes declared by the header
+    pub fn declared_rom_size(&self) -> Option<usize> {
+        match self.rom_size {
+            size @ 0x00..=0x08 => Some(0x8000 << size),
+            _ => None,
+        }
+    }
+
+    /// Returns the licensee code, either the old one or the new ASCII one
+    pub fn licensee(&self) -> String {
+        match self.old_licensee {
+            USE_NEW_LICENSEE => String::from_utf8_lossy(&self.new_licensee).to_string(),
+            code => format!("{:#04X}", code),
+        }
+    }
+
+    /// Returns problems of the header, e.g. mismatching checksums or ROM sizes
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.header_checksum != self.computed_header_checksum {
+            warnings.push("Bad header checksum, the boot ROM would lock up".to_string());
+        }
+        if self.global_checksum != self.computed_global_checksum {
+            warnings.push("Bad global checksum, the ROM may be modified".to_string());
+        }
+        match self.declared_rom_size() {
+            Some(size) if size < self.file_size => {
+                warnings.push(format!("Overdump, the header declares {} bytes", size))
+            }
+            Some(size) if size > self.file_size => {
+                warnings.push(format!("Underdump, the header declares {} bytes", size))
+            }
+            Some(_) => {}
+            None => warnings.push(format!("Unknown ROM size: {:#04X}", self.rom_size)),
+        }
+        warnings
+    }
+}
+
+/// Returns the name of the memory bank controller and the additional hardware
+fn cartridge_type_name(cartridge_type: u8) -> &'static str {
+    match cartridge_type {
+        0x00 => "ROM ONLY",
+        0x01 => "MBC1",
+        0x02 => "MBC1+RAM",
+        0x03 => "MBC1+RAM+BATTERY",
+        0x05 => "MBC2",
+        0x06 => "MBC2+BATTERY",
+        0x08 => "ROM+RAM",
+        0x09 => "ROM+RAM+BATTERY",
+        0x0B => "MMM01",
+        0x0C => "MMM01+RAM",
+        0x0D => "MMM01+RAM+BATTERY",
+        0x0F => "MBC3+TIMER+BATTERY",
+        0x10 => "MBC3+TIMER+RAM+BATTERY",
+        0x11 => "MBC3",
+        0x12 => "MBC3+RAM",
+        0x13 => "MBC3+RAM+BATTERY",
+        0x19 => "MBC5",
+        0x1A => "MBC5+RAM",
+        0x1B => "MBC5+RAM+BATTERY",
+        0x1C => "MBC5+RUMBLE",
+        0x1D => "MBC5+RUMBLE+RAM",
+        0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+        0x20 => "MBC6",
+        0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+        0xFC => "POCKET CAMERA",
+        0xFD => "BANDAI TAMA5",
+        0xFE => "HuC3",
+        0xFF => "HuC1+RAM+BATTERY",
+        _ => "Unknown",
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let supported = Metadata::is_supported(self.cartridge_type);
+        writeln!(f, "Title:           {}", self.title)?;
+        writeln!(
+            f,
+            "Cartridge type:  {:#04X} {}{}",
+            self.cartridge_type,
+            cartridge_type_name(self.cartridge_type),
+            if supported { "" } else { " (unsupported)" }
+        )?;
+        match self.declared_rom_size() {
+            Some(size) => writeln!(f, "ROM size:        {} KiB", size / 1024)?,
+            None => writeln!(f, "ROM size:        unknown ({:#04X})", self.rom_size)?,
+        }
+        writeln!(f, "File size:       {} KiB", self.file_size / 1024)?;
+        writeln!(
+            f,
+            "RAM size:        {} KiB",
+            Metadata::parse_ram_size(self.ram_size) / 1024
+        )?;
+        let cgb = match self.cgb_flag {
+            0x80 => "supported",
+            0xC0 => "required",
+            _ => "no",
+        };
+        writeln!(f, "CGB:             {} ({:#04X})", cgb, self.cgb_flag)?;
+        let sgb = match self.sgb_flag {
+            0x03 => "supported",
+            _ => "no",
+        };
+        writeln!(f, "SGB:             {} ({:#04X})", sgb, self.sgb_flag)?;
+        let destination = match self.destination {
+            0x00 => "Japan",
+            _ => "Overseas",
+        };
+        writeln!(f, "Destination:     {}", destination)?;
+        writeln!(f, "Licensee:        {}", self.licensee())?;
+        writeln!(f, "Version:         {}", self.version)?;
+        writeln!(
+            f,
+            "Header checksum: {:#04X} (computed {:#04X})",
+            self.header_checksum, self.computed_header_checksum
+        )?;
+        write!(
+            f,
+            "Global checksum: {:#06X} (computed {:#06X})",
+            self.global_checksum, self.computed_global_checksum
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0134..0x013A].copy_from_slice(b"TETRIS");
+        rom[0x014B] = USE_NEW_LICENSEE;
+        rom[0x0144..0x0146].copy_from_slice(b"01");
+        let header = Header::parse(&rom).unwrap();
+        assert_eq!(header.title, "TETRIS");
+        assert_eq!(header.licensee(), "01");
+        assert_eq!(header.declared_rom_size(), Some(0x8000));
+        assert_eq!(header.warnings().len(), 2);
+
+        rom[0x014D] = header.computed_header_checksum;
+        let global = Header::parse(&rom).unwrap().computed_global_checksum;
+        rom[0x014E..0x0150].copy_from_slice(&global.to_be_bytes());
+        assert!(Header::parse(&rom).unwrap().warnings().is_empty());
+
+        // The header declares 32 KiB
+        rom.resize(0x10000, 0);
+        assert_eq!(Header::parse(&rom).unwrap().warnings().len(), 1);
+        assert!(Header::parse(&rom[..0x0100]).is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use crate::gb::joypad::Buttons;
+use crate::gb::ppu::misc::Color;
+use crate::gb::{Screen, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const FRAME_SIZE: usize = SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize;
+
+/// Screen without any output device, it only keeps
+/// the last rendered frame in memory for later inspection.
+pub struct Headless {
+    frame: Vec<Color>,
+    frames: usize,
+}
+
+impl Default for Headless {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Headless {
+    pub fn new() -> Self {
+        Self {
+            frame: vec![Color::White; FRAME_SIZE],
+            frames: 0,
+        }
+    }
+
+    /// Returns the number of frames rendered so far
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Returns the last rendered frame
+    pub fn frame(&self) -> &[Color] {
+        &self.frame
+    }
+
+    /// Returns a FNV-1a hash of the last rendered frame,
+    /// which is stable across runs and platforms.
+    pub fn frame_hash(&self) -> u64 {
+        self.frame
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, color| {
+                (hash ^ u64::from(u8::from(*color))).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+}
+
+impl Screen for Headless {
+    fn write_pixel(&mut self, _x: u8, _y: u8, _value: Color) {}
+
+    fn frame_ready(&mut self, frame: &[Color]) {
+        self.frame.copy_from_slice(frame);
+    }
+
+    fn render_screen(&mut self) {
+        self.frames += 1;
+    }
+
+    fn pressed_buttons(&self) -> Buttons {
+        Buttons::empty()
+    }
+}
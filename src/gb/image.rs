@@ -0,0 +1,143 @@
+use crate::gb::ppu::misc::Color;
+use crate::gb::SCREEN_WIDTH;
+use png::{BitDepth, ColorType, Decoder, Encoder, Transformations};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Grey levels used to store the four shades
+const LEVELS: [u8; 4] = [0xFF, 0xAB, 0x55, 0x00];
+
+/// Writes the given pixels as binary greyscale image (PGM)
+pub fn write_pgm(path: &Path, pixels: &[Color]) -> io::Result<()> {
+    let width = usize::from(SCREEN_WIDTH);
+    let mut data = format!("P5\n{} {}\n255\n", width, pixels.len() / width).into_bytes();
+    data.extend(pixels.iter().map(|color| LEVELS[u8::from(*color) as usize]));
+    fs::write(path, data)
+}
+
+/// Reads the pixels of an image written by `write_pgm`,
+/// grey levels are mapped to the nearest shade.
+pub fn read_pgm(path: &Path) -> io::Result<Vec<Color>> {
+    let data = fs::read(path)?;
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid PGM image");
+
+    // The header consists of 4 whitespace separated fields
+    let mut offset = 0;
+    let mut fields = Vec::new();
+    while fields.len() < 4 {
+        let start = offset;
+        while offset < data.len() && !data[offset].is_ascii_whitespace() {
+            offset += 1;
+        }
+        fields.push(std::str::from_utf8(&data[start..offset]).map_err(|_| invalid())?);
+        offset += 1;
+    }
+    if fields[0] != "P5" || fields[3] != "255" {
+        return Err(invalid());
+    }
+    let width: usize = fields[1].parse().map_err(|_| invalid())?;
+    let height: usize = fields[2].parse().map_err(|_| invalid())?;
+    let pixels = data
+        .get(offset..offset + width * height)
+        .ok_or_else(invalid)?;
+
+    Ok(pixels.iter().map(|level| nearest_shade(*level)).collect())
+}
+
+/// Writes the given pixels as greyscale PNG
+pub fn write_png(path: &Path, pixels: &[Color]) -> io::Result<()> {
+    write_png_with_width(path, pixels, usize::from(SCREEN_WIDTH))
+}
+
+/// Writes the given pixels as greyscale PNG with the given number of pixels per row
+pub fn write_png_with_width(path: &Path, pixels: &[Color], width: usize) -> io::Result<()> {
+    encode_png(BufWriter::new(File::create(path)?), pixels, width)
+}
+
+/// Encodes the given pixels as greyscale PNG with the given number of pixels per row
+pub fn encode_png<W: Write>(writer: W, pixels: &[Color], width: usize) -> io::Result<()> {
+    let mut encoder = Encoder::new(writer, width as u32, (pixels.len() / width) as u32);
+    encoder.set_color(ColorType::Grayscale);
+    encoder.set_depth(BitDepth::Eight);
+    let data = pixels
+        .iter()
+        .map(|color| LEVELS[u8::from(*color) as usize])
+        .collect::<Vec<u8>>();
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
+
+/// Reads the pixels of a PNG image, the brightness
+/// of each pixel is mapped to the nearest shade.
+pub fn read_png(path: &Path) -> io::Result<Vec<Color>> {
+    let mut decoder = Decoder::new(File::open(path)?);
+    // Converts palettes and low bit depths to 8 bit samples
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut data = vec![0u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data)?;
+    if info.width != u32::from(SCREEN_WIDTH) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Expected an image width of {}, got {}",
+                SCREEN_WIDTH, info.width
+            ),
+        ));
+    }
+
+    let (samples, colors) = match info.color_type {
+        ColorType::Grayscale => (1, 1),
+        ColorType::GrayscaleAlpha => (2, 1),
+        ColorType::Rgb => (3, 3),
+        _ => (4, 3),
+    };
+    Ok(data[..info.buffer_size()]
+        .chunks(samples)
+        .map(|pixel| {
+            let sum: u32 = pixel[..colors].iter().map(|c| u32::from(*c)).sum();
+            nearest_shade((sum / colors as u32) as u8)
+        })
+        .collect())
+}
+
+/// Maps a grey level to the nearest shade
+fn nearest_shade(level: u8) -> Color {
+    let shade = (0..LEVELS.len())
+        .min_by_key(|i| (i16::from(LEVELS[*i]) - i16::from(level)).abs())
+        .unwrap();
+    Color::from(shade as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_pgm_round_trip() {
+        let pixels = (0..usize::from(SCREEN_WIDTH) * 2)
+            .map(|i| Color::from((i % 4) as u8))
+            .collect::<Vec<Color>>();
+        let path = env::temp_dir().join("romoulade-test-round-trip.pgm");
+        write_pgm(&path, &pixels).unwrap();
+        let result = read_pgm(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(result.unwrap(), pixels);
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let pixels = (0..usize::from(SCREEN_WIDTH) * 2)
+            .map(|i| Color::from((i % 4) as u8))
+            .collect::<Vec<Color>>();
+        let path = env::temp_dir().join("romoulade-test-round-trip.png");
+        write_png(&path, &pixels).unwrap();
+        let result = read_png(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(result.unwrap(), pixels);
+    }
+}
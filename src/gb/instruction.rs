@@ -1,7 +1,7 @@
 use crate::gb::cpu::CPU;
 use crate::gb::AddressSpace;
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum Instruction {
     ADD(ByteSource),          // Add n to target
     ADDHL(WordSource),        // Add nn to HL
@@ -52,16 +52,28 @@ pub enum Instruction {
     XOR(ByteSource), // Logical exclusive OR n with register A, result in A
 }
 
+/// Instructions of all opcodes decoded at compile time, prefixed opcodes start at index 256
+static DECODED: [Option<Instruction>; 512] = Instruction::decode_all();
+
 impl Instruction {
     pub fn from_byte(byte: u8, prefixed: bool) -> Option<Instruction> {
-        match prefixed {
-            true => Instruction::from_byte_prefixed(byte),
-            false => Instruction::from_byte_not_prefixed(byte),
+        DECODED[usize::from(prefixed) << 8 | usize::from(byte)]
+    }
+
+    /// Decodes every opcode, so executing an instruction only needs a table lookup
+    const fn decode_all() -> [Option<Instruction>; 512] {
+        let mut decoded = [None; 512];
+        let mut opcode = 0;
+        while opcode < 256 {
+            decoded[opcode] = Instruction::from_byte_not_prefixed(opcode as u8);
+            decoded[256 + opcode] = Instruction::from_byte_prefixed(opcode as u8);
+            opcode += 1;
         }
+        decoded
     }
 
     /// Maps 0xCB prefixed opcodes to Instructions
-    fn from_byte_prefixed(opcode: u8) -> Option<Instruction> {
+    const fn from_byte_prefixed(opcode: u8) -> Option<Instruction> {
         match opcode {
             0x00 => Some(Instruction::RLC(ByteSource::B)),
             0x01 => Some(Instruction::RLC(ByteSource::C)),
@@ -354,7 +366,7 @@ impl Instruction {
     }
 
     /// Maps non-prefixed opcodes to Instructions
-    fn from_byte_not_prefixed(opcode: u8) -> Option<Instruction> {
+    const fn from_byte_not_prefixed(opcode: u8) -> Option<Instruction> {
         match opcode {
             0x00 => Some(Instruction::NOP),
             0x01 => Some(Instruction::LD(Load::Word(
@@ -811,7 +823,7 @@ impl Instruction {
             0xc8 => Some(Instruction::RET(JumpTest::Zero)),
             0xc9 => Some(Instruction::RET(JumpTest::Always)),
             0xca => Some(Instruction::JP(JumpTest::Zero, WordSource::D16)),
-            0xcb => panic!("CB Prefix"),
+            0xcb => None, // Prefix of the CB instructions, decoded with the next byte
             0xcc => Some(Instruction::CALL(JumpTest::Zero)),
             0xcd => Some(Instruction::CALL(JumpTest::Always)),
             0xce => Some(Instruction::ADC(ByteSource::D8)),
@@ -892,7 +904,194 @@ impl Instruction {
     }
 }
 
-#[derive(Debug)]
+/// Operand semantics, flag effects and cycle count of an instruction
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Reference {
+    pub description: &'static str,
+    /// Effect on the Z, N, H and C flags: `-` unchanged, `0` reset, `1` set or the affected flag
+    pub flags: &'static str,
+    /// Cycles if a conditional branch is not taken
+    pub cycles: u8,
+    /// Cycles if a conditional branch is taken
+    pub cycles_taken: Option<u8>,
+}
+
+impl Reference {
+    fn new(description: &'static str, flags: &'static str, cycles: u8) -> Self {
+        Self {
+            description,
+            flags,
+            cycles,
+            cycles_taken: None,
+        }
+    }
+
+    fn branch(self, test: &JumpTest, cycles_taken: u8) -> Self {
+        match test {
+            JumpTest::Always => Self {
+                cycles: cycles_taken,
+                ..self
+            },
+            _ => Self {
+                cycles_taken: Some(cycles_taken),
+                ..self
+            },
+        }
+    }
+}
+
+impl Instruction {
+    /// Returns the reference of the decoded instruction,
+    /// cycles are verified against the CPU for every opcode.
+    pub fn reference(&self) -> Reference {
+        match self {
+            Instruction::ADD(source) => Reference::new("Add n to A", "Z0HC", 4 + source.cycles()),
+            Instruction::ADDHL(_) => Reference::new("Add nn to HL", "-0HC", 8),
+            Instruction::ADDSP => Reference::new("Add signed immediate to SP", "00HC", 16),
+            Instruction::ADC(source) => {
+                Reference::new("Add n + Carry flag to A", "Z0HC", 4 + source.cycles())
+            }
+            Instruction::AND(source) => Reference::new(
+                "Logical AND n with A, result in A",
+                "Z010",
+                4 + source.cycles(),
+            ),
+            Instruction::BIT(_, source) => Reference::new(
+                "Test bit b in n, Z is set if the bit is 0",
+                "Z01-",
+                8 + source.cycles(),
+            ),
+            Instruction::INC(target) => Reference::new("Increment n", "Z0H-", 4 + target.cycles()),
+            Instruction::INC2(_) => Reference::new("Increment nn", "----", 8),
+            Instruction::CALL(test) => Reference::new(
+                "Push address of next instruction and jump to nn",
+                "----",
+                12,
+            )
+            .branch(test, 24),
+            Instruction::CCF => Reference::new("Complement Carry flag", "-00C", 4),
+            Instruction::CP(source) => Reference::new(
+                "Compare A with n by subtracting without storing the result",
+                "Z1HC",
+                4 + source.cycles(),
+            ),
+            Instruction::CPL => Reference::new("Flip all bits in A", "-11-", 4),
+            Instruction::DAA => Reference::new(
+                "Adjust A to BCD after an addition or subtraction",
+                "Z-0C",
+                4,
+            ),
+            Instruction::DI => Reference::new("Disable interrupts", "----", 4),
+            Instruction::DEC(target) => Reference::new("Decrement n", "Z1H-", 4 + target.cycles()),
+            Instruction::DEC2(_) => Reference::new("Decrement nn", "----", 8),
+            Instruction::EI => {
+                Reference::new("Enable interrupts after the next instruction", "----", 4)
+            }
+            Instruction::HALT => Reference::new("Halt until an interrupt is pending", "----", 4),
+            Instruction::JR(test) => {
+                Reference::new("Relative jump by signed immediate", "----", 8).branch(test, 12)
+            }
+            Instruction::JP(_, WordSource::HL) => Reference::new("Jump to HL", "----", 4),
+            Instruction::JP(test, _) => Reference::new("Jump to nn", "----", 12).branch(test, 16),
+            Instruction::LD(load) => load.reference(),
+            Instruction::NOP => Reference::new("No operation", "----", 4),
+            Instruction::OR(source) => Reference::new(
+                "Logical OR n with A, result in A",
+                "Z000",
+                4 + source.cycles(),
+            ),
+            Instruction::PUSH(_) => Reference::new("Push nn onto the stack", "----", 16),
+            Instruction::POP(StackTarget::AF) => {
+                Reference::new("Pop AF from the stack", "ZNHC", 12)
+            }
+            Instruction::POP(_) => Reference::new("Pop nn from the stack", "----", 12),
+            Instruction::RES(_, source) => {
+                Reference::new("Reset bit b in n", "----", 8 + 2 * source.cycles())
+            }
+            Instruction::RET(JumpTest::Always) => {
+                Reference::new("Pop address from the stack and jump to it", "----", 16)
+            }
+            Instruction::RET(test) => {
+                Reference::new("Pop address from the stack and jump to it", "----", 8)
+                    .branch(test, 20)
+            }
+            Instruction::RETI => Reference::new("Return and enable interrupts", "----", 16),
+            Instruction::RL(source) => Reference::new(
+                "Rotate n left through Carry flag",
+                "Z00C",
+                8 + 2 * source.cycles(),
+            ),
+            Instruction::RLA => Reference::new("Rotate A left through Carry flag", "000C", 4),
+            Instruction::RLC(source) => Reference::new(
+                "Rotate n left, old bit 7 to Carry flag",
+                "Z00C",
+                8 + 2 * source.cycles(),
+            ),
+            Instruction::RLCA => {
+                Reference::new("Rotate A left, old bit 7 to Carry flag", "000C", 4)
+            }
+            Instruction::RR(source) => Reference::new(
+                "Rotate n right through Carry flag",
+                "Z00C",
+                8 + 2 * source.cycles(),
+            ),
+            Instruction::RRA => Reference::new("Rotate A right through Carry flag", "000C", 4),
+            Instruction::RRC(source) => Reference::new(
+                "Rotate n right, old bit 0 to Carry flag",
+                "Z00C",
+                8 + 2 * source.cycles(),
+            ),
+            Instruction::RRCA => {
+                Reference::new("Rotate A right, old bit 0 to Carry flag", "000C", 4)
+            }
+            Instruction::RST(_) => {
+                Reference::new("Push present address and jump to the vector", "----", 16)
+            }
+            Instruction::SBC(source) => Reference::new(
+                "Subtract n + Carry flag from A",
+                "Z1HC",
+                4 + source.cycles(),
+            ),
+            Instruction::SCF => Reference::new("Set Carry flag", "-001", 4),
+            Instruction::SET(_, source) => {
+                Reference::new("Set bit b in n", "----", 8 + 2 * source.cycles())
+            }
+            Instruction::SLA(source) => Reference::new(
+                "Shift n left into Carry flag, bit 0 is reset",
+                "Z00C",
+                8 + 2 * source.cycles(),
+            ),
+            Instruction::SRA(source) => Reference::new(
+                "Shift n right into Carry flag, bit 7 is kept",
+                "Z00C",
+                8 + 2 * source.cycles(),
+            ),
+            Instruction::SRL(source) => Reference::new(
+                "Shift n right into Carry flag, bit 7 is reset",
+                "Z00C",
+                8 + 2 * source.cycles(),
+            ),
+            Instruction::SUB(source) => {
+                Reference::new("Subtract n from A", "Z1HC", 4 + source.cycles())
+            }
+            Instruction::STOP => {
+                Reference::new("Halt CPU and LCD until a button is pressed", "----", 4)
+            }
+            Instruction::SWAP(source) => Reference::new(
+                "Swap upper and lower nibbles of n",
+                "Z000",
+                8 + 2 * source.cycles(),
+            ),
+            Instruction::XOR(source) => Reference::new(
+                "Logical XOR n with A, result in A",
+                "Z000",
+                4 + source.cycles(),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
 pub enum IncDecByteTarget {
     A,
     B,
@@ -905,6 +1104,14 @@ pub enum IncDecByteTarget {
 }
 
 impl IncDecByteTarget {
+    /// Additional cycles to read the value
+    fn cycles(&self) -> u8 {
+        match *self {
+            IncDecByteTarget::HLI => 8,
+            _ => 0,
+        }
+    }
+
     /// Resolves the referring value
     pub fn read<T: AddressSpace>(&self, cpu: &mut CPU<T>) -> u8 {
         match *self {
@@ -934,7 +1141,7 @@ impl IncDecByteTarget {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum IncDecWordTarget {
     BC,
     DE,
@@ -964,7 +1171,7 @@ impl IncDecWordTarget {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum JumpTest {
     NotZero,
     Zero,
@@ -986,7 +1193,7 @@ impl JumpTest {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum LoadByteTarget {
     A,
     B,
@@ -1003,7 +1210,7 @@ pub enum LoadByteTarget {
     D8IFF00, // value refers to address stored in next 8 bits | 0xFF00
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ByteSource {
     A,
     B,
@@ -1022,6 +1229,17 @@ pub enum ByteSource {
 }
 
 impl ByteSource {
+    /// Additional cycles to fetch the immediate value or read the referred value
+    fn cycles(&self) -> u8 {
+        match *self {
+            ByteSource::D8 | ByteSource::BCI | ByteSource::DEI | ByteSource::HLI => 4,
+            ByteSource::CIFF00 => 4,
+            ByteSource::D8IFF00 => 8,
+            ByteSource::D16I => 12,
+            _ => 0,
+        }
+    }
+
     /// Resolves the referring value
     pub fn read<T: AddressSpace>(&self, cpu: &mut CPU<T>) -> u8 {
         match *self {
@@ -1066,7 +1284,20 @@ impl ByteSource {
     }
 }
 
-#[derive(Debug)]
+impl LoadByteTarget {
+    /// Additional cycles to fetch the address and write the value
+    fn cycles(&self) -> u8 {
+        match *self {
+            LoadByteTarget::BCI | LoadByteTarget::DEI | LoadByteTarget::HLI => 4,
+            LoadByteTarget::CIFF00 => 4,
+            LoadByteTarget::D8IFF00 => 8,
+            LoadByteTarget::D16I => 12,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
 pub enum LoadWordTarget {
     BC,
     DE,
@@ -1075,7 +1306,7 @@ pub enum LoadWordTarget {
     D16I, // value refers to address stored in next 16 bits
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum WordSource {
     BC,
     DE,
@@ -1097,7 +1328,7 @@ impl WordSource {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum Load {
     Byte(LoadByteTarget, ByteSource),
     Word(LoadWordTarget, WordSource), // just like the Byte type except with 16-bit values
@@ -1110,7 +1341,37 @@ pub enum Load {
     IndirectFromSPi8(LoadWordTarget), // Put SP plus 8 bit immediate value into target.
 }
 
-#[derive(Debug)]
+impl Load {
+    fn reference(&self) -> Reference {
+        match self {
+            Load::Byte(target, source) | Load::IndirectFrom(target, source) => Reference::new(
+                "Load n into target",
+                "----",
+                4 + target.cycles() + source.cycles(),
+            ),
+            Load::Word(_, WordSource::D16) => Reference::new("Load nn into target", "----", 12),
+            Load::Word(_, _) => Reference::new("Load nn into target", "----", 8),
+            Load::IndirectFromAInc(_) => {
+                Reference::new("Load A into (HL), increment HL", "----", 8)
+            }
+            Load::IndirectFromADec(_) => {
+                Reference::new("Load A into (HL), decrement HL", "----", 8)
+            }
+            Load::FromIndirectAInc(_) => {
+                Reference::new("Load (HL) into A, increment HL", "----", 8)
+            }
+            Load::FromIndirectADec(_) => {
+                Reference::new("Load (HL) into A, decrement HL", "----", 8)
+            }
+            Load::IndirectFromWord(_, _) => Reference::new("Load SP into (nn)", "----", 20),
+            Load::IndirectFromSPi8(_) => {
+                Reference::new("Load SP plus signed immediate into HL", "00HC", 12)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
 pub enum StackTarget {
     AF,
     BC,
@@ -1118,7 +1379,7 @@ pub enum StackTarget {
     HL,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum ResetCode {
     RST00,
     RST08,
@@ -12,15 +12,30 @@ pub enum IRQ {
     VBlank = 0,
     LCD = 1,
     Timer = 2,
+    Serial = 3,
     Joypad = 4,
 }
 
+impl IRQ {
+    /// Returns the address of the interrupt handler
+    pub fn vector(self) -> u16 {
+        match self {
+            IRQ::VBlank => 0x40,
+            IRQ::LCD => 0x48,
+            IRQ::Timer => 0x50,
+            IRQ::Serial => 0x58,
+            IRQ::Joypad => 0x60,
+        }
+    }
+}
+
 impl convert::From<u8> for IRQ {
     fn from(value: u8) -> Self {
         match value {
             0 => IRQ::VBlank,
             1 => IRQ::LCD,
             2 => IRQ::Timer,
+            3 => IRQ::Serial,
             4 => IRQ::Joypad,
             _ => panic!(),
         }
@@ -33,6 +48,7 @@ impl convert::From<IRQ> for u8 {
             IRQ::VBlank => 0,
             IRQ::LCD => 1,
             IRQ::Timer => 2,
+            IRQ::Serial => 3,
             IRQ::Joypad => 4,
         }
     }
@@ -49,44 +65,51 @@ impl<'a, T: AddressSpace> IRQHandler<'a, T> {
     }
 
     /// Handles pending interrupt requests
-    /// TODO: implement HALT instruction bug (Section 4.10): https://github.com/AntonioND/giibiiadvance/blob/master/docs/TCAGBD.pdf
-    pub fn handle(&mut self) {
-        let requests = self.read(INTERRUPT_FLAG);
-        if requests == 0 {
-            return;
+    /// and returns the interrupt which has been serviced, if any.
+    pub fn handle(&mut self) -> Option<IRQ> {
+        if self.pending() == 0 {
+            return None;
         }
-
-        let enabled = self.read(INTERRUPT_ENABLE);
-        for i in 0..5 {
-            if utils::bit_at(requests, i) && utils::bit_at(enabled, i) {
-                // Only serve interrupt if IME is enabled
-                if self.cpu.borrow().ime {
-                    self.service_interrupts(IRQ::from(i));
-                }
-                // CPU should be always woken up from HALT
-                self.cpu.borrow_mut().is_halted = false;
-            }
+        // CPU should be always woken up from HALT
+        self.cpu.borrow_mut().wake();
+        // Only serve interrupt if IME is enabled
+        if !self.cpu.borrow().ime {
+            return None;
         }
+        self.service_interrupt()
     }
 
-    fn service_interrupts(&mut self, interrupt: IRQ) {
-        // TODO: debug log: println!("Serving interrupt: {:?}...", interrupt);
-        self.cpu.borrow_mut().ime = false;
-
-        // Clear interrupt request
-        let req = utils::set_bit(self.read(INTERRUPT_FLAG), u8::from(interrupt), false);
-        self.write(INTERRUPT_FLAG, req);
+    /// Returns the requested interrupts which are enabled
+    fn pending(&self) -> u8 {
+        self.read(INTERRUPT_FLAG) & self.read(INTERRUPT_ENABLE) & 0x1F
+    }
 
-        // Save current execution address by pushing it onto the stack
-        let pc = self.cpu.borrow().pc;
-        self.cpu.borrow_mut().push(pc);
+    /// Pushes the current execution address and jumps to the pending interrupt
+    /// with the highest priority. The interrupt is chosen after the high byte has
+    /// been pushed, if that write to IE disables all pending interrupts the dispatch
+    /// is cancelled and execution continues at 0x0000.
+    fn service_interrupt(&mut self) -> Option<IRQ> {
+        let (pc, sp) = {
+            let mut cpu = self.cpu.borrow_mut();
+            cpu.ime = false;
+            (cpu.return_address(), cpu.sp)
+        };
+        self.write(sp.wrapping_sub(1), (pc >> 8) as u8);
+        let pending = self.pending();
+        self.write(sp.wrapping_sub(2), pc as u8);
+        self.cpu.borrow_mut().sp = sp.wrapping_sub(2);
 
-        match interrupt {
-            IRQ::VBlank => self.cpu.borrow_mut().pc = 0x40,
-            IRQ::LCD => self.cpu.borrow_mut().pc = 0x48,
-            IRQ::Timer => self.cpu.borrow_mut().pc = 0x50,
-            IRQ::Joypad => self.cpu.borrow_mut().pc = 0x60,
-        }
+        let interrupt = (0..5).find(|i| utils::bit_at(pending, *i)).map(IRQ::from);
+        self.cpu.borrow_mut().pc = match interrupt {
+            Some(interrupt) => {
+                // Clear interrupt request
+                let req = utils::set_bit(self.read(INTERRUPT_FLAG), u8::from(interrupt), false);
+                self.write(INTERRUPT_FLAG, req);
+                interrupt.vector()
+            }
+            None => 0x0000,
+        };
+        interrupt
     }
 }
 
@@ -99,3 +122,136 @@ impl<'a, T: AddressSpace> AddressSpace for IRQHandler<'a, T> {
         self.bus.borrow().read(address)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Flat 64KB memory, the interrupt registers are regular bytes
+    struct MockBus {
+        data: Vec<u8>,
+    }
+
+    impl AddressSpace for MockBus {
+        fn write(&mut self, address: u16, value: u8) {
+            self.data[address as usize] = value;
+        }
+
+        fn read(&self, address: u16) -> u8 {
+            self.data[address as usize]
+        }
+    }
+
+    fn bus(program: &[u8]) -> RefCell<MockBus> {
+        let mut data = vec![0; 0x10000];
+        data[..program.len()].copy_from_slice(program);
+        RefCell::new(MockBus { data })
+    }
+
+    #[test]
+    fn test_halt_ime1() {
+        // HALT; NOP
+        let bus = bus(&[0x76, 0x00]);
+        let cpu = RefCell::new(CPU::new(&bus));
+        cpu.borrow_mut().sp = 0xFFFE;
+        let mut irq_handler = IRQHandler::new(&cpu, &bus);
+        bus.borrow_mut().write(INTERRUPT_ENABLE, 0x04);
+
+        cpu.borrow_mut().step();
+        assert!(irq_handler.handle().is_none());
+        assert_eq!(cpu.borrow_mut().step(), 4);
+        assert!(cpu.borrow().is_halted);
+
+        bus.borrow_mut().write(INTERRUPT_FLAG, 0x04);
+        assert!(matches!(irq_handler.handle(), Some(IRQ::Timer)));
+        assert_eq!(cpu.borrow().pc, 0x50);
+        assert_eq!(bus.borrow().read(0xFFFC), 0x01);
+        assert_eq!(bus.borrow().read(INTERRUPT_FLAG), 0x00);
+        // Leaving HALT takes another 4 cycles
+        assert_eq!(cpu.borrow_mut().step(), 8);
+    }
+
+    #[test]
+    fn test_halt_ime0() {
+        // HALT; INC A
+        let bus = bus(&[0x76, 0x3C]);
+        let cpu = RefCell::new(CPU::new(&bus));
+        cpu.borrow_mut().ime = false;
+        let mut irq_handler = IRQHandler::new(&cpu, &bus);
+        bus.borrow_mut().write(INTERRUPT_ENABLE, 0x04);
+
+        cpu.borrow_mut().step();
+        assert!(cpu.borrow().is_halted);
+        bus.borrow_mut().write(INTERRUPT_FLAG, 0x04);
+        // The CPU wakes up without dispatching the interrupt
+        assert!(irq_handler.handle().is_none());
+        assert!(!cpu.borrow().is_halted);
+        assert_eq!(cpu.borrow_mut().step(), 8);
+        assert_eq!(cpu.borrow().pc, 0x02);
+        assert_eq!(cpu.borrow().r.a, 0x01);
+    }
+
+    #[test]
+    fn test_halt_bug() {
+        // HALT; INC A; NOP
+        let bus = bus(&[0x76, 0x3C, 0x00]);
+        let cpu = RefCell::new(CPU::new(&bus));
+        cpu.borrow_mut().ime = false;
+        bus.borrow_mut().write(INTERRUPT_ENABLE, 0x04);
+        bus.borrow_mut().write(INTERRUPT_FLAG, 0x04);
+
+        cpu.borrow_mut().step();
+        assert!(!cpu.borrow().is_halted);
+        // INC A is read twice
+        cpu.borrow_mut().step();
+        assert_eq!(cpu.borrow().pc, 0x01);
+        cpu.borrow_mut().step();
+        assert_eq!(cpu.borrow().pc, 0x02);
+        assert_eq!(cpu.borrow().r.a, 0x02);
+    }
+
+    #[test]
+    fn test_ei_halt_bug() {
+        // EI; HALT; INC A
+        let bus = bus(&[0xFB, 0x76, 0x3C]);
+        let cpu = RefCell::new(CPU::new(&bus));
+        cpu.borrow_mut().ime = false;
+        cpu.borrow_mut().sp = 0xFFFE;
+        let mut irq_handler = IRQHandler::new(&cpu, &bus);
+        bus.borrow_mut().write(INTERRUPT_ENABLE, 0x04);
+        bus.borrow_mut().write(INTERRUPT_FLAG, 0x04);
+
+        cpu.borrow_mut().step();
+        assert!(irq_handler.handle().is_none());
+        cpu.borrow_mut().step();
+        // The interrupt returns to the HALT instruction
+        assert!(matches!(irq_handler.handle(), Some(IRQ::Timer)));
+        assert_eq!(bus.borrow().read(0xFFFC), 0x01);
+    }
+
+    #[test]
+    fn test_ie_push_cancel() {
+        let bus = bus(&[]);
+        let cpu = RefCell::new(CPU::new(&bus));
+        cpu.borrow_mut().pc = 0x1234;
+        let mut irq_handler = IRQHandler::new(&cpu, &bus);
+        bus.borrow_mut().write(INTERRUPT_ENABLE, 0x01);
+        bus.borrow_mut().write(INTERRUPT_FLAG, 0x01);
+
+        // Pushing the high byte overwrites IE with 0x12 which disables VBlank
+        assert!(irq_handler.handle().is_none());
+        assert_eq!(cpu.borrow().pc, 0x0000);
+        assert_eq!(cpu.borrow().sp, 0xFFFE);
+        assert_eq!(bus.borrow().read(INTERRUPT_FLAG), 0x01);
+
+        // The interrupt is chosen after the push, 0x12 enables LCD
+        cpu.borrow_mut().pc = 0x1234;
+        cpu.borrow_mut().sp = 0x0000;
+        cpu.borrow_mut().ime = true;
+        bus.borrow_mut().write(INTERRUPT_ENABLE, 0x01);
+        bus.borrow_mut().write(INTERRUPT_FLAG, 0x03);
+        assert!(matches!(irq_handler.handle(), Some(IRQ::LCD)));
+        assert_eq!(cpu.borrow().pc, 0x48);
+        assert_eq!(bus.borrow().read(INTERRUPT_FLAG), 0x01);
+    }
+}
@@ -0,0 +1,163 @@
+bitflags! {
+    /// Represents the pressed Game Boy buttons
+    pub struct Buttons: u8 {
+        const RIGHT  = 0b00000001;
+        const LEFT   = 0b00000010;
+        const UP     = 0b00000100;
+        const DOWN   = 0b00001000;
+        const A      = 0b00010000;
+        const B      = 0b00100000;
+        const SELECT = 0b01000000;
+        const START  = 0b10000000;
+    }
+}
+
+/// If bit 4 of the joypad register is low, the direction keys can be read
+const SELECT_DIRECTIONS: u8 = 0b00010000;
+/// If bit 5 of the joypad register is low, the action keys can be read
+const SELECT_ACTIONS: u8 = 0b00100000;
+
+/// Computes the value of the joypad register for the given selection and pressed buttons.
+/// The lower nibble contains the state of the selected buttons, a pressed button reads as 0.
+/// Bits 6 and 7 are unused and always read as 1.
+pub fn register_value(select: u8, buttons: Buttons) -> u8 {
+    let mut pressed = 0;
+    if select & SELECT_DIRECTIONS == 0 {
+        pressed |= buttons.bits & 0x0F;
+    }
+    if select & SELECT_ACTIONS == 0 {
+        pressed |= buttons.bits >> 4;
+    }
+    0xC0 | (select & 0x30) | (!pressed & 0x0F)
+}
+
+/// Defines what a macro is currently doing
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum MacroState {
+    Idle,
+    Recording,
+    Playing(usize),
+}
+
+/// Records the pressed buttons of each frame and replays them frame-exactly.
+/// Buttons which are pressed during playback are combined with the macro.
+pub struct InputMacro {
+    state: MacroState,
+    frames: Vec<Buttons>,
+}
+
+impl Default for InputMacro {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputMacro {
+    pub fn new() -> Self {
+        Self {
+            state: MacroState::Idle,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Starts a new recording or stops the current one
+    pub fn toggle_recording(&mut self) {
+        self.state = match self.state {
+            MacroState::Recording => MacroState::Idle,
+            _ => {
+                self.frames.clear();
+                MacroState::Recording
+            }
+        };
+    }
+
+    /// Replays the recorded macro from the beginning
+    pub fn play(&mut self) {
+        if !self.frames.is_empty() {
+            self.state = MacroState::Playing(0);
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.state == MacroState::Recording
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.state, MacroState::Playing(_))
+    }
+
+    /// Advances by one frame and returns the buttons for it
+    pub fn next_frame(&mut self, pressed: Buttons) -> Buttons {
+        match self.state {
+            MacroState::Idle => pressed,
+            MacroState::Recording => {
+                self.frames.push(pressed);
+                pressed
+            }
+            MacroState::Playing(frame) => {
+                self.state = match frame + 1 < self.frames.len() {
+                    true => MacroState::Playing(frame + 1),
+                    false => MacroState::Idle,
+                };
+                self.frames[frame] | pressed
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_macro_replay() {
+        let mut input_macro = InputMacro::new();
+        input_macro.toggle_recording();
+        input_macro.next_frame(Buttons::A);
+        input_macro.next_frame(Buttons::empty());
+        input_macro.next_frame(Buttons::UP | Buttons::B);
+        input_macro.toggle_recording();
+        assert_eq!(input_macro.next_frame(Buttons::empty()), Buttons::empty());
+
+        input_macro.play();
+        assert_eq!(input_macro.next_frame(Buttons::empty()), Buttons::A);
+        assert_eq!(input_macro.next_frame(Buttons::START), Buttons::START);
+        assert_eq!(
+            input_macro.next_frame(Buttons::empty()),
+            Buttons::UP | Buttons::B
+        );
+        assert!(!input_macro.is_playing());
+        assert_eq!(input_macro.next_frame(Buttons::empty()), Buttons::empty());
+    }
+
+    #[test]
+    fn test_macro_play_without_recording() {
+        let mut input_macro = InputMacro::new();
+        input_macro.play();
+        assert!(!input_macro.is_playing());
+    }
+
+    #[test]
+    fn test_register_value_nothing_selected() {
+        assert_eq!(register_value(0x30, Buttons::all()), 0xFF);
+    }
+
+    #[test]
+    fn test_register_value_directions() {
+        let buttons = Buttons::UP | Buttons::A;
+        assert_eq!(register_value(0x20, buttons), 0xEB);
+    }
+
+    #[test]
+    fn test_register_value_both_selected() {
+        // Both groups are wired to the same lines, pressing either pulls it low
+        let buttons = Buttons::RIGHT | Buttons::B | Buttons::START;
+        assert_eq!(register_value(0x00, buttons), 0xC4);
+    }
+
+    #[test]
+    fn test_register_value_actions() {
+        let buttons = Buttons::UP | Buttons::A | Buttons::START;
+        assert_eq!(register_value(0x10, buttons), 0xD6);
+    }
+}
@@ -0,0 +1,158 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Time spent in each stage between a key press and the presented frame
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Measurement {
+    /// The key event waited in the event queue of the host
+    pub queue: Duration,
+    /// Buttons are only polled once per frame, after the frame limiter
+    pub poll: Duration,
+    /// The next frame is emulated with the new state of the joypad register
+    pub emulation: Duration,
+    /// The frame is drawn and presented
+    pub present: Duration,
+}
+
+impl Measurement {
+    pub fn total(&self) -> Duration {
+        self.queue + self.poll + self.emulation + self.present
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{:.1}ms (queue {:.1}ms, poll {:.1}ms, emulation {:.1}ms, present {:.1}ms)",
+            ms(self.total()),
+            ms(self.queue),
+            ms(self.poll),
+            ms(self.emulation),
+            ms(self.present)
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Stage {
+    Idle,
+    Pressed { queue: Duration, at: Instant },
+    Applied { partial: Measurement, at: Instant },
+    Rendered { partial: Measurement, at: Instant },
+}
+
+/// Follows a key press through the input pipeline until the first frame
+/// emulated with the new joypad state has been presented.
+/// Only one key press is measured at a time.
+pub struct LatencyProbe {
+    stage: Stage,
+    measurements: Vec<Measurement>,
+}
+
+impl Default for LatencyProbe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyProbe {
+    pub fn new() -> Self {
+        Self {
+            stage: Stage::Idle,
+            measurements: Vec::new(),
+        }
+    }
+
+    /// A key event has been taken from the event queue after waiting for `queue`
+    pub fn key_pressed(&mut self, queue: Duration, now: Instant) {
+        if self.stage == Stage::Idle {
+            self.stage = Stage::Pressed { queue, at: now };
+        }
+    }
+
+    /// The pressed buttons have been passed to the joypad register
+    pub fn buttons_applied(&mut self, now: Instant) {
+        if let Stage::Pressed { queue, at } = self.stage {
+            let partial = Measurement {
+                queue,
+                poll: now - at,
+                emulation: Duration::default(),
+                present: Duration::default(),
+            };
+            self.stage = Stage::Applied { partial, at: now };
+        }
+    }
+
+    /// A frame has been emulated, returns whether it's the first one
+    /// with the new joypad state, which is flashed on screen.
+    pub fn frame_rendered(&mut self, now: Instant) -> bool {
+        match self.stage {
+            Stage::Applied { mut partial, at } => {
+                partial.emulation = now - at;
+                self.stage = Stage::Rendered { partial, at: now };
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// A frame has been presented, returns the measurement once it's complete
+    pub fn frame_presented(&mut self, now: Instant) -> Option<Measurement> {
+        match self.stage {
+            Stage::Rendered { mut partial, at } => {
+                partial.present = now - at;
+                self.stage = Stage::Idle;
+                self.measurements.push(partial);
+                Some(partial)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the average total latency of all measurements
+    pub fn average(&self) -> Option<Duration> {
+        let count = self.measurements.len() as u32;
+        let total = self
+            .measurements
+            .iter()
+            .map(Measurement::total)
+            .sum::<Duration>();
+        total.checked_div(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measurement() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut probe = LatencyProbe::new();
+        assert!(!probe.frame_rendered(start));
+
+        probe.key_pressed(ms(1), start);
+        // Key presses during a measurement are ignored
+        probe.key_pressed(ms(5), start + ms(2));
+        probe.buttons_applied(start + ms(4));
+        assert!(probe.frame_rendered(start + ms(20)));
+        assert!(!probe.frame_rendered(start + ms(21)));
+
+        let measurement = probe.frame_presented(start + ms(22)).unwrap();
+        assert_eq!(
+            measurement,
+            Measurement {
+                queue: ms(1),
+                poll: ms(4),
+                emulation: ms(16),
+                present: ms(2),
+            }
+        );
+        assert_eq!(measurement.total(), ms(23));
+        assert_eq!(probe.average(), Some(ms(23)));
+        assert_eq!(probe.frame_presented(start + ms(40)), None);
+    }
+}
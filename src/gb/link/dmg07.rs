@@ -0,0 +1,218 @@
+/// Number of Game Boys which can be connected to the adapter
+pub const PORTS: usize = 4;
+
+/// First byte of each ping packet
+const PING_HEADER: u8 = 0xFE;
+/// Player 1 sends this byte four times to start the transmission phase
+const START: u8 = 0xAA;
+/// Sent to all players before the transmission phase starts
+const START_ACK: u8 = 0xCC;
+/// A transmission round which only consists of this byte restarts the ping phase
+const RESTART: u8 = 0xFF;
+/// Length of a ping packet and the start sequence
+const PING_LENGTH: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Phase {
+    Ping,
+    Starting,
+    Transmission,
+}
+
+/// Emulates the protocol of the DMG-07 four player adapter.
+///
+/// During the ping phase the adapter sends `0xFE` followed by three status bytes
+/// with the player ID and the connected players to each Game Boy.
+/// Player 1 answers with the transmission rate and packet size, once it sends
+/// `0xAA` four times the adapter switches to the transmission phase.
+/// In each transmission round the adapter collects one packet of every player
+/// and sends the packets of all players to everyone in the next round.
+pub struct Adapter {
+    phase: Phase,
+    position: usize, // Byte position within the current round
+    connected: [bool; PORTS],
+    rate: u8,
+    size: u8,
+    start_requests: usize,
+    packets: [Vec<u8>; PORTS],
+    frame: Vec<u8>, // Packets of all players received in the last round
+    restart: bool,
+}
+
+impl Default for Adapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Adapter {
+    pub fn new() -> Self {
+        Self {
+            phase: Phase::Ping,
+            position: 0,
+            connected: [false; PORTS],
+            rate: 0,
+            size: 1,
+            start_requests: 0,
+            packets: Default::default(),
+            frame: Vec::new(),
+            restart: true,
+        }
+    }
+
+    /// Returns the bytes which are sent to each port with the next clock
+    pub fn outgoing(&self) -> [u8; PORTS] {
+        let mut bytes = [0; PORTS];
+        for (port, byte) in bytes.iter_mut().enumerate() {
+            *byte = match self.phase {
+                Phase::Ping if self.position == 0 => PING_HEADER,
+                Phase::Ping => self.status(port),
+                Phase::Starting => START_ACK,
+                Phase::Transmission => self.frame[self.position],
+            };
+        }
+        bytes
+    }
+
+    /// Receives the bytes which have been shifted out by each Game Boy,
+    /// None marks ports without a Game Boy.
+    pub fn receive(&mut self, bytes: [Option<u8>; PORTS]) {
+        for (connected, byte) in self.connected.iter_mut().zip(bytes.iter()) {
+            *connected = byte.is_some();
+        }
+        match self.phase {
+            Phase::Ping => self.receive_ping(bytes[0]),
+            Phase::Starting => {}
+            Phase::Transmission => self.receive_packets(bytes),
+        }
+
+        self.position += 1;
+        if self.position == self.round_length() {
+            self.position = 0;
+            self.end_round();
+        }
+    }
+
+    /// Returns the transmission rate requested by player 1
+    pub fn rate(&self) -> u8 {
+        self.rate
+    }
+
+    /// Returns the status byte with the player ID and the connected players
+    fn status(&self, port: usize) -> u8 {
+        let connected = self
+            .connected
+            .iter()
+            .enumerate()
+            .filter(|(_, connected)| **connected)
+            .fold(0, |bits, (port, _)| bits | (0x10 << port));
+        connected | (port as u8 + 1)
+    }
+
+    /// Player 1 defines the rate and packet size or requests the transmission phase
+    fn receive_ping(&mut self, byte: Option<u8>) {
+        let byte = match byte {
+            Some(byte) => byte,
+            None => return,
+        };
+        match byte {
+            START => self.start_requests += 1,
+            _ => self.start_requests = 0,
+        }
+        match self.position {
+            2 if byte != START => self.rate = byte,
+            3 if byte != START => self.size = byte.max(1),
+            _ => {}
+        }
+    }
+
+    fn receive_packets(&mut self, bytes: [Option<u8>; PORTS]) {
+        for (packet, byte) in self.packets.iter_mut().zip(bytes.iter()) {
+            if let Some(byte) = byte {
+                self.restart &= *byte == RESTART;
+            }
+            if self.position < usize::from(self.size) {
+                packet.push(byte.unwrap_or(0));
+            }
+        }
+    }
+
+    fn round_length(&self) -> usize {
+        match self.phase {
+            Phase::Ping | Phase::Starting => PING_LENGTH,
+            Phase::Transmission => PORTS * usize::from(self.size),
+        }
+    }
+
+    fn end_round(&mut self) {
+        self.phase = match self.phase {
+            Phase::Ping if self.start_requests >= PING_LENGTH => Phase::Starting,
+            Phase::Ping => Phase::Ping,
+            Phase::Starting => {
+                self.frame = vec![0; PORTS * usize::from(self.size)];
+                Phase::Transmission
+            }
+            Phase::Transmission if self.restart => Phase::Ping,
+            Phase::Transmission => {
+                self.frame = self.packets.concat();
+                Phase::Transmission
+            }
+        };
+        self.start_requests = 0;
+        self.restart = true;
+        for packet in self.packets.iter_mut() {
+            packet.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a round where every connected player sends the given bytes
+    fn round(adapter: &mut Adapter, players: usize, bytes: &[u8]) -> Vec<[u8; PORTS]> {
+        bytes
+            .iter()
+            .map(|byte| {
+                let outgoing = adapter.outgoing();
+                let mut incoming = [None; PORTS];
+                for port in incoming.iter_mut().take(players) {
+                    *port = Some(*byte);
+                }
+                adapter.receive(incoming);
+                outgoing
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ping() {
+        let mut adapter = Adapter::new();
+        round(&mut adapter, 2, &[0x88, 0x88, 0x10, 0x02]);
+        let sent = round(&mut adapter, 2, &[0x88, 0x88, 0x10, 0x02]);
+        assert_eq!(sent[0], [PING_HEADER; PORTS]);
+        // Players 1 and 2 are connected
+        assert_eq!(sent[1], [0x31, 0x32, 0x33, 0x34]);
+        assert_eq!(adapter.rate(), 0x10);
+        assert_eq!(adapter.size, 2);
+    }
+
+    #[test]
+    fn test_transmission() {
+        let mut adapter = Adapter::new();
+        round(&mut adapter, 2, &[0x88, 0x88, 0x10, 0x01]);
+        round(&mut adapter, 2, &[START; 4]);
+        assert_eq!(round(&mut adapter, 2, &[0; 4])[0], [START_ACK; PORTS]);
+
+        // Every player sends its packet in the first byte of the round
+        round(&mut adapter, 2, &[0x42, 0, 0, 0]);
+        let sent = round(&mut adapter, 2, &[0x42, 0, 0, 0]);
+        let received = sent.iter().map(|bytes| bytes[0]).collect::<Vec<u8>>();
+        assert_eq!(received, vec![0x42, 0x42, 0x00, 0x00]);
+
+        // Sending 0xFF for a whole round restarts the ping phase
+        round(&mut adapter, 2, &[RESTART; 4]);
+        assert_eq!(adapter.outgoing(), [PING_HEADER; PORTS]);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::gb::link::dmg07::{Adapter, PORTS};
+use crate::gb::memory::MemoryBus;
+use log::{info, warn};
+use std::cell::RefCell;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+pub mod dmg07;
+
+/// Runs a DMG-07 four player adapter which emulator instances connect to over TCP.
+/// Players are assigned to the first free port, the adapter provides the serial clock
+/// and waits for each connected Game Boy to exchange a byte before the next one is sent.
+pub fn serve_adapter(address: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    info!("DMG-07 adapter listening on {}", listener.local_addr()?);
+
+    let mut ports: [Option<TcpStream>; PORTS] = Default::default();
+    let mut adapter = Adapter::new();
+    loop {
+        while let Ok((stream, peer)) = listener.accept() {
+            match ports.iter().position(Option::is_none) {
+                Some(port) => {
+                    stream.set_nonblocking(false)?;
+                    stream.set_nodelay(true)?;
+                    info!("Player {} connected from {}", port + 1, peer);
+                    ports[port] = Some(stream);
+                }
+                None => warn!("All ports are in use, rejected {}", peer),
+            }
+        }
+        if ports.iter().all(Option::is_none) {
+            thread::sleep(Duration::from_millis(100));
+            continue;
+        }
+
+        let outgoing = adapter.outgoing();
+        let mut incoming = [None; PORTS];
+        for (port, stream) in ports.iter_mut().enumerate() {
+            if let Some(mut connection) = stream.take() {
+                if connection.write_all(&[outgoing[port]]).is_ok() {
+                    *stream = Some(connection);
+                }
+            }
+        }
+        for (port, stream) in ports.iter_mut().enumerate() {
+            if let Some(mut connection) = stream.take() {
+                let mut byte = [0];
+                match connection.read_exact(&mut byte) {
+                    Ok(()) => {
+                        incoming[port] = Some(byte[0]);
+                        *stream = Some(connection);
+                    }
+                    Err(_) => info!("Player {} disconnected", port + 1),
+                }
+            }
+        }
+        adapter.receive(incoming);
+    }
+}
+
+/// Connects the serial port to a DMG-07 adapter over TCP.
+/// The adapter provides the clock, so a received byte is only exchanged
+/// once the Game Boy started a transfer with external clock.
+pub struct LinkClient {
+    stream: TcpStream,
+    pending: Option<u8>,
+}
+
+impl LinkClient {
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            pending: None,
+        })
+    }
+
+    /// Exchanges the byte received from the adapter if the Game Boy is ready,
+    /// this should be called regularly during emulation.
+    pub fn poll(&mut self, bus: &RefCell<MemoryBus>) -> io::Result<()> {
+        if self.pending.is_none() {
+            let mut byte = [0];
+            match self.stream.read(&mut byte) {
+                Ok(0) => {
+                    let msg = "DMG-07 adapter disconnected";
+                    return Err(io::Error::new(io::ErrorKind::ConnectionAborted, msg));
+                }
+                Ok(_) => self.pending = Some(byte[0]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+        if let Some(byte) = self.pending {
+            if let Some(sent) = bus.borrow_mut().serial_exchange(byte) {
+                self.pending = None;
+                self.stream.write_all(&[sent])?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -76,6 +76,12 @@ pub const HRAM_SIZE: usize = (HRAM_END - HRAM_BEGIN + 1) as usize;
 /// Joypad Input Register
 pub const JOYPAD: u16 = 0xFF00;
 
+// Serial Transfer Registers
+/// Holds the byte to be transferred and receives the incoming byte.
+pub const SERIAL_DATA: u16 = 0xFF01;
+/// Bit 7 starts a transfer, bit 0 selects the internal clock.
+pub const SERIAL_CTRL: u16 = 0xFF02;
+
 // Timer Registers
 /// Counts up at a fixed 16384Hz rate, resets to 0 whenever written to.
 pub const TIMER_DIVIDER: u16 = 0xFF04;
@@ -0,0 +1,83 @@
+use crate::gb::memory::constants::{IO_BEGIN, OAM_SIZE};
+use std::ops::Range;
+
+/// Number of clock cycles it takes to transfer a single byte (1 machine cycle)
+const CYCLES_PER_BYTE: u32 = 4;
+/// The transfer starts one machine cycle after the DMA register has been written
+const START_DELAY: u32 = 1;
+
+/// Represents a running OAM DMA transfer, which copies 0xA0 bytes
+/// from `source` to OAM, one byte per machine cycle.
+pub struct OamDma {
+    pub source: u16,
+    offset: u16,
+    delay: u32,
+    ticks: u32,
+}
+
+impl OamDma {
+    /// Creates a new transfer from the source address `value * 0x100`
+    pub fn new(value: u8) -> Self {
+        Self {
+            source: u16::from(value) << 8,
+            offset: 0,
+            delay: START_DELAY,
+            ticks: 0,
+        }
+    }
+
+    /// Advances the transfer and returns the offsets of the bytes
+    /// which have to be copied within the given clock cycles.
+    pub fn advance(&mut self, cycles: u32) -> Range<u16> {
+        self.ticks += cycles;
+        let start = self.offset;
+        while self.ticks >= CYCLES_PER_BYTE && !self.is_finished() {
+            self.ticks -= CYCLES_PER_BYTE;
+            match self.delay {
+                0 => self.offset += 1,
+                _ => self.delay -= 1,
+            }
+        }
+        start..self.offset
+    }
+
+    pub fn is_finished(&self) -> bool {
+        usize::from(self.offset) == OAM_SIZE
+    }
+
+    /// Returns whether the CPU can access the given address while the transfer is running.
+    /// The transfer occupies the external and video bus, hence only
+    /// I/O registers and HRAM are accessible.
+    pub fn is_accessible(address: u16) -> bool {
+        address >= IO_BEGIN
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_timing() {
+        let mut dma = OamDma::new(0xC0);
+        assert_eq!(dma.source, 0xC000);
+        // Start delay
+        assert_eq!(dma.advance(4), 0..0);
+        assert_eq!(dma.advance(2), 0..0);
+        assert_eq!(dma.advance(6), 0..2);
+        assert_eq!(dma.advance(4 * 157), 2..159);
+        assert!(!dma.is_finished());
+        assert_eq!(dma.advance(100), 159..160);
+        assert!(dma.is_finished());
+    }
+
+    #[test]
+    fn test_is_accessible() {
+        assert!(!OamDma::is_accessible(0x0150));
+        assert!(!OamDma::is_accessible(0xC000));
+        assert!(!OamDma::is_accessible(0xFE00));
+        assert!(OamDma::is_accessible(0xFF46));
+        assert!(OamDma::is_accessible(0xFF80));
+        assert!(OamDma::is_accessible(0xFFFF));
+    }
+}
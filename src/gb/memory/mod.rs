@@ -1,21 +1,53 @@
 pub mod constants;
+pub mod dma;
+pub mod recorder;
 
 use crate::gb::cartridge::Cartridge;
+use crate::gb::cheats::Cheats;
 use crate::gb::interrupt::IRQ;
+use crate::gb::joypad;
+use crate::gb::joypad::Buttons;
 use crate::gb::memory::constants::*;
+use crate::gb::memory::dma::OamDma;
 use crate::gb::AddressSpace;
 use crate::utils;
+use log::{trace, warn};
+use std::collections::HashSet;
+use std::io;
 
 /// Defines a global MemoryBus, all processing units should access memory through this bus.
 pub struct MemoryBus {
     cartridge: Cartridge,
     vram: [u8; VRAM_SIZE],
     wram: [u8; WRAM_SIZE],
-    eram: [u8; ERAM_SIZE],
     oam: [u8; OAM_SIZE],
     io: [u8; IO_SIZE],
     hram: [u8; HRAM_SIZE],
     ie: u8,
+    serial: Vec<u8>,
+    buttons: Buttons,
+    dma: Option<OamDma>,
+    cheats: Cheats,
+    /// Writes to the timer registers since the last `take_timer_writes`
+    timer_writes: Vec<TimerWrite>,
+    /// Machine cycles the CPU spent in the current instruction so far
+    cycle: u32,
+    accurate: bool,
+    /// Row of OAM the PPU is currently reading during OAM search
+    oam_scan_row: u8,
+    /// Panic on writes to unmapped I/O registers instead of ignoring them
+    strict: bool,
+    /// Unmapped I/O registers which have been written and reported already
+    unmapped_writes: HashSet<u16>,
+}
+
+/// Write to a timer register
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TimerWrite {
+    /// Machine cycle of the instruction in which the write happened
+    pub cycle: u32,
+    pub address: u16,
+    pub value: u8,
 }
 
 impl MemoryBus {
@@ -24,25 +56,182 @@ impl MemoryBus {
             cartridge,
             vram: [0u8; VRAM_SIZE],
             wram: [0u8; WRAM_SIZE],
-            eram: [0u8; ERAM_SIZE],
             oam: [0u8; OAM_SIZE],
             io: [0u8; IO_SIZE],
             hram: [0u8; HRAM_SIZE],
             ie: 0,
+            serial: Vec::new(),
+            buttons: Buttons::empty(),
+            dma: None,
+            cheats: Cheats::default(),
+            timer_writes: Vec::new(),
+            cycle: 0,
+            accurate: false,
+            oam_scan_row: 0,
+            strict: false,
+            unmapped_writes: HashSet::new(),
+        }
+    }
+
+    /// Restores the power-on state of the memory and the cartridge's banking registers,
+    /// so the boot ROM runs again. The cartridge RAM and the serial output are kept.
+    pub fn reset(&mut self) {
+        self.cartridge.reset();
+        self.vram = [0u8; VRAM_SIZE];
+        self.wram = [0u8; WRAM_SIZE];
+        self.oam = [0u8; OAM_SIZE];
+        self.io = [0u8; IO_SIZE];
+        self.hram = [0u8; HRAM_SIZE];
+        self.ie = 0;
+        self.dma = None;
+        self.timer_writes.clear();
+        self.cycle = 0;
+        self.oam_scan_row = 0;
+    }
+
+    /// Updates the currently pressed buttons and requests
+    /// an interrupt if a selected button has been pressed.
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        let before = self.read(JOYPAD);
+        self.buttons = buttons;
+        self.joypad_transition(before);
+    }
+
+    /// Requests the joypad interrupt on a high to low transition of any input line,
+    /// either by pressing a button or by selecting a line with a held button.
+    fn joypad_transition(&mut self, before: u8) {
+        if before & !self.read_io(JOYPAD) & 0x0F != 0 {
+            self.irq(IRQ::Joypad);
         }
     }
 
+    /// Writes the battery backed RAM of the cartridge to its save file
+    pub fn save_ram(&self) -> io::Result<()> {
+        self.cartridge.save_ram()
+    }
+
+    /// Writes the battery backed RAM of the cartridge to its next autosave slot
+    pub fn write_autosave(&self) -> io::Result<()> {
+        self.cartridge.write_autosave()
+    }
+
+    /// Returns the buttons which have been pressed during the last frame
+    pub fn buttons(&self) -> Buttons {
+        self.buttons
+    }
+
+    /// Enables the given cheats, ROM patches apply immediately
+    pub fn set_cheats(&mut self, cheats: Cheats) {
+        self.cheats = cheats;
+    }
+
+    /// Applies the RAM writes of all GameShark cheats, this is done once per VBlank
+    pub fn apply_cheats(&mut self) {
+        for i in 0..self.cheats.ram_writes().len() {
+            let (address, value) = self.cheats.ram_writes()[i];
+            self.write(address, value);
+        }
+    }
+
+    /// Returns the contents of WRAM followed by HRAM
+    pub fn export_ram(&self) -> Vec<u8> {
+        [&self.wram[..], &self.hram[..]].concat()
+    }
+
+    /// Restores WRAM and HRAM from the output of `export_ram`
+    pub fn import_ram(&mut self, data: &[u8]) -> Result<(), String> {
+        if data.len() != WRAM_SIZE + HRAM_SIZE {
+            return Err(format!(
+                "Expected {} bytes of RAM, got {}",
+                WRAM_SIZE + HRAM_SIZE,
+                data.len()
+            ));
+        }
+        let (wram, hram) = data.split_at(WRAM_SIZE);
+        self.wram.copy_from_slice(wram);
+        self.hram.copy_from_slice(hram);
+        Ok(())
+    }
+
+    /// Returns all bytes sent over the serial port so far
+    pub fn serial_output(&self) -> &[u8] {
+        &self.serial
+    }
+
     /// Requests an interrupt for the given id
     pub fn irq(&mut self, id: IRQ) {
         let req = utils::set_bit(self.read(INTERRUPT_FLAG), u8::from(id), true);
         self.write(INTERRUPT_FLAG, req);
     }
 
+    /// Emulates hardware quirks which only matter for test ROMs and a few games
+    pub fn set_accurate(&mut self, accurate: bool) {
+        self.accurate = accurate;
+    }
+
+    /// Panics on writes to I/O registers which don't exist on the DMG,
+    /// e.g. to find registers which are missing in the emulator.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Returns whether the PPU currently blocks OAM, i.e. the LCD is on
+    /// and the PPU is in OAM search or pixel transfer.
+    fn oam_blocked(&self) -> bool {
+        self.read_io(PPU_LCDC) & 0x80 != 0 && self.read_io(PPU_STAT) & 0x02 != 0
+    }
+
+    /// Updates the OAM row which is read by the PPU during OAM search,
+    /// each row holds two objects and takes a machine cycle.
+    pub fn set_oam_scan_row(&mut self, row: u8) {
+        self.oam_scan_row = row;
+    }
+
+    /// Emulates the OAM corruption bug of the DMG: accessing 0xFE00-0xFEFF during
+    /// OAM search garbles the row which is read by the PPU in the same cycle.
+    /// The first word of the row is combined with the preceding row, the other
+    /// three words are copied from it. The first row is never affected.
+    fn corrupt_oam(&mut self, address: u16) {
+        let row = self.oam_scan_row as usize * 8;
+        if !self.accurate
+            || !(OAM_BEGIN..=UNUSED_END).contains(&address)
+            || !self.oam_blocked()
+            || self.read_io(PPU_STAT) & 0x03 != 0x02
+            || row == 0
+            || row >= OAM_SIZE
+        {
+            return;
+        }
+        let word = |oam: &[u8], offset: usize| u16::from_le_bytes([oam[offset], oam[offset + 1]]);
+        let a = word(&self.oam, row);
+        let b = word(&self.oam, row - 8);
+        let c = word(&self.oam, row - 4);
+        let corrupted = ((a ^ c) & (b ^ c)) ^ c;
+        self.oam[row..row + 2].copy_from_slice(&corrupted.to_le_bytes());
+        self.oam.copy_within(row - 6..row, row + 2);
+    }
+
+    /// Reads the prohibited area after OAM. The DMG returns 0xFF while OAM
+    /// is blocked by the PPU and 0x00 otherwise.
+    fn read_unused(&self) -> u8 {
+        match !self.accurate || self.oam_blocked() {
+            true => 0xFF,
+            false => 0x00,
+        }
+    }
+
+    /// Returns the writes to DIV, TIMA, TMA and TAC in the order they happened,
+    /// each with the machine cycle of the instruction it happened in
+    pub fn take_timer_writes(&mut self) -> Vec<TimerWrite> {
+        self.cycle = 0;
+        std::mem::take(&mut self.timer_writes)
+    }
+
     /// Unchecked write to avoid memory traps.
     /// This function should only be used in emulator internals!
     pub fn write_unchecked(&mut self, address: u16, value: u8) {
         match address {
-            TIMER_DIVIDER => self.io[(address - IO_BEGIN) as usize] = value,
+            TIMER_DIVIDER | TIMER_COUNTER => self.io[(address - IO_BEGIN) as usize] = value,
             _ => unimplemented!("unchecked_write() for address {:#06x}", address),
         }
     }
@@ -52,67 +241,174 @@ impl MemoryBus {
     fn read_cartridge(&self, address: u16) -> u8 {
         match address {
             BOOT_BEGIN..=BOOT_END if self.read(BOOT_ROM_OFF) == 0 => BOOT_ROM[address as usize],
+            ROM_BANK_0_BEGIN..=ROM_BANK_N_END => {
+                self.cheats.patch_rom(address, self.cartridge.read(address))
+            }
             _ => self.cartridge.read(address),
         }
     }
 
-    /// Initiate DMA transfer
+    /// Handles a serial transfer. There is no link cable partner,
+    /// so the transfer completes instantly and 0xFF is shifted in.
+    fn serial_transfer(&mut self, value: u8) {
+        if value & 0x81 != 0x81 {
+            self.io[(SERIAL_CTRL - IO_BEGIN) as usize] = value;
+            return;
+        }
+        self.serial.push(self.read(SERIAL_DATA));
+        self.io[(SERIAL_DATA - IO_BEGIN) as usize] = 0xFF;
+        self.io[(SERIAL_CTRL - IO_BEGIN) as usize] = value & 0x7F;
+        self.irq(IRQ::Serial);
+    }
+
+    /// Completes a transfer which waits for an external clock, e.g. from a link partner.
+    /// Returns the byte sent by the Game Boy or None if no such transfer has been started.
+    pub fn serial_exchange(&mut self, incoming: u8) -> Option<u8> {
+        let ctrl = self.read(SERIAL_CTRL);
+        if ctrl & 0x81 != 0x80 {
+            return None;
+        }
+        let outgoing = self.read(SERIAL_DATA);
+        self.serial.push(outgoing);
+        self.io[(SERIAL_DATA - IO_BEGIN) as usize] = incoming;
+        self.io[(SERIAL_CTRL - IO_BEGIN) as usize] = ctrl & 0x7F;
+        self.irq(IRQ::Serial);
+        Some(outgoing)
+    }
+
+    /// Starts a DMA transfer, an already running transfer is restarted
     fn dma_transfer(&mut self, value: u8) {
-        let address = u16::from(value) * 100;
-        for offset in 0..0xA0 {
-            let byte = self.read(address + offset);
-            self.write(OAM_BEGIN + offset, byte);
+        self.io[(PPU_DMA - IO_BEGIN) as usize] = value;
+        self.dma = Some(OamDma::new(value));
+    }
+
+    /// Advances a running DMA transfer by the given clock cycles
+    pub fn step_dma(&mut self, cycles: u32) {
+        let (source, offsets, finished) = match &mut self.dma {
+            Some(dma) => (dma.source, dma.advance(cycles), dma.is_finished()),
+            None => return,
+        };
+        for offset in offsets {
+            self.oam[offset as usize] = self.read_unchecked(source + offset);
+        }
+        if finished {
+            self.dma = None;
+        }
+    }
+
+    /// Reads a value without the access restrictions of a running DMA transfer.
+    /// This function should only be used in emulator internals!
+    pub fn read_unchecked(&self, address: u16) -> u8 {
+        match address {
+            ROM_BANK_0_BEGIN..=ROM_BANK_N_END => self.read_cartridge(address),
+            VRAM_BEGIN..=VRAM_END => self.vram[(address - VRAM_BEGIN) as usize],
+            CRAM_BEGIN..=CRAM_END => self.read_cartridge(address),
+            WRAM_BEGIN..=WRAM_END => self.wram[(address - WRAM_BEGIN) as usize],
+            // Echo RAM mirrors Working RAM
+            ERAM_BEGIN..=ERAM_END => self.wram[(address - ERAM_BEGIN) as usize],
+            OAM_BEGIN..=OAM_END => self.oam[(address - OAM_BEGIN) as usize],
+            UNUSED_BEGIN..=UNUSED_END => self.read_unused(),
+            IO_BEGIN..=IO_END => self.read_io(address),
+            HRAM_BEGIN..=HRAM_END => self.hram[(address - HRAM_BEGIN) as usize],
+            INTERRUPT_ENABLE => self.ie,
         }
     }
 
     fn write_io(&mut self, address: u16, value: u8) {
-        //println!("write IO: {:#06x}: {:#04x}", address, value);
+        trace!("write IO: {:#06x}: {:#04x}", address, value);
         match address {
             // Trap the diver register, whenever a ROM writes tries to write
             // to it it will reset to 0
-            TIMER_DIVIDER => self.io[(address - IO_BEGIN) as usize] = 0,
+            TIMER_DIVIDER => {
+                self.io[(address - IO_BEGIN) as usize] = 0;
+                self.latch_timer_write(address, value);
+            }
+            TIMER_COUNTER | TIMER_MODULO | TIMER_CTRL => {
+                self.io[(address - IO_BEGIN) as usize] = value;
+                self.latch_timer_write(address, value);
+            }
             PPU_DMA => self.dma_transfer(value),
+            SERIAL_CTRL => self.serial_transfer(value),
+            // Only the button selection bits are writable
+            JOYPAD => {
+                let before = self.read_io(JOYPAD);
+                self.io[(address - IO_BEGIN) as usize] = value & 0x30;
+                self.joypad_transition(before);
+            }
+            _ if !is_mapped_io(address) => self.write_unmapped(address, value),
             _ => self.io[(address - IO_BEGIN) as usize] = value,
         }
     }
 
-    /// Writes to Echo RAM, effectively mirroring to Working RAM
-    fn write_eram(&mut self, address: u16, value: u8) {
-        self.eram[(address - ERAM_BEGIN) as usize] = value;
-        self.wram[(address - ERAM_SIZE as u16 - WRAM_BEGIN) as usize] = value;
+    /// The timer applies writes at the machine cycle they happened, e.g. to tell
+    /// whether TIMA has been written right before or after it has been reloaded
+    fn latch_timer_write(&mut self, address: u16, value: u8) {
+        self.timer_writes.push(TimerWrite {
+            cycle: self.cycle,
+            address,
+            value,
+        });
+    }
+
+    /// Ignores a write to an I/O register which doesn't exist on the DMG like the open bus,
+    /// each register is only reported once. Panics in strict mode.
+    fn write_unmapped(&mut self, address: u16, value: u8) {
+        if !self.strict && self.unmapped_writes.contains(&address) {
+            return;
+        }
+        let message = format!(
+            "Ignored write of {:#04x} to unmapped I/O register {:#06x}",
+            value, address
+        );
+        if self.strict {
+            panic!("{}", message);
+        }
+        self.unmapped_writes.insert(address);
+        warn!("{}", message);
     }
 
-    /// TODO: document unmapped I/O registers
+    /// Reads an I/O register, registers which don't exist on the DMG read as 0xFF
+    /// like the open bus, this includes the CGB registers, e.g. CGB_WRAM_BANK.
     /// https://gbdev.gg8.se/wiki/articles/CGB_Registers#FF6C_-_Bit_0_.28Read.2FWrite.29_-_CGB_Mode_Only
     fn read_io(&self, address: u16) -> u8 {
         match address {
-            0xFF03 => 0xFF,
-            0xFF08..=0xFF0E => 0xFF,
-            0xFF15 => 0xFF,
-            0xFF1F => 0xFF,
-            0xFF27..=0xFF2F => 0xFF,
-            CGB_PREPARE_SPEED_SWITCH => 0xFF,
-            0xFF4E => 0xFF,
-            0xFF57..=0xFF5F => 0xFF,
-            0xFF6C..=0xFF6F => 0xFF,
-            CGB_WRAM_BANK => 0xFF,
-            0xFF71..=0xFF75 => 0xFF,
-            PCM_AMPLITUDES12 => 0xFF, //TODO: implement me
-            PCM_AMPLITUDES34 => 0xFF, //TODO: implement me
-            0xFF78..=0xFF7F => 0xFF,
+            JOYPAD => joypad::register_value(self.io[(address - IO_BEGIN) as usize], self.buttons),
+            _ if !is_mapped_io(address) => 0xFF,
             _ => self.io[(address - IO_BEGIN) as usize],
         }
     }
 }
 
+/// Returns whether the I/O register at the given address exists on the DMG
+fn is_mapped_io(address: u16) -> bool {
+    matches!(
+        address,
+        JOYPAD..=SERIAL_CTRL
+            | TIMER_DIVIDER..=TIMER_CTRL
+            | INTERRUPT_FLAG
+            // Sound registers and wave RAM
+            | 0xFF10..=0xFF14
+            | 0xFF16..=0xFF1E
+            | 0xFF20..=0xFF26
+            | 0xFF30..=0xFF3F
+            | PPU_LCDC..=PPU_WX
+            | BOOT_ROM_OFF
+    )
+}
+
 impl AddressSpace for MemoryBus {
     fn write(&mut self, address: u16, value: u8) {
+        // Only HRAM and I/O registers are accessible during DMA
+        if self.dma.is_some() && !OamDma::is_accessible(address) {
+            return;
+        }
+        self.corrupt_oam(address);
         match address {
             ROM_BANK_0_BEGIN..=ROM_BANK_N_END => self.cartridge.write(address, value),
             VRAM_BEGIN..=VRAM_END => self.vram[(address - VRAM_BEGIN) as usize] = value,
             CRAM_BEGIN..=CRAM_END => self.cartridge.write(address, value),
             WRAM_BEGIN..=WRAM_END => self.wram[(address - WRAM_BEGIN) as usize] = value,
-            ERAM_BEGIN..=ERAM_END => self.write_eram(address, value),
+            ERAM_BEGIN..=ERAM_END => self.wram[(address - ERAM_BEGIN) as usize] = value,
             OAM_BEGIN..=OAM_END => self.oam[(address - OAM_BEGIN) as usize] = value,
             UNUSED_BEGIN..=UNUSED_END => {}
             IO_BEGIN..=IO_END => self.write_io(address, value),
@@ -122,17 +418,193 @@ impl AddressSpace for MemoryBus {
     }
 
     fn read(&self, address: u16) -> u8 {
-        match address {
-            ROM_BANK_0_BEGIN..=ROM_BANK_N_END => self.read_cartridge(address),
-            VRAM_BEGIN..=VRAM_END => self.vram[(address - VRAM_BEGIN) as usize],
-            CRAM_BEGIN..=CRAM_END => self.read_cartridge(address),
-            WRAM_BEGIN..=WRAM_END => self.wram[(address - WRAM_BEGIN) as usize],
-            ERAM_BEGIN..=ERAM_END => self.eram[(address - ERAM_BEGIN) as usize],
-            OAM_BEGIN..=OAM_END => self.oam[(address - OAM_BEGIN) as usize],
-            UNUSED_BEGIN..=UNUSED_END => 0xFF,
-            IO_BEGIN..=IO_END => self.read_io(address),
-            HRAM_BEGIN..=HRAM_END => self.hram[(address - HRAM_BEGIN) as usize],
-            INTERRUPT_ENABLE => self.ie,
+        match self.dma {
+            Some(_) if !OamDma::is_accessible(address) => 0xFF,
+            _ => self.read_unchecked(address),
+        }
+    }
+
+    fn increment_address(&mut self, address: u16) {
+        self.corrupt_oam(address);
+    }
+
+    fn tick(&mut self) {
+        self.cycle += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_bus() -> MemoryBus {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x14D] = 0xE7;
+        let mut bus = MemoryBus::new(Cartridge::from_bytes(rom));
+        bus.write(BOOT_ROM_OFF, 1);
+        bus
+    }
+
+    #[test]
+    fn test_dma_transfer() {
+        let mut bus = create_bus();
+        for offset in 0..OAM_SIZE as u16 {
+            bus.write(WRAM_BEGIN + offset, offset as u8);
+        }
+        bus.write(PPU_DMA, (WRAM_BEGIN >> 8) as u8);
+        assert_eq!(bus.read(PPU_DMA), 0xC0);
+
+        bus.step_dma(4 + 4 * (OAM_SIZE as u32 - 1));
+        assert_eq!(bus.read_unchecked(OAM_END - 1), 0x9E);
+        assert_eq!(bus.read_unchecked(OAM_END), 0x00);
+        bus.step_dma(4);
+        assert_eq!(bus.read(OAM_END), 0x9F);
+    }
+
+    #[test]
+    fn test_dma_blocks_memory() {
+        let mut bus = create_bus();
+        bus.write(WRAM_BEGIN, 0x42);
+        bus.write(PPU_DMA, (WRAM_BEGIN >> 8) as u8);
+        assert_eq!(bus.read(WRAM_BEGIN), 0xFF);
+        bus.write(WRAM_BEGIN, 0x24);
+        bus.write(HRAM_BEGIN, 0x24);
+        assert_eq!(bus.read(HRAM_BEGIN), 0x24);
+
+        bus.step_dma(4 + 4 * OAM_SIZE as u32);
+        assert_eq!(bus.read(WRAM_BEGIN), 0x42);
+        assert_eq!(bus.read(OAM_BEGIN), 0x42);
+    }
+
+    #[test]
+    fn test_serial_exchange() {
+        let mut bus = create_bus();
+        bus.write(SERIAL_DATA, 0x88);
+        assert_eq!(bus.serial_exchange(0xFE), None);
+
+        // Transfer with external clock
+        bus.write(SERIAL_CTRL, 0x80);
+        assert_eq!(bus.serial_exchange(0xFE), Some(0x88));
+        assert_eq!(bus.read(SERIAL_DATA), 0xFE);
+        assert_eq!(bus.read(SERIAL_CTRL) & 0x80, 0);
+        assert_eq!(bus.serial_exchange(0xFE), None);
+    }
+
+    #[test]
+    fn test_ram_export_import() {
+        let mut bus = create_bus();
+        bus.write(WRAM_BEGIN + 1, 0x42);
+        bus.write(HRAM_END, 0x24);
+        let ram = bus.export_ram();
+
+        let mut other = create_bus();
+        other.import_ram(&ram).unwrap();
+        assert_eq!(other.read(WRAM_BEGIN + 1), 0x42);
+        assert_eq!(other.read(ERAM_BEGIN + 1), 0x42);
+        assert_eq!(other.read(HRAM_END), 0x24);
+        assert!(other.import_ram(&ram[1..]).is_err());
+    }
+
+    #[test]
+    fn test_unmapped_io() {
+        let mut bus = create_bus();
+        // CGB VRAM bank and the unused register after SC
+        for address in [0xFF4F, 0xFF03] {
+            bus.write(address, 0x01);
+            assert_eq!(bus.read(address), 0xFF);
+        }
+        assert!(bus.unmapped_writes.contains(&0xFF4F));
+        bus.write(PPU_SCX, 0x01);
+        assert_eq!(bus.read(PPU_SCX), 0x01);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut bus = create_bus();
+        bus.write(BOOT_ROM_OFF, 1);
+        bus.write(WRAM_BEGIN, 0x42);
+        bus.reset();
+        assert_eq!(bus.read(WRAM_BEGIN), 0x00);
+        assert_eq!(bus.read(0x0000), BOOT_ROM[0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_unmapped_io_strict() {
+        let mut bus = create_bus();
+        bus.set_strict(true);
+        bus.write(0xFF4F, 0x01);
+    }
+
+    #[test]
+    fn test_echo_ram() {
+        let mut bus = create_bus();
+        bus.write(WRAM_BEGIN + 0x10, 0x42);
+        assert_eq!(bus.read(ERAM_BEGIN + 0x10), 0x42);
+        bus.write(ERAM_END, 0x24);
+        assert_eq!(bus.read(ERAM_END - ERAM_BEGIN + WRAM_BEGIN), 0x24);
+    }
+
+    #[test]
+    fn test_unused_area() {
+        let mut bus = create_bus();
+        assert_eq!(bus.read(UNUSED_BEGIN), 0xFF);
+        bus.set_accurate(true);
+        assert_eq!(bus.read(UNUSED_BEGIN), 0x00);
+        // LCD on in OAM search
+        bus.write(PPU_LCDC, 0x80);
+        bus.write(PPU_STAT, 0x02);
+        assert_eq!(bus.read(UNUSED_END), 0xFF);
+        bus.write(PPU_STAT, 0x00);
+        assert_eq!(bus.read(UNUSED_END), 0x00);
+    }
+
+    #[test]
+    fn test_oam_corruption() {
+        let mut bus = create_bus();
+        for offset in 0..16 {
+            bus.write(OAM_BEGIN + offset, offset as u8 + 1);
         }
+        bus.write(PPU_LCDC, 0x80);
+        bus.write(PPU_STAT, 0x02);
+        bus.set_oam_scan_row(1);
+        // Disabled unless accurate memory is enabled
+        bus.increment_address(OAM_BEGIN);
+        assert_eq!(bus.read_unchecked(OAM_BEGIN + 8), 9);
+
+        bus.set_accurate(true);
+        bus.increment_address(0xFF00);
+        assert_eq!(bus.read_unchecked(OAM_BEGIN + 8), 9);
+        bus.increment_address(OAM_BEGIN + 0x10);
+        // a = 0x0A09, b = 0x0201, c = 0x0605: ((a ^ c) & (b ^ c)) ^ c
+        assert_eq!(bus.read_unchecked(OAM_BEGIN + 8), 0x01);
+        assert_eq!(bus.read_unchecked(OAM_BEGIN + 9), 0x02);
+        let row = (10..16)
+            .map(|offset| bus.read_unchecked(OAM_BEGIN + offset))
+            .collect::<Vec<u8>>();
+        assert_eq!(row, vec![3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_joypad_interrupt() {
+        let mut bus = create_bus();
+        bus.write(JOYPAD, 0x30);
+        bus.set_buttons(Buttons::A | Buttons::UP);
+        // No line is selected, nothing changed
+        assert_eq!(bus.read(INTERRUPT_FLAG), 0);
+
+        // Selecting the held buttons pulls their lines low
+        bus.write(JOYPAD, 0x20);
+        assert_eq!(bus.read(INTERRUPT_FLAG), 0x10);
+        bus.write(INTERRUPT_FLAG, 0);
+        bus.write(JOYPAD, 0x00);
+        assert_eq!(bus.read(INTERRUPT_FLAG), 0x10);
+
+        // Releasing is a low to high transition
+        bus.write(INTERRUPT_FLAG, 0);
+        bus.set_buttons(Buttons::empty());
+        assert_eq!(bus.read(INTERRUPT_FLAG), 0);
+        bus.set_buttons(Buttons::B);
+        assert_eq!(bus.read(INTERRUPT_FLAG), 0x10);
     }
 }
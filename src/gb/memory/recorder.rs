@@ -0,0 +1,112 @@
+use crate::gb::AddressSpace;
+use std::cell::{Cell, Ref, RefCell};
+use std::fmt;
+
+/// Defines whether a bus access was a read or a write
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// Represents a single access on the memory bus
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BusAccess {
+    /// Cycle since the start of the recording at which the access happened
+    pub cycle: u32,
+    pub kind: AccessKind,
+    pub address: u16,
+    pub value: u8,
+}
+
+impl BusAccess {
+    pub fn read(cycle: u32, address: u16, value: u8) -> Self {
+        Self {
+            cycle,
+            kind: AccessKind::Read,
+            address,
+            value,
+        }
+    }
+
+    pub fn write(cycle: u32, address: u16, value: u8) -> Self {
+        Self {
+            cycle,
+            kind: AccessKind::Write,
+            address,
+            value,
+        }
+    }
+}
+
+impl fmt::Display for BusAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            AccessKind::Read => "R",
+            AccessKind::Write => "W",
+        };
+        write!(
+            f,
+            "{} {:#06x} {:#04x} @{}",
+            kind, self.address, self.value, self.cycle
+        )
+    }
+}
+
+/// Decorates an AddressSpace and records every access in the order it happened.
+/// Reads are recorded as well, hence the log needs interior mutability.
+/// Accesses are timestamped with the machine cycles the CPU reported with `tick`.
+pub struct RecordingBus<'a, T: AddressSpace> {
+    bus: &'a RefCell<T>,
+    log: RefCell<Vec<BusAccess>>,
+    cycle: Cell<u32>,
+}
+
+impl<'a, T: AddressSpace> RecordingBus<'a, T> {
+    pub fn new(bus: &'a RefCell<T>) -> Self {
+        Self {
+            bus,
+            log: RefCell::new(Vec::new()),
+            cycle: Cell::new(0),
+        }
+    }
+
+    /// Returns all accesses recorded since the last call to `clear()`
+    pub fn activity(&self) -> Ref<'_, Vec<BusAccess>> {
+        self.log.borrow()
+    }
+
+    /// Clears the recorded accesses and restarts counting cycles
+    pub fn clear(&mut self) {
+        self.log.get_mut().clear();
+        self.cycle.set(0);
+    }
+}
+
+impl<'a, T: AddressSpace> AddressSpace for RecordingBus<'a, T> {
+    fn write(&mut self, address: u16, value: u8) {
+        let cycle = self.cycle.get();
+        self.log
+            .get_mut()
+            .push(BusAccess::write(cycle, address, value));
+        self.bus.borrow_mut().write(address, value);
+    }
+
+    fn read(&self, address: u16) -> u8 {
+        let value = self.bus.borrow().read(address);
+        let cycle = self.cycle.get();
+        self.log
+            .borrow_mut()
+            .push(BusAccess::read(cycle, address, value));
+        value
+    }
+
+    fn increment_address(&mut self, address: u16) {
+        self.bus.borrow_mut().increment_address(address);
+    }
+
+    fn tick(&mut self) {
+        self.cycle.set(self.cycle.get().wrapping_add(4));
+        self.bus.borrow_mut().tick();
+    }
+}
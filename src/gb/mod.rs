@@ -1,12 +1,41 @@
+use crate::gb::joypad::Buttons;
+use crate::gb::ppu::misc::Color;
+
+#[cfg(feature = "frontend")]
+pub mod browser;
+pub mod bundle;
 pub mod cartridge;
+pub mod cheats;
+pub mod colors;
 pub mod cpu;
+#[cfg(feature = "frontend")]
 pub mod debugger;
+pub mod diagnostics;
+#[cfg(feature = "frontend")]
 pub mod display;
+#[cfg(feature = "frontend")]
+pub mod frontend;
+pub mod gameboy;
+pub mod header;
+pub mod headless;
+pub mod image;
+// The opcode reference is only shown by the debugger
+#[cfg_attr(not(feature = "frontend"), allow(dead_code))]
 mod instruction;
 pub mod interrupt;
+pub mod joypad;
+pub mod latency;
+pub mod link;
 pub mod memory;
+pub mod movie;
+pub mod pacer;
 pub mod ppu;
+pub mod rtc;
+pub mod sink;
+pub mod stall;
 pub mod timer;
+pub mod triple_buffer;
+pub mod video;
 
 pub const DISPLAY_REFRESH_RATE: u32 = 60; // TODO: exact refresh rate is 59.7
 
@@ -16,8 +45,38 @@ pub const VERTICAL_BLANK_SCAN_LINE_MAX: u8 = 153;
 
 pub const CPU_CLOCK_SPEED: u32 = 1024 * 1024 * 4;
 
+/// Number of clock cycles it takes to render a single frame (154 scanlines).
+pub const CYCLES_PER_FRAME: u32 = 456 * (VERTICAL_BLANK_SCAN_LINE_MAX as u32 + 1);
+
 /// This trait defines a common interface to interact with the memory bus.
 pub trait AddressSpace {
     fn write(&mut self, address: u16, value: u8);
     fn read(&self, address: u16) -> u8;
+
+    /// Called when the CPU increments or decrements a 16-bit register without accessing
+    /// memory. The register is still put on the address bus, which can corrupt OAM.
+    fn increment_address(&mut self, _address: u16) {}
+
+    /// Called by the CPU at the end of each machine cycle of an instruction up to its last
+    /// memory access, internal cycles included. Used to time the accesses within an instruction,
+    /// e.g. by `RecordingBus` and for writes to the timer registers.
+    fn tick(&mut self) {}
+}
+
+/// This trait defines a common interface for devices which display the frames rendered by the PPU.
+/// Input is polled from the same device once per frame.
+pub trait Screen {
+    fn write_pixel(&mut self, x: u8, y: u8, value: Color);
+    fn render_screen(&mut self);
+    fn pressed_buttons(&self) -> Buttons;
+
+    /// Receives the completed frame at the start of VBlank, right before `render_screen`.
+    /// Screens which only need whole frames, e.g. encoders, can ignore `write_pixel`.
+    fn frame_ready(&mut self, _frame: &[Color]) {}
+
+    /// Shows a hint to the user or removes it if None is given
+    fn show_hint(&mut self, _hint: Option<&str>) {}
+
+    /// Receives the bytes which have been sent over the serial port since the last call
+    fn serial_output(&mut self, _bytes: &[u8]) {}
 }
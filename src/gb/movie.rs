@@ -0,0 +1,175 @@
+use crate::gb::joypad::Buttons;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+/// Identifies movie files and their format version
+const MAGIC: &[u8; 4] = b"RMV\x01";
+/// Flag which is set if the movie has been recorded with the cycle accurate PPU
+const FLAG_ACCURATE_PPU: u8 = 0b0000_0001;
+
+/// Describes the initial state a movie has been recorded from.
+/// Movies always start at power-on without cartridge RAM,
+/// so the game and the emulation settings which affect timing are sufficient.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovieHeader {
+    pub title: String,
+    pub accurate_ppu: bool,
+}
+
+impl MovieHeader {
+    fn to_bytes(&self) -> Vec<u8> {
+        let flags = match self.accurate_ppu {
+            true => FLAG_ACCURATE_PPU,
+            false => 0,
+        };
+        // The title is at most 16 characters long
+        let title = self.title.as_bytes();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[flags, title.len() as u8]);
+        bytes.extend_from_slice(title);
+        bytes
+    }
+}
+
+/// Writes the pressed buttons of each frame to a movie file.
+/// Every frame is written right away, so the movie is complete even if the emulator is killed.
+pub struct MovieRecorder {
+    file: File,
+    frames: usize,
+}
+
+impl MovieRecorder {
+    pub fn create(path: &Path, header: &MovieHeader) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        file.write_all(&header.to_bytes())?;
+        Ok(Self { file, frames: 0 })
+    }
+
+    pub fn record(&mut self, buttons: Buttons) -> io::Result<()> {
+        self.frames += 1;
+        self.file.write_all(&[buttons.bits()])
+    }
+}
+
+/// Recorded buttons of each frame which are replayed in order
+pub struct Movie {
+    pub header: MovieHeader,
+    frames: Vec<Buttons>,
+    position: usize,
+}
+
+impl Movie {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    fn parse(data: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        if data.len() < MAGIC.len() + 2 || !data.starts_with(MAGIC) {
+            return Err(invalid("Not a movie file"));
+        }
+        let flags = data[4];
+        let title_end = 6 + usize::from(data[5]);
+        let title = data
+            .get(6..title_end)
+            .ok_or_else(|| invalid("Truncated movie"))?;
+        Ok(Self {
+            header: MovieHeader {
+                title: String::from_utf8_lossy(title).to_string(),
+                accurate_ppu: flags & FLAG_ACCURATE_PPU != 0,
+            },
+            frames: data[title_end..]
+                .iter()
+                .map(|bits| Buttons::from_bits_truncate(*bits))
+                .collect(),
+            position: 0,
+        })
+    }
+
+    /// Returns the buttons of the next frame or None if the movie has ended
+    pub fn next_frame(&mut self) -> Option<Buttons> {
+        let buttons = self.frames.get(self.position).copied();
+        self.position += buttons.is_some() as usize;
+        buttons
+    }
+
+    /// Returns the number of played and total frames
+    pub fn progress(&self) -> (usize, usize) {
+        (self.position, self.frames.len())
+    }
+}
+
+/// Defines whether the inputs of a session are recorded or replayed
+pub enum MovieMode {
+    Recording(MovieRecorder),
+    Playing(Movie),
+}
+
+impl MovieMode {
+    /// Records the pressed buttons or replaces them with the movie.
+    /// Live input takes over once the movie has ended.
+    pub fn next_frame(&mut self, pressed: Buttons) -> io::Result<Buttons> {
+        match self {
+            MovieMode::Recording(recorder) => recorder.record(pressed).map(|_| pressed),
+            MovieMode::Playing(movie) => Ok(movie.next_frame().unwrap_or(pressed)),
+        }
+    }
+
+    /// Returns a short status for the window title
+    pub fn status(&self) -> String {
+        match self {
+            MovieMode::Recording(recorder) => format!("Recording movie ({})", recorder.frames),
+            MovieMode::Playing(movie) => match movie.progress() {
+                (played, total) if played < total => {
+                    format!("Playing movie ({}/{})", played, total)
+                }
+                _ => "Movie finished".to_string(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_record_and_play() {
+        let path = env::temp_dir().join("romoulade-test-movie.rmv");
+        let header = MovieHeader {
+            title: "TETRIS".to_string(),
+            accurate_ppu: true,
+        };
+        let mut recording = MovieMode::Recording(MovieRecorder::create(&path, &header).unwrap());
+        for buttons in [Buttons::A, Buttons::empty(), Buttons::UP | Buttons::START].iter() {
+            assert_eq!(recording.next_frame(*buttons).unwrap(), *buttons);
+        }
+        drop(recording);
+
+        let movie = Movie::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(movie.header, header);
+        assert_eq!(movie.progress(), (0, 3));
+
+        let mut playing = MovieMode::Playing(movie);
+        assert_eq!(playing.next_frame(Buttons::B).unwrap(), Buttons::A);
+        assert_eq!(playing.next_frame(Buttons::B).unwrap(), Buttons::empty());
+        assert_eq!(
+            playing.next_frame(Buttons::B).unwrap(),
+            Buttons::UP | Buttons::START
+        );
+        assert_eq!(playing.status(), "Movie finished");
+        // Live input takes over after the last frame
+        assert_eq!(playing.next_frame(Buttons::B).unwrap(), Buttons::B);
+    }
+
+    #[test]
+    fn test_invalid_movie() {
+        assert!(Movie::parse(b"GIF89a").is_err());
+        assert!(Movie::parse(b"RMV\x01\x00\x05TET").is_err());
+    }
+}
@@ -0,0 +1,60 @@
+use crate::gb::{CYCLES_PER_FRAME, DISPLAY_REFRESH_RATE};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Paces emulation to real time based on the emulated clock cycles.
+/// Deadlines are computed from the start, so delays don't accumulate.
+pub struct Pacer {
+    start: Instant,
+    cycles: u64,
+}
+
+impl Default for Pacer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pacer {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            cycles: 0,
+        }
+    }
+
+    /// Accounts the given cycles and blocks the current thread
+    /// whenever a frame has been emulated faster than real time.
+    pub fn step(&mut self, cycles: u32) {
+        let frames = self.cycles / u64::from(CYCLES_PER_FRAME);
+        self.cycles += u64::from(cycles);
+        if self.cycles / u64::from(CYCLES_PER_FRAME) == frames {
+            return;
+        }
+        let deadline = self.start + deadline(self.cycles);
+        let now = Instant::now();
+        if now < deadline {
+            thread::sleep(deadline - now);
+        }
+    }
+}
+
+/// Returns the real time it takes to run the given cycles
+/// at `DISPLAY_REFRESH_RATE` frames per second.
+fn deadline(cycles: u64) -> Duration {
+    let frames = cycles / u64::from(CYCLES_PER_FRAME);
+    Duration::from_secs(frames) / DISPLAY_REFRESH_RATE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline() {
+        let frame = u64::from(CYCLES_PER_FRAME);
+        assert_eq!(deadline(frame - 1), Duration::from_secs(0));
+        assert_eq!(deadline(frame * 60), Duration::from_secs(1));
+        assert_eq!(deadline(frame * 90 + 1), Duration::from_millis(1500));
+    }
+}
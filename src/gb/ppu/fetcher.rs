@@ -1,6 +1,6 @@
-use crate::gb::memory::constants::{PPU_BGP, PPU_LCDC};
+use crate::gb::memory::constants::{PPU_LCDC, PPU_SCX};
 use crate::gb::memory::MemoryBus;
-use crate::gb::ppu::misc::{Color, Palette, Pixel};
+use crate::gb::ppu::misc::Pixel;
 use crate::gb::ppu::LCDControl;
 use crate::gb::timer::Clock;
 use crate::gb::AddressSpace;
@@ -17,8 +17,9 @@ pub enum FetcherState {
 
 /// Implements the PixelPipeline Fetcher outlined in "Ultimate Gamboy Talk",
 /// it runs at half the speed of the PPU (every 2 clock cycles).
+/// Pushing to the FIFO is attempted on every clock cycle until it succeeds.
 pub struct Fetcher<'a> {
-    pub fifo: VecDeque<Color>, // Pixel FIFO that the PPU will read.
+    pub fifo: VecDeque<Pixel>, // Pixel FIFO that the PPU will read, colorized on output.
     bus: &'a RefCell<MemoryBus>,
     clock: Clock,          // Clock cycle counter for timings.
     state: FetcherState,   // Current state of our state machine.
@@ -28,6 +29,7 @@ pub struct Fetcher<'a> {
     tile_index: u8,        // Index of the tile to read in the current row of the background map.
     tile_id: i16,          // Tile number in the tilemap.
     tile_data: [Pixel; 8], // Pixel data for one row of the fetched tile.
+    window: bool,          // Whether the window is fetched, which isn't scrolled.
 }
 
 impl<'a> Fetcher<'a> {
@@ -43,17 +45,20 @@ impl<'a> Fetcher<'a> {
             tile_line: 0,
             tile_id: 0,
             tile_data: [Pixel::Zero; 8],
+            window: false,
         }
     }
 
-    /// Start fetching a line of pixels starting from the given tile address in the
-    /// background map. Here, tileLine indicates which row of pixels to pick from
-    /// each tile we read.
+    /// Start fetching a line of pixels from the given row in the background map.
+    /// Here, tileLine indicates which row of pixels to pick from each tile we read.
     pub fn start(&mut self, map_address: u16, tile_line: u8) {
         self.tile_index = 0;
         self.map_address = map_address;
         self.tile_line = tile_line;
         self.state = FetcherState::ReadTileID;
+        // The first tile ID is read on the first clock cycle
+        self.clock.reset();
+        self.clock.advance(1);
         self.tile_address = match self.read_ctrl().contains(LCDControl::TILE_SEL) {
             true => 0x8000,
             false => 0x8800,
@@ -62,10 +67,22 @@ impl<'a> Fetcher<'a> {
         // Clear FIFO between calls, as it may still contain leftover tile data
         // from the very end of the previous scanline.
         self.fifo.clear();
+        self.window = false;
+    }
+
+    /// Start fetching a line of pixels from the given row in the window map,
+    /// pixels of the background which are still in the FIFO are dropped.
+    pub fn start_window(&mut self, map_address: u16, tile_line: u8) {
+        self.start(map_address, tile_line);
+        self.window = true;
     }
 
     pub fn step(&mut self) {
         self.clock.advance(1);
+        if let FetcherState::PushToFIFO = self.state {
+            self.push_to_fifo();
+            return;
+        }
         if self.clock.ticks() < 2 {
             return;
         }
@@ -75,8 +92,13 @@ impl<'a> Fetcher<'a> {
             FetcherState::ReadTileID => {
                 // Read the tile's number from the background map. This will be used
                 // in the next states to find the address where the tile's actual pixel
-                // data is stored in memory.
-                let address = self.map_address + u16::from(self.tile_index);
+                // data is stored in memory. SCX is read for every tile, so writes in
+                // the middle of a scanline take effect with the next fetched tile.
+                let column = match self.window {
+                    true => self.tile_index % 32,
+                    false => (self.read(PPU_SCX) / 8).wrapping_add(self.tile_index) % 32,
+                };
+                let address = self.map_address + u16::from(column);
                 // The double casts are very important, because depending on the
                 // memory address we read from the values can be u8 or i8!
                 self.tile_id = match self.tile_address {
@@ -94,20 +116,23 @@ impl<'a> Fetcher<'a> {
                 self.read_tile_line(1);
                 self.state = FetcherState::PushToFIFO;
             }
-            FetcherState::PushToFIFO => {
-                if self.fifo.len() <= 8 {
-                    let palette = Palette::from(self.read(PPU_BGP));
-                    // We stored pixel bits from least significant (rightmost) to most
-                    // (leftmost) in the data array, so we must push them in reverse
-                    // order.
-                    for i in (0..8).rev() {
-                        self.fifo.push_back(palette.colorize(self.tile_data[i]));
-                    }
-                    // Advance to the next tile in the map's row.
-                    self.tile_index += 1;
-                    self.state = FetcherState::ReadTileID;
-                }
+            FetcherState::PushToFIFO => self.push_to_fifo(),
+        }
+    }
+
+    /// Pushes the fetched pixels once the FIFO has room for them
+    fn push_to_fifo(&mut self) {
+        if self.fifo.len() <= 8 {
+            // We stored pixel bits from least significant (rightmost) to most
+            // (leftmost) in the data array, so we must push them in reverse
+            // order.
+            for i in (0..8).rev() {
+                self.fifo.push_back(self.tile_data[i]);
             }
+            // Advance to the next tile in the map's row.
+            self.tile_index = self.tile_index.wrapping_add(1);
+            self.state = FetcherState::ReadTileID;
+            self.clock.reset();
         }
     }
 
@@ -148,6 +173,6 @@ impl<'a> Fetcher<'a> {
     }
 
     fn read(&self, address: u16) -> u8 {
-        self.bus.borrow().read(address)
+        self.bus.borrow().read_unchecked(address)
     }
 }
@@ -63,7 +63,7 @@ impl convert::From<u8> for Pixel {
 
 /// Defines a colorized Pixel created
 /// from a non-colorized Pixel with a Palette.
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 #[repr(u8)]
 pub enum Color {
     White = 0x00,
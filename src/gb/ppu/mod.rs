@@ -1,13 +1,13 @@
 mod fetcher;
 pub mod misc;
 
-use crate::gb::display::Display;
 use crate::gb::interrupt::IRQ;
 use crate::gb::memory::constants::*;
 use crate::gb::memory::MemoryBus;
 use crate::gb::ppu::fetcher::Fetcher;
+use crate::gb::ppu::misc::{Color, Palette};
 use crate::gb::timer::Clock;
-use crate::gb::{AddressSpace, SCREEN_HEIGHT, SCREEN_WIDTH, VERTICAL_BLANK_SCAN_LINE_MAX};
+use crate::gb::{AddressSpace, Screen, SCREEN_HEIGHT, SCREEN_WIDTH, VERTICAL_BLANK_SCAN_LINE_MAX};
 use std::cell::RefCell;
 use std::convert;
 
@@ -38,6 +38,16 @@ bitflags! {
     }
 }
 
+/// Number of clock cycles it takes to scan the OAM at the start of a scanline
+const OAM_SEARCH_CYCLES: u32 = 80;
+/// WX is the X position of the window plus 7, larger values never show the window
+const WINDOW_X_OFFSET: u8 = 7;
+const WINDOW_X_MAX: u8 = 166;
+/// Number of clock cycles of a scanline
+const LINE_CYCLES: u32 = 456;
+/// LY reads 153 only at the start of the last line, afterwards it already reads 0
+const LAST_LINE_CYCLES: u32 = 4;
+
 /// Represents the first two bits in LCDState
 #[derive(Debug, Copy, Clone, PartialEq)]
 #[repr(u8)]
@@ -76,75 +86,174 @@ pub struct PPU<'a> {
     clock: Clock,
     bus: &'a RefCell<MemoryBus>,
     fetcher: Fetcher<'a>,
-    display: &'a mut Display,
+    display: &'a mut dyn Screen,
+    frame: Vec<Color>,
     x: u8,
+    discard: u8, // Pixels to discard at the start of the scanline for SCX fine scrolling
+    cycle_accurate: bool,
+    /// Whether LY matched WY in the current frame, which is required to show the window
+    window_y_reached: bool,
+    /// Whether the window is drawn from the current pixel to the end of the scanline
+    in_window: bool,
+    /// Window internal line counter, only advances on scanlines which show the window
+    window_line: u8,
+    /// Whether any enabled source of the STAT interrupt is active
+    stat_line: bool,
 }
 
 impl<'a> PPU<'a> {
-    pub fn new(bus: &'a RefCell<MemoryBus>, display: &'a mut Display) -> Self {
+    pub fn new(bus: &'a RefCell<MemoryBus>, display: &'a mut dyn Screen) -> Self {
         Self {
             clock: Clock::new(),
             bus,
             fetcher: Fetcher::new(&bus),
             display,
+            frame: vec![Color::White; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize],
             x: 0,
+            discard: 0,
+            cycle_accurate: false,
+            window_y_reached: false,
+            in_window: false,
+            window_line: 0,
+            stat_line: false,
         }
     }
 
+    /// Advances the PPU by a single clock cycle at a time in all modes, so mode changes
+    /// and STAT interrupts happen at the exact clock cycle instead of after an instruction.
+    /// This is considerably slower, hence it's disabled by default.
+    pub fn enable_cycle_accuracy(&mut self) {
+        self.cycle_accurate = true;
+    }
+
+    /// Restores the power-on state, the screen keeps showing the last frame
+    pub fn reset(&mut self) {
+        self.clock = Clock::new();
+        self.fetcher = Fetcher::new(self.bus);
+        self.frame.fill(Color::White);
+        self.x = 0;
+        self.discard = 0;
+        self.window_y_reached = false;
+        self.in_window = false;
+        self.window_line = 0;
+        self.stat_line = false;
+    }
+
+    /// Shows a hint on the display or removes it if None is given
+    pub fn show_hint(&mut self, hint: Option<&str>) {
+        self.display.show_hint(hint);
+    }
+
+    /// Passes bytes sent over the serial port on to the display
+    pub fn serial_output(&mut self, bytes: &[u8]) {
+        self.display.serial_output(bytes);
+    }
+
+    /// Returns the framebuffer as it is right now. Pixels which haven't
+    /// been rendered yet in the current frame still hold the previous frame.
+    pub fn framebuffer(&self) -> &[Color] {
+        &self.frame
+    }
+
     pub fn step(&mut self, cycles: u32) {
+        // DMA is independent of the LCD state
+        self.bus.borrow_mut().step_dma(cycles);
+        if self.cycle_accurate {
+            (0..cycles).for_each(|_| self.tick(1));
+            return;
+        }
+        // The pixel transfer is always advanced cycle by cycle,
+        // its length determines when HBlank starts.
+        let mut remaining = cycles;
+        while remaining > 0 && self.lcd_mode() == LCDMode::PixelTransfer {
+            self.tick(1);
+            remaining -= 1;
+        }
+        if remaining > 0 {
+            self.tick(remaining);
+        }
+    }
+
+    /// Advances the state machine, the pixel transfer outputs a single pixel per call.
+    fn tick(&mut self, cycles: u32) {
         if !self.read_ctrl().contains(LCDControl::LCD_EN) {
             self.set_lcd_mode(LCDMode::VBlank);
+            self.stat_line = false;
             // Screen is off, PPU remains idle.
             return;
         }
 
         self.clock.advance(cycles);
 
-        let cur_mode = self.lcd_mode();
-        let (mode, irq) = match cur_mode {
+        let mode = match self.lcd_mode() {
             // In this state, the PPU would scan the OAM (Objects Attribute Memory)
             // from 0xfe00 to 0xfe9f to mix sprite pixels in the current line later.
-            // This always takes 40 ticks.
-            LCDMode::OAMSearch if self.clock.ticks() >= 40 => self.handle_oam_search(),
+            // This always takes 80 ticks.
+            LCDMode::OAMSearch if self.clock.ticks() >= OAM_SEARCH_CYCLES => {
+                self.handle_oam_search()
+            }
             LCDMode::PixelTransfer => self.handle_pixel_transfer(),
             // Nothing much to do here but wait the proper number of clock cycles.
-            // A full scanline takes 456 clock cycles to complete. At the end of a
+            // A full scanline takes 456 clock cycles to complete, so HBlank
+            // is shortened by the variable length of the pixel transfer. At the end of a
             // scanline, the PPU goes back to the initial OAM Search state.
             // When we reach line 144, we switch to VBlank state instead.
-            LCDMode::HBlank if self.clock.ticks() >= 456 => self.handle_hblank(),
+            LCDMode::HBlank if self.clock.ticks() >= LINE_CYCLES => self.handle_hblank(),
             // Nothing much to do here either. VBlank is when the CPU is supposed to
             // do stuff that takes time. It takes as many cycles as would be needed
             // to keep displaying scanlines up to line 153.
-            LCDMode::VBlank if self.clock.ticks() >= 456 => self.handle_vblank(),
+            LCDMode::VBlank
+                if self.read(PPU_LY) == VERTICAL_BLANK_SCAN_LINE_MAX
+                    && self.clock.ticks() >= LAST_LINE_CYCLES =>
+            {
+                self.write(PPU_LY, 0);
+                LCDMode::VBlank
+            }
+            LCDMode::VBlank if self.clock.ticks() >= LINE_CYCLES => self.handle_vblank(),
             // No mode change occurred
-            mode => (mode, false),
+            mode => mode,
         };
 
-        // handle pending interrupts if mode changed
-        if mode != cur_mode && irq {
-            self.bus.borrow_mut().irq(IRQ::LCD);
-        }
         self.set_lcd_mode(mode);
+        if mode == LCDMode::OAMSearch {
+            let row = (self.clock.ticks() / 4).min(19) as u8;
+            self.bus.borrow_mut().set_oam_scan_row(row);
+        }
 
-        self.handle_coincidence_flag();
+        self.update_coincidence_flag();
+        self.update_stat_line();
     }
 
-    /// Checks the coincidence flag and requests an interrupt if required.
-    fn handle_coincidence_flag(&mut self) {
+    /// Sets the coincidence flag while LY equals LYC
+    fn update_coincidence_flag(&mut self) {
         let state = self.read_stat();
-        if self.read(PPU_LY) == self.read(PPU_LYC) {
-            if state.contains(LCDState::LY_INT) {
-                self.bus.borrow_mut().irq(IRQ::LCD);
-            }
-            self.write_stat(state - LCDState::LYC_STAT);
-        } else {
-            self.write_stat(state | LCDState::LYC_STAT);
+        match self.read(PPU_LY) == self.read(PPU_LYC) {
+            true => self.write_stat(state | LCDState::LYC_STAT),
+            false => self.write_stat(state - LCDState::LYC_STAT),
         }
     }
 
+    /// The STAT interrupt line is the OR of all enabled sources and the interrupt is only
+    /// requested on its rising edge. A source which becomes active while another one
+    /// already is, e.g. HBlank directly followed by a LY coincidence, doesn't request it.
+    fn update_stat_line(&mut self) {
+        let state = self.read_stat();
+        let mode = match self.lcd_mode() {
+            LCDMode::HBlank => state.contains(LCDState::H_BLANK_INT),
+            LCDMode::VBlank => state.contains(LCDState::V_BLANK_INT),
+            LCDMode::OAMSearch => state.contains(LCDState::OAM_INT),
+            LCDMode::PixelTransfer => false,
+        };
+        let line = mode || state.contains(LCDState::LY_INT | LCDState::LYC_STAT);
+        if line && !self.stat_line {
+            self.bus.borrow_mut().irq(IRQ::LCD);
+        }
+        self.stat_line = line;
+    }
+
     /// Handles the OAMSearch mode.
-    /// Returns a tuple with the new LCDMode and whether a interrupt has been requested.
-    fn handle_oam_search(&mut self) -> (LCDMode, bool) {
+    /// Returns the new LCDMode.
+    fn handle_oam_search(&mut self) -> LCDMode {
         // Move to Pixel Transfer state. Initialize the fetcher to start
         // reading background tiles from VRAM. The boot ROM does nothing
         // fancy with map addresses, so we just give the fetcher the base
@@ -157,9 +266,14 @@ impl<'a> PPU<'a> {
         // each tile, we can tell which 8-pixel line to fetch by computing
         // Y modulo 8.
         self.x = 0;
-        // TODO: add case for drawing windows
+        self.discard = self.read(PPU_SCX) % 8;
+        self.in_window = false;
+        // WY is compared once per scanline, once it matched the window
+        // can be shown for the rest of the frame even if WY changes.
+        if self.read(PPU_LY) == self.read(PPU_WY) {
+            self.window_y_reached = true;
+        }
         let y = self.read(PPU_SCY).wrapping_add(self.read(PPU_LY));
-        let x = self.read(PPU_SCX).wrapping_add(self.x);
 
         let bg_address = match self.read_ctrl().contains(LCDControl::BG_MAP) {
             true => 0x9C00,
@@ -167,68 +281,119 @@ impl<'a> PPU<'a> {
         };
 
         let tile_row = u16::from(y / 8) * 32;
-        let tile_column = u16::from(x / 8);
-        let tile_map_row_addr = bg_address + tile_row + tile_column;
+        let tile_map_row_addr = bg_address + tile_row;
 
         let tile_line = y % 8;
         self.fetcher.start(tile_map_row_addr, tile_line);
-        (LCDMode::PixelTransfer, false)
+        LCDMode::PixelTransfer
     }
 
     /// Handles the HBlank mode.
-    /// Returns a tuple with the new LCDMode and whether a interrupt has been requested.
-    fn handle_hblank(&mut self) -> (LCDMode, bool) {
-        self.clock.reset();
+    /// Returns the new LCDMode.
+    fn handle_hblank(&mut self) -> LCDMode {
+        self.start_next_line();
+        if self.in_window {
+            self.window_line = self.window_line.wrapping_add(1);
+        }
         self.write(PPU_LY, self.read(PPU_LY).wrapping_add(1));
 
-        let state = self.read_stat();
         if self.read(PPU_LY) == SCREEN_HEIGHT {
+            self.bus.borrow_mut().irq(IRQ::VBlank);
+            self.display.frame_ready(&self.frame);
             self.display.render_screen();
-            return (LCDMode::VBlank, state.contains(LCDState::V_BLANK_INT));
+            let buttons = self.display.pressed_buttons();
+            self.bus.borrow_mut().set_buttons(buttons);
+            self.bus.borrow_mut().apply_cheats();
+            return LCDMode::VBlank;
         }
-        (LCDMode::OAMSearch, state.contains(LCDState::OAM_INT))
+        LCDMode::OAMSearch
     }
 
-    /// Handles the VBlank mode.
-    /// Returns a tuple with the new LCDMode and whether a interrupt has been requested.
-    fn handle_vblank(&mut self) -> (LCDMode, bool) {
+    /// Restarts the clock for the next scanline. Instructions take several clock cycles,
+    /// the cycles past the end of the scanline count towards the next one.
+    fn start_next_line(&mut self) {
+        let overshoot = self.clock.ticks().saturating_sub(LINE_CYCLES);
         self.clock.reset();
-        self.write(PPU_LY, self.read(PPU_LY).wrapping_add(1));
+        self.clock.advance(overshoot);
+    }
 
-        let state = self.read_stat();
-        if self.read(PPU_LY) == VERTICAL_BLANK_SCAN_LINE_MAX {
-            self.write(PPU_LY, 0);
-            return (LCDMode::OAMSearch, state.contains(LCDState::OAM_INT));
+    /// Handles the VBlank mode.
+    /// Returns the new LCDMode.
+    fn handle_vblank(&mut self) -> LCDMode {
+        self.start_next_line();
+        // LY has been reset early during line 153, the next frame starts
+        if self.read(PPU_LY) == 0 {
+            self.window_y_reached = false;
+            self.window_line = 0;
+            return LCDMode::OAMSearch;
         }
-        (LCDMode::VBlank, false)
+        self.write(PPU_LY, self.read(PPU_LY).wrapping_add(1));
+        LCDMode::VBlank
     }
 
     /// Handles the PixelTransfer mode.
-    /// Returns a tuple with the new LCDMode and whether a interrupt has been requested.
-    fn handle_pixel_transfer(&mut self) -> (LCDMode, bool) {
+    /// Returns the new LCDMode.
+    fn handle_pixel_transfer(&mut self) -> LCDMode {
+        if !self.in_window && self.window_starts() {
+            self.start_window();
+        }
         // Fetch pixel data into our pixel FIFO.
         self.fetcher.step();
         // Stop here if the FIFO isn't holding at least 8 pixels. This will
         // be used to mix in sprite data when we implement these. It also
         // guarantees the FIFO will always have data to Pop() later.
         if self.fetcher.fifo.len() <= 8 {
-            return (LCDMode::PixelTransfer, false);
+            return LCDMode::PixelTransfer;
         }
         // Put a pixel from the FIFO on screen if we have any.
-        if let Some(color) = self.fetcher.fifo.pop_front() {
-            self.display.write_pixel(self.x, self.read(PPU_LY), color);
+        // The palette is applied on output, so BGP writes take effect immediately.
+        if let Some(pixel) = self.fetcher.fifo.pop_front() {
+            // Pixels scrolled out to the left are dropped
+            if self.discard > 0 {
+                self.discard -= 1;
+                return LCDMode::PixelTransfer;
+            }
+            // On the DMG clearing BG_EN blanks both the background and the window
+            let color = match self.read_ctrl().contains(LCDControl::BG_EN) {
+                true => Palette::from(self.read(PPU_BGP)).colorize(pixel),
+                false => Color::White,
+            };
+            let y = self.read(PPU_LY);
+            self.frame[usize::from(y) * usize::from(SCREEN_WIDTH) + usize::from(self.x)] = color;
+            self.display.write_pixel(self.x, y, color);
         }
         // Check when the scanline is complete (160 pixels).
         self.x = self.x.wrapping_add(1);
         match self.x == SCREEN_WIDTH {
-            true => (
-                LCDMode::HBlank,
-                self.read_stat().contains(LCDState::H_BLANK_INT),
-            ),
-            false => (LCDMode::PixelTransfer, false),
+            true => LCDMode::HBlank,
+            false => LCDMode::PixelTransfer,
         }
     }
 
+    /// Returns whether the window starts at the current pixel. WIN_EN is checked for
+    /// each pixel, so enabling the window in the middle of a scanline shows it right away.
+    fn window_starts(&self) -> bool {
+        let wx = self.read(PPU_WX);
+        self.window_y_reached
+            && self.read_ctrl().contains(LCDControl::WIN_EN)
+            && wx <= WINDOW_X_MAX
+            && u16::from(self.x) + u16::from(WINDOW_X_OFFSET) >= u16::from(wx)
+    }
+
+    /// Switches the fetcher to the row of the window map given by the internal line counter.
+    /// With WX below 7 the window starts left of the screen and its first pixels are dropped.
+    fn start_window(&mut self) {
+        self.in_window = true;
+        self.discard = WINDOW_X_OFFSET.saturating_sub(self.read(PPU_WX)).min(7);
+        let map_address = match self.read_ctrl().contains(LCDControl::WIN_MAP) {
+            true => 0x9C00,
+            false => 0x9800,
+        };
+        let tile_row = u16::from(self.window_line / 8) * 32;
+        self.fetcher
+            .start_window(map_address + tile_row, self.window_line % 8);
+    }
+
     /// Fetches the current LCD_MODE from PPU_STAT register
     fn lcd_mode(&self) -> LCDMode {
         LCDMode::from(self.read(PPU_STAT) & 0b11)
@@ -261,6 +426,264 @@ impl<'a> AddressSpace for PPU<'a> {
     }
 
     fn read(&self, address: u16) -> u8 {
-        self.bus.borrow().read(address)
+        self.bus.borrow().read_unchecked(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gb::cartridge::Cartridge;
+    use crate::gb::headless::Headless;
+    use crate::gb::CYCLES_PER_FRAME;
+
+    /// Creates a bus where the whole background shows black tiles
+    fn create_bus() -> RefCell<MemoryBus> {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x14D] = 0xE7;
+        let mut bus = MemoryBus::new(Cartridge::from_bytes(rom));
+        for offset in 0..16 {
+            bus.write(VRAM_BEGIN + offset, 0xFF);
+        }
+        bus.write(PPU_BGP, 0xE4);
+        // LCD on, BG tile data at 0x8000, BG on
+        bus.write(PPU_LCDC, 0x91);
+        RefCell::new(bus)
+    }
+
+    /// Steps until the pixel transfer of a scanline started and returns the scanline
+    fn start_pixel_transfer(ppu: &mut PPU) -> usize {
+        while ppu.lcd_mode() != LCDMode::PixelTransfer {
+            ppu.step(4);
+        }
+        usize::from(ppu.read(PPU_LY))
+    }
+
+    /// Returns the pixels of the given scanline
+    fn scanline<'a>(ppu: &'a PPU, y: usize) -> &'a [Color] {
+        let width = usize::from(SCREEN_WIDTH);
+        &ppu.framebuffer()[y * width..(y + 1) * width]
+    }
+
+    /// Shows a white background and a window whose first tile column is black
+    fn create_window_bus(wx: u8) -> RefCell<MemoryBus> {
+        let bus = create_bus();
+        {
+            let mut bus = bus.borrow_mut();
+            for offset in 0..0x400 {
+                bus.write(0x9800 + offset, 0x01);
+                let tile = if offset % 32 == 0 { 0x00 } else { 0x01 };
+                bus.write(0x9C00 + offset, tile);
+            }
+            bus.write(PPU_WX, wx);
+            // The first scanline rendered by the tests
+            bus.write(PPU_WY, 1);
+            // Window on with its map at 0x9C00
+            bus.write(PPU_LCDC, 0xF1);
+        }
+        bus
+    }
+
+    /// Renders the next scanline and returns it
+    fn render_scanline(ppu: &mut PPU) -> Vec<Color> {
+        let y = start_pixel_transfer(ppu);
+        while ppu.lcd_mode() == LCDMode::PixelTransfer {
+            ppu.step(4);
+        }
+        scanline(ppu, y).to_vec()
+    }
+
+    #[test]
+    fn test_window() {
+        let bus = create_window_bus(7 + 16);
+        let mut screen = Headless::new();
+        let mut ppu = PPU::new(&bus, &mut screen);
+        ppu.enable_cycle_accuracy();
+        let line = render_scanline(&mut ppu);
+        assert!(line[..16].iter().all(|color| *color == Color::White));
+        assert!(line[16..24].iter().all(|color| *color == Color::Black));
+        assert_eq!(line[24], Color::White);
+    }
+
+    #[test]
+    fn test_window_left_edge() {
+        // The window starts 4 pixels left of the screen
+        let bus = create_window_bus(3);
+        bus.borrow_mut().write(PPU_SCX, 5);
+        let mut screen = Headless::new();
+        let mut ppu = PPU::new(&bus, &mut screen);
+        ppu.enable_cycle_accuracy();
+        let line = render_scanline(&mut ppu);
+        assert!(line[..4].iter().all(|color| *color == Color::Black));
+        assert_eq!(line[4], Color::White);
+    }
+
+    #[test]
+    fn test_window_line_counter() {
+        let bus = create_window_bus(7);
+        let mut screen = Headless::new();
+        let mut ppu = PPU::new(&bus, &mut screen);
+        ppu.enable_cycle_accuracy();
+        render_scanline(&mut ppu);
+        render_scanline(&mut ppu);
+        // Lines without the window don't advance the counter
+        bus.borrow_mut().write(PPU_LCDC, 0xD1);
+        render_scanline(&mut ppu);
+        bus.borrow_mut().write(PPU_LCDC, 0xF1);
+        render_scanline(&mut ppu);
+        // The counter advances at the end of the scanline
+        assert!(ppu.in_window);
+        assert_eq!(ppu.window_line, 2);
+
+        // Moving WY below LY after it matched keeps the window
+        bus.borrow_mut().write(PPU_WY, 100);
+        render_scanline(&mut ppu);
+        assert!(ppu.in_window);
+        assert_eq!(ppu.window_line, 3);
+    }
+
+    #[test]
+    fn test_bg_disabled() {
+        let bus = create_window_bus(7 + 16);
+        // The background is black like the first column of the window
+        for offset in 0..0x400 {
+            bus.borrow_mut().write(0x9800 + offset, 0x00);
+        }
+        // Window on, BG off
+        bus.borrow_mut().write(PPU_LCDC, 0xF0);
+        let mut screen = Headless::new();
+        let mut ppu = PPU::new(&bus, &mut screen);
+        ppu.enable_cycle_accuracy();
+        let line = render_scanline(&mut ppu);
+        assert!(line.iter().all(|color| *color == Color::White));
+    }
+
+    #[test]
+    fn test_last_line() {
+        let bus = create_bus();
+        let mut screen = Headless::new();
+        let mut ppu = PPU::new(&bus, &mut screen);
+        while ppu.read(PPU_LY) != VERTICAL_BLANK_SCAN_LINE_MAX {
+            ppu.step(4);
+        }
+        // LY reads 0 after the first machine cycle of line 153, LYC=0 matches already
+        ppu.step(4);
+        assert_eq!(ppu.read(PPU_LY), 0);
+        assert_eq!(ppu.lcd_mode(), LCDMode::VBlank);
+        assert!(ppu.read_stat().contains(LCDState::LYC_STAT));
+
+        // The rest of the line is still VBlank
+        for _ in 0..(LINE_CYCLES - 8) / 4 {
+            ppu.step(4);
+            assert_eq!(ppu.lcd_mode(), LCDMode::VBlank);
+        }
+        ppu.step(4);
+        assert_eq!(ppu.read(PPU_LY), 0);
+        assert_eq!(ppu.lcd_mode(), LCDMode::OAMSearch);
+    }
+
+    #[test]
+    fn test_vblank_interrupt() {
+        let bus = create_bus();
+        let mut screen = Headless::new();
+        let mut ppu = PPU::new(&bus, &mut screen);
+        while ppu.read(PPU_LY) != SCREEN_HEIGHT - 1 {
+            ppu.step(4);
+        }
+        ppu.write(INTERRUPT_FLAG, 0);
+        let mut cycles = 0;
+        while cycles < CYCLES_PER_FRAME {
+            ppu.step(4);
+            cycles += 4;
+        }
+        assert_eq!(ppu.read(INTERRUPT_FLAG) & 1, 1);
+    }
+
+    /// Returns whether the LCD interrupt is requested between HBlank of line 1
+    /// and the start of line 2 with the given STAT interrupt sources and LYC=2.
+    fn lcd_interrupt_on_line(stat: u8) -> bool {
+        let bus = create_bus();
+        bus.borrow_mut().write(PPU_STAT, stat);
+        bus.borrow_mut().write(PPU_LYC, 2);
+        let mut screen = Headless::new();
+        let mut ppu = PPU::new(&bus, &mut screen);
+        while ppu.read(PPU_LY) != 1 || ppu.lcd_mode() != LCDMode::HBlank {
+            ppu.step(4);
+        }
+        bus.borrow_mut().write(INTERRUPT_FLAG, 0x00);
+        while ppu.read(PPU_LY) != 2 {
+            ppu.step(4);
+        }
+        let requested = bus.borrow().read(INTERRUPT_FLAG) & 0x02 != 0;
+        requested
+    }
+
+    #[test]
+    fn test_stat_blocking() {
+        // LY=LYC requests the interrupt if no other source is active
+        assert!(lcd_interrupt_on_line(LCDState::LY_INT.bits));
+        // The HBlank source is still active when the coincidence starts
+        assert!(!lcd_interrupt_on_line(
+            (LCDState::LY_INT | LCDState::H_BLANK_INT).bits
+        ));
+    }
+
+    #[test]
+    fn test_mid_scanline_palette_write() {
+        let bus = create_bus();
+        let mut screen = Headless::new();
+        let mut ppu = PPU::new(&bus, &mut screen);
+        ppu.enable_cycle_accuracy();
+        let y = start_pixel_transfer(&mut ppu);
+        for _ in 0..25 {
+            ppu.step(4);
+        }
+        bus.borrow_mut().write(PPU_BGP, 0x00);
+        while ppu.lcd_mode() == LCDMode::PixelTransfer {
+            ppu.step(4);
+        }
+
+        let line = scanline(&ppu, y);
+        assert_eq!(line[0], Color::Black);
+        assert_eq!(line[usize::from(SCREEN_WIDTH) - 1], Color::White);
+    }
+
+    #[test]
+    fn test_fine_scroll() {
+        let bus = create_bus();
+        // Only the first tile of the map row is black
+        for offset in 1..32 {
+            bus.borrow_mut().write(0x9800 + offset, 0x01);
+        }
+        bus.borrow_mut().write(PPU_SCX, 3);
+        let mut screen = Headless::new();
+        let mut ppu = PPU::new(&bus, &mut screen);
+        ppu.enable_cycle_accuracy();
+        let y = start_pixel_transfer(&mut ppu);
+        while ppu.lcd_mode() == LCDMode::PixelTransfer {
+            ppu.step(4);
+        }
+
+        let line = scanline(&ppu, y);
+        assert!(line[..5].iter().all(|color| *color == Color::Black));
+        assert_eq!(line[5], Color::White);
+    }
+
+    #[test]
+    fn test_pixel_transfer_length() {
+        for scx in [0, 3, 7].iter() {
+            let bus = create_bus();
+            bus.borrow_mut().write(PPU_SCX, *scx);
+            let mut screen = Headless::new();
+            let mut ppu = PPU::new(&bus, &mut screen);
+            start_pixel_transfer(&mut ppu);
+            let mut cycles = 0;
+            while ppu.lcd_mode() == LCDMode::PixelTransfer {
+                ppu.step(1);
+                cycles += 1;
+            }
+            // Scrolled out pixels are fetched and discarded
+            assert_eq!(cycles, 172 + u32::from(*scx));
+        }
     }
 }
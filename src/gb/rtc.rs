@@ -0,0 +1,264 @@
+use std::convert::TryInto;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Size of the clock state appended to the save file. This is the format used by most
+/// emulators: the current and latched registers as 32 bit values and a 64 bit UNIX timestamp.
+pub const RTC_SAVE_SIZE: usize = 48;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+/// The day counter has 9 bits
+const MAX_DAYS: u64 = 512;
+/// Bits of the registers S, M, H, DL and DH which are used
+const REGISTER_MASKS: [u8; 5] = [0x3F, 0x3F, 0x1F, 0xFF, 0xC1];
+const DH_HALT: u8 = 0b0100_0000;
+const DH_CARRY: u8 = 0b1000_0000;
+
+/// Returns the host time in seconds since the UNIX epoch
+pub fn host_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// Parses a time in the format `<day>:<hours>:<minutes>:<seconds>` to seconds since day 0
+pub fn parse_time(time: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid time, expected <day>:<hh>:<mm>:<ss>: {}", time);
+    let fields = time
+        .split(':')
+        .map(|field| field.parse::<u64>())
+        .collect::<Result<Vec<u64>, _>>()
+        .map_err(|_| invalid())?;
+    match fields[..] {
+        [days, hours, minutes, seconds]
+            if days < MAX_DAYS && hours < 24 && minutes < 60 && seconds < 60 =>
+        {
+            Ok(days * SECONDS_PER_DAY + hours * 3600 + minutes * 60 + seconds)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Real time clock of MBC3 cartridges. The emulated clock follows the host clock
+/// with an offset, it can be paused or run at a multiple of the host speed.
+/// All methods take the current host time in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rtc {
+    base_host: u64,    // Host time the clock has been set at
+    base_seconds: u64, // Emulated seconds at `base_host`
+    speed: u64,
+    halted: bool,
+    carry: bool,
+    latched: [u8; 5],
+}
+
+impl Rtc {
+    pub fn new(host: u64) -> Self {
+        Self {
+            base_host: host,
+            base_seconds: 0,
+            speed: 1,
+            halted: false,
+            carry: false,
+            latched: [0; 5],
+        }
+    }
+
+    /// Returns the emulated seconds since day 0
+    pub fn seconds(&self, host: u64) -> u64 {
+        match self.halted {
+            true => self.base_seconds,
+            false => self.base_seconds + host.saturating_sub(self.base_host) * self.speed,
+        }
+    }
+
+    /// Sets the emulated seconds since day 0
+    pub fn set(&mut self, seconds: u64, host: u64) {
+        self.base_host = host;
+        self.base_seconds = seconds;
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Pauses or resumes the clock
+    pub fn set_halted(&mut self, halted: bool, host: u64) {
+        self.set(self.seconds(host), host);
+        self.halted = halted;
+    }
+
+    /// Runs the clock at a multiple of the host speed, e.g. to test day/night events
+    pub fn set_speed(&mut self, speed: u64, host: u64) {
+        self.set(self.seconds(host), host);
+        self.speed = speed;
+    }
+
+    /// Returns the registers S, M, H, DL and DH.
+    /// The carry bit is set once the day counter overflowed and stays set until it's cleared.
+    fn registers(&self, host: u64) -> [u8; 5] {
+        let seconds = self.seconds(host);
+        let carry = self.carry || seconds >= MAX_DAYS * SECONDS_PER_DAY;
+        let seconds = seconds % (MAX_DAYS * SECONDS_PER_DAY);
+        let days = seconds / SECONDS_PER_DAY;
+        let mut dh = (days >> 8) as u8;
+        if self.halted {
+            dh |= DH_HALT;
+        }
+        if carry {
+            dh |= DH_CARRY;
+        }
+        [
+            (seconds % 60) as u8,
+            (seconds / 60 % 60) as u8,
+            (seconds / 3600 % 24) as u8,
+            days as u8,
+            dh,
+        ]
+    }
+
+    /// Sets the clock to the given registers
+    fn set_registers(&mut self, registers: [u8; 5], host: u64) {
+        let [s, m, h, dl, dh] = registers;
+        let days = u64::from(dl) | u64::from(dh & 0x01) << 8;
+        let seconds = u64::from(s) + u64::from(m) * 60 + u64::from(h) * 3600;
+        self.halted = dh & DH_HALT != 0;
+        self.carry = dh & DH_CARRY != 0;
+        self.set(days * SECONDS_PER_DAY + seconds, host);
+    }
+
+    /// Copies the current time to the registers which are read by the game
+    pub fn latch(&mut self, host: u64) {
+        self.latched = self.registers(host);
+    }
+
+    /// Reads the latched register selected with 0x08-0x0C
+    pub fn read(&self, register: u8) -> u8 {
+        self.latched[usize::from(register - 0x08)]
+    }
+
+    /// Writes the register selected with 0x08-0x0C, which sets the clock
+    pub fn write(&mut self, register: u8, value: u8, host: u64) {
+        let index = usize::from(register - 0x08);
+        let mut registers = self.registers(host);
+        registers[index] = value & REGISTER_MASKS[index];
+        self.set_registers(registers, host);
+    }
+
+    /// Returns the current time and state, e.g. `day 3, 12:04:05 (paused)`
+    pub fn status(&self, host: u64) -> String {
+        let [s, m, h, dl, dh] = self.registers(host);
+        let days = u16::from(dl) | u16::from(dh & 0x01) << 8;
+        let mut status = format!("day {}, {:02}:{:02}:{:02}", days, h, m, s);
+        if self.halted {
+            status.push_str(" (paused)");
+        } else if self.speed != 1 {
+            status.push_str(&format!(" ({}x)", self.speed));
+        }
+        status
+    }
+
+    /// Serializes the clock to be appended to the save file
+    pub fn to_bytes(&self, host: u64) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(RTC_SAVE_SIZE);
+        for register in self.registers(host).iter().chain(self.latched.iter()) {
+            bytes.extend_from_slice(&u32::from(*register).to_le_bytes());
+        }
+        bytes.extend_from_slice(&host.to_le_bytes());
+        bytes
+    }
+
+    /// Restores a clock serialized with `to_bytes`,
+    /// the clock advanced by the time which passed since it has been saved.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != RTC_SAVE_SIZE {
+            return None;
+        }
+        let register = |i: usize| bytes[i * 4];
+        let host = u64::from_le_bytes(bytes[40..48].try_into().ok()?);
+        let mut rtc = Self::new(host);
+        rtc.set_registers(
+            [
+                register(0),
+                register(1),
+                register(2),
+                register(3),
+                register(4),
+            ],
+            host,
+        );
+        rtc.latched = [
+            register(5),
+            register(6),
+            register(7),
+            register(8),
+            register(9),
+        ];
+        Some(rtc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock() {
+        let mut rtc = Rtc::new(1000);
+        rtc.set(SECONDS_PER_DAY * 257 + 3661, 1000);
+        rtc.latch(1001);
+        let registers = (0x08..=0x0C).map(|r| rtc.read(r)).collect::<Vec<u8>>();
+        assert_eq!(registers, [2, 1, 1, 1, 0x01]);
+
+        // The registers are latched until the next latch
+        assert_eq!(rtc.read(0x08), 2);
+        rtc.set_speed(60, 1001);
+        assert_eq!(rtc.status(1002), "day 257, 01:02:02 (60x)");
+
+        rtc.write(0x0C, DH_HALT | 0x01, 1002);
+        assert_eq!(rtc.status(5000), "day 257, 01:02:02 (paused)");
+        // Hours beyond 23 carry over into the days
+        rtc.write(0x0A, 0xFF, 5000);
+        assert_eq!(rtc.status(5000), "day 258, 07:02:02 (paused)");
+    }
+
+    #[test]
+    fn test_day_carry() {
+        let mut rtc = Rtc::new(0);
+        rtc.set(MAX_DAYS * SECONDS_PER_DAY - 1, 0);
+        rtc.latch(1);
+        assert_eq!(rtc.read(0x0C), DH_CARRY);
+        assert_eq!(rtc.read(0x0B), 0);
+
+        // The carry stays set until it's cleared by the game
+        rtc.write(0x08, 30, 1);
+        rtc.latch(2);
+        assert_eq!(rtc.read(0x0C), DH_CARRY);
+        rtc.write(0x0C, 0, 2);
+        rtc.latch(2);
+        assert_eq!(rtc.read(0x0C), 0);
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("1:02:03:04"), Ok(SECONDS_PER_DAY + 7384));
+        assert!(parse_time("1:24:00:00").is_err());
+        assert!(parse_time("512:00:00:00").is_err());
+        assert!(parse_time("12:00").is_err());
+    }
+
+    #[test]
+    fn test_save() {
+        let mut rtc = Rtc::new(100);
+        rtc.set(SECONDS_PER_DAY + 60, 100);
+        rtc.latch(100);
+        let bytes = rtc.to_bytes(100);
+        assert_eq!(bytes.len(), RTC_SAVE_SIZE);
+
+        // The clock keeps running while the emulator is closed
+        let restored = Rtc::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.seconds(110), SECONDS_PER_DAY + 70);
+        assert_eq!(restored.read(0x0A), 0);
+        assert_eq!(restored.read(0x0B), 1);
+        assert!(Rtc::from_bytes(&bytes[..44]).is_none());
+    }
+}
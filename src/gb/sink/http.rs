@@ -0,0 +1,213 @@
+use crate::gb::colors::ColorScheme;
+use crate::gb::ppu::misc::Color;
+use crate::gb::sink::FrameSink;
+use crate::gb::triple_buffer::{triple_buffer, Reader, Writer};
+use crate::gb::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use jpeg_encoder::{ColorType, Encoder};
+use log::error;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::thread::Thread;
+
+/// Quality of the encoded JPEG frames, chroma subsampling is disabled from 90 upwards
+const JPEG_QUALITY: u8 = 90;
+/// Separates the frames of the MJPEG stream
+const BOUNDARY: &str = "frame";
+/// Page which shows the stream in a browser
+const INDEX: &str = "<!DOCTYPE html><html><head><title>Romoulade</title></head>\
+<body style=\"margin:0;background:#202020\">\
+<img src=\"/stream\" style=\"width:100vw;height:100vh;object-fit:contain;image-rendering:pixelated\">\
+</body></html>";
+
+/// The most recent frame, shared with all client threads
+struct LatestFrame {
+    frame: Mutex<(u64, Arc<Vec<u8>>)>, // Frame number and JPEG data
+    updated: Condvar,
+}
+
+impl LatestFrame {
+    /// Blocks until a frame newer than the given frame number is available
+    fn wait_newer(&self, number: u64) -> (u64, Arc<Vec<u8>>) {
+        let mut frame = self.frame.lock().unwrap();
+        while frame.0 <= number {
+            frame = self.updated.wait(frame).unwrap();
+        }
+        frame.clone()
+    }
+}
+
+/// Embedded HTTP server which serves the frames as MJPEG stream at `/stream`
+/// and the most recent frame as JPEG at `/frame.jpg`, `/` shows the stream in a browser.
+/// Frames are passed to the encoder thread through a triple buffer and each client is
+/// served on its own thread, so neither encoding nor slow clients slow down emulation.
+/// Frames which arrive while the encoder is busy are skipped.
+pub struct HttpSink {
+    address: SocketAddr,
+    frames: Writer<Vec<Color>>,
+    encoder: Thread,
+}
+
+impl HttpSink {
+    /// Binds the server to the given address and starts accepting clients
+    pub fn bind(address: &str, scheme: ColorScheme) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let latest = Arc::new(LatestFrame {
+            frame: Mutex::new((0, Arc::new(Vec::new()))),
+            updated: Condvar::new(),
+        });
+        let (frames, reader) = triple_buffer(Vec::new());
+        let encoder = {
+            let latest = Arc::clone(&latest);
+            thread::spawn(move || encode(reader, scheme, &latest))
+        };
+        let sink = Self {
+            address: listener.local_addr()?,
+            frames,
+            encoder: encoder.thread().clone(),
+        };
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let latest = Arc::clone(&latest);
+                thread::spawn(move || {
+                    // Errors are expected whenever a client disconnects
+                    let _ = serve(stream, &latest);
+                });
+            }
+        });
+        Ok(sink)
+    }
+
+    /// Returns the address the server is listening on
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl FrameSink for HttpSink {
+    fn push_frame(&mut self, frame: &[Color]) -> io::Result<()> {
+        self.frames.publish(|buffer| {
+            buffer.clear();
+            buffer.extend_from_slice(frame);
+        });
+        self.encoder.unpark();
+        Ok(())
+    }
+}
+
+/// Encodes the most recent frame as JPEG whenever a new one is available
+fn encode(mut frames: Reader<Vec<Color>>, scheme: ColorScheme, latest: &LatestFrame) {
+    let mut pixels = Vec::new();
+    loop {
+        thread::park();
+        while let Some(frame) = frames.latest() {
+            pixels.clear();
+            for color in frame.iter() {
+                let (r, g, b) = scheme.rgb(*color);
+                pixels.extend_from_slice(&[r, g, b]);
+            }
+            drop(frame);
+            let mut jpeg = Vec::new();
+            let result = Encoder::new(&mut jpeg, JPEG_QUALITY).encode(
+                &pixels,
+                u16::from(SCREEN_WIDTH),
+                u16::from(SCREEN_HEIGHT),
+                ColorType::Rgb,
+            );
+            if let Err(err) = result {
+                error!("Unable to encode frame: {}", err);
+                continue;
+            }
+
+            let mut current = latest.frame.lock().unwrap();
+            *current = (current.0 + 1, Arc::new(jpeg));
+            latest.updated.notify_all();
+        }
+    }
+}
+
+/// Handles a single client request
+fn serve(mut stream: TcpStream, latest: &LatestFrame) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Skip the remaining request headers
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    match request.split_whitespace().nth(1) {
+        Some("/") => write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\r\n{}",
+            INDEX.len(),
+            INDEX
+        ),
+        Some("/frame.jpg") => {
+            let (_, jpeg) = latest.wait_newer(0);
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\nCache-Control: no-cache\r\n\r\n",
+                jpeg.len()
+            )?;
+            stream.write_all(&jpeg)
+        }
+        Some("/stream") => {
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={}\r\nCache-Control: no-cache\r\n\r\n",
+                BOUNDARY
+            )?;
+            let mut number = 0;
+            loop {
+                let (next, jpeg) = latest.wait_newer(number);
+                number = next;
+                write!(
+                    stream,
+                    "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                    BOUNDARY,
+                    jpeg.len()
+                )?;
+                stream.write_all(&jpeg)?;
+                stream.write_all(b"\r\n")?;
+            }
+        }
+        _ => write!(
+            stream,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn request(address: SocketAddr, path: &str) -> Vec<u8> {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_serve_frame() {
+        let mut sink = HttpSink::bind("127.0.0.1:0", ColorScheme::default()).unwrap();
+        let frame = vec![Color::Black; usize::from(SCREEN_WIDTH) * usize::from(SCREEN_HEIGHT)];
+        sink.push_frame(&frame).unwrap();
+
+        let response = request(sink.address(), "/frame.jpg");
+        assert!(response.starts_with(b"HTTP/1.1 200 OK"));
+        let header_end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        assert_eq!(&response[header_end + 4..header_end + 6], &[0xFF, 0xD8]);
+
+        let response = request(sink.address(), "/unknown");
+        assert!(response.starts_with(b"HTTP/1.1 404 Not Found"));
+    }
+}
@@ -0,0 +1,92 @@
+use crate::gb::colors::ColorScheme;
+use crate::gb::ppu::misc::Color;
+use crate::gb::sink::http::HttpSink;
+use log::info;
+use std::io;
+use std::io::Write;
+
+pub mod http;
+
+/// Names of all frame sinks which can be selected on the command line
+pub const NAMES: [&str; 2] = ["raw", "http"];
+
+/// This trait defines a common interface for outputs which receive
+/// the rendered frames without a window, e.g. to stream or pipe them.
+pub trait FrameSink {
+    /// Receives a completely rendered frame
+    fn push_frame(&mut self, frame: &[Color]) -> io::Result<()>;
+}
+
+/// Creates the frame sink with the given name,
+/// network sinks are listening on the given address.
+pub fn create(name: &str, scheme: ColorScheme, address: &str) -> io::Result<Box<dyn FrameSink>> {
+    match name {
+        "raw" => Ok(Box::new(RawSink::new(io::stdout(), scheme))),
+        "http" => {
+            let sink = HttpSink::bind(address, scheme)?;
+            info!("Streaming at http://{}", sink.address());
+            Ok(Box::new(sink))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown frame sink: {}", name),
+        )),
+    }
+}
+
+/// Writes each frame as raw RGB24 pixels, which can be piped to other tools, e.g.
+/// `ffmpeg -f rawvideo -pixel_format rgb24 -video_size 160x144 -framerate 60 -i -`.
+pub struct RawSink<W: Write> {
+    writer: W,
+    scheme: ColorScheme,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> RawSink<W> {
+    pub fn new(writer: W, scheme: ColorScheme) -> Self {
+        Self {
+            writer,
+            scheme,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> FrameSink for RawSink<W> {
+    fn push_frame(&mut self, frame: &[Color]) -> io::Result<()> {
+        self.buffer.clear();
+        for color in frame {
+            let (r, g, b) = self.scheme.rgb(*color);
+            self.buffer.extend_from_slice(&[r, g, b]);
+        }
+        self.writer.write_all(&self.buffer)?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_sink() {
+        let mut output = Vec::new();
+        {
+            let mut sink = RawSink::new(&mut output, ColorScheme::default());
+            sink.push_frame(&[Color::White, Color::Black]).unwrap();
+            sink.push_frame(&[Color::DarkGrey]).unwrap();
+        }
+        assert_eq!(
+            output,
+            vec![0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x55, 0x55, 0x55]
+        );
+    }
+
+    #[test]
+    fn test_create() {
+        let scheme = ColorScheme::default();
+        assert!(create("raw", scheme, "127.0.0.1:0").is_ok());
+        assert!(create("http", scheme, "127.0.0.1:0").is_ok());
+        assert!(create("unknown", scheme, "127.0.0.1:0").is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use crate::gb::ppu::misc::Color;
+use crate::gb::DISPLAY_REFRESH_RATE;
+use std::fmt;
+
+/// Number of frames a game has to be stuck until it's reported
+pub const STALL_FRAMES: u32 = 10 * DISPLAY_REFRESH_RATE;
+/// Instructions within this many bytes of each other form a tight loop
+const LOOP_SIZE: u16 = 16;
+
+/// Address range of the loop a game is stuck in
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Stall {
+    pub start: u16,
+    pub end: u16,
+}
+
+/// Changes of the stall state which are reported at the end of a frame
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StallEvent {
+    Stuck(Stall),
+    Resumed,
+}
+
+impl fmt::Display for Stall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Game appears stuck at {:#06x}-{:#06x}, possibly an emulation bug, please report it with a trace",
+            self.start, self.end
+        )
+    }
+}
+
+/// Detects games which are stuck, i.e. the CPU executes the same tight loop
+/// while the frame doesn't change and no button is pressed.
+/// Idle games are not reported, since their main loop and interrupt handlers span more code.
+pub struct StallDetector {
+    range: Option<(u16, u16)>, // Lowest and highest address executed in the current frame
+    last_frame: Vec<Color>,
+    frames: u32,
+    reported: bool,
+}
+
+impl Default for StallDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StallDetector {
+    pub fn new() -> Self {
+        Self {
+            range: None,
+            last_frame: Vec::new(),
+            frames: 0,
+            reported: false,
+        }
+    }
+
+    /// Adds the address of an executed instruction
+    pub fn instruction(&mut self, pc: u16) {
+        self.range = match self.range {
+            Some((low, high)) => Some((low.min(pc), high.max(pc))),
+            None => Some((pc, pc)),
+        };
+    }
+
+    /// Finishes the current frame and returns whether the game
+    /// has been stuck for `STALL_FRAMES` or resumed after being reported.
+    pub fn end_frame(&mut self, frame: &[Color], input: bool) -> Option<StallEvent> {
+        let range = self.range.take();
+        let unchanged = frame == self.last_frame.as_slice();
+        if !unchanged {
+            self.last_frame = frame.to_vec();
+        }
+
+        match range {
+            Some((start, end)) if !input && unchanged && end - start < LOOP_SIZE => {
+                self.frames += 1;
+                if self.frames < STALL_FRAMES || self.reported {
+                    return None;
+                }
+                self.reported = true;
+                Some(StallEvent::Stuck(Stall { start, end }))
+            }
+            _ => {
+                self.frames = 0;
+                match self.reported {
+                    true => {
+                        self.reported = false;
+                        Some(StallEvent::Resumed)
+                    }
+                    false => None,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_frames(detector: &mut StallDetector, frames: u32, pcs: &[u16]) -> Option<StallEvent> {
+        let frame = vec![Color::White; 4];
+        let mut event = None;
+        for _ in 0..frames {
+            for pc in pcs {
+                detector.instruction(*pc);
+            }
+            event = detector.end_frame(&frame, false).or(event);
+        }
+        event
+    }
+
+    #[test]
+    fn test_stall() {
+        let mut detector = StallDetector::new();
+        // An idle game executes more than a tight loop
+        assert_eq!(
+            run_frames(&mut detector, STALL_FRAMES * 2, &[0x0150, 0x0040]),
+            None
+        );
+
+        let stall = Stall {
+            start: 0x0200,
+            end: 0x0204,
+        };
+        assert_eq!(
+            run_frames(&mut detector, STALL_FRAMES, &[0x0200, 0x0204]),
+            Some(StallEvent::Stuck(stall))
+        );
+        // Stalls are only reported once
+        assert_eq!(run_frames(&mut detector, STALL_FRAMES, &[0x0200]), None);
+
+        // Pressing a button resumes
+        detector.instruction(0x0200);
+        assert_eq!(
+            detector.end_frame(&[Color::White; 4], true),
+            Some(StallEvent::Resumed)
+        );
+    }
+}
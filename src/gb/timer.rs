@@ -1,7 +1,7 @@
 use crate::gb::interrupt::IRQ;
 use crate::gb::memory::constants::{TIMER_COUNTER, TIMER_CTRL, TIMER_DIVIDER, TIMER_MODULO};
-use crate::gb::memory::MemoryBus;
-use crate::gb::{AddressSpace, CPU_CLOCK_SPEED};
+use crate::gb::memory::{MemoryBus, TimerWrite};
+use crate::gb::AddressSpace;
 use std::cell::RefCell;
 
 bitflags! {
@@ -12,77 +12,152 @@ bitflags! {
     }
 }
 
-/// System Timer, counts up at configurable frequency.
+impl Control {
+    /// Returns the bit of the internal counter whose falling edge increments TIMA
+    fn counter_bit(self) -> u16 {
+        match self.bits() & 0x03 {
+            0 => 1 << 9, // 4096 Hz
+            1 => 1 << 3, // 262144 Hz
+            2 => 1 << 5, // 65536 Hz
+            _ => 1 << 7, // 16384 Hz
+        }
+    }
+}
+
+/// System Timer, DIV is the upper byte of a 16-bit counter which is incremented
+/// every cycle. TIMA is incremented on each falling edge of the counter bit selected
+/// by TAC while the timer is running, which is why resetting DIV or changing TAC can
+/// increment TIMA as well. After an overflow TIMA reads 0 for one machine cycle
+/// before it is reloaded from TMA and the interrupt is requested. Writes of the CPU
+/// are applied at the machine cycle of the instruction in which they happened.
 pub struct Timer<'a> {
     bus: &'a RefCell<MemoryBus>,
-    timer: u32,
-    divider: u32,
-    clock_speed: u32,
+    counter: u16,
+    /// TAC as of the last step, to detect writes which cause a falling edge
+    control: Control,
+    /// TIMA as left by the last step or written by the CPU
+    tima: u8,
+    /// TMA as of the current machine cycle, the bus holds later writes of the instruction already
+    tma: u8,
+    /// TIMA overflowed and is reloaded in the next machine cycle
+    reloading: bool,
+    /// TIMA has been reloaded from TMA in the last machine cycle
+    reloaded: bool,
 }
 
 impl<'a> Timer<'a> {
     pub fn new(bus: &'a RefCell<MemoryBus>) -> Self {
         Self {
             bus,
-            timer: 0,
-            divider: 0,
-            clock_speed: CPU_CLOCK_SPEED / 4096,
+            counter: 0,
+            control: Control::empty(),
+            tima: 0,
+            tma: 0,
+            reloading: false,
+            reloaded: false,
         }
     }
 
+    /// Restores the power-on state, the registers are reset by the bus
+    pub fn reset(&mut self) {
+        self.counter = 0;
+        self.control = Control::empty();
+        self.tima = 0;
+        self.tma = 0;
+        self.reloading = false;
+        self.reloaded = false;
+    }
+
     pub fn step(&mut self, cycles: u32) {
-        // Check if clock is running
-        if self.read_ctrl().contains(Control::RUNNING) {
-            self.timer += cycles;
-
-            // Check if enough cpu clock cycles have happened to update the timer
-            if self.timer >= self.clock_speed {
-                self.timer = 0;
-
-                let (new_counter, did_overflow) = self.read(TIMER_COUNTER).overflowing_add(1);
-                if did_overflow {
-                    // Reset counter to modulo and request interrupt if overflow did happen
-                    self.write(TIMER_COUNTER, self.read(TIMER_MODULO));
-                    self.bus.borrow_mut().irq(IRQ::Timer);
-                } else {
-                    self.write(TIMER_COUNTER, new_counter);
-                }
+        let writes = self.bus.borrow_mut().take_timer_writes();
+        let mut writes = writes.into_iter().peekable();
+        for cycle in 0..cycles / 4 {
+            while let Some(write) = writes.next_if(|write| write.cycle <= cycle) {
+                self.apply_write(write);
             }
+            self.tick();
         }
-        self.update_clock_speed();
-        self.update_divider(cycles);
-    }
-
-    /// Updates clock frequency
-    fn update_clock_speed(&mut self) {
-        let freq = self.read(TIMER_CTRL) & 0x03;
-        let clock_speed = match freq {
-            0 => 1024, // 4096 Hz
-            1 => 16,   // 262144 Hz
-            2 => 64,   // 65536 Hz
-            3 => 256,  // 16382 Hz
-            _ => panic!(),
-        };
-        if clock_speed != self.clock_speed {
-            self.timer = 0;
+        writes.for_each(|write| self.apply_write(write));
+        self.bus
+            .borrow_mut()
+            .write_unchecked(TIMER_DIVIDER, (self.counter >> 8) as u8);
+    }
+
+    /// Applies a write of the CPU to a timer register
+    fn apply_write(&mut self, write: TimerWrite) {
+        match write.address {
+            TIMER_DIVIDER => {
+                let before = self.signal();
+                self.counter = 0;
+                if before && !self.signal() {
+                    self.increment();
+                }
+            }
+            TIMER_CTRL => {
+                let before = self.signal();
+                self.control = Control::from_bits_truncate(write.value);
+                if before && !self.signal() {
+                    self.increment();
+                }
+            }
+            // TIMA is loaded from TMA during this cycle, the write is lost
+            TIMER_COUNTER if self.reloaded => {
+                self.bus
+                    .borrow_mut()
+                    .write_unchecked(TIMER_COUNTER, self.tima);
+            }
+            // Writing TIMA during the cycle after an overflow cancels the reload
+            TIMER_COUNTER => {
+                self.tima = write.value;
+                self.reloading = false;
+                self.bus
+                    .borrow_mut()
+                    .write_unchecked(TIMER_COUNTER, self.tima);
+            }
+            TIMER_MODULO => {
+                self.tma = write.value;
+                // TIMA is loaded from TMA during this cycle, it gets the new value as well
+                if self.reloaded {
+                    self.tima = self.tma;
+                    self.bus
+                        .borrow_mut()
+                        .write_unchecked(TIMER_COUNTER, self.tima);
+                }
+            }
+            _ => (),
         }
-        self.clock_speed = clock_speed;
     }
 
-    /// Updates divider
-    fn update_divider(&mut self, cycles: u32) {
-        self.divider += cycles;
-        if self.divider >= 256 {
-            self.divider = 0;
-            let divider = self.read(TIMER_DIVIDER);
+    /// Advances the timer by one machine cycle
+    fn tick(&mut self) {
+        self.reloaded = false;
+        if self.reloading {
+            self.reloading = false;
+            self.reloaded = true;
+            self.tima = self.tma;
             self.bus
                 .borrow_mut()
-                .write_unchecked(TIMER_DIVIDER, divider.wrapping_add(1));
+                .write_unchecked(TIMER_COUNTER, self.tima);
+            self.bus.borrow_mut().irq(IRQ::Timer);
+        }
+
+        let before = self.signal();
+        self.counter = self.counter.wrapping_add(4);
+        if before && !self.signal() {
+            self.increment();
         }
     }
 
-    fn read_ctrl(&self) -> Control {
-        Control::from_bits(self.read(TIMER_CTRL)).expect("Got invalid bits!")
+    /// Returns the input of the TIMA falling edge detector
+    fn signal(&self) -> bool {
+        self.control.contains(Control::RUNNING) && self.counter & self.control.counter_bit() != 0
+    }
+
+    fn increment(&mut self) {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        self.reloading = overflow;
+        self.bus.borrow_mut().write_unchecked(TIMER_COUNTER, tima);
     }
 }
 
@@ -102,6 +177,12 @@ pub struct Clock {
     t_cycle: u32,
 }
 
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Clock {
     pub fn new() -> Self {
         Self { t_cycle: 0 }
@@ -119,3 +200,176 @@ impl Clock {
         self.t_cycle = 0;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gb::cartridge::Cartridge;
+    use crate::gb::memory::constants::INTERRUPT_FLAG;
+
+    fn create_bus() -> RefCell<MemoryBus> {
+        RefCell::new(MemoryBus::new(Cartridge::from_bytes(vec![0; 0x8000])))
+    }
+
+    #[test]
+    fn test_divider() {
+        let bus = create_bus();
+        let mut timer = Timer::new(&bus);
+        timer.step(252);
+        assert_eq!(bus.borrow().read(TIMER_DIVIDER), 0);
+        timer.step(4);
+        assert_eq!(bus.borrow().read(TIMER_DIVIDER), 1);
+        bus.borrow_mut().write(TIMER_DIVIDER, 0x42);
+        timer.step(4);
+        assert_eq!(bus.borrow().read(TIMER_DIVIDER), 0);
+    }
+
+    #[test]
+    fn test_tima_increments() {
+        let bus = create_bus();
+        let mut timer = Timer::new(&bus);
+        bus.borrow_mut().write(TIMER_CTRL, 0x05);
+        timer.step(12);
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 0);
+        timer.step(4);
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 1);
+        timer.step(32);
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 3);
+    }
+
+    #[test]
+    fn test_div_write_increments() {
+        let bus = create_bus();
+        let mut timer = Timer::new(&bus);
+        bus.borrow_mut().write(TIMER_CTRL, 0x05);
+        // Bit 3 of the counter is set
+        timer.step(8);
+        bus.borrow_mut().write(TIMER_DIVIDER, 0);
+        timer.step(4);
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 1);
+        // The counter restarted, the next increment is 16 cycles after the reset
+        timer.step(8);
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 1);
+        timer.step(4);
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 2);
+    }
+
+    #[test]
+    fn test_tac_write_increments() {
+        let bus = create_bus();
+        let mut timer = Timer::new(&bus);
+        bus.borrow_mut().write(TIMER_CTRL, 0x05);
+        timer.step(8);
+        // Stopping the timer while the selected bit is set is a falling edge
+        bus.borrow_mut().write(TIMER_CTRL, 0x01);
+        timer.step(4);
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 1);
+        timer.step(64);
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 1);
+    }
+
+    #[test]
+    fn test_tima_reload() {
+        let bus = create_bus();
+        let mut timer = Timer::new(&bus);
+        bus.borrow_mut().write(TIMER_COUNTER, 0xFF);
+        bus.borrow_mut().write(TIMER_MODULO, 0x42);
+        bus.borrow_mut().write(TIMER_CTRL, 0x05);
+        timer.step(16);
+        // TIMA reads 0 for one machine cycle before it is reloaded
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 0);
+        assert_eq!(bus.borrow().read(INTERRUPT_FLAG), 0);
+        timer.step(4);
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 0x42);
+        assert_eq!(bus.borrow().read(INTERRUPT_FLAG), 0x04);
+    }
+
+    #[test]
+    fn test_tima_write_cancels_reload() {
+        let bus = create_bus();
+        let mut timer = Timer::new(&bus);
+        bus.borrow_mut().write(TIMER_COUNTER, 0xFF);
+        bus.borrow_mut().write(TIMER_MODULO, 0x42);
+        bus.borrow_mut().write(TIMER_CTRL, 0x05);
+        timer.step(16);
+        bus.borrow_mut().write(TIMER_COUNTER, 0x10);
+        timer.step(4);
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 0x10);
+        assert_eq!(bus.borrow().read(INTERRUPT_FLAG), 0);
+    }
+
+    #[test]
+    fn test_tima_same_value_write_cancels_reload() {
+        let bus = create_bus();
+        let mut timer = Timer::new(&bus);
+        bus.borrow_mut().write(TIMER_COUNTER, 0xFF);
+        bus.borrow_mut().write(TIMER_MODULO, 0x42);
+        bus.borrow_mut().write(TIMER_CTRL, 0x05);
+        timer.step(16);
+        // TIMA reads 0 during the reload cycle, writing 0 still cancels the reload
+        bus.borrow_mut().write(TIMER_COUNTER, 0);
+        timer.step(4);
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 0);
+        assert_eq!(bus.borrow().read(INTERRUPT_FLAG), 0);
+    }
+
+    /// Sets up TIMA to overflow in the first machine cycle of the next step
+    fn create_overflowing_timer(bus: &RefCell<MemoryBus>) -> Timer<'_> {
+        let mut timer = Timer::new(bus);
+        bus.borrow_mut().write(TIMER_COUNTER, 0xFF);
+        bus.borrow_mut().write(TIMER_MODULO, 0x42);
+        bus.borrow_mut().write(TIMER_CTRL, 0x05);
+        timer.step(12);
+        timer
+    }
+
+    #[test]
+    fn test_tima_write_within_instruction_cancels_reload() {
+        let bus = create_bus();
+        let mut timer = create_overflowing_timer(&bus);
+        // Written in the second machine cycle, right after the overflow
+        bus.borrow_mut().tick();
+        bus.borrow_mut().write(TIMER_COUNTER, 0x10);
+        timer.step(12);
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 0x10);
+        assert_eq!(bus.borrow().read(INTERRUPT_FLAG), 0);
+    }
+
+    #[test]
+    fn test_tima_write_in_reload_cycle_is_ignored() {
+        let bus = create_bus();
+        let mut timer = create_overflowing_timer(&bus);
+        // Written in the third machine cycle, while TIMA is loaded from TMA
+        bus.borrow_mut().tick();
+        bus.borrow_mut().tick();
+        bus.borrow_mut().write(TIMER_COUNTER, 0x10);
+        timer.step(12);
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 0x42);
+        assert_eq!(bus.borrow().read(INTERRUPT_FLAG), 0x04);
+    }
+
+    #[test]
+    fn test_tma_write_in_reload_cycle_reaches_tima() {
+        let bus = create_bus();
+        let mut timer = create_overflowing_timer(&bus);
+        bus.borrow_mut().tick();
+        bus.borrow_mut().tick();
+        bus.borrow_mut().write(TIMER_MODULO, 0x10);
+        timer.step(12);
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 0x10);
+        assert_eq!(bus.borrow().read(TIMER_MODULO), 0x10);
+        assert_eq!(bus.borrow().read(INTERRUPT_FLAG), 0x04);
+    }
+
+    #[test]
+    fn test_tma_write_after_reload_cycle() {
+        let bus = create_bus();
+        let mut timer = create_overflowing_timer(&bus);
+        bus.borrow_mut().tick();
+        bus.borrow_mut().tick();
+        bus.borrow_mut().tick();
+        bus.borrow_mut().write(TIMER_MODULO, 0x10);
+        timer.step(16);
+        assert_eq!(bus.borrow().read(TIMER_COUNTER), 0x42);
+    }
+}
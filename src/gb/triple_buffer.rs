@@ -0,0 +1,92 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Set in the shared index once a value has been published which hasn't been read yet
+const FRESH: usize = 0b100;
+const INDEX: usize = 0b011;
+
+/// Three buffers of which the writer and the reader own one each,
+/// the third one holds the most recently published value.
+struct Shared<T> {
+    buffers: [Mutex<T>; 3],
+    middle: AtomicUsize,
+}
+
+/// Creates a triple buffer to pass values from one thread to another, the latest value wins.
+/// Writer and reader swap their buffer with the shared one, so neither of them ever waits
+/// for the other. The locks are never contended, they only keep the buffers accessible safely.
+pub fn triple_buffer<T: Clone>(initial: T) -> (Writer<T>, Reader<T>) {
+    let shared = Arc::new(Shared {
+        buffers: [
+            Mutex::new(initial.clone()),
+            Mutex::new(initial.clone()),
+            Mutex::new(initial),
+        ],
+        middle: AtomicUsize::new(1),
+    });
+    let writer = Writer {
+        shared: Arc::clone(&shared),
+        index: 0,
+    };
+    (writer, Reader { shared, index: 2 })
+}
+
+/// Publishing side of a triple buffer
+pub struct Writer<T> {
+    shared: Arc<Shared<T>>,
+    index: usize,
+}
+
+impl<T> Writer<T> {
+    /// Updates the back buffer with `f` and publishes it, replacing an unread value.
+    /// The back buffer still holds an older value which has to be overwritten.
+    pub fn publish<F: FnOnce(&mut T)>(&mut self, f: F) {
+        f(&mut self.shared.buffers[self.index].lock().unwrap());
+        self.index = self
+            .shared
+            .middle
+            .swap(self.index | FRESH, Ordering::AcqRel)
+            & INDEX;
+    }
+}
+
+/// Reading side of a triple buffer
+pub struct Reader<T> {
+    shared: Arc<Shared<T>>,
+    index: usize,
+}
+
+impl<T> Reader<T> {
+    /// Returns the most recently published value if it hasn't been read yet
+    pub fn latest(&mut self) -> Option<MutexGuard<'_, T>> {
+        if self.shared.middle.load(Ordering::Acquire) & FRESH == 0 {
+            return None;
+        }
+        self.index = self.shared.middle.swap(self.index, Ordering::AcqRel) & INDEX;
+        Some(self.shared.buffers[self.index].lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latest_value_wins() {
+        let (mut writer, mut reader) = triple_buffer(0);
+        assert!(reader.latest().is_none());
+        writer.publish(|value| *value = 1);
+        writer.publish(|value| *value = 2);
+        assert_eq!(*reader.latest().unwrap(), 2);
+        assert!(reader.latest().is_none());
+
+        // The writer continues while the reader holds its value
+        writer.publish(|value| *value = 3);
+        let value = reader.latest().unwrap();
+        writer.publish(|value| *value = 4);
+        writer.publish(|value| *value = 5);
+        assert_eq!(*value, 3);
+        drop(value);
+        assert_eq!(*reader.latest().unwrap(), 5);
+    }
+}
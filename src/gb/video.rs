@@ -0,0 +1,137 @@
+use crate::gb::colors::ColorScheme;
+use crate::gb::ppu::misc::Color;
+use crate::gb::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use gif::{Encoder, Frame, Repeat};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::mpsc::{channel, sync_channel, Receiver, SyncSender, TrySendError};
+use std::thread;
+use std::thread::JoinHandle;
+
+/// Number of frames which can be queued before frames are dropped
+const QUEUE_SIZE: usize = 120;
+
+/// Records the emulated frames to an animated GIF.
+/// Frames are encoded on a separate thread to not slow down emulation,
+/// they are dropped if the encoder can't keep up.
+/// Encoded frame buffers are sent back and reused for the next frames.
+pub struct VideoRecorder {
+    sender: SyncSender<Vec<u8>>,
+    recycled: Receiver<Vec<u8>>,
+    encoder: JoinHandle<io::Result<()>>,
+    frames: usize,
+    dropped: usize,
+}
+
+impl VideoRecorder {
+    /// Creates the GIF at the given path and starts the encoder thread
+    pub fn start(path: &Path, scheme: ColorScheme) -> io::Result<Self> {
+        let mut palette = Vec::with_capacity(12);
+        for shade in 0..4 {
+            let (r, g, b) = scheme.rgb(Color::from(shade));
+            palette.extend_from_slice(&[r, g, b]);
+        }
+        let mut encoder = Encoder::new(
+            File::create(path)?,
+            u16::from(SCREEN_WIDTH),
+            u16::from(SCREEN_HEIGHT),
+            &palette,
+        )
+        .map_err(to_io_error)?;
+        encoder.set_repeat(Repeat::Infinite).map_err(to_io_error)?;
+
+        let (sender, receiver) = sync_channel::<Vec<u8>>(QUEUE_SIZE);
+        let (recycle, recycled) = channel();
+        let encoder = thread::spawn(move || {
+            for (n, buffer) in receiver.iter().enumerate() {
+                let frame = Frame {
+                    width: u16::from(SCREEN_WIDTH),
+                    height: u16::from(SCREEN_HEIGHT),
+                    buffer: buffer.into(),
+                    delay: frame_delay(n),
+                    ..Frame::default()
+                };
+                encoder.write_frame(&frame).map_err(to_io_error)?;
+                // The recorder is gone if sending fails, the buffer is just dropped then
+                let _ = recycle.send(frame.buffer.into_owned());
+            }
+            Ok(())
+        });
+
+        Ok(Self {
+            sender,
+            recycled,
+            encoder,
+            frames: 0,
+            dropped: 0,
+        })
+    }
+
+    /// Queues a frame consisting of one shade index per pixel.
+    /// Only every second frame is recorded, since GIF delays have a resolution of 10ms.
+    pub fn push_frame(&mut self, frame: &[u8]) {
+        self.frames += 1;
+        if self.frames.is_multiple_of(2) {
+            return;
+        }
+        let mut buffer = self.recycled.try_recv().unwrap_or_default();
+        buffer.clear();
+        buffer.extend_from_slice(frame);
+        match self.sender.try_send(buffer) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => self.dropped += 1,
+        }
+    }
+
+    /// Finishes the recording and waits until all queued frames are encoded.
+    /// Returns the number of dropped frames.
+    pub fn finish(self) -> io::Result<usize> {
+        drop(self.sender);
+        self.encoder
+            .join()
+            .map_err(|_| io::Error::other("GIF encoder panicked"))??;
+        Ok(self.dropped)
+    }
+}
+
+/// Returns the delay of the n-th recorded frame in 10ms units.
+/// Recording 30 out of 60 frames per second requires 3.33 units per frame,
+/// hence the delays alternate to avoid drift.
+fn frame_delay(n: usize) -> u16 {
+    let end = ((n + 1) * 10 + 1) / 3;
+    let start = (n * 10 + 1) / 3;
+    (end - start) as u16
+}
+
+fn to_io_error(err: gif::EncodingError) -> io::Error {
+    io::Error::other(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_frame_delay() {
+        let total: u16 = (0..30).map(frame_delay).sum();
+        assert_eq!(total, 100);
+        assert!((0..30).all(|n| frame_delay(n) >= 3));
+    }
+
+    #[test]
+    fn test_record_gif() {
+        let path = env::temp_dir().join("romoulade-test-record.gif");
+        let mut recorder = VideoRecorder::start(&path, ColorScheme::default()).unwrap();
+        let frame = vec![0u8; usize::from(SCREEN_WIDTH) * usize::from(SCREEN_HEIGHT)];
+        for _ in 0..4 {
+            recorder.push_frame(&frame);
+        }
+        assert_eq!(recorder.finish().unwrap(), 0);
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(&data[..6], b"GIF89a");
+    }
+}
@@ -0,0 +1,88 @@
+//! Experimental Game Boy emulator.
+//!
+//! [`gb::gameboy::GameBoy`] embeds the emulator core without any output device,
+//! e.g. for fuzzing or alternative frontends. Building with `default-features = false`
+//! leaves out the SDL window and the terminal debugger:
+//!
+//! ```
+//! use romoulade::gb::gameboy::GameBoy;
+//! use romoulade::gb::joypad::Buttons;
+//!
+//! // An empty ROM with a valid header checksum
+//! let mut rom = vec![0; 0x8000];
+//! rom[0x14D] = 0xE7;
+//!
+//! GameBoy::run(rom, |game_boy| {
+//!     game_boy.set_input(Buttons::START);
+//!     for _ in 0..60 {
+//!         game_boy.step_frame();
+//!     }
+//!     assert_eq!(game_boy.framebuffer().len(), 160 * 144);
+//!
+//!     // Runs exactly one frame worth of cycles, e.g. to compare the pixels in tests
+//!     let frame = game_boy.run_frame();
+//!     assert_eq!(frame.len(), 160 * 144);
+//!     println!("{:#04x}", game_boy.read_memory(0xC000));
+//! });
+//! ```
+//!
+//! The components can also be wired up directly. Every component shares the
+//! [`gb::memory::MemoryBus`] and frames are written to a [`gb::Screen`]:
+//!
+//! ```
+//! use romoulade::gb::cartridge::Cartridge;
+//! use romoulade::gb::cpu::CPU;
+//! use romoulade::gb::headless::Headless;
+//! use romoulade::gb::interrupt::IRQHandler;
+//! use romoulade::gb::memory::MemoryBus;
+//! use romoulade::gb::ppu::PPU;
+//! use romoulade::gb::timer::Timer;
+//! use romoulade::gb::CYCLES_PER_FRAME;
+//! use std::cell::RefCell;
+//!
+//! let mut rom = vec![0; 0x8000];
+//! rom[0x14D] = 0xE7;
+//! let cartridge = Cartridge::from_bytes(rom);
+//!
+//! let bus = RefCell::new(MemoryBus::new(cartridge));
+//! let mut screen = Headless::new();
+//! {
+//!     let mut ppu = PPU::new(&bus, &mut screen);
+//!     let cpu = RefCell::new(CPU::new(&bus));
+//!     let mut irq_handler = IRQHandler::new(&cpu, &bus);
+//!     let mut timer = Timer::new(&bus);
+//!
+//!     // Run 60 frames
+//!     let mut cycles = 0;
+//!     while cycles < 60 * CYCLES_PER_FRAME {
+//!         let step = cpu.borrow_mut().step();
+//!         timer.step(step);
+//!         ppu.step(step);
+//!         irq_handler.handle();
+//!         cycles += step;
+//!     }
+//! }
+//!
+//! // Read the screen
+//! assert!(screen.frames() > 0);
+//! println!("{:016x}", screen.frame_hash());
+//! ```
+
+#[macro_use]
+extern crate bitflags;
+
+#[cfg(feature = "automation")]
+pub mod automation;
+pub mod batch;
+pub mod bench;
+pub mod compat;
+pub mod config;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+pub mod gb;
+pub mod logger;
+pub mod notifications;
+pub mod testsuite;
+pub mod utils;
+#[cfg(feature = "verify")]
+pub mod verify;
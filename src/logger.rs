@@ -0,0 +1,126 @@
+use crate::notifications::{self, Level};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::cmp::Reverse;
+use std::env;
+use std::error::Error;
+
+/// Environment variable with the log filter, e.g. `warn,romoulade::gb::ppu=trace`
+pub const FILTER_VAR: &str = "ROMOULADE_LOG";
+
+/// Log levels of targets, the longest matching target prefix wins
+#[derive(Debug, PartialEq)]
+struct Filter {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Filter {
+    /// Parses comma separated directives which are either a level or `target=level`
+    fn parse(default: LevelFilter, spec: &str) -> Result<Self, String> {
+        let mut filter = Self {
+            default,
+            targets: Vec::new(),
+        };
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => filter
+                    .targets
+                    .push((target.to_string(), parse_level(level)?)),
+                None => filter.default = parse_level(directive)?,
+            }
+        }
+        filter
+            .targets
+            .sort_by_key(|(target, _)| Reverse(target.len()));
+        Ok(filter)
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .find(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    /// Returns the most verbose level of all targets
+    fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    level
+        .parse()
+        .map_err(|_| format!("Unknown log level: {}", level))
+}
+
+/// Writes log records to stderr, warnings and errors are
+/// passed on as notifications so they are shown in the window.
+struct Logger {
+    filter: Filter,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= self.filter.level(metadata.target())
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        match record.level() {
+            log::Level::Error => notifications::notify(Level::Error, message),
+            log::Level::Warn => notifications::notify(Level::Warning, message),
+            log::Level::Info => eprintln!("{}", message),
+            level => eprintln!("{} {}: {}", level, record.target(), message),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger, `verbosity` is the number of `-v` flags: info is logged
+/// by default, debug with one and trace with two. `ROMOULADE_LOG` takes precedence.
+pub fn init(verbosity: u64) -> Result<(), Box<dyn Error>> {
+    let default = match verbosity {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let filter = Filter::parse(default, &env::var(FILTER_VAR).unwrap_or_default())?;
+    log::set_max_level(filter.max_level());
+    log::set_boxed_logger(Box::new(Logger { filter }))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let filter = Filter::parse(
+            LevelFilter::Info,
+            "warn, romoulade::gb=debug,romoulade::gb::ppu=trace",
+        )
+        .unwrap();
+        assert_eq!(
+            filter.level("romoulade::gb::ppu::fetcher"),
+            LevelFilter::Trace
+        );
+        assert_eq!(filter.level("romoulade::gb::cartridge"), LevelFilter::Debug);
+        assert_eq!(filter.level("romoulade::config"), LevelFilter::Warn);
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+
+        assert_eq!(
+            Filter::parse(LevelFilter::Debug, "").unwrap().max_level(),
+            LevelFilter::Debug
+        );
+        assert!(Filter::parse(LevelFilter::Info, "romoulade=loud").is_err());
+    }
+}
@@ -1,27 +1,62 @@
-use crate::gb::cartridge::Cartridge;
-use crate::gb::cpu::CPU;
-use crate::gb::debugger::Debugger;
-use crate::gb::display::Display;
-use crate::gb::interrupt::IRQHandler;
-use crate::gb::memory::MemoryBus;
-use crate::gb::ppu::PPU;
-use crate::gb::timer::Timer;
-use crate::gb::{AddressSpace, DISPLAY_REFRESH_RATE};
 use backtrace::Backtrace;
-use clap::{App, Arg, ArgMatches};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use log::{error, info, warn};
+use romoulade::compat::{Compatibility, Database};
+use romoulade::config::{Config, GameSettings, LaunchOptions};
+use romoulade::gb::bundle::DebugBundle;
+use romoulade::gb::cartridge::Cartridge;
+use romoulade::gb::cheats;
+use romoulade::gb::cheats::{Cheat, Cheats, Code};
+#[cfg(feature = "host-calls")]
+use romoulade::gb::cpu::hostcall;
+use romoulade::gb::cpu::CPU;
+use romoulade::gb::debugger::trace::{Tracer, TRACE_CAPACITY};
+use romoulade::gb::debugger::Debugger;
+use romoulade::gb::diagnostics::Diagnostics;
+use romoulade::gb::display;
+use romoulade::gb::display::{Display, Orientation, Rotation};
+use romoulade::gb::frontend::Frontend;
+use romoulade::gb::header::Header;
+use romoulade::gb::headless::Headless;
+use romoulade::gb::interrupt::IRQHandler;
+use romoulade::gb::link;
+use romoulade::gb::link::LinkClient;
+use romoulade::gb::memory::recorder::RecordingBus;
+use romoulade::gb::memory::MemoryBus;
+use romoulade::gb::movie::{Movie, MovieHeader, MovieMode, MovieRecorder};
+use romoulade::gb::pacer::Pacer;
+use romoulade::gb::ppu::PPU;
+use romoulade::gb::rtc;
+use romoulade::gb::timer::Timer;
+#[cfg(feature = "host-calls")]
+use romoulade::gb::AddressSpace;
+use romoulade::gb::{browser, frontend, sink};
+use romoulade::gb::{Screen, DISPLAY_REFRESH_RATE};
+#[cfg(feature = "verify")]
+use romoulade::verify::{Dat, Verification};
+use romoulade::{batch, bench, compat, config, logger, testsuite, utils};
+use sdl2::messagebox::{self, MessageBoxFlag};
 use std::cell::RefCell;
 use std::error::Error;
-use std::panic;
 use std::panic::PanicInfo;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{env, fs, io, panic, thread};
 
 #[macro_use]
 extern crate clap;
-#[macro_use]
-extern crate bitflags;
 
-mod gb;
-mod utils;
+/// If this file exists next to the executable, portable mode is enabled.
+const PORTABLE_MARKER: &str = "portable.txt";
+/// DAT file in the configuration directory which is used without `--dat`
+#[cfg(feature = "verify")]
+const DAT_FILE: &str = "gb.dat";
+/// Compatibility database in the configuration directory which is used without `--database`
+const COMPAT_FILE: &str = "compat.json";
+
+/// Set while the emulator window is open, headless runs report crashes on stderr only
+static WINDOWED: AtomicBool = AtomicBool::new(false);
 
 fn main() -> Result<(), Box<dyn Error>> {
     panic::set_hook(Box::new(|info| {
@@ -29,55 +64,722 @@ fn main() -> Result<(), Box<dyn Error>> {
     }));
 
     let matches = parse_args();
-    let path = Path::new(matches.value_of("rom").unwrap());
+    logger::init(matches.occurrences_of("verbose"))?;
+    if let Some(matches) = matches.subcommand_matches("batch") {
+        let dir = Path::new(matches.value_of("dir").unwrap());
+        let frames = value_t!(matches, "frames", u32)?;
+        let report = Path::new(matches.value_of("report").unwrap());
+        let unlocked = matches.is_present("unlocked");
+        let jobs = match matches.value_of("jobs") {
+            Some(_) => value_t!(matches, "jobs", usize)?,
+            None => thread::available_parallelism().map_or(1, usize::from),
+        };
+        return batch::run(dir, frames, unlocked, jobs, report);
+    }
+    if let Some(matches) = matches.subcommand_matches("test-suite") {
+        let dir = Path::new(matches.value_of("dir").unwrap());
+        let frames = value_t!(matches, "frames", u32)?;
+        return testsuite::run(dir, frames);
+    }
+    #[cfg(feature = "automation")]
+    if let Some(matches) = matches.subcommand_matches("automation") {
+        return Ok(romoulade::automation::listen(
+            matches.value_of("listen").unwrap(),
+        )?);
+    }
+    if let Some(matches) = matches.subcommand_matches("dmg07") {
+        return Ok(link::serve_adapter(matches.value_of("listen").unwrap())?);
+    }
+    if let Some(matches) = matches.subcommand_matches("compat") {
+        let report = Path::new(matches.value_of("report").unwrap());
+        return compat::regenerate(report, &database_path(matches)?);
+    }
+    if let Some(matches) = matches.subcommand_matches("controls") {
+        let config_path = config::config_path(&resolve_config_dir(is_portable(matches))?);
+        let mut config = Config::load(&config_path)?;
+        display::remap_keys(config.profile_mut(matches.value_of("profile")))?;
+        config.save(&config_path)?;
+        println!("Saved key bindings to {}", config_path.display());
+        return Ok(());
+    }
+    if let Some(matches) = matches.subcommand_matches("profile") {
+        let config_path = config::config_path(&resolve_config_dir(is_portable(matches))?);
+        let mut config = Config::load(&config_path)?;
+        let cartridge = Cartridge::from_path(Path::new(matches.value_of("rom").unwrap()), None)?;
+        let profile = matches.value_of("name");
+        config.assign_profile(&cartridge.meta.title, profile)?;
+        config.save(&config_path)?;
+        println!(
+            "Assigned profile {} to {}",
+            profile.unwrap_or("default"),
+            cartridge.meta.title
+        );
+        return Ok(());
+    }
+    if let Some(matches) = matches.subcommand_matches("cheats") {
+        return edit_cheats(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("launch") {
+        return edit_launch_options(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("game") {
+        return edit_game_settings(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("info") {
+        let header = Header::parse(&fs::read(matches.value_of("rom").unwrap())?)?;
+        println!("{}", header);
+        for warning in header.warnings() {
+            println!("Warning: {}", warning);
+        }
+        return Ok(());
+    }
+    if let Some(matches) = matches.subcommand_matches("rtc") {
+        return edit_rtc(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("sav") {
+        return transfer_save(matches);
+    }
+
+    if let Some(path) = matches.value_of("dump-frame") {
+        let rom = Path::new(matches.value_of("rom").unwrap());
+        let frames = value_t!(matches, "frames", u32)?;
+        let reference = matches.value_of("reference").map(Path::new);
+        return batch::dump_frame(rom, frames, Path::new(path), reference);
+    }
+    if matches.is_present("bench") {
+        let rom = Path::new(matches.value_of("rom").unwrap());
+        return bench::run(rom, value_t!(matches, "frames", u32)?);
+    }
 
-    let fps_limit = match matches.is_present("no-fps-limit") {
+    let portable = match matches.subcommand_matches("browse") {
+        Some(matches) => is_portable(matches),
+        None => is_portable(&matches),
+    };
+    let config_dir = resolve_config_dir(portable)?;
+    let thumbnail_dir = config_dir.join("thumbnails");
+    let path = match matches.is_present("browse") {
+        true => {
+            let config = Config::load(&config::config_path(&config_dir))?;
+            let scheme = config.color_scheme(&config.palette)?;
+            match browser::browse(&config.recent_roms, &thumbnail_dir, scheme)? {
+                Some(path) => path,
+                None => return Ok(()),
+            }
+        }
+        false => PathBuf::from(matches.value_of("rom").unwrap()),
+    };
+    let path = path.as_path();
+
+    let config_path = config::config_path(&config_dir);
+    let mut config = Config::load(&config_path)?;
+    config.add_recent_rom(&path.canonicalize()?);
+    config.save(&config_path)?;
+    let options = config.launch_options_for(&path.canonicalize()?);
+
+    let fps_limit = match matches.is_present("no-fps-limit") || options.no_fps_limit {
         true => 0,
         false => DISPLAY_REFRESH_RATE,
     };
-    let debug = matches.is_present("debug");
+    let debug = matches.is_present("debug") || options.debug;
+    let save_dir = match options.save_dir {
+        Some(save_dir) => save_dir,
+        None => resolve_save_dir(path, portable)?,
+    };
 
-    println!("Loading cartridge {}...", &path.display());
-    let cartridge = Cartridge::from_path(&path).expect("Unable to load cartridge from path");
-    println!("  -> {}", &cartridge.meta);
+    let degrees = match matches.is_present("rotate") {
+        true => value_t!(matches, "rotate", u16)?,
+        false => config.rotation,
+    };
+    let orientation = Orientation {
+        rotation: Rotation::from_degrees(degrees)
+            .ok_or_else(|| format!("Unsupported rotation: {} degrees", degrees))?,
+        mirror: matches.is_present("mirror") || config.mirror,
+    };
+    let ambient = matches.is_present("ambient") || config.ambient;
+
+    let link = match matches.value_of("link") {
+        Some(address) => Some(LinkClient::connect(address)?),
+        None => None,
+    };
+    let movie = match matches.value_of("play") {
+        Some(movie) => Some(Movie::load(Path::new(movie))?),
+        None => None,
+    };
+    // Movies start at power-on, hence cartridge RAM isn't loaded
+    let movies = movie.is_some() || matches.is_present("record");
+    let save_dir = match movies {
+        true => None,
+        false => Some(save_dir.as_path()),
+    };
 
+    // Status messages are written to stderr, since stdout may be used by a frame sink
+    info!("Loading cartridge {}...", &path.display());
+    let mut cartridge = match Cartridge::from_path(path, save_dir) {
+        Ok(cartridge) => cartridge,
+        Err(err) => {
+            let message = format!("Unable to load {}: {}", path.display(), err);
+            show_error_dialog(&message);
+            return Err(message.into());
+        }
+    };
+    cartridge.set_backups(config.save_backups);
+    info!("  -> {}", &cartridge.meta);
+    let game_id = cartridge.meta.id();
+    let settings = config.game_settings_for(&game_id);
+    if settings != GameSettings::default() {
+        info!("  -> Game settings: {:?}", settings);
+    }
+    cartridge.set_autosave(settings.autosave.unwrap_or(config.autosave));
+    cartridge.set_autosave_slots(config.autosave_slots);
+    let autosave_interval = match matches.is_present("autosave-interval") {
+        true => value_t!(matches, "autosave-interval", u64)?,
+        false => config.autosave_interval,
+    };
+    let autosave_interval = match (autosave_interval, config.autosave_slots) {
+        (0, _) | (_, 0) => None,
+        (seconds, _) => Some(Duration::from_secs(seconds)),
+    };
+    let upscale = match matches.is_present("upscale") {
+        true => value_t!(matches, "upscale", u8)?,
+        false => settings.upscale.unwrap_or(config.upscale),
+    };
+    let palette = matches
+        .value_of("palette")
+        .or(settings.palette.as_deref())
+        .unwrap_or(&config.palette);
+    let scheme = config.color_scheme(palette)?;
+    let mut accurate_ppu = matches.is_present("accurate-ppu")
+        || options.accurate_ppu
+        || settings.accurate_ppu.unwrap_or(config.accurate_ppu);
+    if matches.is_present("weak-battery") {
+        let fraction = value_t!(matches, "weak-battery", f64)?;
+        let seed = match matches.is_present("seed") {
+            true => value_t!(matches, "seed", u64)?,
+            false => SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as u64,
+        };
+        let flipped = cartridge.corrupt_ram(fraction, seed);
+        info!("  -> Weak battery flipped {} bits (seed {})", flipped, seed);
+    }
+    if let Some(rtc) = cartridge.rtc_mut() {
+        if matches.is_present("rtc-speed") {
+            rtc.set_speed(value_t!(matches, "rtc-speed", u64)?, rtc::host_time());
+        }
+        info!("  -> RTC: {}", rtc.status(rtc::host_time()));
+    }
+
+    let movie = match (movie, matches.value_of("record")) {
+        (Some(movie), _) => {
+            if movie.header.title != cartridge.meta.title {
+                return Err(format!("Movie was recorded with {}", movie.header.title).into());
+            }
+            accurate_ppu = movie.header.accurate_ppu;
+            Some(MovieMode::Playing(movie))
+        }
+        (None, Some(path)) => {
+            let header = MovieHeader {
+                title: cartridge.meta.title.clone(),
+                accurate_ppu,
+            };
+            Some(MovieMode::Recording(MovieRecorder::create(
+                Path::new(path),
+                &header,
+            )?))
+        }
+        (None, None) => None,
+    };
+
+    let database = Database::load(&database_path(&matches)?)?;
+    let compatibility = match database.lookup(&cartridge.meta.title) {
+        Some(entry) => {
+            info!(
+                "  -> Compatibility: {} ({})",
+                entry.status,
+                entry.path.display()
+            );
+            if let Some(details) = &entry.details {
+                info!("     {}", details);
+            }
+            entry.status
+        }
+        None => Compatibility::Unknown,
+    };
+    #[cfg(feature = "verify")]
+    let status = match verify_dump(path, &config_dir, &matches)? {
+        Some(verification) => format!("{} - {}", compatibility, verification),
+        None => compatibility.to_string(),
+    };
+    #[cfg(not(feature = "verify"))]
+    let status = compatibility.to_string();
+
+    let cheats = Cheats::from_list(config.cheats_for(&cartridge.meta.title))?;
+    if !cheats.is_empty() {
+        info!("  -> Cheats enabled");
+    }
+    let key_bindings = config.game_key_bindings(&cartridge.meta.title, &settings);
+    let bundle = DebugBundle::new(
+        &cartridge.meta,
+        &fs::read_to_string(&config_path).unwrap_or_default(),
+    );
     let bus = RefCell::new(MemoryBus::new(cartridge));
-    let mut display = Display::new(2, fps_limit).expect("Unable to create sdl2 Display");
-    let mut ppu = PPU::new(&bus, &mut display);
-    let cpu = RefCell::new(CPU::new(&bus));
-    let mut irq_handler = IRQHandler::new(&cpu, &bus);
+    bus.borrow_mut().set_cheats(cheats);
+    // The emulation loops stop on SIGINT and SIGTERM, the guard then saves the RAM
+    // which has been written since the game disabled it the last time, also on panics.
+    let _save_guard = SaveGuard(&bus, config.save_on_exit);
+    ctrlc::set_handler(utils::request_shutdown)?;
+    bus.borrow_mut().set_accurate(
+        matches.is_present("accurate-memory")
+            || settings.accurate_memory.unwrap_or(config.accurate_memory),
+    );
+    bus.borrow_mut()
+        .set_strict(matches.is_present("strict") || config.strict);
+    let sink = match matches.value_of("sink") {
+        Some(name) => Some(sink::create(
+            name,
+            scheme,
+            matches.value_of("listen").unwrap(),
+        )?),
+        None => None,
+    };
+    // Frame sinks run headless without a window
+    let mut headless = Headless::new();
+    let mut display;
+    let screen: &mut dyn Screen = match sink {
+        Some(_) => &mut headless,
+        None => {
+            display = Display::new(
+                upscale,
+                orientation,
+                ambient,
+                scheme,
+                fps_limit,
+                key_bindings,
+            )
+            .expect("Unable to create sdl2 Display");
+            WINDOWED.store(true, Ordering::Relaxed);
+            display.set_status(&status);
+            if matches.is_present("power-save") || config.power_save {
+                display.enable_power_saving();
+            }
+            if matches.is_present("pause-on-focus-loss") || config.pause_on_focus_loss {
+                display.enable_pause_on_focus_loss();
+            }
+            if let Some(movie) = movie {
+                display.set_movie(movie);
+            }
+            if matches.is_present("measure-latency") {
+                display.enable_latency_measurement();
+            }
+            &mut display
+        }
+    };
+    let mut ppu = PPU::new(&bus, screen);
+    if accurate_ppu {
+        ppu.enable_cycle_accuracy();
+    }
     let mut timer = Timer::new(&bus);
 
+    let tracer = match matches.value_of("trace") {
+        Some(path) => {
+            let tracer = Tracer::new(TRACE_CAPACITY).with_file(Path::new(path))?;
+            match matches.is_present("trace-memory") {
+                true => Some(tracer.with_memory()),
+                false => Some(tracer),
+            }
+        }
+        None => None,
+    };
+
+    // The hooks run in every emulation loop, including the debugger
+    let mut frontend = Frontend::new(&bus);
+    // Only capture a thumbnail on the first boot of a game
+    let thumbnail = browser::thumbnail_path(&thumbnail_dir, path);
+    if !thumbnail.exists() {
+        frontend.set_thumbnail(thumbnail);
+    }
+    if let Some(link) = link {
+        frontend.set_link(link);
+    }
+    if let Some(interval) = autosave_interval {
+        frontend.set_autosave(interval);
+    }
+    if matches.is_present("stall-report") {
+        frontend.set_stall_report(bundle.clone());
+    }
+
     match debug {
+        false if matches.is_present("diagnostics") => {
+            // The recorder is used to detect reads of LY
+            let recorder = RefCell::new(RecordingBus::new(&bus));
+            let cpu = RefCell::new(CPU::new(&recorder));
+            #[cfg(feature = "host-calls")]
+            install_host_calls(&cpu, &matches);
+            let mut irq_handler = IRQHandler::new(&cpu, &recorder);
+            let mut diagnostics = Diagnostics::new(config.diagnostics);
+            if matches.is_present("memory-warnings") {
+                diagnostics.enable_memory_warnings();
+            }
+            frontend::diagnose(
+                &cpu,
+                &bus,
+                &recorder,
+                &mut ppu,
+                &mut timer,
+                &mut irq_handler,
+                frontend,
+                diagnostics,
+            )
+        }
         true => {
-            let mut debugger = Debugger::new(&cpu, &bus, &mut ppu, &mut timer, &mut irq_handler);
+            // Route CPU accesses through a recorder to show the bus activity per instruction
+            let recorder = RefCell::new(RecordingBus::new(&bus));
+            let cpu = RefCell::new(CPU::new(&recorder));
+            #[cfg(feature = "host-calls")]
+            install_host_calls(&cpu, &matches);
+            let mut irq_handler = IRQHandler::new(&cpu, &recorder);
+            let mut debugger = Debugger::new(
+                &cpu,
+                &bus,
+                &recorder,
+                &mut ppu,
+                &mut timer,
+                &mut irq_handler,
+            );
+            debugger.set_bundle(bundle);
+            debugger.set_frontend(frontend);
+            let breakpoints = config_dir
+                .join("breakpoints")
+                .join(format!("{}.json", game_id));
+            debugger.persist_breakpoints(breakpoints)?;
+            if let Some(tracer) = tracer {
+                debugger.set_tracer(tracer);
+            }
             debugger.emulate()?
         }
-        false => emulate(&cpu, &mut ppu, &mut timer, &mut irq_handler),
+        false if tracer.is_some() => {
+            // The recorder provides the memory accesses of each instruction
+            let recorder = RefCell::new(RecordingBus::new(&bus));
+            let cpu = RefCell::new(CPU::new(&recorder));
+            #[cfg(feature = "host-calls")]
+            install_host_calls(&cpu, &matches);
+            let mut irq_handler = IRQHandler::new(&cpu, &recorder);
+            let tracer = tracer.unwrap();
+            frontend::trace(
+                &cpu,
+                &bus,
+                &recorder,
+                &mut ppu,
+                &mut timer,
+                &mut irq_handler,
+                frontend,
+                tracer,
+            )?
+        }
+        false => {
+            let cpu = RefCell::new(CPU::new(&bus));
+            #[cfg(feature = "host-calls")]
+            install_host_calls(&cpu, &matches);
+            let mut irq_handler = IRQHandler::new(&cpu, &bus);
+            match sink {
+                Some(sink) => {
+                    let pacer = match fps_limit {
+                        0 => None,
+                        _ => Some(Pacer::new()),
+                    };
+                    let result = frontend::stream(
+                        &cpu,
+                        &mut ppu,
+                        &mut timer,
+                        &mut irq_handler,
+                        frontend,
+                        sink,
+                        pacer,
+                    );
+                    match result {
+                        // The receiving end has been closed
+                        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {}
+                        result => result?,
+                    }
+                }
+                None => frontend::emulate(&cpu, &mut ppu, &mut timer, &mut irq_handler, frontend),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the battery backed RAM when emulation ends, no matter how,
+/// unless saving on exit has been disabled
+struct SaveGuard<'a>(&'a RefCell<MemoryBus>, bool);
+
+impl<'a> Drop for SaveGuard<'a> {
+    fn drop(&mut self) {
+        if !self.1 {
+            return;
+        }
+        if let Err(err) = self.0.borrow().save_ram() {
+            let message = format!("Unable to write save file: {}", err);
+            error!("{}", message);
+            show_error_dialog(&message);
+        }
+    }
+}
+
+/// Shows an error dialog without a parent window, e.g. if it has been closed
+/// already. Notifications are shown by the window itself while it's open.
+fn show_error_dialog(message: &str) {
+    let flags = MessageBoxFlag::ERROR;
+    if let Err(err) = messagebox::show_simple_message_box(flags, "Romoulade", message, None) {
+        warn!("Unable to show dialog: {}", err);
+    }
+}
+
+/// Adds, removes, enables, disables or lists the cheats of a game
+fn edit_cheats(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let config_path = config::config_path(&resolve_config_dir(is_portable(matches))?);
+    let mut config = Config::load(&config_path)?;
+    let cartridge = Cartridge::from_path(Path::new(matches.value_of("rom").unwrap()), None)?;
+    let list = config.cheats_mut(&cartridge.meta.title);
+    match matches.subcommand() {
+        ("add", Some(args)) => {
+            let name = args.value_of("name").unwrap();
+            let code = args.value_of("code").unwrap();
+            Code::parse(code)?;
+            if list.iter().any(|cheat| cheat.name == name) {
+                return Err(format!("Cheat {} already exists", name).into());
+            }
+            list.push(Cheat {
+                name: name.to_string(),
+                code: code.to_uppercase(),
+                enabled: true,
+            });
+        }
+        ("remove", Some(args)) => {
+            let name = args.value_of("name").unwrap();
+            cheats::find_mut(list, name)?;
+            list.retain(|cheat| cheat.name != name);
+        }
+        ("enable", Some(args)) => {
+            cheats::find_mut(list, args.value_of("name").unwrap())?.enabled = true
+        }
+        ("disable", Some(args)) => {
+            cheats::find_mut(list, args.value_of("name").unwrap())?.enabled = false
+        }
+        _ => {
+            for cheat in list.iter() {
+                let state = if cheat.enabled { "enabled" } else { "disabled" };
+                println!("{:<24} {:<12} {}", cheat.name, cheat.code, state);
+            }
+            return Ok(());
+        }
     }
+    config.save(&config_path)?;
     Ok(())
 }
 
-/// Starts the emulating loop
-fn emulate<T: AddressSpace>(
-    cpu: &RefCell<CPU<T>>,
-    ppu: &mut PPU,
-    timer: &mut Timer,
-    irq_handler: &mut IRQHandler<T>,
-) {
-    loop {
-        let cycles = cpu.borrow_mut().step();
-        timer.step(cycles);
-        ppu.step(cycles);
-        irq_handler.handle();
+/// Stores or prints the options which are applied whenever a ROM is launched
+fn edit_launch_options(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let config_path = config::config_path(&resolve_config_dir(is_portable(matches))?);
+    let mut config = Config::load(&config_path)?;
+    let rom = Path::new(matches.value_of("rom").unwrap()).canonicalize()?;
+    let mut options = match matches.is_present("clear") {
+        true => LaunchOptions::default(),
+        false => config.launch_options_for(&rom),
+    };
+    options.debug |= matches.is_present("debug");
+    options.accurate_ppu |= matches.is_present("accurate-ppu");
+    options.no_fps_limit |= matches.is_present("no-fps-limit");
+    if let Some(save_dir) = matches.value_of("save-dir") {
+        options.save_dir = Some(Path::new(save_dir).canonicalize()?);
+    }
+    println!("Launch options of {}: {:?}", rom.display(), options);
+    config.set_launch_options(&rom, options);
+    config.save(&config_path)?;
+    Ok(())
+}
+
+/// Matches the ROM against the DAT file given with `--dat` or the one in the configuration
+/// directory and prints the result together with problems of the header.
+/// Returns None if there is no DAT file.
+#[cfg(feature = "verify")]
+fn verify_dump(
+    rom: &Path,
+    config_dir: &Path,
+    matches: &ArgMatches,
+) -> Result<Option<Verification>, Box<dyn Error>> {
+    let rom = fs::read(rom)?;
+    let header = Header::parse(&rom)?;
+    for warning in header.warnings() {
+        warn!("{}", warning);
+    }
+    let default = config_dir.join(DAT_FILE);
+    let dat = match matches.value_of("dat") {
+        Some(path) => Dat::load(Path::new(path))?,
+        None if default.exists() => Dat::load(&default)?,
+        None => return Ok(None),
+    };
+    let verification = dat.verify(&rom);
+    match &verification {
+        Verification::Verified(name) => info!("  -> {}: {}", verification, name),
+        Verification::Unknown => {
+            info!("  -> {}", verification);
+            // Overdumps contain a good dump followed by garbage
+            let overdump = header
+                .declared_rom_size()
+                .filter(|size| *size < rom.len())
+                .and_then(|size| dat.lookup(&rom[..size]));
+            if let Some(entry) = overdump {
+                warn!("Overdump of {}", entry.name);
+            }
+        }
+    }
+    Ok(Some(verification))
+}
+
+/// Stores or prints the settings which override the configuration for the game of a ROM
+fn edit_game_settings(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let config_path = config::config_path(&resolve_config_dir(is_portable(matches))?);
+    let mut config = Config::load(&config_path)?;
+    let cartridge = Cartridge::from_path(Path::new(matches.value_of("rom").unwrap()), None)?;
+    let id = cartridge.meta.id();
+    let mut settings = match matches.is_present("clear") {
+        true => GameSettings::default(),
+        false => config.game_settings_for(&id),
+    };
+    if let Some(palette) = matches.value_of("palette") {
+        settings.palette = Some(palette.to_string());
+    }
+    if matches.is_present("upscale") {
+        settings.upscale = Some(value_t!(matches, "upscale", u8)?);
+    }
+    if let Some(profile) = matches.value_of("profile") {
+        settings.profile = Some(profile.to_string());
+    }
+    let switch = |name| matches.value_of(name).map(|value| value == "on");
+    settings.autosave = switch("autosave").or(settings.autosave);
+    settings.accurate_ppu = switch("accurate-ppu").or(settings.accurate_ppu);
+    settings.accurate_memory = switch("accurate-memory").or(settings.accurate_memory);
+    println!("Settings of {}: {:?}", id, settings);
+    config.set_game_settings(&id, settings)?;
+    config.save(&config_path)?;
+    Ok(())
+}
+
+/// Shows, pauses, resumes or sets the real time clock stored in the save file of a ROM
+fn edit_rtc(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let mut cartridge = load_saved_cartridge(matches)?;
+    let host = rtc::host_time();
+    let rtc = cartridge
+        .rtc_mut()
+        .ok_or("The cartridge doesn't have a real time clock")?;
+    if let Some(time) = matches.value_of("set") {
+        rtc.set(rtc::parse_time(time)?, host);
+    }
+    if matches.is_present("pause") || matches.is_present("resume") {
+        rtc.set_halted(matches.is_present("pause"), host);
+    }
+    println!("{}", rtc.status(host));
+    cartridge.save_ram()?;
+    Ok(())
+}
+
+/// Imports or exports the save file of a ROM as raw `.sav` file used by most emulators
+fn transfer_save(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let mut cartridge = load_saved_cartridge(matches)?;
+    if !cartridge.meta.battery {
+        return Err("The cartridge doesn't have battery backed RAM".into());
+    }
+    if let Some(path) = matches.value_of("import") {
+        cartridge.load_save_data(&fs::read(path)?);
+        cartridge.save_ram()?;
+        println!("Imported {}", path);
+    }
+    if let Some(path) = matches.value_of("export") {
+        fs::write(path, cartridge.save_data())?;
+        println!("Exported {}", path);
+    }
+    Ok(())
+}
+
+/// Loads the cartridge of the ROM given in `matches` with its save file,
+/// which is located like when the ROM is launched.
+fn load_saved_cartridge(matches: &ArgMatches) -> Result<Cartridge, Box<dyn Error>> {
+    let portable = is_portable(matches);
+    let config = Config::load(&config::config_path(&resolve_config_dir(portable)?))?;
+    let rom = Path::new(matches.value_of("rom").unwrap()).canonicalize()?;
+    let save_dir = match config.launch_options_for(&rom).save_dir {
+        Some(save_dir) => save_dir,
+        None => resolve_save_dir(&rom, portable)?,
+    };
+    let mut cartridge = Cartridge::from_path(&rom, Some(&save_dir))?;
+    cartridge.set_backups(config.save_backups);
+    Ok(cartridge)
+}
+
+/// Installs the built-in host calls if they have been requested
+#[cfg(feature = "host-calls")]
+fn install_host_calls<T: AddressSpace>(cpu: &RefCell<CPU<T>>, matches: &ArgMatches) {
+    if matches.is_present("host-calls") {
+        hostcall::install_defaults(&mut cpu.borrow_mut());
+    }
+}
+
+/// Returns the directory which contains the executable
+fn exe_dir() -> io::Result<PathBuf> {
+    Ok(env::current_exe()?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default())
+}
+
+/// Portable mode is enabled either by flag or by a marker file next to the executable
+fn is_portable(matches: &ArgMatches) -> bool {
+    matches.is_present("portable") || exe_dir().is_ok_and(|d| d.join(PORTABLE_MARKER).exists())
+}
+
+/// Returns the directory where save files should be stored.
+/// In portable mode this is a folder beside the executable,
+/// otherwise save files are stored next to the ROM.
+fn resolve_save_dir(rom: &Path, portable: bool) -> io::Result<PathBuf> {
+    if portable {
+        let save_dir = exe_dir()?.join("saves");
+        fs::create_dir_all(&save_dir)?;
+        return Ok(save_dir);
+    }
+    Ok(rom.parent().map(Path::to_path_buf).unwrap_or_default())
+}
+
+/// Returns the directory where the configuration is stored.
+/// In portable mode this is the directory of the executable,
+/// otherwise the platform config directory is used.
+fn resolve_config_dir(portable: bool) -> io::Result<PathBuf> {
+    if portable {
+        return exe_dir();
+    }
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".config"),
+            None => exe_dir()?,
+        },
+    };
+    Ok(config_home.join(crate_name!()))
+}
+
+/// Returns the path of the compatibility database, by default it's stored in the configuration directory
+fn database_path(matches: &ArgMatches) -> io::Result<PathBuf> {
+    match matches.value_of("database") {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => Ok(resolve_config_dir(is_portable(matches))?.join(COMPAT_FILE)),
     }
 }
 
 fn parse_args() -> ArgMatches<'static> {
-    App::new(crate_name!())
+    let app = App::new(crate_name!())
         .version(crate_version!())
         .author(crate_authors!())
         .about("Experimental GB Emulator")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .setting(AppSettings::ArgsNegateSubcommands)
         .arg(
             Arg::with_name("rom")
                 .help("Path of the ROM to load")
@@ -91,26 +793,633 @@ fn parse_args() -> ArgMatches<'static> {
                 .help("Enable debugger")
                 .long("debug"),
         )
+        .arg(
+            Arg::with_name("diagnostics")
+                .help("Report timing patterns which may cause compatibility problems")
+                .long("diagnostics")
+                .conflicts_with("debug"),
+        )
+        .arg(
+            Arg::with_name("memory-warnings")
+                .help("Report accesses to Echo RAM, unusable memory and write-only registers")
+                .long("memory-warnings")
+                .requires("diagnostics"),
+        )
+        .arg(
+            Arg::with_name("sink")
+                .help("Run without a window and send the frames to the given sink")
+                .long("sink")
+                .value_name("NAME")
+                .possible_values(&sink::NAMES)
+                .conflicts_with_all(&["debug", "diagnostics"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("listen")
+                .help("Address the http sink is listening on")
+                .long("listen")
+                .value_name("ADDRESS")
+                .default_value("127.0.0.1:8080")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("link")
+                .help("Connect the serial port to a DMG-07 four player adapter")
+                .long("link")
+                .value_name("ADDRESS")
+                .conflicts_with_all(&["debug", "diagnostics", "sink", "dump-frame"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("record")
+                .help("Record the buttons of each frame to a movie file")
+                .long("record")
+                .value_name("FILE")
+                .conflicts_with_all(&["play", "sink", "dump-frame"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("play")
+                .help("Replay the buttons of a movie file, the movie's PPU mode is used")
+                .long("play")
+                .value_name("FILE")
+                .conflicts_with_all(&["sink", "dump-frame"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dump-frame")
+                .help("Run without a window and write the last frame to a PNG")
+                .long("dump-frame")
+                .value_name("FILE")
+                .conflicts_with_all(&["debug", "diagnostics", "sink"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("bench")
+                .help("Run without a window as fast as possible and print the emulation speed")
+                .long("bench")
+                .conflicts_with_all(&["debug", "diagnostics", "sink", "dump-frame"]),
+        )
+        .arg(
+            Arg::with_name("frames")
+                .help("Number of frames to emulate for --dump-frame and --bench")
+                .long("frames")
+                .value_name("N")
+                .default_value("600")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("reference")
+                .help("Compare the dumped frame with a reference PNG, fails on any difference")
+                .long("reference")
+                .value_name("FILE")
+                .requires("dump-frame")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("no-fps-limit")
                 .help("Disable fps limit for debugging purposes")
                 .long("no-fps-limit"),
         )
-        .get_matches()
+        .arg(
+            Arg::with_name("rotate")
+                .help("Rotate the display output clockwise")
+                .long("rotate")
+                .value_name("DEGREES")
+                .possible_values(&["0", "90", "180", "270"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("palette")
+                .help("Colors used to display the LCD, custom colors are read from the config")
+                .long("palette")
+                .value_name("NAME")
+                .possible_values(&["grayscale", "green", "sgb", "custom"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("upscale")
+                .help("Scale the display output by 2^N")
+                .long("upscale")
+                .value_name("N")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mirror")
+                .help("Mirror the display output horizontally")
+                .long("mirror"),
+        )
+        .arg(
+            Arg::with_name("ambient")
+                .help("Surround the LCD with a border in the average color of the frame")
+                .long("ambient"),
+        )
+        .arg(
+            Arg::with_name("accurate-ppu")
+                .help("Advance all PPU modes cycle by cycle for exact STAT timing, this is slower")
+                .long("accurate-ppu"),
+        )
+        .arg(
+            Arg::with_name("accurate-memory")
+                .help("Emulate memory quirks of the DMG like the prohibited area after OAM")
+                .long("accurate-memory"),
+        )
+        .arg(
+            Arg::with_name("measure-latency")
+                .help("Flash the screen on each key press and print the input latency")
+                .long("measure-latency")
+                .conflicts_with_all(&["sink", "dump-frame"]),
+        )
+        .arg(
+            Arg::with_name("trace")
+                .help("Log every executed instruction with the registers to a file")
+                .long("trace")
+                .value_name("FILE")
+                .conflicts_with_all(&["diagnostics", "sink", "dump-frame", "link"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("trace-memory")
+                .help("Add the memory accesses of each instruction to the trace")
+                .long("trace-memory")
+                .requires("trace"),
+        )
+        .arg(
+            Arg::with_name("weak-battery")
+                .help("Flip a random bit in the given fraction of cartridge RAM bytes at load")
+                .long("weak-battery")
+                .value_name("FRACTION")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .help("Seed of the weak battery corruption to reproduce it")
+                .long("seed")
+                .value_name("N")
+                .requires("weak-battery")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("stall-report")
+                .help("Write a debug bundle once the game appears stuck")
+                .long("stall-report"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .help("Log debug messages, twice to also log traces")
+                .short("v")
+                .long("verbose")
+                .multiple(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("autosave-interval")
+                .help("Write the save file to the next autosave slot every SECS seconds, 0 disables it")
+                .long("autosave-interval")
+                .value_name("SECS")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("strict")
+                .help("Panic on writes to unmapped I/O registers instead of ignoring them")
+                .long("strict"),
+        )
+        .arg(
+            Arg::with_name("rtc-speed")
+                .help("Run the real time clock of MBC3 cartridges N times faster")
+                .long("rtc-speed")
+                .value_name("N")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("pause-on-focus-loss")
+                .help("Pause emulation while the window is minimized or unfocused")
+                .long("pause-on-focus-loss"),
+        )
+        .arg(
+            Arg::with_name("power-save")
+                .help("Throttle emulation while the game is idle")
+                .long("power-save"),
+        )
+        .arg(
+            Arg::with_name("portable")
+                .help("Store configuration and save files next to the executable")
+                .long("portable")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("database")
+                .help("Path of the compatibility database")
+                .long("database")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .subcommand(
+            SubCommand::with_name("batch")
+                .visible_alias("scan")
+                .about("Runs all ROMs in a directory headlessly and writes a JSON or CSV report")
+                .arg(
+                    Arg::with_name("dir")
+                        .help("Directory containing the ROMs to run")
+                        .index(1)
+                        .required(true)
+                        .value_name("DIR")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("frames")
+                        .help("Number of frames to emulate for each ROM")
+                        .long("frames")
+                        .value_name("N")
+                        .default_value("600")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("unlocked")
+                        .help("Run as fast as possible instead of pacing to real time")
+                        .long("unlocked"),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .help("Number of ROMs to run in parallel, defaults to the number of CPUs")
+                        .long("jobs")
+                        .short("j")
+                        .value_name("N")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("report")
+                        .help("Path of the report, written as CSV if it ends with .csv")
+                        .long("report")
+                        .value_name("FILE")
+                        .default_value("report.json")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("test-suite")
+                .about("Runs blargg and mooneye test ROMs headlessly and prints a summary")
+                .arg(
+                    Arg::with_name("dir")
+                        .help("Directory containing the test ROMs, subdirectories are included")
+                        .index(1)
+                        .required(true)
+                        .value_name("DIR")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("frames")
+                        .help("Number of frames after which a test ROM times out")
+                        .long("frames")
+                        .value_name("N")
+                        .default_value("3600")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("dmg07")
+                .about("Runs a DMG-07 four player adapter which emulators connect to with --link")
+                .arg(
+                    Arg::with_name("listen")
+                        .help("Address the adapter is listening on")
+                        .long("listen")
+                        .value_name("ADDRESS")
+                        .default_value("127.0.0.1:8766")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("compat")
+                .about("Regenerates the compatibility database from a batch report")
+                .arg(
+                    Arg::with_name("report")
+                        .help("Path of the JSON report created by the batch runner")
+                        .long("report")
+                        .value_name("FILE")
+                        .default_value("report.json")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("database")
+                        .help("Path of the compatibility database")
+                        .long("database")
+                        .value_name("FILE")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("browse")
+                .about("Shows the recently used ROMs with their thumbnails to select one"),
+        )
+        .subcommand(
+            SubCommand::with_name("controls")
+                .about("Opens a window to remap the key bindings of each button")
+                .arg(
+                    Arg::with_name("profile")
+                        .help("Remap the given profile instead of the default bindings")
+                        .long("profile")
+                        .value_name("NAME")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("cheats")
+                .about(
+                    "Manages the GameShark and Game Genie cheats of a game, lists them by default",
+                )
+                .arg(
+                    Arg::with_name("rom")
+                        .help("Path of the ROM")
+                        .index(1)
+                        .required(true)
+                        .value_name("ROM")
+                        .takes_value(true),
+                )
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Adds an enabled cheat")
+                        .arg(
+                            Arg::with_name("name")
+                                .help("Name of the cheat")
+                                .index(1)
+                                .required(true)
+                                .value_name("NAME")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("code")
+                                .help("GameShark (01VVAAAA) or Game Genie (ABC-DEF-GHI) code")
+                                .index(2)
+                                .required(true)
+                                .value_name("CODE")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("Removes a cheat")
+                        .arg(
+                            Arg::with_name("name")
+                                .help("Name of the cheat")
+                                .index(1)
+                                .required(true)
+                                .value_name("NAME")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("enable")
+                        .about("Enables a cheat")
+                        .arg(
+                            Arg::with_name("name")
+                                .help("Name of the cheat")
+                                .index(1)
+                                .required(true)
+                                .value_name("NAME")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("disable")
+                        .about("Disables a cheat")
+                        .arg(
+                            Arg::with_name("name")
+                                .help("Name of the cheat")
+                                .index(1)
+                                .required(true)
+                                .value_name("NAME")
+                                .takes_value(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("profile")
+                .about("Assigns a key binding profile to the game of a ROM")
+                .arg(
+                    Arg::with_name("rom")
+                        .help("Path of the ROM")
+                        .index(1)
+                        .required(true)
+                        .value_name("ROM")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .help("Name of the profile, restores the default bindings if omitted")
+                        .index(2)
+                        .value_name("NAME")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("rtc")
+                .about("Shows or adjusts the real time clock stored in the save file of a ROM")
+                .arg(
+                    Arg::with_name("rom")
+                        .help("Path of the ROM")
+                        .index(1)
+                        .required(true)
+                        .value_name("ROM")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("set")
+                        .help("Sets the clock, e.g. 12:18:30:00 for day 12, 18:30")
+                        .long("set")
+                        .value_name("D:HH:MM:SS")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("pause")
+                        .help("Stops the clock, also while the emulator is closed")
+                        .long("pause")
+                        .conflicts_with("resume"),
+                )
+                .arg(
+                    Arg::with_name("resume")
+                        .help("Resumes a stopped clock")
+                        .long("resume"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sav")
+                .about("Imports or exports the save file of a ROM as raw .sav file")
+                .arg(
+                    Arg::with_name("rom")
+                        .help("Path of the ROM")
+                        .index(1)
+                        .required(true)
+                        .value_name("ROM")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("import")
+                        .help("Replaces the save file with the given raw .sav file")
+                        .long("import")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .required_unless("export"),
+                )
+                .arg(
+                    Arg::with_name("export")
+                        .help("Writes the save file to the given raw .sav file")
+                        .long("export")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .conflicts_with("import"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("launch")
+                .about("Stores options which are applied whenever a ROM is launched")
+                .arg(
+                    Arg::with_name("rom")
+                        .help("Path of the ROM")
+                        .index(1)
+                        .required(true)
+                        .value_name("ROM")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("clear")
+                        .help("Removes the stored options before applying the given ones")
+                        .long("clear"),
+                )
+                .arg(
+                    Arg::with_name("debug")
+                        .help("Start the debugger")
+                        .long("debug"),
+                )
+                .arg(
+                    Arg::with_name("accurate-ppu")
+                        .help("Advance all PPU modes cycle by cycle")
+                        .long("accurate-ppu"),
+                )
+                .arg(
+                    Arg::with_name("no-fps-limit")
+                        .help("Disable the frame rate limit")
+                        .long("no-fps-limit"),
+                )
+                .arg(
+                    Arg::with_name("save-dir")
+                        .help("Directory of the save file")
+                        .long("save-dir")
+                        .value_name("DIR")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Prints the cartridge header of a ROM and checks its checksums")
+                .arg(
+                    Arg::with_name("rom")
+                        .help("Path of the ROM")
+                        .index(1)
+                        .required(true)
+                        .value_name("ROM")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("game")
+                .about("Stores settings which override the configuration for the game of a ROM")
+                .arg(
+                    Arg::with_name("rom")
+                        .help("Path of the ROM")
+                        .index(1)
+                        .required(true)
+                        .value_name("ROM")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("clear")
+                        .help("Removes the stored settings before applying the given ones")
+                        .long("clear"),
+                )
+                .arg(
+                    Arg::with_name("palette")
+                        .help("Colors used to display the LCD")
+                        .long("palette")
+                        .value_name("NAME")
+                        .possible_values(&["grayscale", "green", "sgb", "custom"])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("upscale")
+                        .help("Scale the display output by 2^N")
+                        .long("upscale")
+                        .value_name("N")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("profile")
+                        .help("Name of the key binding profile")
+                        .long("profile")
+                        .value_name("NAME")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("autosave")
+                        .help("Write the save file whenever the game disables cartridge RAM")
+                        .long("autosave")
+                        .value_name("on|off")
+                        .possible_values(&["on", "off"])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("accurate-ppu")
+                        .help("Advance all PPU modes cycle by cycle")
+                        .long("accurate-ppu")
+                        .value_name("on|off")
+                        .possible_values(&["on", "off"])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("accurate-memory")
+                        .help("Emulate memory quirks of the DMG which only few games depend on")
+                        .long("accurate-memory")
+                        .value_name("on|off")
+                        .possible_values(&["on", "off"])
+                        .takes_value(true),
+                ),
+        );
+    #[cfg(feature = "host-calls")]
+    let app = app.arg(
+        Arg::with_name("host-calls")
+            .help("Handle the illegal opcodes 0xD3 (print), 0xDB (assert) and 0xDD (benchmark)")
+            .long("host-calls"),
+    );
+    #[cfg(feature = "verify")]
+    let app = app.arg(
+        Arg::with_name("dat")
+            .help("No-Intro style DAT file to verify the ROM against")
+            .long("dat")
+            .value_name("FILE")
+            .takes_value(true),
+    );
+    #[cfg(feature = "automation")]
+    let app = app.subcommand(
+        SubCommand::with_name("automation")
+            .about("Starts a JSON-RPC server to control the emulator over a local socket")
+            .arg(
+                Arg::with_name("listen")
+                    .help("Address the server is listening on")
+                    .long("listen")
+                    .value_name("ADDRESS")
+                    .default_value("127.0.0.1:8765")
+                    .takes_value(true),
+            ),
+    );
+    app.get_matches()
 }
 
 fn panic_hook(info: &PanicInfo<'_>) {
-    if cfg!(debug_assertions) {
+    let msg = utils::panic_message(info.payload());
+    if !cfg!(debug_assertions) {
+        eprintln!("The emulator crashed: {}", msg);
+    } else {
         let location = info.location().unwrap();
 
-        let msg = match info.payload().downcast_ref::<&'static str>() {
-            Some(s) => *s,
-            None => match info.payload().downcast_ref::<String>() {
-                Some(s) => &s[..],
-                None => "Box<Any>",
-            },
-        };
-
         let stacktrace: String = format!("{:?}", Backtrace::new()).replace('\n', "\n\r");
 
         println!(
@@ -121,4 +1430,8 @@ fn panic_hook(info: &PanicInfo<'_>) {
             stacktrace
         );
     }
+    // Without a terminal the crash would otherwise go unnoticed
+    if WINDOWED.load(Ordering::Relaxed) {
+        show_error_dialog(&format!("The emulator crashed: {}", msg));
+    }
 }
@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::{fmt, mem};
+
+/// Maximum number of notifications which are kept for the log
+const LOG_CAPACITY: usize = 100;
+
+/// Severity of a notification, the frontend shows errors as dialog
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Info => write!(f, "Info"),
+            Level::Warning => write!(f, "Warning"),
+            Level::Error => write!(f, "Error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub level: Level,
+    pub message: String,
+}
+
+impl fmt::Display for Notification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.level, self.message)
+    }
+}
+
+/// Recent notifications, the last `unread` ones haven't been shown by the frontend yet
+struct Log {
+    entries: VecDeque<Notification>,
+    unread: usize,
+}
+
+static LOG: Mutex<Log> = Mutex::new(Log {
+    entries: VecDeque::new(),
+    unread: 0,
+});
+
+/// Notifications are also sent while panicking, so a poisoned log is still used
+fn lock() -> MutexGuard<'static, Log> {
+    LOG.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Prints the message to stderr and keeps it for the frontend,
+/// so problems are noticed without watching the terminal.
+pub fn notify(level: Level, message: impl Into<String>) {
+    let notification = Notification {
+        level,
+        message: message.into(),
+    };
+    eprintln!("{}", notification);
+    let mut log = lock();
+    if log.entries.len() == LOG_CAPACITY {
+        log.entries.pop_front();
+    }
+    log.entries.push_back(notification);
+    log.unread = (log.unread + 1).min(LOG_CAPACITY);
+}
+
+pub fn info(message: impl Into<String>) {
+    notify(Level::Info, message);
+}
+
+pub fn warn(message: impl Into<String>) {
+    notify(Level::Warning, message);
+}
+
+pub fn error(message: impl Into<String>) {
+    notify(Level::Error, message);
+}
+
+/// Returns the notifications which haven't been taken yet, oldest first
+pub fn take_unread() -> Vec<Notification> {
+    let mut log = lock();
+    let unread = mem::take(&mut log.unread);
+    let skip = log.entries.len() - unread;
+    log.entries.iter().skip(skip).cloned().collect()
+}
+
+/// Returns all kept notifications, oldest first
+pub fn log() -> Vec<Notification> {
+    lock().entries.iter().cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notify() {
+        warn("Unknown register");
+        error("Unable to write save file");
+        let unread = take_unread();
+        let expected = Notification {
+            level: Level::Error,
+            message: "Unable to write save file".to_string(),
+        };
+        assert!(unread.contains(&expected));
+        assert!(!take_unread().contains(&expected));
+        assert!(log().contains(&expected));
+        assert_eq!(expected.to_string(), "Error: Unable to write save file");
+    }
+}
@@ -0,0 +1,187 @@
+use crate::batch;
+use crate::gb::cartridge::Cartridge;
+use crate::gb::headless::Headless;
+use crate::gb::memory::MemoryBus;
+use crate::utils;
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+
+/// Opcode of LD B,B which is used by mooneye test ROMs as debug breakpoint
+const LD_B_B: u8 = 0x40;
+/// Mooneye test ROMs load the fibonacci numbers into B, C, D, E, H and L on success
+const MOONEYE_PASSED: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+/// Describes the outcome of a single test ROM
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    Timeout,
+    Panic(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Passed => write!(f, "Passed"),
+            Outcome::Failed => write!(f, "Failed"),
+            Outcome::Timeout => write!(f, "Timeout"),
+            Outcome::Panic(msg) => write!(f, "Panic: {}", msg),
+        }
+    }
+}
+
+/// Runs all blargg and mooneye test ROMs within `dir` and its subdirectories
+/// and prints a summary table. ROMs which don't finish within the given
+/// amount of frames time out. Returns an error if any test didn't pass.
+pub fn run(dir: &Path, frames: u32) -> Result<(), Box<dyn Error>> {
+    let roms = find_roms(dir)?;
+
+    // Panics are part of the summary, don't clutter the output with them.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let outcomes = roms
+        .iter()
+        .map(|path| run_rom(path, frames))
+        .collect::<Vec<Outcome>>();
+    panic::set_hook(hook);
+
+    let names = roms
+        .iter()
+        .map(|path| path.strip_prefix(dir).unwrap_or(path).display().to_string())
+        .collect::<Vec<String>>();
+    let width = names.iter().map(String::len).max().unwrap_or(0);
+    println!("{:<width$}  Result", "ROM", width = width);
+    for (name, outcome) in names.iter().zip(&outcomes) {
+        println!("{:<width$}  {}", name, outcome, width = width);
+    }
+
+    let passed = outcomes.iter().filter(|o| **o == Outcome::Passed).count();
+    println!("\n{} of {} test ROMs passed", passed, outcomes.len());
+    match passed == outcomes.len() {
+        true => Ok(()),
+        false => Err(format!("{} test ROMs didn't pass", outcomes.len() - passed).into()),
+    }
+}
+
+/// Returns all ROMs in the given directory and its subdirectories sorted by path
+fn find_roms(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut roms = batch::find_roms(dir)?;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            roms.extend(find_roms(&path)?);
+        }
+    }
+    roms.sort();
+    Ok(roms)
+}
+
+/// Runs a single test ROM headlessly until it reports a result
+fn run_rom(path: &Path, frames: u32) -> Outcome {
+    // Battery backed RAM is not persisted to keep runs reproducible
+    let cartridge = match Cartridge::from_path(path, None) {
+        Ok(cartridge) => cartridge,
+        Err(err) => return Outcome::Panic(format!("Unable to load cartridge: {}", err)),
+    };
+
+    let bus = RefCell::new(MemoryBus::new(cartridge));
+    let mut screen = Headless::new();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut serial_len = 0;
+        batch::emulate_until(&bus, &mut screen, frames, None, |cpu, opcode| {
+            if opcode == LD_B_B {
+                let r = &cpu.r;
+                return match [r.b, r.c, r.d, r.e, r.h, r.l] == MOONEYE_PASSED {
+                    true => Some(Outcome::Passed),
+                    false => Some(Outcome::Failed),
+                };
+            }
+            // The serial output is only checked when it grows
+            let bus = bus.borrow();
+            let serial = bus.serial_output();
+            if serial.len() == serial_len {
+                return None;
+            }
+            serial_len = serial.len();
+            serial_outcome(serial)
+        })
+        .unwrap_or(Outcome::Timeout)
+    }));
+    result.unwrap_or_else(|payload| Outcome::Panic(utils::panic_message(&*payload).to_string()))
+}
+
+/// Blargg test ROMs print the result to the serial port
+fn serial_outcome(serial: &[u8]) -> Option<Outcome> {
+    let output = String::from_utf8_lossy(serial);
+    if output.contains("Passed") {
+        Some(Outcome::Passed)
+    } else if output.contains("Failed") {
+        Some(Outcome::Failed)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gb::memory::constants::BOOT_ROM;
+    use std::env;
+
+    /// Creates a ROM which passes the boot ROM checks and loads the given registers
+    /// into B, C, D, E, H and L before hitting the LD B,B breakpoint.
+    fn create_mooneye_rom(name: &str, registers: [u8; 6]) -> PathBuf {
+        let mut rom = vec![0u8; 0x8000];
+        // The boot ROM compares the logo with its own copy
+        rom[0x104..0x134].copy_from_slice(&BOOT_ROM[0xA8..0xD8]);
+        rom[0x14D] = rom[0x134..0x14D]
+            .iter()
+            .fold(0u8, |x, byte| x.wrapping_sub(*byte).wrapping_sub(1));
+        // JP 0x0150
+        rom[0x100..0x103].copy_from_slice(&[0xC3, 0x50, 0x01]);
+        // LD B,n; LD C,n; LD D,n; LD E,n; LD H,n; LD L,n
+        for (i, (opcode, value)) in [0x06, 0x0E, 0x16, 0x1E, 0x26, 0x2E]
+            .iter()
+            .zip(registers.iter())
+            .enumerate()
+        {
+            rom[0x150 + i * 2] = *opcode;
+            rom[0x151 + i * 2] = *value;
+        }
+        // LD B,B; JR -2
+        rom[0x15C..0x15F].copy_from_slice(&[LD_B_B, 0x18, 0xFE]);
+
+        let path = env::temp_dir().join(name);
+        fs::write(&path, rom).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_mooneye_breakpoint() {
+        let passed = create_mooneye_rom("romoulade-test-passed.gb", MOONEYE_PASSED);
+        let failed = create_mooneye_rom("romoulade-test-failed.gb", [0x42; 6]);
+        assert_eq!(run_rom(&passed, 600), Outcome::Passed);
+        assert_eq!(run_rom(&failed, 600), Outcome::Failed);
+        fs::remove_file(passed).unwrap();
+        fs::remove_file(failed).unwrap();
+    }
+
+    #[test]
+    fn test_serial_outcome() {
+        assert_eq!(serial_outcome(b"01-special\n\n"), None);
+        assert_eq!(
+            serial_outcome(b"01-special\n\n\nPassed\n"),
+            Some(Outcome::Passed)
+        );
+        assert_eq!(
+            serial_outcome(b"02-interrupts\n\nEI\nFailed #2\n"),
+            Some(Outcome::Failed)
+        );
+    }
+}
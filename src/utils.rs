@@ -1,3 +1,11 @@
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set once the emulator has been asked to terminate
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+/// Set once the emulator has been asked to reset the Game Boy
+static RESET: AtomicBool = AtomicBool::new(false);
+
 /// Gets the bit at position `n`.
 /// Bits are numbered from 0 (least significant) to 7 (most significant).
 pub fn bit_at(input: u8, n: u8) -> bool {
@@ -21,6 +29,46 @@ pub fn half_carry_u8(x: u8, y: u8) -> bool {
     ((x & 0x0F) + (y & 0x0F)) & 0x10 == 0x10
 }
 
+/// Advances the xorshift state and returns the next pseudo random number,
+/// the state must not be zero.
+pub fn xorshift64(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+/// Asks the emulation loop to stop, e.g. on SIGTERM or when the window is closed
+pub fn request_shutdown() {
+    SHUTDOWN.store(true, Ordering::Relaxed);
+}
+
+/// Returns whether the emulation loop should stop
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN.load(Ordering::Relaxed)
+}
+
+/// Asks the emulation loop to reset the Game Boy while keeping the cartridge loaded
+pub fn request_reset() {
+    RESET.store(true, Ordering::Relaxed);
+}
+
+/// Returns whether a reset has been requested since the last call
+pub fn take_reset_request() -> bool {
+    RESET.swap(false, Ordering::Relaxed)
+}
+
+/// Extracts the message from a panic payload.
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&'static str>() {
+        Some(s) => s,
+        None => match payload.downcast_ref::<String>() {
+            Some(s) => &s[..],
+            None => "Box<Any>",
+        },
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -41,6 +89,14 @@ mod tests {
         assert_eq!(set_bit(x, 7, false), 0b01110000u8);
     }
 
+    #[test]
+    fn test_xorshift64() {
+        let mut state = 1;
+        let first = xorshift64(&mut state);
+        assert_ne!(first, xorshift64(&mut state));
+        assert_eq!(xorshift64(&mut 1), first);
+    }
+
     #[test]
     fn test_half_carry_u8_true() {
         let x = 62;
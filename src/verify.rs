@@ -0,0 +1,143 @@
+use std::error::Error;
+use std::path::Path;
+use std::{fmt, fs};
+
+/// Single ROM of a DAT file
+#[derive(Debug, PartialEq)]
+pub struct DatRom {
+    pub name: String,
+    pub size: usize,
+    pub crc32: u32,
+    /// Lowercase hex digest, not all DAT files contain it
+    pub sha1: Option<String>,
+}
+
+/// Known good dumps as listed by a No-Intro style DAT file in the Logiqx XML format
+#[derive(Debug, Default)]
+pub struct Dat {
+    roms: Vec<DatRom>,
+}
+
+/// Result of matching a ROM against a DAT file
+#[derive(Debug, PartialEq)]
+pub enum Verification {
+    /// The ROM is a known good dump with the given name
+    Verified(String),
+    Unknown,
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verification::Verified(_) => write!(f, "Verified good dump"),
+            Verification::Unknown => write!(f, "Unknown/modified dump"),
+        }
+    }
+}
+
+impl Dat {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::parse(&fs::read_to_string(path)?)?)
+    }
+
+    /// Parses the `<rom>` elements of a DAT file, all other elements are ignored
+    pub fn parse(dat: &str) -> Result<Self, String> {
+        let mut roms = Vec::new();
+        for element in dat.split("<rom ").skip(1) {
+            let tag = &element[..element.find('>').ok_or("Unterminated rom element")?];
+            let name = attribute(tag, "name").ok_or("Missing name of rom element")?;
+            let size = attribute(tag, "size").and_then(|size| size.parse().ok());
+            let crc32 = attribute(tag, "crc").and_then(|crc| u32::from_str_radix(&crc, 16).ok());
+            let (size, crc32) = match (size, crc32) {
+                (Some(size), Some(crc32)) => (size, crc32),
+                _ => return Err(format!("Invalid size or crc of {}", name)),
+            };
+            roms.push(DatRom {
+                name,
+                size,
+                crc32,
+                sha1: attribute(tag, "sha1").map(|sha1| sha1.to_lowercase()),
+            });
+        }
+        Ok(Self { roms })
+    }
+
+    /// Returns the entry of the given ROM, the SHA-1 is compared if the DAT contains it
+    pub fn lookup(&self, rom: &[u8]) -> Option<&DatRom> {
+        let crc32 = crc32fast::hash(rom);
+        let mut sha1 = None;
+        self.roms.iter().find(|entry| {
+            if entry.size != rom.len() || entry.crc32 != crc32 {
+                return false;
+            }
+            match &entry.sha1 {
+                Some(expected) => {
+                    let actual =
+                        sha1.get_or_insert_with(|| sha1_smol::Sha1::from(rom).digest().to_string());
+                    actual == expected
+                }
+                None => true,
+            }
+        })
+    }
+
+    pub fn verify(&self, rom: &[u8]) -> Verification {
+        match self.lookup(rom) {
+            Some(entry) => Verification::Verified(entry.name.clone()),
+            None => Verification::Unknown,
+        }
+    }
+}
+
+/// Returns the unescaped value of the given attribute of an XML tag
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    // The first attribute directly follows the element name
+    let tag = format!(" {}", tag);
+    let start = tag.find(&format!(" {}=\"", name))? + name.len() + 3;
+    let end = start + tag[start..].find('"')?;
+    let value = tag[start..end]
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&");
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let rom = vec![0; 0x8000];
+        let dat = format!(
+            r#"<?xml version="1.0"?>
+<datafile>
+    <game name="Empty &amp; Blank (World)">
+        <rom name="Empty &amp; Blank (World).gb" size="32768" crc="{:08X}" sha1="{}"/>
+    </game>
+    <game name="Other (Japan)">
+        <rom name="Other (Japan).gb" size="32768" crc="DEADBEEF"/>
+    </game>
+</datafile>"#,
+            crc32fast::hash(&rom),
+            sha1_smol::Sha1::from(&rom)
+                .digest()
+                .to_string()
+                .to_uppercase()
+        );
+        let dat = Dat::parse(&dat).unwrap();
+        assert_eq!(
+            dat.verify(&rom),
+            Verification::Verified("Empty & Blank (World).gb".to_string())
+        );
+
+        let mut modified = rom.clone();
+        modified[0x0150] = 0x01;
+        assert_eq!(dat.verify(&modified), Verification::Unknown);
+        assert_eq!(dat.verify(&rom[..0x4000]), Verification::Unknown);
+
+        assert!(Dat::parse(r#"<rom name="Broken.gb" size="32768"/>"#).is_err());
+    }
+}